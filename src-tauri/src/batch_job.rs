@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::supported_source_extensions;
+
+/// Check whether a configured source is a glob pattern rather than a plain path
+pub fn is_glob_pattern(source: &str) -> bool {
+    source.contains(['*', '?', '['])
+}
+
+/// Resolve the configured sources of a batch job to existing paths
+///
+/// Plain paths must exist; glob patterns must match at least one directory or
+/// file with a supported extension. Matches of a pattern are sorted by name and
+/// every path is returned only once, in the order it was first listed.
+pub fn expand_sources(sources: &[String]) -> MailSourceResult<Vec<PathBuf>> {
+    let mut seen = HashSet::new();
+    let mut paths = Vec::new();
+
+    for source in sources {
+        let matches = if is_glob_pattern(source) {
+            let entries = glob::glob(source).map_err(|e| {
+                MailSourceError::InvalidFormat(format!("Ungültiges Suchmuster '{}': {}", source, e))
+            })?;
+            let mut matches: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|path| path.is_dir() || has_source_extension(path))
+                .collect();
+            matches.sort();
+            matches
+        } else {
+            let path = PathBuf::from(source);
+            if !path.exists() {
+                return Err(MailSourceError::NotFound(source.clone()));
+            }
+            vec![path]
+        };
+
+        if matches.is_empty() {
+            return Err(MailSourceError::NotFound(source.clone()));
+        }
+
+        for path in matches {
+            if seen.insert(path.clone()) {
+                paths.push(path);
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Output subfolder name for each source: the sanitized file stem, made unique with a "_2", "_3", ... suffix
+pub fn source_output_folders(paths: &[PathBuf]) -> Vec<String> {
+    let mut used = HashSet::new();
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path
+                .file_stem()
+                .map(|stem| sanitize_folder_name(&stem.to_string_lossy()))
+                .filter(|stem| !stem.is_empty())
+                .unwrap_or_else(|| "Quelle".to_string());
+
+            let mut name = stem.clone();
+            let mut counter = 2;
+            while !used.insert(name.to_lowercase()) {
+                name = format!("{}_{}", stem, counter);
+                counter += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+fn has_source_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| supported_source_extensions().contains(&ext.as_str()))
+}
+
+/// Replace characters that are not allowed in folder names
+fn sanitize_folder_name(name: &str) -> String {
+    let invalid_chars = ['<', '>', ':', '"', '|', '?', '*', '/', '\\'];
+    name.chars()
+        .map(|ch| if invalid_chars.contains(&ch) || ch.is_control() { '_' } else { ch })
+        .collect::<String>()
+        .trim()
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_expand_glob_and_plain_paths() {
+        let dir = tempdir().unwrap();
+        for name in ["b.pst", "a.pst", "notes.txt", "c.mbox"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let pattern = dir.path().join("*.pst").to_string_lossy().to_string();
+        let plain = dir.path().join("c.mbox").to_string_lossy().to_string();
+        let duplicate = dir.path().join("a.pst").to_string_lossy().to_string();
+
+        let paths = expand_sources(&[plain, pattern, duplicate]).unwrap();
+        let names: Vec<String> = paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["c.mbox", "a.pst", "b.pst"]);
+    }
+
+    #[test]
+    fn test_expand_reports_missing_sources() {
+        let dir = tempdir().unwrap();
+        let pattern = dir.path().join("*.pst").to_string_lossy().to_string();
+        let missing = dir.path().join("fehlt.pst").to_string_lossy().to_string();
+
+        assert!(matches!(expand_sources(&[pattern]), Err(MailSourceError::NotFound(_))));
+        assert!(matches!(expand_sources(&[missing]), Err(MailSourceError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_output_folders_are_unique() {
+        let paths = vec![
+            PathBuf::from("/2023/Archiv.pst"),
+            PathBuf::from("/2024/archiv.pst"),
+            PathBuf::from("/Postfach: Meier.mbox"),
+        ];
+
+        assert_eq!(source_output_folders(&paths), vec!["Archiv", "archiv_2", "Postfach_ Meier"]);
+    }
+}
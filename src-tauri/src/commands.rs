@@ -1,14 +1,26 @@
 use tauri::command;
-use crate::types::{ProcessingConfig, ProcessingProgress, PstInfo, ProcessingSession};
+use crate::types::{BatchExecution, BatchOutput, DedupScope, Email, ImapConfig, MailFolder, ProcessingConfig, ProcessingMode, ProcessingProgress, PstInfo, ProcessingSession, SourceProgress};
 use crate::pst_processor::PstProcessor;
+use crate::mail_source::{open_mail_source, open_mail_source_with, supported_source_extensions, MailSource, SourceOptions};
+use crate::imap_source::{ImapSource, IMAP_SYNC_STATE_FILE};
+use crate::batch_job::{expand_sources, source_output_folders};
+use crate::dedup::{Deduplicator, DUPLICATES_REPORT_FILE};
+use crate::near_duplicates::NearDuplicateDetector;
+use crate::virtual_mailbox::VirtualMailbox;
+use crate::mailbox_diff::{compare_mailboxes, MailboxDiff, MailboxSnapshot};
 use crate::pdf_generator::PdfGenerator;
+use crate::pdf_fonts::FontChain;
+use crate::pdf_attachments::EmbeddingLimits;
+use crate::pdf_metadata::{file_hash, mailbox_owner, ArchiveProvenance};
+use crate::thread_builder::{ConversationThread, ThreadBuilder};
 use crate::errors::{AppError, AppResult};
 use crate::directory_validator::DirectoryValidator;
-use std::sync::{Arc, Mutex};
-use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::path::{Path, PathBuf};
 use tokio::sync::oneshot;
 use tokio::task;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 // Global state for managing processing sessions
 lazy_static::lazy_static! {
@@ -23,6 +35,14 @@ lazy_static::lazy_static! {
 // Tauri commands for frontend-backend communication
 #[command]
 pub async fn validate_pst_file(file_path: String) -> Result<PstInfo, String> {
+    // Opening a PST walks its node B-tree, so it runs off the async runtime
+    task::spawn_blocking(move || pst_file_info(file_path))
+        .await
+        .map_err(|e| format!("Validierung abgebrochen: {}", e))?
+}
+
+/// Header, size and format checks of a PST file, with its email count if it is valid
+fn pst_file_info(file_path: String) -> Result<PstInfo, String> {
     use std::path::Path;
     use std::fs;
     use chrono::{DateTime, Utc};
@@ -117,6 +137,68 @@ pub async fn validate_pst_file(file_path: String) -> Result<PstInfo, String> {
     Ok(pst_info)
 }
 
+/// Validate any supported input file (PST, MBOX, EML, MSG) or a Maildir / EML directory
+/// PST files get the detailed header checks of `validate_pst_file`
+#[command]
+pub async fn validate_mail_source(file_path: String) -> Result<PstInfo, String> {
+    // Counting reads the whole source (or talks to the IMAP server), so it runs off the async runtime
+    task::spawn_blocking(move || mail_source_info(file_path))
+        .await
+        .map_err(|e| format!("Validierung abgebrochen: {}", e))?
+}
+
+/// Existence, type and email count of a mail source
+fn mail_source_info(file_path: String) -> Result<PstInfo, String> {
+    use std::path::Path;
+    use chrono::{DateTime, Utc};
+
+    let path = Path::new(&file_path);
+    if PstProcessor::has_supported_extension(&path.to_path_buf()) {
+        return pst_file_info(file_path);
+    }
+
+    let mut source_info = PstInfo::new(file_path.clone());
+
+    if !path.exists() {
+        source_info.mark_invalid(vec!["Eingabedatei nicht gefunden".to_string()]);
+        return Ok(source_info);
+    }
+
+    // Directories are read as Maildir or EML trees
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !path.is_dir() && !supported_source_extensions().contains(&extension.as_str()) {
+        source_info.mark_invalid(vec![format!(
+            "Dateityp wird nicht unterstützt. Unterstützte Formate: {}",
+            supported_source_extensions().join(", ")
+        )]);
+        return Ok(source_info);
+    }
+
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            source_info.mark_invalid(vec![format!("Fehler beim Lesen der Datei-Informationen: {}", e)]);
+            return Ok(source_info);
+        }
+    };
+
+    let last_modified = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|duration| DateTime::from_timestamp(duration.as_secs() as i64, 0))
+        .unwrap_or_else(Utc::now);
+
+    match open_mail_source(path).and_then(|source| source.email_count()) {
+        Ok(email_count) => source_info.mark_valid(email_count, metadata.len(), last_modified),
+        Err(e) => source_info.mark_invalid(vec![e.to_string()]),
+    }
+
+    Ok(source_info)
+}
+
 #[command]
 pub async fn start_processing(config: ProcessingConfig) -> Result<String, String> {
     // Validate configuration first
@@ -136,42 +218,53 @@ pub async fn start_processing(config: ProcessingConfig) -> Result<String, String
     let mut session = ProcessingSession::new(config.clone());
     let session_id = session.session_id.clone();
 
-    // Validate PST file exists and is readable
-    let pst_path = PathBuf::from(&config.pst_file_path);
-    let processor = match PstProcessor::new(pst_path) {
-        Ok(processor) => processor,
-        Err(e) => return Err(format!("PST-Datei konnte nicht geöffnet werden: {}", e)),
+    // Validate output directory using DirectoryValidator
+    let validated_output_dir = match DirectoryValidator::validate_directory_path(&config.output_directory) {
+        Ok(path) => path,
+        Err(e) => return Err(format!("Ausgabeverzeichnis ungültig: {}", e)),
     };
 
-    // Get total email count for progress tracking
-    let total_emails = match processor.get_email_count() {
-        Ok(count) => count,
-        Err(e) => return Err(format!("Fehler beim Zählen der E-Mails: {}", e)),
-    };
+    // Validate input files exist and are readable and get the email counts for progress tracking
+    // Opening blocks (IMAP connects and searches here), so it runs off the async runtime
+    let open_config = config.clone();
+    let open_output_dir = validated_output_dir.clone();
+    let opened = task::spawn_blocking(move || {
+        let jobs = open_processing_sources(&open_config, &open_output_dir)
+            .map_err(|e| format!("Eingabedatei konnte nicht geöffnet werden: {}", e))?;
+        let mut source_progress = Vec::with_capacity(jobs.len());
+        for job in &jobs {
+            match job.source.email_count() {
+                Ok(count) => source_progress.push(SourceProgress::new(job.source.source_name(), count)),
+                Err(e) => {
+                    // Still on the blocking thread, so the IMAP LOGOUT does not stall the runtime
+                    let message = format!("Fehler beim Zählen der E-Mails in {}: {}", job.source.source_name(), e);
+                    close_sources(&jobs);
+                    return Err(message);
+                }
+            }
+        }
+        Ok::<_, String>((jobs, source_progress))
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    let (jobs, source_progress) = opened?;
+    let total_emails: usize = source_progress.iter().map(|source| source.total_emails).sum();
 
     if total_emails == 0 {
-        return Err("Die PST-Datei enthält keine E-Mails zum Verarbeiten.".to_string());
+        task::spawn_blocking(move || close_sources(&jobs));
+        return Err("Die Eingabedatei enthält keine E-Mails zum Verarbeiten.".to_string());
     }
 
-    // Validate output directory using DirectoryValidator
-    let validated_output_dir = match DirectoryValidator::validate_directory_path(&config.output_directory) {
-        Ok(path) => path,
-        Err(e) => return Err(format!("Ausgabeverzeichnis ungültig: {}", e)),
-    };
-    
     // Check available space (estimate 10MB per PDF)
-    let estimated_space_needed = (total_emails / config.emails_per_pdf as usize + 1) * 10 * 1024 * 1024;
+    let estimated_space_needed = (total_emails / config.emails_per_pdf as usize + jobs.len()) * 10 * 1024 * 1024;
     if let Err(e) = DirectoryValidator::check_available_space(&validated_output_dir, estimated_space_needed as u64) {
+        task::spawn_blocking(move || close_sources(&jobs));
         return Err(format!("Speicherplatz-Problem: {}", e));
     }
-    
-    let pdf_generator = match PdfGenerator::new(validated_output_dir, config.base_file_name.clone()) {
-        Ok(generator) => generator,
-        Err(e) => return Err(format!("PDF-Generator konnte nicht initialisiert werden: {}", e)),
-    };
 
     // Initialize progress tracking
     session.progress.start(total_emails);
+    session.progress.sources = source_progress;
 
     // Store session in global state
     {
@@ -194,10 +287,9 @@ pub async fn start_processing(config: ProcessingConfig) -> Result<String, String
     task::spawn(async move {
         let result = process_emails_background(
             session_id_clone.clone(),
-            processor,
-            pdf_generator,
+            jobs,
+            validated_output_dir,
             config,
-            total_emails,
             cancel_rx,
         ).await;
 
@@ -341,72 +433,478 @@ pub async fn select_directory(app_handle: tauri::AppHandle) -> Result<Option<Str
     }
 }
 
+/// An opened input source and the directory its PDFs are written to
+struct SourceJob {
+    source: Box<dyn MailSource>,
+    /// Mail file or directory the source was opened from (None for IMAP)
+    path: Option<PathBuf>,
+    output_dir: PathBuf,
+}
+
+/// State shared by the workers of one processing session
+struct JobContext {
+    session_id: String,
+    config: ProcessingConfig,
+    cancelled: AtomicBool,
+    /// Number of PDFs started so far across all sources
+    pdf_counter: AtomicU32,
+    deduplicator: Mutex<Deduplicator>,
+    /// Sources done with deduplication, so a global scope keeps the copy of the earliest source
+    dedup_turns: Mutex<DedupTurns>,
+    dedup_turn_changed: Condvar,
+    /// Older mailbox copy when only the delta is archived
+    delta_base: Option<MailboxSnapshot>,
+}
+
+/// Next source allowed to deduplicate, plus later sources that already finished out of order
+#[derive(Default)]
+struct DedupTurns {
+    next: usize,
+    finished: BTreeSet<usize>,
+}
+
+impl JobContext {
+    fn check_cancelled(&self) -> AppResult<()> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(AppError::ProcessingCancelled);
+        }
+        Ok(())
+    }
+
+    /// Drop the duplicates among the emails of one source and record them in the session
+    /// With a global scope, parallel workers wait until all earlier sources were deduplicated
+    fn deduplicate(&self, index: usize, source_name: &str, emails: Vec<Email>) -> Vec<Email> {
+        if self.config.dedup_scope == DedupScope::Global {
+            let mut turns = self.dedup_turns.lock().unwrap();
+            while turns.next < index {
+                turns = self.dedup_turn_changed.wait(turns).unwrap();
+            }
+        }
+
+        let kept = self.deduplicate_now(index, source_name, emails);
+        self.finish_dedup_turn(index);
+        kept
+    }
+
+    /// Let the following sources deduplicate; also called for sources that failed before deduplicating
+    fn finish_dedup_turn(&self, index: usize) {
+        let mut turns = self.dedup_turns.lock().unwrap();
+        turns.finished.insert(index);
+        while turns.finished.contains(&turns.next) {
+            turns.next += 1;
+        }
+        self.dedup_turn_changed.notify_all();
+    }
+
+    fn deduplicate_now(&self, index: usize, source_name: &str, emails: Vec<Email>) -> Vec<Email> {
+        let mut deduplicator = self.deduplicator.lock().unwrap();
+        let known_records = deduplicator.records().len();
+        let kept = deduplicator.deduplicate(source_name, emails);
+
+        let new_records = &deduplicator.records()[known_records..];
+        if !new_records.is_empty() {
+            let mut sessions = PROCESSING_SESSIONS.lock().unwrap();
+            if let Some(session) = sessions.get_mut(&self.session_id) {
+                session.progress.record_duplicates(index, new_records.len());
+                session.duplicates.extend_from_slice(new_records);
+            }
+        }
+        kept
+    }
+
+    /// Drop emails that are unchanged since the delta base, then drop or mark near-duplicates as configured
+    fn filter_emails(&self, emails: Vec<Email>) -> Vec<Email> {
+        let emails = match &self.delta_base {
+            Some(snapshot) => snapshot.delta(emails),
+            None => emails,
+        };
+        NearDuplicateDetector::new(self.config.near_duplicate_threshold).apply(emails, self.config.near_duplicates)
+    }
+}
+
 /// Background processing function that handles the actual email processing
 async fn process_emails_background(
     session_id: String,
-    processor: PstProcessor,
-    pdf_generator: PdfGenerator,
+    jobs: Vec<SourceJob>,
+    output_dir: PathBuf,
     config: ProcessingConfig,
-    total_emails: usize,
-    mut cancel_rx: oneshot::Receiver<()>,
+    cancel_rx: oneshot::Receiver<()>,
 ) -> AppResult<()> {
-    let emails_per_pdf = config.emails_per_pdf as usize;
-    let mut processed_emails = 0;
-    let mut current_pdf = 1;
+    // Read the older mailbox copy up front when only the delta is archived
+    let delta_base = match config.delta_base.clone() {
+        Some(path) => {
+            update_session_progress(&session_id, 0, 1, "Lese Vergleichsquelle...".to_string());
+            let options = SourceOptions::from_config(&config);
+            let snapshot = task::spawn_blocking(move || {
+                let source = open_mail_source_with(Path::new(&path), &options)?;
+                MailboxSnapshot::read(source.as_ref())
+            })
+            .await
+            .map_err(|e| AppError::InternalError(e.to_string()))??;
+            Some(snapshot)
+        }
+        None => None,
+    };
+
+    let context = Arc::new(JobContext {
+        session_id,
+        deduplicator: Mutex::new(Deduplicator::new(config.dedup_scope)),
+        dedup_turns: Mutex::new(DedupTurns::default()),
+        dedup_turn_changed: Condvar::new(),
+        delta_base,
+        config,
+        cancelled: AtomicBool::new(false),
+        pdf_counter: AtomicU32::new(0),
+    });
 
-    // Extract all emails in chronological order
-    let all_emails = processor.get_all_emails_chronological()
-        .map_err(|e| AppError::PstError(e.to_string()))?;
+    // Turn the cancellation signal into a flag the blocking workers can poll
+    {
+        let context = context.clone();
+        task::spawn(async move {
+            if cancel_rx.await.is_ok() {
+                context.cancelled.store(true, Ordering::SeqCst);
+            }
+        });
+    }
 
-    // Process emails in chunks
-    for chunk in all_emails.chunks(emails_per_pdf) {
-        // Check for cancellation
-        if cancel_rx.try_recv().is_ok() {
-            return Err(AppError::ProcessingCancelled);
+    let result = if context.config.batch_output == BatchOutput::Merged && jobs.len() > 1 {
+        process_merged_sources(context.clone(), jobs, output_dir.clone()).await
+    } else {
+        process_separate_sources(context.clone(), jobs).await
+    };
+
+    // Report which copy of each duplicate was archived
+    let deduplicator = context.deduplicator.lock().unwrap();
+    if !deduplicator.records().is_empty() {
+        let report = serde_json::to_string_pretty(deduplicator.records())
+            .map_err(|e| AppError::InternalError(e.to_string()))?;
+        std::fs::write(output_dir.join(DUPLICATES_REPORT_FILE), report)?;
+    }
+
+    result
+}
+
+/// Write each source as its own PDF series into its own output directory
+async fn process_separate_sources(context: Arc<JobContext>, jobs: Vec<SourceJob>) -> AppResult<()> {
+    let run_job = |index: usize, job: SourceJob| {
+        let context = context.clone();
+        task::spawn_blocking(move || {
+            let result = process_source_job(&context, index, &job);
+            context.finish_dedup_turn(index);
+            close_sources(std::slice::from_ref(&job));
+            match &result {
+                Err(AppError::ProcessingCancelled) => {}
+                other => complete_source_progress(&context.session_id, index, other.as_ref().err().map(|e| e.to_string())),
+            }
+            result
+        })
+    };
+
+    let mut results = Vec::with_capacity(jobs.len());
+    match context.config.batch_execution {
+        BatchExecution::Sequential => {
+            for (index, job) in jobs.into_iter().enumerate() {
+                results.push(run_job(index, job).await);
+            }
+        }
+        BatchExecution::Parallel => {
+            let handles: Vec<_> = jobs.into_iter().enumerate().map(|(index, job)| run_job(index, job)).collect();
+            for handle in handles {
+                results.push(handle.await);
+            }
+        }
+    }
+
+    collect_source_results(results)
+}
+
+/// Read all sources, then write their emails as one chronological PDF series
+async fn process_merged_sources(context: Arc<JobContext>, jobs: Vec<SourceJob>, output_dir: PathBuf) -> AppResult<()> {
+    // Workers hand their source back so it can be finished after the PDFs are written
+    let read_job = |index: usize, job: SourceJob| {
+        let session_id = context.session_id.clone();
+        task::spawn_blocking(move || {
+            update_source_status(&session_id, index, "Lese E-Mails...".to_string());
+            let result = job.source.emails_chronological();
+            close_sources(std::slice::from_ref(&job));
+            if let Err(e) = &result {
+                complete_source_progress(&session_id, index, Some(e.to_string()));
+            }
+            (job, result)
+        })
+    };
+
+    let mut results = Vec::with_capacity(jobs.len());
+    match context.config.batch_execution {
+        BatchExecution::Sequential => {
+            for (index, job) in jobs.into_iter().enumerate() {
+                context.check_cancelled()?;
+                results.push(read_job(index, job).await);
+            }
+        }
+        BatchExecution::Parallel => {
+            let handles: Vec<_> = jobs.into_iter().enumerate().map(|(index, job)| read_job(index, job)).collect();
+            for handle in handles {
+                results.push(handle.await);
+            }
+        }
+    }
+
+    // Deduplicate in source order so the same copy wins regardless of execution mode
+    let mut jobs = Vec::with_capacity(results.len());
+    let mut all_emails = Vec::new();
+    for (index, result) in results.into_iter().enumerate() {
+        let (job, emails) = result.map_err(|e| AppError::InternalError(e.to_string()))?;
+        all_emails.extend(context.deduplicate(index, &job.source.source_name(), emails?));
+        jobs.push(job);
+    }
+    all_emails.sort_by_key(|email| email.date);
+    let all_emails = context.filter_emails(all_emails);
+
+    let derived_owner = mailbox_owner(&all_emails);
+    let batches = build_pdf_batches(all_emails, context.config.processing_mode, context.config.emails_per_pdf as usize);
+    let source_names = jobs.iter().map(|job| job.source.source_name()).collect::<Vec<_>>().join(", ");
+    task::spawn_blocking(move || {
+        let provenance = archive_provenance(&context, &jobs, derived_owner)?;
+        let pdf_generator = create_pdf_generator(&context.config, output_dir, source_names, provenance)?;
+        write_pdf_batches(&context, &batches, &pdf_generator, |processed, current_pdf, status| {
+            update_session_progress(&context.session_id, processed, current_pdf, status)
+        })?;
+
+        // Sources only persist their state once the merged archive is complete
+        for (index, job) in jobs.iter().enumerate() {
+            job.source.finish()?;
+            complete_source_progress(&context.session_id, index, None);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| AppError::InternalError(e.to_string()))?
+}
+
+/// Log out of the sources that hold a connection; failures only matter to the server, so they are logged
+fn close_sources(jobs: &[SourceJob]) {
+    for job in jobs {
+        if let Err(e) = job.source.close() {
+            eprintln!("Warning: Failed to close {}: {}", job.source.source_name(), e);
         }
+    }
+}
+
+/// Extract, group and write the PDFs of a single source
+fn process_source_job(context: &JobContext, index: usize, job: &SourceJob) -> AppResult<()> {
+    context.check_cancelled()?;
+
+    std::fs::create_dir_all(&job.output_dir)?;
+
+    // Extract all emails in chronological order and skip duplicates and unchanged emails
+    update_source_status(&context.session_id, index, "Lese E-Mails...".to_string());
+    let all_emails = job.source.emails_chronological()?;
+    let all_emails = context.deduplicate(index, &job.source.source_name(), all_emails);
+    let all_emails = context.filter_emails(all_emails);
+
+    update_source_status(&context.session_id, index, "Berechne Prüfsumme...".to_string());
+    let provenance = archive_provenance(context, std::slice::from_ref(job), mailbox_owner(&all_emails))?;
+    let pdf_generator = create_pdf_generator(&context.config, job.output_dir.clone(), job.source.source_name(), provenance)?;
+
+    // Group emails into the batches that become individual PDFs
+    let batches = build_pdf_batches(all_emails, context.config.processing_mode, context.config.emails_per_pdf as usize);
+    write_pdf_batches(context, &batches, &pdf_generator, |processed, current_pdf, status| {
+        update_source_progress(&context.session_id, index, processed, current_pdf, status)
+    })?;
+
+    job.source.finish()?;
+    Ok(())
+}
+
+/// Create a PDF generator that uses the configured fonts before the bundled ones
+fn create_pdf_generator(
+    config: &ProcessingConfig,
+    output_dir: PathBuf,
+    source_name: String,
+    provenance: ArchiveProvenance,
+) -> AppResult<PdfGenerator> {
+    let font_paths: Vec<PathBuf> = config.font_files.iter().map(PathBuf::from).collect();
+    let fonts = FontChain::load(&font_paths)?.followed_by(FontChain::bundled());
+
+    let generator = PdfGenerator::new(output_dir, config.base_file_name.clone())?
+        .with_fonts(fonts)
+        .with_image_attachments(config.render_image_attachments)
+        .with_pdf_standard(config.pdf_standard)
+        .with_table_of_contents(config.table_of_contents)
+        .with_page_frame(config.page_frame.clone())
+        .with_source_name(source_name)
+        .with_provenance(provenance);
+
+    if !config.embed_attachments {
+        return Ok(generator);
+    }
+    const MEGABYTE: usize = 1024 * 1024;
+    Ok(generator.with_embedded_attachments(EmbeddingLimits {
+        max_file_size: config.max_embedded_file_mb as usize * MEGABYTE,
+        max_pdf_size: config.max_embedded_pdf_mb as usize * MEGABYTE,
+    }))
+}
+
+/// Owner, source file checksums and session recorded in the properties of every PDF
+/// The configured owner wins over the one derived from the emails; only mail files are hashed
+fn archive_provenance(context: &JobContext, jobs: &[SourceJob], derived_owner: Option<String>) -> AppResult<ArchiveProvenance> {
+    let mut hashes = Vec::new();
+    for path in jobs.iter().filter_map(|job| job.path.as_deref()).filter(|path| path.is_file()) {
+        hashes.push(file_hash(path)?);
+    }
+
+    let configured_owner = context.config.mailbox_owner.as_deref().map(str::trim).filter(|owner| !owner.is_empty());
+    Ok(ArchiveProvenance {
+        owner: configured_owner.map(str::to_string).or(derived_owner),
+        source_hash: (!hashes.is_empty()).then(|| hashes.join(", ")),
+        session_id: Some(context.session_id.clone()),
+    })
+}
+
+/// Generate one PDF per batch and report progress after every PDF
+fn write_pdf_batches(
+    context: &JobContext,
+    batches: &[Vec<Email>],
+    pdf_generator: &PdfGenerator,
+    report: impl Fn(usize, u32, String),
+) -> AppResult<()> {
+    let total_pdfs = batches.len();
+    let total_emails: usize = batches.iter().map(Vec::len).sum();
+    let mut processed_emails = 0;
+
+    for (index, chunk) in batches.iter().enumerate() {
+        // Check for cancellation
+        context.check_cancelled()?;
+
+        let sequence = index as u32 + 1;
+        let current_pdf = context.pdf_counter.fetch_add(1, Ordering::SeqCst) + 1;
 
         // Update progress before processing this chunk
-        update_session_progress(
-            &session_id,
-            processed_emails,
-            current_pdf,
-            format!("Erstelle PDF {} von {}", current_pdf, calculate_total_pdfs(total_emails, emails_per_pdf)),
-        );
+        report(processed_emails, current_pdf, format!("Erstelle PDF {} von {}", sequence, total_pdfs));
 
         // Generate PDF for this chunk
-        let pdf_path = pdf_generator.generate_pdf(chunk.to_vec(), current_pdf)
+        let pdf_path = pdf_generator.generate_pdf(chunk.to_vec(), sequence)
             .map_err(|e| AppError::PdfError(e.to_string()))?;
 
         // Add generated file to session
         {
             let mut sessions = PROCESSING_SESSIONS.lock().unwrap();
-            if let Some(session) = sessions.get_mut(&session_id) {
+            if let Some(session) = sessions.get_mut(&context.session_id) {
                 session.add_generated_file(pdf_path.to_string_lossy().to_string());
             }
         }
 
         processed_emails += chunk.len();
-        current_pdf += 1;
 
         // Update progress after completing this chunk
-        update_session_progress(
-            &session_id,
+        report(
             processed_emails,
-            current_pdf - 1,
+            current_pdf,
             if processed_emails >= total_emails {
                 "Verarbeitung abgeschlossen".to_string()
             } else {
-                format!("PDF {} erstellt, verarbeite weiter...", current_pdf - 1)
+                format!("PDF {} erstellt, verarbeite weiter...", sequence)
             },
         );
-
-        // Small delay to allow for cancellation checks and UI updates
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
 
     Ok(())
 }
 
+/// Turn the per-source results into the session result
+/// Cancellation wins; otherwise failed sources are listed by name after all others finished
+fn collect_source_results(results: Vec<Result<AppResult<()>, task::JoinError>>) -> AppResult<()> {
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(AppError::ProcessingCancelled)) => return Err(AppError::ProcessingCancelled),
+            Ok(Err(e)) => errors.push(e.to_string()),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(AppError::MailSourceError(errors.remove(0))),
+        count => Err(AppError::MailSourceError(format!(
+            "{} Quellen fehlgeschlagen: {}",
+            count,
+            errors.join("; ")
+        ))),
+    }
+}
+
+/// Open all configured inputs together with the directory their PDFs go to
+/// Batch jobs with per-source output write each source into its own subfolder
+fn open_processing_sources(config: &ProcessingConfig, output_dir: &Path) -> crate::errors::MailSourceResult<Vec<SourceJob>> {
+    if config.imap.is_some() || config.sources.is_empty() {
+        return Ok(vec![SourceJob {
+            source: open_processing_source(config)?,
+            path: config.imap.is_none().then(|| PathBuf::from(&config.pst_file_path)),
+            output_dir: output_dir.to_path_buf(),
+        }]);
+    }
+
+    let paths = expand_sources(&config.sources)?;
+    let folders = source_output_folders(&paths);
+    paths
+        .iter()
+        .zip(folders)
+        .map(|(path, folder)| {
+            Ok(SourceJob {
+                source: open_mail_source_with(path, &SourceOptions::from_config(config))?,
+                path: Some(path.clone()),
+                output_dir: match config.batch_output {
+                    BatchOutput::PerSource if paths.len() > 1 => output_dir.join(folder),
+                    _ => output_dir.to_path_buf(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Open the configured input; PST files additionally get the configured worker thread count
+/// IMAP runs remember the last archived UID per folder in the output directory
+fn open_processing_source(config: &ProcessingConfig) -> crate::errors::MailSourceResult<Box<dyn MailSource>> {
+    if let Some(imap) = &config.imap {
+        let state_path = PathBuf::from(&config.output_directory).join(IMAP_SYNC_STATE_FILE);
+        return Ok(Box::new(ImapSource::connect(imap.clone(), Some(state_path))?));
+    }
+
+    open_mail_source_with(Path::new(&config.pst_file_path), &SourceOptions::from_config(config))
+}
+
+/// Split the extracted emails into the email lists for each PDF
+/// Conversations longer than `emails_per_pdf` continue in the following PDFs
+/// Recovered items follow in PDFs of their own so they never mix with the regular archive
+fn build_pdf_batches(emails: Vec<Email>, mode: ProcessingMode, emails_per_pdf: usize) -> Vec<Vec<Email>> {
+    let (recovered, emails): (Vec<Email>, Vec<Email>) = emails.into_iter().partition(|email| email.recovered);
+    let mut batches = build_mode_batches(emails, mode, emails_per_pdf);
+    batches.extend(build_mode_batches(recovered, mode, emails_per_pdf));
+    batches
+}
+
+fn build_mode_batches(emails: Vec<Email>, mode: ProcessingMode, emails_per_pdf: usize) -> Vec<Vec<Email>> {
+    match mode {
+        ProcessingMode::Chronological => emails
+            .chunks(emails_per_pdf)
+            .map(|chunk| chunk.to_vec())
+            .collect(),
+        ProcessingMode::ByConversation => ThreadBuilder::build_threads(emails)
+            .into_iter()
+            .map(ConversationThread::into_emails)
+            .flat_map(|thread| {
+                thread
+                    .chunks(emails_per_pdf)
+                    .map(|chunk| chunk.to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    }
+}
+
 /// Update the progress of a processing session
 fn update_session_progress(session_id: &str, processed_emails: usize, current_pdf: u32, status: String) {
     let mut sessions = PROCESSING_SESSIONS.lock().unwrap();
@@ -415,9 +913,38 @@ fn update_session_progress(session_id: &str, processed_emails: usize, current_pd
     }
 }
 
-/// Calculate the total number of PDFs that will be generated
-fn calculate_total_pdfs(total_emails: usize, emails_per_pdf: usize) -> u32 {
-    ((total_emails + emails_per_pdf - 1) / emails_per_pdf) as u32
+/// Update the progress of one source of a processing session
+/// With several sources the overall status names the source it refers to
+fn update_source_progress(session_id: &str, index: usize, processed_emails: usize, current_pdf: u32, status: String) {
+    let mut sessions = PROCESSING_SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get_mut(session_id) {
+        let progress = &mut session.progress;
+        progress.current_pdf = current_pdf;
+        progress.status = match progress.sources.get(index) {
+            Some(source) if progress.sources.len() > 1 => format!("{}: {}", source.source, status),
+            _ => status.clone(),
+        };
+        progress.update_source_progress(index, processed_emails, status);
+    }
+}
+
+/// Update only the status message of one source
+fn update_source_status(session_id: &str, index: usize, status: String) {
+    let mut sessions = PROCESSING_SESSIONS.lock().unwrap();
+    if let Some(source) = sessions
+        .get_mut(session_id)
+        .and_then(|session| session.progress.sources.get_mut(index))
+    {
+        source.status = status;
+    }
+}
+
+/// Mark one source of a processing session as finished
+fn complete_source_progress(session_id: &str, index: usize, error: Option<String>) {
+    let mut sessions = PROCESSING_SESSIONS.lock().unwrap();
+    if let Some(session) = sessions.get_mut(session_id) {
+        session.progress.complete_source(index, error);
+    }
 }
 
 /// Get the current processing session information
@@ -437,6 +964,82 @@ pub async fn get_processing_session() -> Result<Option<ProcessingSession>, Strin
     }
 }
 
+/// Reconstruct the conversation threads of a mail source
+/// The source is read with the reader settings of `config`, so the threads show the emails an archive run would
+#[command]
+pub async fn get_conversation_threads(file_path: String, config: ProcessingConfig) -> Result<Vec<ConversationThread>, String> {
+    task::spawn_blocking(move || {
+        let source = open_mail_source_with(&PathBuf::from(&file_path), &SourceOptions::from_config(&config))
+            .map_err(|e| format!("Eingabedatei konnte nicht geöffnet werden: {}", e))?;
+
+        let emails = source.emails_chronological()
+            .map_err(|e| format!("Fehler beim Lesen der E-Mails: {}", e))?;
+
+        Ok(ThreadBuilder::build_threads(emails))
+    })
+    .await
+    .map_err(|e| format!("Threadabfrage abgebrochen: {}", e))?
+}
+
+/// Compare an old and a new copy of a mailbox and list added, removed and modified items per folder
+#[command]
+pub async fn compare_mail_sources(old_path: String, new_path: String) -> Result<MailboxDiff, String> {
+    task::spawn_blocking(move || {
+        let old_source = open_mail_source(&PathBuf::from(&old_path))
+            .map_err(|e| format!("Alte Quelle konnte nicht geöffnet werden: {}", e))?;
+        let new_source = open_mail_source(&PathBuf::from(&new_path))
+            .map_err(|e| format!("Neue Quelle konnte nicht geöffnet werden: {}", e))?;
+
+        compare_mailboxes(old_source.as_ref(), new_source.as_ref())
+            .map_err(|e| format!("Fehler beim Vergleichen der Quellen: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Vergleich abgebrochen: {}", e))?
+}
+
+/// List the folders of one or more mail sources, merged by path into one virtual mailbox
+#[command]
+pub async fn get_mail_folders(file_paths: Vec<String>) -> Result<Vec<MailFolder>, String> {
+    task::spawn_blocking(move || {
+        let mailbox = open_virtual_mailbox(&file_paths)?;
+        mailbox.folders().map_err(|e| format!("Ordner konnten nicht gelesen werden: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Ordnerabfrage abgebrochen: {}", e))?
+}
+
+/// Get the emails of one folder of the merged mailbox; duplicates across sources are listed once
+#[command]
+pub async fn browse_mail_folder(file_paths: Vec<String>, folder_path: String) -> Result<Vec<Email>, String> {
+    task::spawn_blocking(move || {
+        let mailbox = open_virtual_mailbox(&file_paths)?;
+        mailbox.folder_emails(&folder_path).map_err(|e| format!("Fehler beim Lesen der E-Mails: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Ordnerabfrage abgebrochen: {}", e))?
+}
+
+fn open_virtual_mailbox(file_paths: &[String]) -> Result<VirtualMailbox, String> {
+    let paths: Vec<PathBuf> = file_paths.iter().map(PathBuf::from).collect();
+    VirtualMailbox::open(&paths).map_err(|e| format!("Eingabedatei konnte nicht geöffnet werden: {}", e))
+}
+
+/// List the folders of an IMAP account with their message counts
+#[command]
+pub async fn list_imap_folders(config: ImapConfig) -> Result<Vec<MailFolder>, String> {
+    if let Err(e) = config.validate() {
+        return Err(format!("Konfigurationsfehler: {}", e));
+    }
+
+    task::spawn_blocking(move || {
+        let source = ImapSource::connect(config, None)
+            .map_err(|e| format!("Verbindung zum IMAP-Server fehlgeschlagen: {}", e))?;
+        source.folders().map_err(|e| format!("Ordner konnten nicht gelesen werden: {}", e))
+    })
+    .await
+    .map_err(|e| format!("IMAP-Abfrage abgebrochen: {}", e))?
+}
+
 /// Clean up completed or cancelled sessions
 #[command]
 pub async fn cleanup_session(session_id: String) -> Result<(), String> {
@@ -496,4 +1099,86 @@ pub struct DirectoryInfo {
     pub available_space_bytes: u64,
     pub available_space_mb: u64,
     pub last_modified: Option<chrono::DateTime<chrono::Utc>>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn email(subject: &str, day: u32, message_id: &str, in_reply_to: Option<&str>) -> Email {
+        let date = Utc.with_ymd_and_hms(2024, 5, day, 8, 0, 0).unwrap();
+        let mut email = Email::new(subject.to_string(), "a@example.com".to_string(), "b@example.com".to_string(), date, "Text".to_string());
+        email.message_id = Some(message_id.to_string());
+        email.in_reply_to = in_reply_to.map(str::to_string);
+        email
+    }
+
+    #[test]
+    fn test_long_conversations_are_split_across_pdfs() {
+        let emails = vec![
+            email("Projekt", 1, "<1@x>", None),
+            email("Re: Projekt", 2, "<2@x>", Some("<1@x>")),
+            email("Re: Projekt", 3, "<3@x>", Some("<2@x>")),
+            email("Urlaub", 4, "<4@x>", None),
+        ];
+
+        let batches = build_pdf_batches(emails, ProcessingMode::ByConversation, 2);
+        let sizes: Vec<usize> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 1, 1]);
+        assert_eq!(batches[1][0].message_id.as_deref(), Some("<3@x>"));
+        assert_eq!(batches[2][0].subject, "Urlaub");
+    }
+
+    fn job_context(scope: DedupScope) -> Arc<JobContext> {
+        let mut config = ProcessingConfig::new(String::new(), 10, "archiv".to_string(), String::new());
+        config.dedup_scope = scope;
+        Arc::new(JobContext {
+            session_id: String::new(),
+            deduplicator: Mutex::new(Deduplicator::new(scope)),
+            dedup_turns: Mutex::new(DedupTurns::default()),
+            dedup_turn_changed: Condvar::new(),
+            delta_base: None,
+            config,
+            cancelled: AtomicBool::new(false),
+            pdf_counter: AtomicU32::new(0),
+        })
+    }
+
+    #[test]
+    fn test_global_dedup_keeps_copy_of_first_source_in_parallel() {
+        let context = job_context(DedupScope::Global);
+        let later = {
+            let context = context.clone();
+            std::thread::spawn(move || context.deduplicate(1, "live.ost", vec![email("Termin", 1, "<1@x>", None)]))
+        };
+
+        // The later source starts first but must wait for the first one
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let first = context.deduplicate(0, "archiv.pst", vec![email("Termin", 1, "<1@x>", None)]);
+        assert_eq!(first.len(), 1);
+        assert!(later.join().unwrap().is_empty());
+        assert_eq!(context.deduplicator.lock().unwrap().records()[0].kept.source, "archiv.pst");
+
+        // A source that failed before deduplicating does not block the following ones
+        let context = job_context(DedupScope::Global);
+        context.finish_dedup_turn(0);
+        assert_eq!(context.deduplicate(1, "live.ost", vec![email("Termin", 1, "<1@x>", None)]).len(), 1);
+    }
+
+    #[test]
+    fn test_recovered_emails_get_their_own_pdfs() {
+        let mut deleted = email("Re: Projekt", 2, "<2@x>", Some("<1@x>"));
+        deleted.recovered = true;
+        let emails = vec![email("Projekt", 1, "<1@x>", None), deleted, email("Urlaub", 3, "<3@x>", None)];
+
+        for mode in [ProcessingMode::Chronological, ProcessingMode::ByConversation] {
+            let batches = build_pdf_batches(emails.clone(), mode, 10);
+            let (last, regular) = batches.split_last().unwrap();
+            assert!(regular.iter().flatten().all(|email| !email.recovered));
+            assert_eq!(regular.iter().map(Vec::len).sum::<usize>(), 2);
+            assert_eq!(last.len(), 1);
+            assert!(last[0].recovered);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::types::{DedupScope, Email};
+
+/// File name of the duplicates report written to the output directory
+pub const DUPLICATES_REPORT_FILE: &str = "duplicates-report.json";
+
+/// How a duplicate was recognized
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DuplicateMatch {
+    /// Both copies carry the same Message-ID
+    MessageId,
+    /// Same date, sender, subject and body after normalization
+    ContentHash,
+    /// Same date, sender and subject; matches items without Message-ID across mailbox copies
+    Headers,
+}
+
+/// Where a copy of an email was found
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EmailLocation {
+    /// Display name of the source the copy was read from
+    pub source: String,
+
+    /// Folder of the copy within its source
+    pub folder: Option<String>,
+}
+
+/// A skipped duplicate together with the copy that was archived instead
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DuplicateRecord {
+    /// Subject of the email
+    pub subject: String,
+
+    /// Sender of the email
+    pub sender: String,
+
+    /// Sent date of the email
+    pub date: DateTime<Utc>,
+
+    /// How the duplicate was recognized
+    pub matched_by: DuplicateMatch,
+
+    /// The copy that was archived
+    pub kept: EmailLocation,
+
+    /// The copy that was skipped
+    pub removed: EmailLocation,
+}
+
+/// Removes emails that were already seen within the configured scope
+///
+/// The first copy encountered is kept. With a global scope, sources are
+/// compared in the order they are passed to `deduplicate`.
+pub struct Deduplicator {
+    scope: DedupScope,
+    /// Kept copies by scope and identity key
+    seen: HashMap<(String, String), EmailLocation>,
+    records: Vec<DuplicateRecord>,
+}
+
+impl Deduplicator {
+    /// Create a deduplicator for the given scope
+    pub fn new(scope: DedupScope) -> Self {
+        Self {
+            scope,
+            seen: HashMap::new(),
+            records: Vec::new(),
+        }
+    }
+
+    /// Drop the emails of `source` that duplicate an already kept copy
+    pub fn deduplicate(&mut self, source: &str, emails: Vec<Email>) -> Vec<Email> {
+        if self.scope == DedupScope::Disabled {
+            return emails;
+        }
+
+        let mut kept = Vec::with_capacity(emails.len());
+        for email in emails {
+            if self.keep(source, &email) {
+                kept.push(email);
+            }
+        }
+        kept
+    }
+
+    /// Check a single email of `source`; duplicates are recorded and return false
+    pub fn keep(&mut self, source: &str, email: &Email) -> bool {
+        let scope_key = match self.scope {
+            DedupScope::Disabled => return true,
+            DedupScope::PerFolder => format!("{}\u{0}{}", source, email.folder.as_deref().unwrap_or_default()),
+            DedupScope::PerSource => source.to_string(),
+            DedupScope::Global => String::new(),
+        };
+        let (identity, matched_by) = Self::identity(email);
+        let location = EmailLocation {
+            source: source.to_string(),
+            folder: email.folder.clone(),
+        };
+
+        match self.seen.get(&(scope_key.clone(), identity.clone())) {
+            Some(original) => {
+                self.records.push(DuplicateRecord {
+                    subject: email.subject.clone(),
+                    sender: email.sender.clone(),
+                    date: email.date,
+                    matched_by,
+                    kept: original.clone(),
+                    removed: location,
+                });
+                false
+            }
+            None => {
+                self.seen.insert((scope_key, identity), location);
+                true
+            }
+        }
+    }
+
+    /// Duplicates removed so far, in the order they were found
+    pub fn records(&self) -> &[DuplicateRecord] {
+        &self.records
+    }
+
+    /// Identity key of an email: its Message-ID, or a hash of the normalized content
+    pub fn identity(email: &Email) -> (String, DuplicateMatch) {
+        match Self::message_id_identity(email) {
+            Some(identity) => (identity, DuplicateMatch::MessageId),
+            None => (format!("hash:{}", Self::content_hash(email)), DuplicateMatch::ContentHash),
+        }
+    }
+
+    /// Identity key from the normalized Message-ID, if the email has one
+    pub fn message_id_identity(email: &Email) -> Option<String> {
+        email
+            .message_id
+            .as_deref()
+            .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>').to_lowercase())
+            .filter(|id| !id.is_empty())
+            .map(|id| format!("id:{}", id))
+    }
+
+    /// SHA-256 (hex) of date, sender address, subject and body with whitespace and case normalized
+    fn content_hash(email: &Email) -> String {
+        fields_hash(email, &[sender_address(&email.sender), normalize_text(&email.subject), normalize_text(&email.body)])
+    }
+}
+
+/// SHA-256 (hex) of the email date and the given fields
+/// Each field is length-prefixed so no two field combinations hash the same input
+pub(crate) fn fields_hash(email: &Email, fields: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(email.date.timestamp().to_be_bytes());
+    for field in fields {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Address part of "Name <address>", lowercased
+pub(crate) fn sender_address(sender: &str) -> String {
+    let address = match (sender.rfind('<'), sender.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &sender[start + 1..end],
+        _ => sender,
+    };
+    address.trim().to_lowercase()
+}
+
+/// Collapse all whitespace runs to single spaces and lowercase the text
+pub(crate) fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn email(subject: &str, message_id: Option<&str>, folder: &str) -> Email {
+        let mut email = Email::new(
+            subject.to_string(),
+            "Anna <anna@example.com>".to_string(),
+            "bernd@example.com".to_string(),
+            Utc.with_ymd_and_hms(2024, 1, 15, 10, 0, 0).unwrap(),
+            "Hallo  Bernd,\r\n\r\nbis morgen.".to_string(),
+        );
+        email.message_id = message_id.map(str::to_string);
+        email.folder = Some(folder.to_string());
+        email
+    }
+
+    #[test]
+    fn test_global_scope_matches_across_sources() {
+        let mut dedup = Deduplicator::new(DedupScope::Global);
+
+        let archive = dedup.deduplicate("archiv.pst", vec![email("Termin", Some("<A1@example.com>"), "Inbox")]);
+        let live = dedup.deduplicate(
+            "live.ost",
+            vec![email("Termin", Some("a1@example.com"), "Archiv"), email("Anderes", Some("<b2@example.com>"), "Inbox")],
+        );
+
+        assert_eq!(archive.len(), 1);
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].subject, "Anderes");
+
+        let record = &dedup.records()[0];
+        assert_eq!(record.matched_by, DuplicateMatch::MessageId);
+        assert_eq!(record.kept.source, "archiv.pst");
+        assert_eq!(record.removed.source, "live.ost");
+        assert_eq!(record.removed.folder.as_deref(), Some("Archiv"));
+    }
+
+    #[test]
+    fn test_content_hash_ignores_whitespace_and_sender_name() {
+        let mut dedup = Deduplicator::new(DedupScope::PerSource);
+        let mut copy = email("Termin", None, "Inbox");
+        copy.sender = "ANNA@example.com".to_string();
+        copy.body = "hallo bernd, bis morgen.".to_string();
+
+        let kept = dedup.deduplicate("archiv.pst", vec![email("Termin", None, "Inbox"), copy]);
+        let other_source = dedup.deduplicate("live.ost", vec![email("Termin", None, "Inbox")]);
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(other_source.len(), 1);
+        assert_eq!(dedup.records()[0].matched_by, DuplicateMatch::ContentHash);
+
+        // SHA-256 keys are stable across runs and builds, unlike DefaultHasher
+        let (identity, _) = Deduplicator::identity(&email("Termin", None, "Inbox"));
+        assert_eq!(identity.len(), "hash:".len() + 64);
+        assert_ne!(identity, Deduplicator::identity(&email("Termine", None, "Inbox")).0);
+    }
+
+    #[test]
+    fn test_per_folder_scope_keeps_copies_in_other_folders() {
+        let mut dedup = Deduplicator::new(DedupScope::PerFolder);
+
+        let kept = dedup.deduplicate(
+            "archiv.pst",
+            vec![
+                email("Termin", Some("<a1@example.com>"), "Inbox"),
+                email("Termin", Some("<a1@example.com>"), "Inbox"),
+                email("Termin", Some("<a1@example.com>"), "Gesendet"),
+            ],
+        );
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(dedup.records().len(), 1);
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::{file_modified_date, EmailIterator, MailSource};
+use crate::mime_parser::MimeParser;
+use crate::types::{Email, MailFolder};
+
+/// Reader for single `.eml` files and directories of them
+///
+/// Directories are searched recursively; each subdirectory becomes a folder
+/// whose path is relative to the selected directory. Files directly in the
+/// selected directory belong to the root folder.
+pub struct EmlReader {
+    root: PathBuf,
+    /// EML files with the folder path they belong to (None = root)
+    files: Vec<(PathBuf, Option<String>)>,
+}
+
+impl EmlReader {
+    /// Open a single EML file or a directory containing EML files
+    pub fn new(path: PathBuf) -> MailSourceResult<Self> {
+        if !path.exists() {
+            return Err(MailSourceError::NotFound(path.to_string_lossy().to_string()));
+        }
+
+        let mut files = Vec::new();
+        if path.is_dir() {
+            Self::collect_files(&path, None, &mut files)?;
+        } else {
+            files.push((path.clone(), None));
+        }
+
+        Ok(Self { root: path, files })
+    }
+
+    /// Get supported EML file extensions
+    pub fn supported_extensions() -> Vec<&'static str> {
+        vec!["eml"]
+    }
+
+    /// Check if a file path has a supported EML extension
+    pub fn has_supported_extension(path: &Path) -> bool {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| Self::supported_extensions().contains(&ext.as_str()))
+    }
+
+    /// Recursively collect EML files in a stable (name-sorted) order
+    fn collect_files(
+        directory: &Path,
+        folder: Option<String>,
+        files: &mut Vec<(PathBuf, Option<String>)>,
+    ) -> MailSourceResult<()> {
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(directory)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::PermissionDenied => {
+                    MailSourceError::PermissionDenied(directory.to_string_lossy().to_string())
+                }
+                _ => MailSourceError::IoError(e.to_string()),
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        entries.sort();
+
+        for entry in entries {
+            if entry.is_dir() {
+                let name = entry
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let subfolder = match &folder {
+                    Some(parent) => format!("{}/{}", parent, name),
+                    None => name,
+                };
+                Self::collect_files(&entry, Some(subfolder), files)?;
+            } else if Self::has_supported_extension(&entry) {
+                files.push((entry, folder.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_email(path: &Path, folder: &Option<String>) -> MailSourceResult<Email> {
+        let raw = std::fs::read(path)?;
+        let mut email = MimeParser::parse_email_with_fallback_date(&raw, file_modified_date(path))?;
+        email.folder = folder.clone();
+        Ok(email)
+    }
+}
+
+impl MailSource for EmlReader {
+    fn source_name(&self) -> String {
+        self.root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.root.to_string_lossy().to_string())
+    }
+
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(self.files.len())
+    }
+
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        // Count emails per folder and make sure every parent folder is listed
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        let mut root_count = 0;
+        for (_, folder) in &self.files {
+            let Some(folder) = folder else {
+                root_count += 1;
+                continue;
+            };
+
+            let mut parent = String::new();
+            for segment in folder.split('/') {
+                if !parent.is_empty() {
+                    parent.push('/');
+                }
+                parent.push_str(segment);
+                counts.entry(parent.clone()).or_insert(0);
+            }
+            *counts.entry(folder.clone()).or_insert(0) += 1;
+        }
+
+        let mut folders = vec![MailFolder::new(String::new(), self.source_name(), root_count)];
+        folders.extend(counts.into_iter().map(|(path, count)| {
+            let name = path.rsplit('/').next().unwrap_or_default().to_string();
+            MailFolder::new(path, name, count)
+        }));
+        Ok(folders)
+    }
+
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        Ok(Box::new(self.files.iter().map(|(path, folder)| {
+            Self::read_email(path, folder).map_err(|e| match e {
+                MailSourceError::ParsingError(message) => {
+                    MailSourceError::ParsingError(format!("{}: {}", path.display(), message))
+                }
+                other => other,
+            })
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const ENCODED_MESSAGE: &str = concat!(
+        "From: =?ISO-8859-1?Q?J=F6rg?= <joerg@example.com>\r\n",
+        "Subject: =?UTF-8?B?QW5nZWJvdCBmw7xyIE3DpHJ6?=\r\n",
+        "Date: Fri, 1 Mar 2024 14:00:00 +0000\r\n",
+        "MIME-Version: 1.0\r\n",
+        "Content-Type: text/plain; charset=utf-8\r\n",
+        "Content-Transfer-Encoding: base64\r\n",
+        "\r\n",
+        "R3LDvMOfZSBhdXMgS8O2bG4=\r\n",
+    );
+
+    fn write_eml(dir: &Path, name: &str, subject: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join(name),
+            format!("Subject: {}\nDate: Mon, 1 Jan 2024 10:00:00 +0000\n\nText\n", subject),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_single_file_with_encoded_headers_and_body() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("angebot.eml");
+        std::fs::write(&path, ENCODED_MESSAGE).unwrap();
+
+        let reader = EmlReader::new(path).unwrap();
+        let emails: Vec<Email> = reader.emails().unwrap().map(|email| email.unwrap()).collect();
+
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Angebot für März");
+        assert_eq!(emails[0].sender, "Jörg <joerg@example.com>");
+        assert_eq!(emails[0].body, "Grüße aus Köln");
+        assert_eq!(emails[0].folder, None);
+    }
+
+    #[test]
+    fn test_directory_maps_subdirectories_to_folders() {
+        let dir = tempdir().unwrap();
+        write_eml(dir.path(), "a.eml", "Wurzel");
+        write_eml(&dir.path().join("Kunden").join("Meier"), "b.EML", "Meier");
+        write_eml(&dir.path().join("Kunden"), "c.eml", "Kunden");
+        std::fs::write(dir.path().join("notiz.txt"), "keine Mail").unwrap();
+
+        let reader = EmlReader::new(dir.path().to_path_buf()).unwrap();
+        let emails: Vec<Email> = reader.emails().unwrap().map(|email| email.unwrap()).collect();
+
+        assert_eq!(reader.email_count().unwrap(), 3);
+        let folder_of = |subject: &str| emails.iter().find(|e| e.subject == subject).unwrap().folder.clone();
+        assert_eq!(folder_of("Wurzel"), None);
+        assert_eq!(folder_of("Kunden").as_deref(), Some("Kunden"));
+        assert_eq!(folder_of("Meier").as_deref(), Some("Kunden/Meier"));
+
+        let folders = reader.folders().unwrap();
+        let paths: Vec<&str> = folders.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["", "Kunden", "Kunden/Meier"]);
+        assert_eq!(folders[2].name, "Meier");
+        assert_eq!(folders[2].email_count, 1);
+    }
+
+    #[test]
+    fn test_parent_folders_listed_without_direct_emails() {
+        let dir = tempdir().unwrap();
+        write_eml(&dir.path().join("2023").join("Q4"), "a.eml", "Tief");
+
+        let reader = EmlReader::new(dir.path().to_path_buf()).unwrap();
+        let folders = reader.folders().unwrap();
+
+        assert_eq!(folders.len(), 3);
+        assert_eq!(folders[1].path, "2023");
+        assert_eq!(folders[1].email_count, 0);
+    }
+}
@@ -8,6 +8,9 @@ pub enum AppError {
     #[error("PST file error: {0}")]
     PstError(String),
 
+    #[error("Mail source error: {0}")]
+    MailSourceError(String),
+
     #[error("PDF generation error: {0}")]
     PdfError(String),
 
@@ -59,6 +62,38 @@ pub enum PstError {
     IoError(String),
 }
 
+/// Error types for mail sources other than the PST processor internals
+#[derive(Debug, Error, Serialize, Deserialize)]
+#[serde(tag = "type", content = "message")]
+pub enum MailSourceError {
+    #[error("Mail source not found: {0}")]
+    NotFound(String),
+
+    #[error("Unsupported mail source: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Invalid mail source format: {0}")]
+    InvalidFormat(String),
+
+    #[error("Permission denied accessing mail source: {0}")]
+    PermissionDenied(String),
+
+    #[error("Message parsing error: {0}")]
+    ParsingError(String),
+
+    #[error("IO error while reading mail source: {0}")]
+    IoError(String),
+
+    #[error("Connection to mail server failed: {0}")]
+    ConnectionError(String),
+
+    #[error("Mail server login failed: {0}")]
+    AuthenticationFailed(String),
+
+    #[error("Mail server protocol error: {0}")]
+    ProtocolError(String),
+}
+
 /// PDF generation specific error types
 #[derive(Debug, Error, Serialize, Deserialize)]
 #[serde(tag = "type", content = "message")]
@@ -83,6 +118,9 @@ pub enum PdfError {
 
     #[error("Template error: {0}")]
     TemplateError(String),
+
+    #[error("PDF/A conformance check failed: {0}")]
+    ConformanceViolation(String),
 }
 
 /// File system operation error types
@@ -118,6 +156,9 @@ pub enum ValidationError {
     #[error("Invalid email count: must be between {min} and {max}, got {actual}")]
     InvalidEmailCount { min: u32, max: u32, actual: u32 },
 
+    #[error("Invalid thread count: must be at most {max}, got {actual}")]
+    InvalidThreadCount { max: u32, actual: u32 },
+
     #[error("Invalid filename: {0}")]
     InvalidFilename(String),
 
@@ -129,6 +170,9 @@ pub enum ValidationError {
 
     #[error("Invalid character in field {field}: {character}")]
     InvalidCharacter { field: String, character: String },
+
+    #[error("Invalid value for {field}: {reason}")]
+    InvalidValue { field: String, reason: String },
 }
 
 // Conversion implementations for error types
@@ -138,6 +182,24 @@ impl From<PstError> for AppError {
     }
 }
 
+impl From<MailSourceError> for AppError {
+    fn from(err: MailSourceError) -> Self {
+        AppError::MailSourceError(err.to_string())
+    }
+}
+
+impl From<PstError> for MailSourceError {
+    fn from(err: PstError) -> Self {
+        match err {
+            PstError::FileNotFound(path) => MailSourceError::NotFound(path),
+            PstError::PermissionDenied(path) => MailSourceError::PermissionDenied(path),
+            PstError::InvalidFormat(message) => MailSourceError::InvalidFormat(message),
+            PstError::IoError(message) => MailSourceError::IoError(message),
+            other => MailSourceError::ParsingError(other.to_string()),
+        }
+    }
+}
+
 impl From<PdfError> for AppError {
     fn from(err: PdfError) -> Self {
         AppError::PdfError(err.to_string())
@@ -168,6 +230,12 @@ impl From<std::io::Error> for PstError {
     }
 }
 
+impl From<std::io::Error> for MailSourceError {
+    fn from(err: std::io::Error) -> Self {
+        MailSourceError::IoError(err.to_string())
+    }
+}
+
 impl From<std::io::Error> for PdfError {
     fn from(err: std::io::Error) -> Self {
         PdfError::FileWriteError(err.to_string())
@@ -183,6 +251,7 @@ impl From<std::io::Error> for FileSystemError {
 /// Result type aliases for convenience
 pub type AppResult<T> = Result<T, AppError>;
 pub type PstResult<T> = Result<T, PstError>;
+pub type MailSourceResult<T> = Result<T, MailSourceError>;
 pub type PdfResult<T> = Result<T, PdfError>;
 pub type FileSystemResult<T> = Result<T, FileSystemError>;
 pub type ValidationResult<T> = Result<T, ValidationError>;
\ No newline at end of file
@@ -0,0 +1,1073 @@
+use crate::pdf_fonts::FontStyle;
+use crate::text_layout::Span;
+
+/// Font size of `<h1>` to `<h6>` relative to the body text
+const HEADING_SCALES: [f32; 6] = [1.6, 1.4, 1.2, 1.1, 1.0, 0.9];
+
+/// Font size of `<font size="1">` to `<font size="7">` relative to the body text
+const FONT_SIZE_SCALES: [f32; 7] = [0.63, 0.82, 1.0, 1.13, 1.5, 2.0, 3.0];
+
+/// Elements that end a text block and start a new one
+const BLOCK_ELEMENTS: &[&str] = &[
+    "html", "body", "div", "section", "article", "header", "footer", "main", "nav", "aside", "center",
+    "address", "form", "fieldset", "legend", "figure", "figcaption", "details", "summary", "dl", "dt",
+    "caption", "tr",
+];
+
+/// Elements without content or end tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose content is not rendered
+const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "title", "template", "noscript", "xml"];
+
+/// Deepest element nesting kept in the tree; deeper elements are attached at this depth
+///
+/// Layout and dropping the tree recurse once per level, so the cap bounds the stack use
+/// for bodies with thousands of unclosed tags.
+const MAX_NESTING_DEPTH: usize = 256;
+
+/// Visual style of a run of HTML text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub font: FontStyle,
+    pub underline: bool,
+    /// Text color; black when not set
+    pub color: Option<[u8; 3]>,
+    /// Font size relative to the body text
+    pub scale: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font: FontStyle::REGULAR,
+            underline: false,
+            color: None,
+            scale: 1.0,
+        }
+    }
+}
+
+/// A run of HTML text in one style
+pub type StyledSpan = Span<TextStyle>;
+
+/// A table with more than one column
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlTable {
+    /// Cell contents of each row; rows may have different numbers of cells
+    pub rows: Vec<Vec<Vec<StyledSpan>>>,
+    /// Whether the table asks for visible borders
+    pub bordered: bool,
+}
+
+/// An image that is part of the message itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlImage {
+    pub source: String,
+    /// Alternative text shown when the image cannot be drawn
+    pub alt: Option<String>,
+    /// Requested size in CSS pixels
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+}
+
+/// What a block of an HTML body is
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockKind {
+    /// Text of a `<div>`, list item or other container without spacing of its own
+    Text,
+    /// A `<p>` paragraph, followed by some space
+    Paragraph,
+    /// A heading of level 1 to 6
+    Heading(u8),
+    /// Text of a `<pre>` element with whitespace kept as is
+    Preformatted,
+    Table(HtmlTable),
+    /// An embedded image; `source` is the `src` attribute ("cid:..." or "data:...")
+    Image(HtmlImage),
+    /// A horizontal rule
+    Rule,
+}
+
+/// A block of an HTML body in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub struct HtmlBlock {
+    pub kind: BlockKind,
+    /// Text of the block; "\n" marks a line break
+    pub spans: Vec<StyledSpan>,
+    /// List marker ("•", "3.") drawn before the first line
+    pub marker: Option<String>,
+    /// Nesting depth of lists and definition descriptions
+    pub indent: usize,
+    /// Nesting depth of block quotes
+    pub quote_depth: usize,
+}
+
+/// Lay out an HTML email body as a sequence of blocks
+///
+/// Parsing is lenient: unknown elements are rendered as their content,
+/// unclosed elements end with their parent, and CSS that is not understood is
+/// ignored. Content hidden with `display: none`, scripts and style sheets are
+/// left out.
+pub fn parse_html(html: &str) -> Vec<HtmlBlock> {
+    let root = build_tree(tokenize(html));
+    let mut builder = LayoutBuilder::default();
+    builder.children(&root, TextStyle::default());
+    builder.flush(BlockKind::Text);
+    builder.blocks
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Start {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
+    End(String),
+    Text(String),
+}
+
+/// Split HTML into tags and text; comments, doctypes and processing instructions are dropped
+fn tokenize(html: &str) -> Vec<Token> {
+    // ASCII lowercasing keeps byte offsets, so both strings can share positions
+    let lower = html.to_ascii_lowercase();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let Some(offset) = html[pos..].find('<') else {
+            tokens.push(Token::Text(decode_entities(&html[pos..])));
+            break;
+        };
+        if offset > 0 {
+            tokens.push(Token::Text(decode_entities(&html[pos..pos + offset])));
+            pos += offset;
+        }
+
+        let rest = &html[pos..];
+        if rest.starts_with("<!--") {
+            pos = rest.find("-->").map_or(html.len(), |end| pos + end + 3);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            pos = rest.find('>').map_or(html.len(), |end| pos + end + 1);
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let name_start = pos + if closing { 2 } else { 1 };
+        let name_length = lower[name_start..]
+            .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == ':' || ch == '-'))
+            .unwrap_or(lower.len() - name_start);
+        if name_length == 0 {
+            // A "<" that does not start a tag is text
+            tokens.push(Token::Text("<".to_string()));
+            pos += 1;
+            continue;
+        }
+
+        let name = lower[name_start..name_start + name_length].to_string();
+        let attributes_start = name_start + name_length;
+        let tag_end = find_tag_end(html, attributes_start);
+        let attribute_source = &html[attributes_start..tag_end];
+        pos = (tag_end + 1).min(html.len());
+
+        if closing {
+            tokens.push(Token::End(name));
+            continue;
+        }
+
+        let self_closing = attribute_source.trim_end().ends_with('/');
+        let raw_text = matches!(name.as_str(), "script" | "style" | "title" | "xml");
+        tokens.push(Token::Start {
+            name: name.clone(),
+            attributes: parse_attributes(attribute_source),
+            self_closing,
+        });
+
+        // The content of raw text elements is not markup
+        if raw_text && !self_closing {
+            let end = lower[pos..].find(&format!("</{}", name)).map_or(html.len(), |end| pos + end);
+            tokens.push(Token::Text(html[pos..end].to_string()));
+            pos = end;
+        }
+    }
+    tokens
+}
+
+/// Position of the ">" that ends a tag, skipping quoted attribute values
+fn find_tag_end(html: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (offset, ch) in html[start..].char_indices() {
+        match (quote, ch) {
+            (None, '"' | '\'') => quote = Some(ch),
+            (Some(open), _) if ch == open => quote = None,
+            (None, '>') => return start + offset,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Attributes of a start tag with lowercased names and decoded values
+fn parse_attributes(source: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    loop {
+        while chars.next_if(|ch| ch.is_whitespace() || *ch == '/').is_some() {}
+        let mut name = String::new();
+        while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace() && *ch != '=' && *ch != '/') {
+            name.push(ch.to_ascii_lowercase());
+        }
+        if name.is_empty() {
+            break;
+        }
+
+        while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+            match chars.next_if(|ch| *ch == '"' || *ch == '\'') {
+                Some(quote) => value.extend(chars.by_ref().take_while(|ch| *ch != quote)),
+                None => {
+                    while let Some(ch) = chars.next_if(|ch| !ch.is_whitespace()) {
+                        value.push(ch);
+                    }
+                }
+            }
+        }
+        attributes.push((name, decode_entities(&value)));
+    }
+    attributes
+}
+
+/// Replace character references; unknown entities are kept as written
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..=end]).map(|ch| (ch, end + 2)));
+        match decoded {
+            Some((ch, length)) => {
+                // A soft hyphen is only shown where a word is broken
+                if ch != '\u{ad}' {
+                    result.push(ch);
+                }
+                rest = &rest[length..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+
+    let ch = match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "shy" => '\u{ad}',
+        "auml" => 'ä',
+        "ouml" => 'ö',
+        "uuml" => 'ü',
+        "Auml" => 'Ä',
+        "Ouml" => 'Ö',
+        "Uuml" => 'Ü',
+        "szlig" => 'ß',
+        "eacute" => 'é',
+        "egrave" => 'è',
+        "agrave" => 'à',
+        "ccedil" => 'ç',
+        "euro" => '€',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "sect" => '§',
+        "middot" => '·',
+        "bull" => '•',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "sbquo" => '‚',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "bdquo" => '„',
+        "laquo" => '«',
+        "raquo" => '»',
+        "times" => '×',
+        _ => return None,
+    };
+    Some(ch)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn new(name: &str, attributes: Vec<(String, String)>) -> Self {
+        Self {
+            name: name.to_string(),
+            attributes,
+            children: Vec::new(),
+        }
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+}
+
+/// Build an element tree, closing elements the way browsers do for sloppy markup
+fn build_tree(tokens: Vec<Token>) -> Element {
+    let mut stack = vec![Element::new("#root", Vec::new())];
+
+    for token in tokens {
+        match token {
+            Token::Text(text) => push_child(&mut stack, Node::Text(text)),
+            Token::Start { name, attributes, self_closing } => {
+                close_implied(&mut stack, &name);
+                let element = Element::new(&name, attributes);
+                if self_closing || VOID_ELEMENTS.contains(&name.as_str()) || stack.len() >= MAX_NESTING_DEPTH {
+                    push_child(&mut stack, Node::Element(element));
+                } else {
+                    stack.push(element);
+                }
+            }
+            // "</br>" is treated as a line break by browsers
+            Token::End(name) if name == "br" => push_child(&mut stack, Node::Element(Element::new("br", Vec::new()))),
+            Token::End(name) => {
+                // End tags without a matching open element are ignored
+                if let Some(index) = stack.iter().rposition(|element| element.name == name).filter(|index| *index > 0) {
+                    close_until(&mut stack, index);
+                }
+            }
+        }
+    }
+
+    close_until(&mut stack, 1);
+    stack.pop().unwrap_or_else(|| Element::new("#root", Vec::new()))
+}
+
+fn push_child(stack: &mut [Element], node: Node) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(node);
+    }
+}
+
+/// Close open elements down to and including the one at `index`
+fn close_until(stack: &mut Vec<Element>, index: usize) {
+    while stack.len() > index.max(1) {
+        let element = stack.pop().expect("stack holds more than the root");
+        push_child(stack, Node::Element(element));
+    }
+}
+
+/// Close elements that a new `name` element implicitly ends, e.g. an open `<li>` before the next `<li>`
+fn close_implied(stack: &mut Vec<Element>, name: &str) {
+    let is_block = BLOCK_ELEMENTS.contains(&name)
+        || matches!(name, "p" | "ul" | "ol" | "li" | "table" | "blockquote" | "pre" | "hr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6");
+    if is_block && stack.last().is_some_and(|element| element.name == "p") {
+        let index = stack.len() - 1;
+        close_until(stack, index);
+    }
+
+    let (closes, boundaries): (&[&str], &[&str]) = match name {
+        "li" => (&["li"], &["ul", "ol"]),
+        "dt" | "dd" => (&["dt", "dd"], &["dl"]),
+        "td" | "th" => (&["td", "th"], &["tr", "table"]),
+        "tr" => (&["tr"], &["table", "thead", "tbody", "tfoot"]),
+        "thead" | "tbody" | "tfoot" => (&["thead", "tbody", "tfoot"], &["table"]),
+        _ => return,
+    };
+    for index in (1..stack.len()).rev() {
+        let open = stack[index].name.as_str();
+        if closes.contains(&open) {
+            close_until(stack, index);
+            return;
+        }
+        if boundaries.contains(&open) {
+            return;
+        }
+    }
+}
+
+/// Collects blocks while walking the element tree
+#[derive(Default)]
+struct LayoutBuilder {
+    blocks: Vec<HtmlBlock>,
+    spans: Vec<StyledSpan>,
+    marker: Option<String>,
+    indent: usize,
+    quote_depth: usize,
+    preformatted: bool,
+    /// Open lists; ordered lists carry their next number
+    lists: Vec<Option<u32>>,
+}
+
+impl LayoutBuilder {
+    fn children(&mut self, element: &Element, style: TextStyle) {
+        for child in &element.children {
+            match child {
+                Node::Text(text) => self.text(text, style),
+                Node::Element(child) => self.element(child, style),
+            }
+        }
+    }
+
+    fn element(&mut self, element: &Element, inherited: TextStyle) {
+        let name = element.name.as_str();
+        if HIDDEN_ELEMENTS.contains(&name) {
+            return;
+        }
+        let Some(style) = element_style(element, inherited) else {
+            return;
+        };
+
+        match name {
+            "br" => self.push_text("\n", style),
+            "hr" => {
+                self.flush(BlockKind::Text);
+                self.blocks.push(HtmlBlock {
+                    kind: BlockKind::Rule,
+                    spans: Vec::new(),
+                    marker: None,
+                    indent: self.indent,
+                    quote_depth: self.quote_depth,
+                });
+            }
+            "img" => {
+                let alt = element.attribute("alt").map(str::trim).filter(|alt| !alt.is_empty());
+                let source = element.attribute("src").map(str::trim).unwrap_or_default();
+
+                // Images from the web are not fetched; only their alternative text is shown
+                if !source.starts_with("cid:") && !source.starts_with("data:") {
+                    if let Some(alt) = alt {
+                        self.text(&format!("[{}]", alt), style);
+                    }
+                    return;
+                }
+
+                self.flush(BlockKind::Text);
+                let css = element.attribute("style").unwrap_or_default();
+                self.blocks.push(HtmlBlock {
+                    kind: BlockKind::Image(HtmlImage {
+                        source: source.to_string(),
+                        alt: alt.map(str::to_string),
+                        width: image_dimension(element.attribute("width"), css, "width"),
+                        height: image_dimension(element.attribute("height"), css, "height"),
+                    }),
+                    spans: Vec::new(),
+                    marker: self.marker.take(),
+                    indent: self.indent,
+                    quote_depth: self.quote_depth,
+                });
+            }
+            "p" => self.block(element, style, BlockKind::Paragraph),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                self.block(element, style, BlockKind::Heading(level));
+            }
+            "ul" | "ol" | "menu" => {
+                self.flush(BlockKind::Text);
+                let start = element.attribute("start").and_then(|start| start.trim().parse().ok()).unwrap_or(1);
+                self.lists.push((name == "ol").then_some(start));
+                self.indent += 1;
+                self.block(element, style, BlockKind::Text);
+                self.indent -= 1;
+                self.lists.pop();
+            }
+            "li" => {
+                self.flush(BlockKind::Text);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                self.marker = Some(marker);
+                self.block(element, style, BlockKind::Text);
+                self.marker = None;
+            }
+            "dd" => {
+                self.flush(BlockKind::Text);
+                self.indent += 1;
+                self.block(element, style, BlockKind::Text);
+                self.indent -= 1;
+            }
+            "blockquote" => {
+                self.flush(BlockKind::Text);
+                self.quote_depth += 1;
+                self.block(element, style, BlockKind::Text);
+                self.quote_depth -= 1;
+            }
+            "pre" | "listing" | "plaintext" => {
+                self.flush(BlockKind::Text);
+                self.preformatted = true;
+                self.children(element, style);
+                self.flush(BlockKind::Preformatted);
+                self.preformatted = false;
+            }
+            "table" => {
+                self.flush(BlockKind::Text);
+                self.table(element, style);
+            }
+            "td" | "th" => {
+                self.children(element, style);
+                self.push_text(" ", style);
+            }
+            _ if BLOCK_ELEMENTS.contains(&name) => self.block(element, style, BlockKind::Text),
+            _ => self.children(element, style),
+        }
+    }
+
+    /// Render an element as a block of its own
+    fn block(&mut self, element: &Element, style: TextStyle, kind: BlockKind) {
+        self.flush(BlockKind::Text);
+        self.children(element, style);
+        self.flush(kind);
+    }
+
+    /// Tables with several columns become a table block; layout tables with one column are transparent
+    fn table(&mut self, table: &Element, style: TextStyle) {
+        let rows: Vec<(&Element, TextStyle)> = table_rows(table)
+            .into_iter()
+            .filter_map(|row| element_style(row, style).map(|row_style| (row, row_style)))
+            .collect();
+        let columns = rows.iter().map(|(row, _)| table_cells(row).count()).max().unwrap_or(0);
+
+        for caption in table.child_elements().filter(|child| child.name == "caption") {
+            self.element(caption, style);
+        }
+
+        if columns <= 1 {
+            for (row, row_style) in rows {
+                for cell in table_cells(row) {
+                    if let Some(cell_style) = element_style(cell, row_style) {
+                        self.block(cell, cell_style, BlockKind::Text);
+                    }
+                }
+            }
+            return;
+        }
+
+        let rows = rows
+            .into_iter()
+            .map(|(row, row_style)| table_cells(row).map(|cell| cell_spans(cell, row_style)).collect())
+            .collect();
+        let bordered = table
+            .attribute("border")
+            .is_some_and(|border| border.trim().parse::<u32>().map_or(true, |width| width > 0));
+
+        self.blocks.push(HtmlBlock {
+            kind: BlockKind::Table(HtmlTable { rows, bordered }),
+            spans: Vec::new(),
+            marker: self.marker.take(),
+            indent: self.indent,
+            quote_depth: self.quote_depth,
+        });
+    }
+
+    fn text(&mut self, text: &str, style: TextStyle) {
+        if self.preformatted {
+            // A newline directly after <pre> is not part of the content
+            let text = if self.spans.is_empty() {
+                text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')).unwrap_or(text)
+            } else {
+                text
+            };
+            self.push_text(&text.replace("\r\n", "\n").replace('\t', "    "), style);
+            return;
+        }
+
+        // Whitespace runs collapse to one space, and no block starts with one
+        let mut at_space = self.spans.last().is_none_or(|span| span.text.ends_with([' ', '\n']));
+        let mut collapsed = String::with_capacity(text.len());
+        for ch in text.chars() {
+            if matches!(ch, ' ' | '\t' | '\n' | '\r' | '\u{c}') {
+                if !at_space {
+                    collapsed.push(' ');
+                }
+                at_space = true;
+            } else {
+                collapsed.push(ch);
+                at_space = false;
+            }
+        }
+        self.push_text(&collapsed, style);
+    }
+
+    fn push_text(&mut self, text: &str, style: TextStyle) {
+        if text.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.style == style => last.text.push_str(text),
+            _ => self.spans.push(Span::new(text, style)),
+        }
+    }
+
+    /// End the current block; blocks with nothing but whitespace are dropped
+    fn flush(&mut self, kind: BlockKind) {
+        let mut spans = std::mem::take(&mut self.spans);
+        while let Some(last) = spans.last_mut() {
+            let length = last.text.trim_end_matches([' ', '\n']).len();
+            if length > 0 {
+                last.text.truncate(length);
+                break;
+            }
+            spans.pop();
+        }
+
+        if spans.iter().all(|span| span.text.trim().is_empty()) {
+            return;
+        }
+        self.blocks.push(HtmlBlock {
+            kind,
+            spans,
+            marker: self.marker.take(),
+            indent: self.indent,
+            quote_depth: self.quote_depth,
+        });
+    }
+}
+
+/// Rows of a table, including those in `<thead>`, `<tbody>` and `<tfoot>`
+fn table_rows(table: &Element) -> Vec<&Element> {
+    table
+        .child_elements()
+        .flat_map(|child| match child.name.as_str() {
+            "tr" => vec![child],
+            "thead" | "tbody" | "tfoot" => child.child_elements().filter(|row| row.name == "tr").collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+fn table_cells(row: &Element) -> impl Iterator<Item = &Element> {
+    row.child_elements().filter(|cell| cell.name == "td" || cell.name == "th")
+}
+
+/// Content of a table cell as one run of text; blocks inside the cell become lines
+fn cell_spans(cell: &Element, row_style: TextStyle) -> Vec<StyledSpan> {
+    let Some(style) = element_style(cell, row_style) else {
+        return Vec::new();
+    };
+    let mut builder = LayoutBuilder::default();
+    builder.children(cell, style);
+    builder.flush(BlockKind::Text);
+
+    let mut spans = Vec::new();
+    for block in builder.blocks {
+        if !spans.is_empty() {
+            spans.push(Span::new("\n", style));
+        }
+        if let Some(marker) = block.marker {
+            spans.push(Span::new(format!("{} ", marker), style));
+        }
+        match block.kind {
+            // Nested tables are flattened to one line per row
+            BlockKind::Table(table) => {
+                for (index, row) in table.rows.into_iter().enumerate() {
+                    if index > 0 {
+                        spans.push(Span::new("\n", style));
+                    }
+                    for (column, cell) in row.into_iter().enumerate() {
+                        if column > 0 {
+                            spans.push(Span::new("  ", style));
+                        }
+                        spans.extend(cell);
+                    }
+                }
+            }
+            BlockKind::Image(image) => {
+                if let Some(alt) = image.alt {
+                    spans.push(Span::new(format!("[{}]", alt), style));
+                }
+            }
+            _ => spans.extend(block.spans),
+        }
+    }
+    spans
+}
+
+/// Size of an image in CSS pixels from its attribute or inline style; percentages are ignored
+fn image_dimension(attribute: Option<&str>, css: &str, property: &str) -> Option<f32> {
+    let from_css = css.split(';').find_map(|declaration| {
+        let (name, value) = declaration.split_once(':')?;
+        (name.trim().eq_ignore_ascii_case(property)).then(|| value.trim().to_ascii_lowercase())
+    });
+    let value = from_css.or_else(|| attribute.map(str::to_string))?;
+    value.trim().trim_end_matches("px").trim().parse().ok().filter(|size: &f32| *size > 0.0)
+}
+
+/// Style of an element's content; None when the element is hidden
+fn element_style(element: &Element, inherited: TextStyle) -> Option<TextStyle> {
+    if element.attribute("hidden").is_some() {
+        return None;
+    }
+
+    let mut style = inherited;
+    match element.name.as_str() {
+        "b" | "strong" | "th" => style.font.bold = true,
+        "i" | "em" | "cite" | "var" | "dfn" => style.font.italic = true,
+        "u" | "ins" => style.underline = true,
+        "code" | "tt" | "kbd" | "samp" | "pre" | "listing" | "plaintext" => style.font.monospace = true,
+        "small" => style.scale *= 0.85,
+        "big" => style.scale *= 1.2,
+        "a" if element.attribute("href").is_some() => {
+            style.underline = true;
+            style.color = Some([0, 0, 238]);
+        }
+        "font" => {
+            if let Some(color) = element.attribute("color").and_then(parse_color) {
+                style.color = Some(color).filter(|color| !is_unreadable(*color));
+            }
+            if let Some(face) = element.attribute("face") {
+                style.font.monospace = is_monospace_family(face);
+            }
+            if let Some(size) = element.attribute("size").map(str::trim) {
+                let size = match size.parse::<i32>() {
+                    Ok(relative) if size.starts_with(['+', '-']) => Some(3 + relative),
+                    Ok(absolute) => Some(absolute),
+                    Err(_) => None,
+                };
+                if let Some(size) = size {
+                    style.scale = FONT_SIZE_SCALES[size.clamp(1, 7) as usize - 1];
+                }
+            }
+        }
+        name if name.len() == 2 && name.starts_with('h') => {
+            if let Ok(level @ 1..=6) = name[1..].parse::<usize>() {
+                style.font.bold = true;
+                style.scale = HEADING_SCALES[level - 1];
+            }
+        }
+        _ => {}
+    }
+
+    match element.attribute("style") {
+        Some(css) => apply_css(css, style),
+        None => Some(style),
+    }
+}
+
+/// Apply inline CSS declarations; None when they hide the element
+fn apply_css(css: &str, mut style: TextStyle) -> Option<TextStyle> {
+    for declaration in css.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let property = property.trim().to_ascii_lowercase();
+        let value = value.to_ascii_lowercase().replace("!important", "");
+        let value = value.trim();
+
+        match property.as_str() {
+            "display" if value == "none" => return None,
+            "visibility" if value == "hidden" => return None,
+            "color" => {
+                if let Some(color) = parse_color(value) {
+                    style.color = Some(color).filter(|color| !is_unreadable(*color));
+                }
+            }
+            "font-weight" => {
+                style.font.bold = match value {
+                    "bold" | "bolder" => true,
+                    "normal" | "lighter" => false,
+                    weight => weight.parse::<u32>().map_or(style.font.bold, |weight| weight >= 600),
+                }
+            }
+            "font-style" => style.font.italic = value == "italic" || value == "oblique",
+            "text-decoration" | "text-decoration-line" => style.underline = value.contains("underline"),
+            "font-family" => style.font.monospace = is_monospace_family(value),
+            "font-size" => {
+                if let Some(scale) = parse_font_size(value, style.scale) {
+                    style.scale = scale.clamp(0.5, 3.0);
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(style)
+}
+
+fn is_monospace_family(family: &str) -> bool {
+    let family = family.to_ascii_lowercase();
+    ["monospace", "courier", "consolas", "menlo", "monaco", "lucida console"]
+        .iter()
+        .any(|name| family.contains(name))
+}
+
+/// Font size relative to the body text; 16px/12pt is the body size
+fn parse_font_size(value: &str, inherited: f32) -> Option<f32> {
+    let number = |unit: &str| value.strip_suffix(unit).and_then(|number| number.trim().parse::<f32>().ok());
+
+    let scale = match value {
+        "xx-small" => 0.6,
+        "x-small" => 0.75,
+        "small" => 0.89,
+        "medium" => 1.0,
+        "large" => 1.2,
+        "x-large" => 1.5,
+        "xx-large" => 2.0,
+        "smaller" => inherited * 0.83,
+        "larger" => inherited * 1.2,
+        _ => {
+            if let Some(px) = number("px") {
+                px / 16.0
+            } else if let Some(pt) = number("pt") {
+                pt / 12.0
+            } else if let Some(rem) = number("rem") {
+                rem
+            } else if let Some(em) = number("em") {
+                inherited * em
+            } else if let Some(percent) = number("%") {
+                inherited * percent / 100.0
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(scale)
+}
+
+/// Parse "#rgb", "#rrggbb", "rgb(r, g, b)" and common color names
+fn parse_color(value: &str) -> Option<[u8; 3]> {
+    let value = value.trim().to_ascii_lowercase();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u8> = hex.chars().map(|ch| ch.to_digit(16).map(|digit| digit as u8)).collect::<Option<_>>()?;
+        return match digits.as_slice() {
+            [r, g, b] => Some([r * 17, g * 17, b * 17]),
+            [r1, r2, g1, g2, b1, b2] => Some([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2]),
+            _ => None,
+        };
+    }
+
+    if let Some(arguments) = value.strip_prefix("rgba(").or_else(|| value.strip_prefix("rgb(")) {
+        let components: Vec<u8> = arguments
+            .trim_end_matches(')')
+            .split(',')
+            .take(3)
+            .map(|component| component.trim().parse::<f32>().ok().map(|component| component.clamp(0.0, 255.0) as u8))
+            .collect::<Option<_>>()?;
+        return <[u8; 3]>::try_from(components).ok();
+    }
+
+    let color = match value.as_str() {
+        "black" | "windowtext" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "red" => [255, 0, 0],
+        "darkred" | "maroon" => [128, 0, 0],
+        "green" => [0, 128, 0],
+        "darkgreen" => [0, 100, 0],
+        "lime" => [0, 255, 0],
+        "blue" => [0, 0, 255],
+        "darkblue" | "navy" => [0, 0, 128],
+        "gray" | "grey" => [128, 128, 128],
+        "darkgray" | "darkgrey" => [169, 169, 169],
+        "silver" => [192, 192, 192],
+        "purple" => [128, 0, 128],
+        "teal" => [0, 128, 128],
+        "olive" => [128, 128, 0],
+        "orange" => [255, 165, 0],
+        "yellow" => [255, 255, 0],
+        "aqua" | "cyan" => [0, 255, 255],
+        "fuchsia" | "magenta" => [255, 0, 255],
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// Backgrounds are not drawn, so text colors this light would vanish on white paper
+fn is_unreadable(color: [u8; 3]) -> bool {
+    color.iter().all(|component| *component > 0xe0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(block: &HtmlBlock) -> Vec<&str> {
+        block.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_paragraphs_and_inline_styles() {
+        let html = "<html><head><style>p { color: red }</style></head><body>\
+            <h2>Rechnung</h2>\
+            <p>Bitte <b>bis  Freitag</b>\n überweisen.<br>Danke &amp; Gruß</p>\
+            <p><span style=\"color:#c00; font-style: italic\">Wichtig</span><span style=\"display:none\">Vorschau</span></p>\
+            </body></html>";
+        let blocks = parse_html(html);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].kind, BlockKind::Heading(2));
+        assert!(blocks[0].spans[0].style.font.bold);
+        assert_eq!(blocks[0].spans[0].style.scale, 1.4);
+
+        assert_eq!(blocks[1].kind, BlockKind::Paragraph);
+        assert_eq!(texts(&blocks[1]), vec!["Bitte ", "bis Freitag", " überweisen.\nDanke & Gruß"]);
+        assert!(blocks[1].spans[1].style.font.bold);
+
+        let important = &blocks[2].spans[0];
+        assert_eq!(texts(&blocks[2]), vec!["Wichtig"]);
+        assert_eq!(important.style.color, Some([0xcc, 0, 0]));
+        assert!(important.style.font.italic);
+    }
+
+    #[test]
+    fn test_lists_quotes_and_preformatted_text() {
+        let html = "<ol start=3><li>Erstens<li>Zweitens<ul><li>Unterpunkt</ul></ol>\
+            <blockquote>Zitat<blockquote>Älteres Zitat</blockquote></blockquote>\
+            <pre>\nfn main() {\n    42\n}</pre>";
+        let blocks = parse_html(html);
+
+        let items: Vec<(Option<&str>, usize, Vec<&str>)> = blocks[..3]
+            .iter()
+            .map(|block| (block.marker.as_deref(), block.indent, texts(block)))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                (Some("3."), 1, vec!["Erstens"]),
+                (Some("4."), 1, vec!["Zweitens"]),
+                (Some("•"), 2, vec!["Unterpunkt"]),
+            ]
+        );
+
+        assert_eq!((blocks[3].quote_depth, texts(&blocks[3])), (1, vec!["Zitat"]));
+        assert_eq!((blocks[4].quote_depth, texts(&blocks[4])), (2, vec!["Älteres Zitat"]));
+
+        assert_eq!(blocks[5].kind, BlockKind::Preformatted);
+        assert_eq!(texts(&blocks[5]), vec!["fn main() {\n    42\n}"]);
+        assert!(blocks[5].spans[0].style.font.monospace);
+    }
+
+    #[test]
+    fn test_tables() {
+        let html = "<table width=600><tr><td>\
+            <table border=1><tr><th>Artikel</th><th>Preis</th></tr><tr><td>Stuhl<td>49,90 &euro;</table>\
+            </td></tr></table>";
+        let blocks = parse_html(html);
+
+        // The single-column layout table disappears, the data table is kept
+        assert_eq!(blocks.len(), 1);
+        let BlockKind::Table(table) = &blocks[0].kind else {
+            panic!("expected a table, got {:?}", blocks[0].kind);
+        };
+        assert!(table.bordered);
+        let cells: Vec<Vec<String>> = table
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.iter().map(|span| span.text.as_str()).collect()).collect())
+            .collect();
+        assert_eq!(cells, vec![vec!["Artikel", "Preis"], vec!["Stuhl", "49,90 €"]]);
+        assert!(table.rows[0][0][0].style.font.bold);
+    }
+
+    #[test]
+    fn test_embedded_images_become_blocks() {
+        let html = "<p>Mit freundlichen Grüßen<br><img src=\"cid:logo@example.com\" width=\"120\" style=\"height: 40px\" alt=\"Logo\">Firma</p>";
+        let blocks = parse_html(html);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[1].kind,
+            BlockKind::Image(HtmlImage {
+                source: "cid:logo@example.com".to_string(),
+                alt: Some("Logo".to_string()),
+                width: Some(120.0),
+                height: Some(40.0),
+            })
+        );
+        assert_eq!(texts(&blocks[2]), vec!["Firma"]);
+    }
+
+    #[test]
+    fn test_degrades_gracefully() {
+        let html = "<div>a < b <!-- Kommentar --><unknown>Text</unknown><p>offen<p>zweiter</i> &bogus; \
+            <font color=\"#fafafa\" size=\"+2\">hell</font> <img src=\"https://example.com/logo.png\" alt=\"Logo\"></div>";
+        let blocks = parse_html(html);
+
+        let lines: Vec<String> = blocks.iter().map(|block| texts(block).concat()).collect();
+        assert_eq!(lines, vec!["a < b Text", "offen", "zweiter &bogus; hell [Logo]"]);
+
+        let light = blocks[2].spans.iter().find(|span| span.text == "hell").unwrap();
+        assert_eq!(light.style.color, None);
+        assert_eq!(light.style.scale, 1.5);
+    }
+
+    #[test]
+    fn test_deeply_nested_markup_is_capped() {
+        let depth = 100_000;
+        let html = format!("{}Tief{}", "<div><span>".repeat(depth / 2), "</span></div>".repeat(depth / 2));
+        let blocks = parse_html(&html);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(texts(&blocks[0]), vec!["Tief"]);
+
+        let unclosed = format!("{}Ende", "<blockquote><b>".repeat(depth / 2));
+        let root = build_tree(tokenize(&unclosed));
+        let mut nesting = 0;
+        let mut element = &root;
+        while let Some(child) = element.child_elements().next() {
+            nesting += 1;
+            element = child;
+        }
+        assert_eq!(nesting, MAX_NESTING_DEPTH);
+        let blocks = parse_html(&unclosed);
+        assert!(blocks.iter().any(|block| texts(block) == vec!["Ende"]));
+    }
+}
@@ -0,0 +1,1038 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::{decode_modified_utf7, EmailIterator, MailSource};
+use crate::mime_parser::MimeParser;
+use crate::types::{Email, ImapConfig, ImapSecurity, MailFolder, MessageFlags};
+
+/// File name of the per-folder UID bookkeeping, stored in the output directory
+pub const IMAP_SYNC_STATE_FILE: &str = ".imap-sync-state.json";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long `ImapSource::close` waits for the server to answer LOGOUT
+const LOGOUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Size of a single socket read
+const READ_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Plain TCP or TLS stream carrying the IMAP session
+trait ImapStream: Read + Write + Send {}
+impl<T: Read + Write + Send> ImapStream for T {}
+
+/// Last archived UID of one folder, valid for one UIDVALIDITY
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct FolderSyncState {
+    pub uid_validity: u32,
+    pub last_uid: u32,
+}
+
+/// UID bookkeeping across archive runs, keyed by "user@host/mailbox"
+///
+/// Contains no credentials, so it can be stored next to the generated PDFs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ImapSyncState {
+    folders: HashMap<String, FolderSyncState>,
+}
+
+impl ImapSyncState {
+    /// Load the state from `path`; a missing or unreadable file starts from scratch
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("Warning: Ignoring invalid IMAP sync state {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Write the state to `path` (via a temporary file, so a crash never leaves half a file)
+    pub fn save(&self, path: &Path) -> MailSourceResult<()> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| MailSourceError::IoError(format!("Sync-Status konnte nicht serialisiert werden: {}", e)))?;
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, content)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Highest archived UID of a folder, or 0 if the folder is new or its UIDVALIDITY changed
+    pub fn last_uid(&self, key: &str, uid_validity: u32) -> u32 {
+        self.folders
+            .get(key)
+            .filter(|state| state.uid_validity == uid_validity)
+            .map(|state| state.last_uid)
+            .unwrap_or(0)
+    }
+
+    /// Remember `uid` as archived
+    pub fn record(&mut self, key: &str, uid_validity: u32, uid: u32) {
+        let state = self.folders.entry(key.to_string()).or_default();
+        if state.uid_validity != uid_validity {
+            *state = FolderSyncState { uid_validity, last_uid: 0 };
+        }
+        state.last_uid = state.last_uid.max(uid);
+    }
+}
+
+/// Untagged server response; literals are replaced by "{n}" in `text` and kept in order
+#[derive(Debug, Default)]
+struct Response {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+/// Token of a response line
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(String),
+    Quoted(String),
+    Literal(usize),
+    Open,
+    Close,
+}
+
+/// Minimal IMAP4rev1 protocol client
+struct ImapConnection {
+    stream: Box<dyn ImapStream>,
+    /// Handle on the underlying socket for adjusting timeouts below TLS
+    socket: Option<TcpStream>,
+    buffer: Vec<u8>,
+    next_tag: u32,
+}
+
+impl ImapConnection {
+    fn new(stream: Box<dyn ImapStream>) -> Self {
+        Self { stream, socket: None, buffer: Vec::new(), next_tag: 1 }
+    }
+
+    fn set_timeout(&self, timeout: Duration) -> MailSourceResult<()> {
+        if let Some(socket) = &self.socket {
+            socket.set_read_timeout(Some(timeout)).map_err(connection_error)?;
+            socket.set_write_timeout(Some(timeout)).map_err(connection_error)?;
+        }
+        Ok(())
+    }
+
+    fn into_stream(self) -> Box<dyn ImapStream> {
+        self.stream
+    }
+
+    fn fill_buffer(&mut self) -> MailSourceResult<()> {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        let read = self.stream.read(&mut chunk).map_err(connection_error)?;
+        if read == 0 {
+            return Err(MailSourceError::ConnectionError("Server hat die Verbindung geschlossen".to_string()));
+        }
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(())
+    }
+
+    /// Read one CRLF-terminated line without the line ending
+    fn read_line(&mut self) -> MailSourceResult<Vec<u8>> {
+        loop {
+            if let Some(position) = self.buffer.windows(2).position(|window| window == b"\r\n") {
+                let line = self.buffer[..position].to_vec();
+                self.buffer.drain(..position + 2);
+                return Ok(line);
+            }
+            self.fill_buffer()?;
+        }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> MailSourceResult<Vec<u8>> {
+        while self.buffer.len() < len {
+            self.fill_buffer()?;
+        }
+        Ok(self.buffer.drain(..len).collect())
+    }
+
+    /// Read a full logical response line, following literals
+    fn read_response(&mut self) -> MailSourceResult<Response> {
+        let mut response = Response::default();
+        loop {
+            let line = self.read_line()?;
+            response.text.push_str(&String::from_utf8_lossy(&line));
+
+            match literal_length(&line) {
+                Some(len) => {
+                    let literal = self.read_bytes(len)?;
+                    response.literals.push(literal);
+                }
+                None => return Ok(response),
+            }
+        }
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> MailSourceResult<()> {
+        self.stream.write_all(data).map_err(connection_error)?;
+        self.stream.flush().map_err(connection_error)
+    }
+
+    fn read_greeting(&mut self) -> MailSourceResult<()> {
+        let greeting = self.read_response()?;
+        if greeting.text.starts_with("* OK") || greeting.text.starts_with("* PREAUTH") {
+            Ok(())
+        } else {
+            Err(MailSourceError::ConnectionError(format!("Unerwartete Begrüßung: {}", greeting.text)))
+        }
+    }
+
+    fn tag(&mut self) -> String {
+        let tag = format!("A{:04}", self.next_tag);
+        self.next_tag += 1;
+        tag
+    }
+
+    /// Send a command and collect its untagged responses
+    fn command(&mut self, command: &str) -> MailSourceResult<Vec<Response>> {
+        let tag = self.tag();
+        self.write_all(format!("{} {}\r\n", tag, command).as_bytes())?;
+        self.read_until_tagged(&tag)
+    }
+
+    /// Send a command whose arguments are literals, waiting for continuation requests
+    fn command_with_literals(&mut self, command: &str, literals: &[&[u8]]) -> MailSourceResult<Vec<Response>> {
+        let tag = self.tag();
+        let mut line = format!("{} {}", tag, command).into_bytes();
+        for literal in literals {
+            line.extend_from_slice(format!(" {{{}}}\r\n", literal.len()).as_bytes());
+            self.write_all(&line)?;
+
+            let continuation = self.read_response()?;
+            if !continuation.text.starts_with('+') {
+                return Err(tagged_error(&tag, &continuation.text));
+            }
+            line = literal.to_vec();
+        }
+        line.extend_from_slice(b"\r\n");
+        self.write_all(&line)?;
+        self.read_until_tagged(&tag)
+    }
+
+    fn read_until_tagged(&mut self, tag: &str) -> MailSourceResult<Vec<Response>> {
+        let mut responses = Vec::new();
+        loop {
+            let response = self.read_response()?;
+            if let Some(status) = response.text.strip_prefix(tag).map(str::trim_start) {
+                if status.len() >= 2 && status[..2].eq_ignore_ascii_case("OK") {
+                    // Keep the tagged OK; it may carry response codes such as [READ-ONLY]
+                    responses.push(response);
+                    return Ok(responses);
+                }
+                return Err(tagged_error(tag, &response.text));
+            }
+            responses.push(response);
+        }
+    }
+}
+
+/// Folder selected for archiving with the UIDs still to be fetched
+#[derive(Debug, Clone)]
+struct ImapFolder {
+    /// Display path ("/" separated, decoded)
+    path: String,
+    /// Raw mailbox name as used on the wire
+    mailbox: String,
+    uid_validity: u32,
+    pending_uids: Vec<u32>,
+}
+
+/// IMAP4rev1 mail source
+///
+/// Credentials are only kept in the in-memory `ImapConfig`. Folders are
+/// opened read-only with EXAMINE and messages fetched with BODY.PEEK[], so
+/// archiving never changes the \Seen flag on the server.
+pub struct ImapSource {
+    config: ImapConfig,
+    connection: Mutex<ImapConnection>,
+    capabilities: Vec<String>,
+    folders: Vec<ImapFolder>,
+    state_path: Option<PathBuf>,
+    sync_state: Mutex<ImapSyncState>,
+    /// Sync keys of folders with a message that could not be read; their UIDs stop advancing
+    failed_folders: Mutex<HashSet<String>>,
+    /// Set once LOGOUT was sent
+    closed: AtomicBool,
+}
+
+impl ImapSource {
+    /// Connect, log in and determine the messages to archive
+    /// With a `state_path`, only messages newer than the last archived UID of each folder are fetched
+    pub fn connect(config: ImapConfig, state_path: Option<PathBuf>) -> MailSourceResult<Self> {
+        let mut connection = open_connection(&config)?;
+
+        let capabilities = capabilities(&mut connection)?;
+        if !capabilities.iter().any(|capability| capability == "IMAP4REV1") {
+            return Err(MailSourceError::ProtocolError("Server unterstützt kein IMAP4rev1".to_string()));
+        }
+        if capabilities.iter().any(|capability| capability == "LOGINDISABLED") {
+            return Err(MailSourceError::AuthenticationFailed(
+                "Server erlaubt keine Anmeldung über diese Verbindung".to_string(),
+            ));
+        }
+
+        connection
+            .command_with_literals("LOGIN", &[config.username.as_bytes(), config.password.as_bytes()])
+            .map_err(|e| match e {
+                MailSourceError::ProtocolError(message) => MailSourceError::AuthenticationFailed(message),
+                other => other,
+            })?;
+
+        // Servers may advertise more capabilities after login
+        let capabilities = self::capabilities(&mut connection)
+            .ok()
+            .filter(|list| !list.is_empty())
+            .unwrap_or(capabilities);
+
+        let sync_state = state_path.as_deref().map(ImapSyncState::load).unwrap_or_default();
+        let mut folders = Vec::new();
+        for (mailbox, path) in list_mailboxes(&mut connection)? {
+            if !config.folders.is_empty() && !config.folders.contains(&path) {
+                continue;
+            }
+
+            let (uid_validity, exists) = examine(&mut connection, &mailbox)?;
+            let last_uid = sync_state.last_uid(&sync_key(&config, &mailbox), uid_validity);
+            let pending_uids = if exists == 0 { Vec::new() } else { search_uids_after(&mut connection, last_uid)? };
+
+            folders.push(ImapFolder { path, mailbox, uid_validity, pending_uids });
+        }
+
+        Ok(Self {
+            config,
+            connection: Mutex::new(connection),
+            capabilities,
+            folders,
+            state_path,
+            sync_state: Mutex::new(sync_state),
+            failed_folders: Mutex::new(HashSet::new()),
+            closed: AtomicBool::new(false),
+        })
+    }
+
+    /// Log out and close the connection, waiting at most `LOGOUT_TIMEOUT` for the server
+    /// Further calls do nothing
+    pub fn close(&self) -> MailSourceResult<()> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut connection = self.lock_connection();
+        connection.set_timeout(LOGOUT_TIMEOUT)?;
+        connection.command("LOGOUT").map(|_| ())
+    }
+
+    /// Whether the server advertises UIDPLUS (RFC 4315)
+    pub fn supports_uidplus(&self) -> bool {
+        self.capabilities.iter().any(|capability| capability == "UIDPLUS")
+    }
+
+    fn lock_connection(&self) -> std::sync::MutexGuard<'_, ImapConnection> {
+        self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Fetch one batch of messages from `folder`
+    fn fetch_batch(&self, folder: &ImapFolder, uids: &[u32], select: bool) -> MailSourceResult<Vec<MailSourceResult<Email>>> {
+        let mut connection = self.lock_connection();
+        if select {
+            let (uid_validity, _) = examine(&mut connection, &folder.mailbox)?;
+            if uid_validity != folder.uid_validity {
+                return Err(MailSourceError::ProtocolError(format!(
+                    "UIDVALIDITY von {} hat sich während des Archivierens geändert",
+                    folder.path
+                )));
+            }
+        }
+
+        let uid_set = uids.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+        let responses = connection.command(&format!("UID FETCH {} (UID FLAGS INTERNALDATE BODY.PEEK[])", uid_set))?;
+        drop(connection);
+
+        let mut messages: Vec<(u32, FetchedMessage)> = responses
+            .iter()
+            .filter(|response| is_fetch_response(&response.text))
+            .map(parse_fetch)
+            .filter_map(|fetched| fetched.uid.map(|uid| (uid, fetched)))
+            .collect();
+        messages.sort_by_key(|(uid, _)| *uid);
+
+        // Only UIDs below the first unreadable message are recorded, so the next run fetches it again
+        let key = sync_key(&self.config, &folder.mailbox);
+        let mut sync_state = self.sync_state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut failed_folders = self.failed_folders.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut results = Vec::with_capacity(messages.len());
+        for (uid, fetched) in messages {
+            let result = MimeParser::parse_email_with_fallback_date(&fetched.body, fetched.internal_date).map(|mut email| {
+                email.folder = Some(folder.path.clone());
+                email.flags = fetched.flags;
+                email
+            });
+            match &result {
+                Ok(_) if !failed_folders.contains(&key) => sync_state.record(&key, folder.uid_validity, uid),
+                Ok(_) => {}
+                Err(_) => {
+                    failed_folders.insert(key.clone());
+                }
+            }
+            results.push(result);
+        }
+        Ok(results)
+    }
+}
+
+impl MailSource for ImapSource {
+    fn source_name(&self) -> String {
+        format!("{}@{}", self.config.username, self.config.host)
+    }
+
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(self.folders.iter().map(|folder| folder.pending_uids.len()).sum())
+    }
+
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        Ok(self
+            .folders
+            .iter()
+            .map(|folder| {
+                let name = folder.path.rsplit('/').next().unwrap_or_default().to_string();
+                MailFolder::new(folder.path.clone(), name, folder.pending_uids.len())
+            })
+            .collect())
+    }
+
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        Ok(Box::new(ImapEmailIterator {
+            source: self,
+            folder_index: 0,
+            offset: 0,
+            queue: VecDeque::new(),
+        }))
+    }
+
+    /// Persist the highest fetched UID of every folder for the next run
+    fn finish(&self) -> MailSourceResult<()> {
+        match &self.state_path {
+            Some(path) => self.sync_state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).save(path),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&self) -> MailSourceResult<()> {
+        ImapSource::close(self)
+    }
+}
+
+/// Iterates folder by folder, fetching `batch_size` messages per UID FETCH
+struct ImapEmailIterator<'a> {
+    source: &'a ImapSource,
+    folder_index: usize,
+    offset: usize,
+    queue: VecDeque<MailSourceResult<Email>>,
+}
+
+impl Iterator for ImapEmailIterator<'_> {
+    type Item = MailSourceResult<Email>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(result) = self.queue.pop_front() {
+                return Some(result);
+            }
+
+            let folder = self.source.folders.get(self.folder_index)?;
+            let batch_size = self.source.config.batch_size.max(1) as usize;
+            let end = (self.offset + batch_size).min(folder.pending_uids.len());
+            if self.offset >= end {
+                self.folder_index += 1;
+                self.offset = 0;
+                continue;
+            }
+
+            let uids = &folder.pending_uids[self.offset..end];
+            let select = self.offset == 0;
+            self.offset = end;
+
+            match self.source.fetch_batch(folder, uids, select) {
+                Ok(results) => self.queue.extend(results),
+                Err(e) => {
+                    // Skip the rest of the folder; the next folder starts with a fresh EXAMINE
+                    self.folder_index += 1;
+                    self.offset = 0;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Open the TCP connection, negotiate TLS and read the greeting
+fn open_connection(config: &ImapConfig) -> MailSourceResult<ImapConnection> {
+    let address = (config.host.as_str(), config.port)
+        .to_socket_addrs()
+        .map_err(connection_error)?
+        .next()
+        .ok_or_else(|| MailSourceError::ConnectionError(format!("Host nicht gefunden: {}", config.host)))?;
+
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(connection_error)?;
+    tcp.set_read_timeout(Some(READ_TIMEOUT)).map_err(connection_error)?;
+    let socket = tcp.try_clone().map_err(connection_error)?;
+
+    let mut connection = match config.security {
+        ImapSecurity::Tls => {
+            let mut connection = ImapConnection::new(Box::new(wrap_tls(&config.host, tcp)?));
+            connection.read_greeting()?;
+            connection
+        }
+        ImapSecurity::StartTls => {
+            let mut plain = ImapConnection::new(Box::new(tcp.try_clone().map_err(connection_error)?));
+            plain.read_greeting()?;
+            plain.command("STARTTLS")?;
+            drop(plain.into_stream());
+            ImapConnection::new(Box::new(wrap_tls(&config.host, tcp)?))
+        }
+        ImapSecurity::Plain => {
+            let mut connection = ImapConnection::new(Box::new(tcp));
+            connection.read_greeting()?;
+            connection
+        }
+    };
+    connection.socket = Some(socket);
+    Ok(connection)
+}
+
+fn wrap_tls(host: &str, tcp: TcpStream) -> MailSourceResult<native_tls::TlsStream<TcpStream>> {
+    let connector = native_tls::TlsConnector::new()
+        .map_err(|e| MailSourceError::ConnectionError(format!("TLS konnte nicht initialisiert werden: {}", e)))?;
+    connector
+        .connect(host, tcp)
+        .map_err(|e| MailSourceError::ConnectionError(format!("TLS-Verbindung fehlgeschlagen: {}", e)))
+}
+
+fn capabilities(connection: &mut ImapConnection) -> MailSourceResult<Vec<String>> {
+    let responses = connection.command("CAPABILITY")?;
+    Ok(responses
+        .iter()
+        .filter_map(|response| response.text.strip_prefix("* CAPABILITY "))
+        .flat_map(|list| list.split_whitespace().map(str::to_uppercase))
+        .collect())
+}
+
+/// List selectable mailboxes as (raw name, decoded "/" path)
+fn list_mailboxes(connection: &mut ImapConnection) -> MailSourceResult<Vec<(String, String)>> {
+    let responses = connection.command("LIST \"\" \"*\"")?;
+    let mut mailboxes = Vec::new();
+
+    for response in responses.iter().filter(|response| response.text.starts_with("* LIST ")) {
+        let tokens = tokenize(&response.text["* LIST ".len()..]);
+        let Some(close) = tokens.iter().position(|token| *token == Token::Close) else {
+            continue;
+        };
+
+        let attributes: Vec<String> = tokens[..close]
+            .iter()
+            .filter_map(|token| match token {
+                Token::Atom(atom) => Some(atom.to_lowercase()),
+                _ => None,
+            })
+            .collect();
+        if attributes.iter().any(|attribute| attribute == "\\noselect" || attribute == "\\nonexistent") {
+            continue;
+        }
+
+        let delimiter = match tokens.get(close + 1) {
+            Some(Token::Quoted(delimiter)) => Some(delimiter.clone()),
+            _ => None,
+        };
+        let Some(mailbox) = tokens.get(close + 2).and_then(|token| token_string(token, &response.literals)) else {
+            continue;
+        };
+
+        let path = match delimiter.as_deref().filter(|delimiter| !delimiter.is_empty()) {
+            Some(delimiter) => mailbox.split(delimiter).map(decode_modified_utf7).collect::<Vec<_>>().join("/"),
+            None => decode_modified_utf7(&mailbox),
+        };
+        mailboxes.push((mailbox, path));
+    }
+
+    Ok(mailboxes)
+}
+
+/// Open a mailbox read-only and return (UIDVALIDITY, EXISTS)
+fn examine(connection: &mut ImapConnection, mailbox: &str) -> MailSourceResult<(u32, u32)> {
+    let responses = connection.command(&format!("EXAMINE {}", quote(mailbox)))?;
+    let mut uid_validity = 0;
+    let mut exists = 0;
+
+    for response in &responses {
+        if let Some(value) = response_code_value(&response.text, "UIDVALIDITY") {
+            uid_validity = value;
+        }
+        let words: Vec<&str> = response.text.split_whitespace().collect();
+        if words.len() == 3 && words[0] == "*" && words[2].eq_ignore_ascii_case("EXISTS") {
+            exists = words[1].parse().unwrap_or(0);
+        }
+    }
+
+    Ok((uid_validity, exists))
+}
+
+/// UIDs greater than `last_uid` in the selected mailbox, ascending
+fn search_uids_after(connection: &mut ImapConnection, last_uid: u32) -> MailSourceResult<Vec<u32>> {
+    let responses = connection.command(&format!("UID SEARCH UID {}:*", last_uid.saturating_add(1)))?;
+    let mut uids: Vec<u32> = responses
+        .iter()
+        .filter_map(|response| response.text.strip_prefix("* SEARCH"))
+        .flat_map(|list| list.split_whitespace().filter_map(|uid| uid.parse().ok()))
+        // "n:*" always matches the highest UID, even when it is below n
+        .filter(|&uid| uid > last_uid)
+        .collect();
+    uids.sort_unstable();
+    uids.dedup();
+    Ok(uids)
+}
+
+/// Data of one FETCH response
+#[derive(Debug, Default)]
+struct FetchedMessage {
+    uid: Option<u32>,
+    flags: MessageFlags,
+    internal_date: Option<DateTime<Utc>>,
+    body: Vec<u8>,
+}
+
+fn is_fetch_response(text: &str) -> bool {
+    let words: Vec<&str> = text.splitn(4, ' ').collect();
+    words.len() >= 3 && words[0] == "*" && words[2].eq_ignore_ascii_case("FETCH")
+}
+
+fn parse_fetch(response: &Response) -> FetchedMessage {
+    let mut fetched = FetchedMessage::default();
+    let Some(start) = response.text.find('(') else {
+        return fetched;
+    };
+    let tokens = tokenize(&response.text[start + 1..]);
+
+    let mut index = 0;
+    while index < tokens.len() {
+        let Token::Atom(name) = &tokens[index] else {
+            index += 1;
+            continue;
+        };
+
+        match name.to_uppercase().as_str() {
+            "UID" => {
+                if let Some(Token::Atom(value)) = tokens.get(index + 1) {
+                    fetched.uid = value.parse().ok();
+                }
+                index += 2;
+            }
+            "FLAGS" => {
+                index += 2; // skip name and "("
+                while let Some(Token::Atom(flag)) = tokens.get(index) {
+                    match flag.to_lowercase().as_str() {
+                        "\\seen" => fetched.flags.seen = true,
+                        "\\answered" => fetched.flags.replied = true,
+                        "\\flagged" => fetched.flags.flagged = true,
+                        "\\deleted" => fetched.flags.trashed = true,
+                        "\\draft" => fetched.flags.draft = true,
+                        _ => {}
+                    }
+                    index += 1;
+                }
+                index += 1; // ")"
+            }
+            "INTERNALDATE" => {
+                if let Some(Token::Quoted(value)) = tokens.get(index + 1) {
+                    fetched.internal_date = DateTime::parse_from_str(value.trim(), "%d-%b-%Y %H:%M:%S %z")
+                        .ok()
+                        .map(|date| date.with_timezone(&Utc));
+                }
+                index += 2;
+            }
+            "BODY[]" => {
+                fetched.body = match tokens.get(index + 1) {
+                    Some(Token::Literal(literal)) => response.literals.get(*literal).cloned().unwrap_or_default(),
+                    Some(Token::Quoted(value)) => value.clone().into_bytes(),
+                    _ => Vec::new(),
+                };
+                index += 2;
+            }
+            _ => index += 1,
+        }
+    }
+
+    fetched
+}
+
+/// Split a response into atoms, quoted strings, literal references and parentheses
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut literal_index = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        _ => value.push(c),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            '{' => {
+                // Literal marker "{n}" left by read_response
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+                tokens.push(Token::Literal(literal_index));
+                literal_index += 1;
+            }
+            _ => {
+                let mut atom = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == ' ' || c == '(' || c == ')' {
+                        break;
+                    }
+                    atom.push(c);
+                    chars.next();
+                    // Keep section specs like BODY[HEADER.FIELDS (FROM)] in one atom
+                    if c == '[' {
+                        for c in chars.by_ref() {
+                            atom.push(c);
+                            if c == ']' {
+                                break;
+                            }
+                        }
+                    }
+                }
+                tokens.push(Token::Atom(atom));
+            }
+        }
+    }
+
+    tokens
+}
+
+fn token_string(token: &Token, literals: &[Vec<u8>]) -> Option<String> {
+    match token {
+        Token::Atom(atom) if !atom.eq_ignore_ascii_case("NIL") => Some(atom.clone()),
+        Token::Quoted(value) => Some(value.clone()),
+        Token::Literal(index) => literals.get(*index).map(|literal| String::from_utf8_lossy(literal).to_string()),
+        _ => None,
+    }
+}
+
+/// Length of a literal announced at the end of a line ("... {123}")
+fn literal_length(line: &[u8]) -> Option<usize> {
+    let line = std::str::from_utf8(line).ok()?;
+    let open = line.strip_suffix('}')?.rfind('{')?;
+    line[open + 1..line.len() - 1].trim_end_matches('+').parse().ok()
+}
+
+/// Numeric value of a response code such as "[UIDVALIDITY 123]"
+fn response_code_value(text: &str, code: &str) -> Option<u32> {
+    let start = text.find(&format!("[{} ", code))? + code.len() + 2;
+    let end = text[start..].find(']')? + start;
+    text[start..end].trim().parse().ok()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn sync_key(config: &ImapConfig, mailbox: &str) -> String {
+    format!("{}@{}/{}", config.username, config.host, mailbox)
+}
+
+fn tagged_error(tag: &str, text: &str) -> MailSourceError {
+    let message = text.strip_prefix(tag).unwrap_or(text).trim().to_string();
+    MailSourceError::ProtocolError(message)
+}
+
+fn connection_error(error: std::io::Error) -> MailSourceError {
+    MailSourceError::ConnectionError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    /// Mailbox served by the stub: (name, UIDVALIDITY, [(UID, flags, raw message)])
+    type StubMailbox = (&'static str, u32, Vec<(u32, &'static str, String)>);
+
+    /// In-process IMAP server that understands just enough of the protocol for ImapSource
+    struct StubServer {
+        port: u16,
+        fetch_commands: Arc<AtomicUsize>,
+    }
+
+    impl StubServer {
+        fn start(mailboxes: Vec<StubMailbox>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let fetch_commands = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::clone(&fetch_commands);
+            let mailboxes = Arc::new(mailboxes);
+
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else { break };
+                    let mailboxes = Arc::clone(&mailboxes);
+                    let counter = Arc::clone(&counter);
+                    std::thread::spawn(move || serve(stream, &mailboxes, &counter));
+                }
+            });
+
+            Self { port, fetch_commands }
+        }
+
+        fn config(&self, password: &str) -> ImapConfig {
+            ImapConfig {
+                host: "127.0.0.1".to_string(),
+                port: self.port,
+                username: "archiv".to_string(),
+                password: password.to_string(),
+                security: ImapSecurity::Plain,
+                folders: Vec::new(),
+                batch_size: 2,
+            }
+        }
+    }
+
+    fn serve(stream: TcpStream, mailboxes: &[StubMailbox], fetch_commands: &AtomicUsize) {
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut selected: Option<usize> = None;
+        writer.write_all(b"* OK IMAP4rev1 stub ready\r\n").unwrap();
+
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            let (tag, command) = line.trim_end().split_once(' ').unwrap_or(("*", ""));
+            let tag = tag.to_string();
+            let upper = command.to_uppercase();
+            let mut out = String::new();
+
+            if upper == "CAPABILITY" {
+                out.push_str("* CAPABILITY IMAP4rev1 UIDPLUS\r\n");
+            } else if upper.starts_with("LOGIN") {
+                let username = read_literal(&mut reader, &mut writer, command);
+                let mut rest = String::new();
+                reader.read_line(&mut rest).unwrap();
+                let password = read_literal(&mut reader, &mut writer, &rest);
+                let mut end = String::new();
+                reader.read_line(&mut end).unwrap();
+
+                if username != "archiv" || password != "geheim" {
+                    writer.write_all(format!("{} NO [AUTHENTICATIONFAILED] Invalid credentials\r\n", tag).as_bytes()).unwrap();
+                    line.clear();
+                    continue;
+                }
+            } else if upper.starts_with("LIST") {
+                out.push_str("* LIST (\\Noselect) \"/\" \"Archiv\"\r\n");
+                for (name, _, _) in mailboxes {
+                    out.push_str(&format!("* LIST (\\HasNoChildren) \"/\" \"{}\"\r\n", name));
+                }
+            } else if upper.starts_with("EXAMINE") {
+                let name = command[8..].trim_matches('"');
+                selected = mailboxes.iter().position(|(mailbox, _, _)| *mailbox == name);
+                let (_, uid_validity, messages) = &mailboxes[selected.unwrap()];
+                out.push_str(&format!("* {} EXISTS\r\n* OK [UIDVALIDITY {}] UIDs valid\r\n", messages.len(), uid_validity));
+            } else if upper.starts_with("UID SEARCH UID ") {
+                let from: u32 = command[15..].trim_end_matches(":*").parse().unwrap();
+                let messages = &mailboxes[selected.unwrap()].2;
+                let mut uids: Vec<u32> = messages.iter().map(|(uid, _, _)| *uid).filter(|&uid| uid >= from).collect();
+                if uids.is_empty() {
+                    uids.extend(messages.iter().map(|(uid, _, _)| *uid).max());
+                }
+                let list: Vec<String> = uids.iter().map(u32::to_string).collect();
+                out.push_str(&format!("* SEARCH {}\r\n", list.join(" ")));
+            } else if upper.starts_with("UID FETCH ") {
+                fetch_commands.fetch_add(1, Ordering::SeqCst);
+                let set = command[10..].split(' ').next().unwrap();
+                let messages = &mailboxes[selected.unwrap()].2;
+                for uid in set.split(',').map(|uid| uid.parse::<u32>().unwrap()) {
+                    let Some(position) = messages.iter().position(|(message_uid, _, _)| *message_uid == uid) else {
+                        continue;
+                    };
+                    let (_, flags, raw) = &messages[position];
+                    out.push_str(&format!(
+                        "* {} FETCH (UID {} FLAGS ({}) INTERNALDATE \" 2-Jan-2024 10:00:00 +0100\" BODY[] {{{}}}\r\n{})\r\n",
+                        position + 1,
+                        uid,
+                        flags,
+                        raw.len(),
+                        raw
+                    ));
+                }
+            } else if upper == "LOGOUT" {
+                out.push_str("* BYE Logging out\r\n");
+            } else {
+                writer.write_all(format!("{} BAD Unknown command\r\n", tag).as_bytes()).unwrap();
+                line.clear();
+                continue;
+            }
+
+            out.push_str(&format!("{} OK Done\r\n", tag));
+            if writer.write_all(out.as_bytes()).is_err() || upper == "LOGOUT" {
+                return;
+            }
+            line.clear();
+        }
+    }
+
+    /// Answer a literal announcement ("{n}") with a continuation and read the literal
+    fn read_literal(reader: &mut BufReader<TcpStream>, writer: &mut TcpStream, announcement: &str) -> String {
+        let len: usize = announcement.trim_end().rsplit('{').next().unwrap().trim_end_matches('}').parse().unwrap();
+        writer.write_all(b"+ Ready\r\n").unwrap();
+        let mut value = vec![0u8; len];
+        reader.read_exact(&mut value).unwrap();
+        String::from_utf8(value).unwrap()
+    }
+
+    fn message(subject: &str) -> String {
+        format!("Subject: {}\r\nFrom: a@example.com\r\n\r\nInhalt\r\n", subject)
+    }
+
+    fn standard_mailboxes() -> Vec<StubMailbox> {
+        vec![
+            ("INBOX", 7, vec![
+                (3, "\\Seen", message("Eins")),
+                (5, "\\Seen \\Answered", message("Zwei")),
+                (9, "", message("Drei")),
+            ]),
+            ("Kunden/Entw&APw-rfe", 11, vec![(1, "\\Draft \\Flagged", message("Entwurf"))]),
+        ]
+    }
+
+    #[test]
+    fn test_fetch_all_folders_in_batches() {
+        let server = StubServer::start(standard_mailboxes());
+        let source = ImapSource::connect(server.config("geheim"), None).unwrap();
+
+        assert!(source.supports_uidplus());
+        assert_eq!(source.email_count().unwrap(), 4);
+        let folders = source.folders().unwrap();
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders[1].path, "Kunden/Entwürfe");
+        assert_eq!(folders[1].name, "Entwürfe");
+
+        let emails: Vec<Email> = source.emails().unwrap().map(|email| email.unwrap()).collect();
+        let subjects: Vec<&str> = emails.iter().map(|email| email.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["Eins", "Zwei", "Drei", "Entwurf"]);
+        assert!(emails[1].flags.seen && emails[1].flags.replied);
+        assert!(emails[3].flags.draft && emails[3].flags.flagged);
+        assert_eq!(emails[3].folder.as_deref(), Some("Kunden/Entwürfe"));
+        // No Date header: INTERNALDATE is used
+        assert_eq!(emails[0].date, DateTime::parse_from_rfc3339("2024-01-02T09:00:00Z").unwrap());
+
+        // INBOX: 3 messages in batches of 2, Entwürfe: 1 message
+        assert_eq!(server.fetch_commands.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_rerun_fetches_only_new_messages() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join(IMAP_SYNC_STATE_FILE);
+
+        let server = StubServer::start(standard_mailboxes());
+        let source = ImapSource::connect(server.config("geheim"), Some(state_path.clone())).unwrap();
+        assert_eq!(source.emails().unwrap().count(), 4);
+        source.finish().unwrap();
+        drop(source);
+
+        let mut mailboxes = standard_mailboxes();
+        mailboxes[0].2.push((12, "", message("Vier")));
+        let server = StubServer::start(mailboxes);
+        let source = ImapSource::connect(server.config("geheim"), Some(state_path.clone())).unwrap();
+        let emails: Vec<Email> = source.emails().unwrap().map(|email| email.unwrap()).collect();
+
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Vier");
+        let state_file = std::fs::read_to_string(&state_path).unwrap();
+        assert!(!state_file.contains("geheim"));
+    }
+
+    #[test]
+    fn test_unreadable_message_stops_uid_bookkeeping() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join(IMAP_SYNC_STATE_FILE);
+        let mut mailboxes = standard_mailboxes();
+        mailboxes[0].2[1].2 = " Defekt\r\n\r\nInhalt\r\n".to_string();
+
+        let server = StubServer::start(mailboxes.clone());
+        let source = ImapSource::connect(server.config("geheim"), Some(state_path.clone())).unwrap();
+        let results: Vec<MailSourceResult<Email>> = source.emails().unwrap().collect();
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        source.finish().unwrap();
+        source.close().unwrap();
+        source.close().unwrap();
+
+        // "Drei" was archived after the failure, but the next run fetches from the unreadable message on
+        let server = StubServer::start(mailboxes);
+        let source = ImapSource::connect(server.config("geheim"), Some(state_path)).unwrap();
+        let subjects: Vec<String> = source.emails().unwrap().filter_map(Result::ok).map(|email| email.subject).collect();
+        assert_eq!(subjects, vec!["Drei"]);
+        assert_eq!(source.email_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_changed_uid_validity_fetches_everything_again() {
+        let mut state = ImapSyncState::default();
+        state.record("key", 7, 40);
+
+        assert_eq!(state.last_uid("key", 7), 40);
+        assert_eq!(state.last_uid("key", 8), 0);
+        state.record("key", 8, 3);
+        assert_eq!(state.last_uid("key", 8), 3);
+    }
+
+    #[test]
+    fn test_wrong_password_is_rejected() {
+        let server = StubServer::start(standard_mailboxes());
+
+        match ImapSource::connect(server.config("falsch"), None) {
+            Err(MailSourceError::AuthenticationFailed(message)) => assert!(message.contains("Invalid credentials")),
+            other => panic!("Expected AuthenticationFailed error, got {:?}", other.map(|source| source.source_name())),
+        }
+    }
+
+    #[test]
+    fn test_password_not_serialized() {
+        let config = StubServer { port: 993, fetch_commands: Arc::new(AtomicUsize::new(0)) }.config("geheim");
+
+        assert!(!serde_json::to_string(&config).unwrap().contains("geheim"));
+        assert!(!format!("{:?}", config).contains("geheim"));
+    }
+}
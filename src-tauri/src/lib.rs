@@ -1,26 +1,89 @@
 // Module declarations
 pub mod commands;
 pub mod pst_processor;
+pub mod pst_reader;
+pub mod pst_ndb;
+pub mod pst_ltp;
 pub mod pdf_generator;
+pub mod pdf_fonts;
+pub mod pdf_images;
+pub mod pdf_attachments;
+pub mod pdf_archive;
+pub mod pdf_outline;
+pub mod pdf_metadata;
+pub mod text_layout;
+pub mod html_layout;
 pub mod errors;
 pub mod types;
 pub mod directory_validator;
+pub mod thread_builder;
+pub mod mail_source;
+pub mod mime_parser;
+pub mod mbox_reader;
+pub mod eml_reader;
+pub mod mapi;
+pub mod msg_reader;
+pub mod maildir_reader;
+pub mod imap_source;
+pub mod batch_job;
+pub mod dedup;
+pub mod near_duplicates;
+pub mod virtual_mailbox;
+pub mod mailbox_diff;
+#[cfg(test)]
+mod test_support;
 
 // Re-export modules for external use
 pub use commands::*;
 pub use pst_processor::*;
+pub use pst_reader::*;
+pub use pst_ndb::*;
+pub use pst_ltp::*;
 pub use pdf_generator::*;
+pub use pdf_fonts::*;
+pub use pdf_images::*;
+pub use pdf_attachments::*;
+pub use pdf_archive::*;
+pub use pdf_outline::*;
+pub use pdf_metadata::*;
+pub use text_layout::*;
+pub use html_layout::*;
 pub use errors::*;
 pub use types::*;
 pub use directory_validator::*;
+pub use thread_builder::*;
+pub use mail_source::*;
+pub use mime_parser::*;
+pub use mbox_reader::*;
+pub use eml_reader::*;
+pub use mapi::*;
+pub use msg_reader::*;
+pub use maildir_reader::*;
+pub use imap_source::*;
+pub use batch_job::*;
+pub use dedup::*;
+pub use near_duplicates::*;
+pub use virtual_mailbox::*;
+pub use mailbox_diff::*;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    use tauri::Manager;
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            // Bundled fonts ship as resources; without them PDFs fall back to Helvetica
+            match app.path().resource_dir() {
+                Ok(directory) => pdf_fonts::set_resource_dir(directory),
+                Err(e) => eprintln!("Warning: Resource directory not available: {}", e),
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::validate_pst_file,
+            commands::validate_mail_source,
             commands::start_processing,
             commands::get_processing_progress,
             commands::cancel_processing,
@@ -28,7 +91,12 @@ pub fn run() {
             commands::get_processing_session,
             commands::cleanup_session,
             commands::validate_directory,
-            commands::get_directory_info
+            commands::get_directory_info,
+            commands::get_conversation_threads,
+            commands::list_imap_folders,
+            commands::get_mail_folders,
+            commands::browse_mail_folder,
+            commands::compare_mail_sources
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
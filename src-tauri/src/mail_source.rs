@@ -0,0 +1,261 @@
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::eml_reader::EmlReader;
+use crate::maildir_reader::MaildirReader;
+use crate::mbox_reader::MboxReader;
+use crate::msg_reader::MsgReader;
+use crate::pst_processor::PstProcessor;
+use crate::pst_reader::DEFAULT_CACHE_BUDGET;
+use crate::types::{Email, MailFolder, ProcessingConfig};
+
+/// Iterator over the emails of a mail source
+pub type EmailIterator<'a> = Box<dyn Iterator<Item = MailSourceResult<Email>> + 'a>;
+
+/// Common interface for every mailbox format the archiver can read
+///
+/// The PDF pipeline only talks to this trait, so new input formats only
+/// need to map their messages onto `types::Email`.
+pub trait MailSource: Send {
+    /// Display name of the source, e.g. the file name
+    fn source_name(&self) -> String;
+
+    /// Total number of emails in the source
+    fn email_count(&self) -> MailSourceResult<usize>;
+
+    /// Folders contained in the source, parents before children
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>>;
+
+    /// Iterate over all emails in source order
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>>;
+
+    /// Called after all emails were archived successfully, e.g. to persist sync state
+    fn finish(&self) -> MailSourceResult<()> {
+        Ok(())
+    }
+
+    /// Release connections held by the source; called once its emails are no longer needed
+    fn close(&self) -> MailSourceResult<()> {
+        Ok(())
+    }
+
+    /// Collect all emails sorted by date (oldest first)
+    /// Unreadable messages are logged and skipped so one bad message does not stop an archive run
+    fn emails_chronological(&self) -> MailSourceResult<Vec<Email>> {
+        let mut emails = Vec::new();
+        for (index, result) in self.emails()?.enumerate() {
+            match result {
+                Ok(email) => emails.push(email),
+                Err(e) => {
+                    eprintln!("Warning: Failed to read email {} from {}: {}", index, self.source_name(), e);
+                }
+            }
+        }
+
+        emails.sort_by_key(|email| email.date);
+        Ok(emails)
+    }
+}
+
+/// File extensions that can be opened with `open_mail_source`
+pub fn supported_source_extensions() -> Vec<&'static str> {
+    let mut extensions = PstProcessor::supported_extensions();
+    extensions.extend(MboxReader::supported_extensions());
+    extensions.extend(EmlReader::supported_extensions());
+    extensions.extend(MsgReader::supported_extensions());
+    extensions
+}
+
+/// Reader settings that `open_mail_source_with` applies to the formats supporting them
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceOptions {
+    /// Byte budget of the PST block and node cache
+    pub pst_cache_budget: usize,
+    /// Worker threads for PST extraction, 0 for one per CPU core
+    pub threads: usize,
+    /// Also archive deleted and orphaned PST messages
+    pub recover_deleted_items: bool,
+}
+
+impl Default for SourceOptions {
+    fn default() -> Self {
+        Self {
+            pst_cache_budget: DEFAULT_CACHE_BUDGET,
+            threads: 0,
+            recover_deleted_items: false,
+        }
+    }
+}
+
+impl SourceOptions {
+    /// Reader settings of a processing run
+    pub fn from_config(config: &ProcessingConfig) -> Self {
+        Self {
+            pst_cache_budget: config.pst_cache_budget_mb as usize * 1024 * 1024,
+            threads: config.threads as usize,
+            recover_deleted_items: config.recover_deleted_items,
+        }
+    }
+}
+
+/// Open the mail source at `path` with the default reader settings
+pub fn open_mail_source(path: &Path) -> MailSourceResult<Box<dyn MailSource>> {
+    open_mail_source_with(path, &SourceOptions::default())
+}
+
+/// Open the mail source at `path`, choosing the reader by file extension
+/// Directories are read as a Maildir if they contain cur/ and new/, otherwise as a tree of EML files
+pub fn open_mail_source_with(path: &Path, options: &SourceOptions) -> MailSourceResult<Box<dyn MailSource>> {
+    if !path.exists() {
+        return Err(MailSourceError::NotFound(path.to_string_lossy().to_string()));
+    }
+
+    if MaildirReader::is_maildir(path) {
+        return Ok(Box::new(MaildirReader::new(path.to_path_buf())?));
+    }
+
+    if path.is_dir() {
+        return Ok(Box::new(EmlReader::new(path.to_path_buf())?));
+    }
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if PstProcessor::supported_extensions().contains(&extension.as_str()) {
+        let mut processor = PstProcessor::with_cache_budget(path.to_path_buf(), options.pst_cache_budget)?;
+        processor.set_thread_count(options.threads);
+        if options.recover_deleted_items {
+            processor.recover_orphaned_items()?;
+        }
+        return Ok(Box::new(processor));
+    }
+
+    if MboxReader::supported_extensions().contains(&extension.as_str()) {
+        return Ok(Box::new(MboxReader::new(path.to_path_buf())?));
+    }
+
+    if EmlReader::supported_extensions().contains(&extension.as_str()) {
+        return Ok(Box::new(EmlReader::new(path.to_path_buf())?));
+    }
+
+    if MsgReader::supported_extensions().contains(&extension.as_str()) {
+        return Ok(Box::new(MsgReader::new(path.to_path_buf())?));
+    }
+
+    Err(MailSourceError::UnsupportedFormat(format!(
+        "Dateityp wird nicht unterstützt: {}. Unterstützte Formate: {}",
+        path.display(),
+        supported_source_extensions().join(", ")
+    )))
+}
+
+/// Modification time of a file, the last resort for messages without any date
+pub fn file_modified_date(path: &Path) -> Option<DateTime<Utc>> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok().map(DateTime::<Utc>::from)
+}
+
+/// Decode an IMAP modified UTF-7 folder name (RFC 3501 5.1.3), also used by Maildir++
+/// Invalid sequences are kept as they are
+pub fn decode_modified_utf7(name: &str) -> String {
+    use base64::Engine;
+
+    let mut result = String::new();
+    let mut rest = name;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('-') else {
+            result.push_str(&rest[start..]);
+            return result;
+        };
+
+        let encoded = &after[..end];
+        if encoded.is_empty() {
+            result.push('&');
+        } else {
+            let decoded = base64::engine::general_purpose::STANDARD_NO_PAD
+                .decode(encoded.replace(',', "/"))
+                .ok()
+                .filter(|bytes| bytes.len() % 2 == 0)
+                .and_then(|bytes| {
+                    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                    String::from_utf16(&units).ok()
+                });
+            match decoded {
+                Some(text) => result.push_str(&text),
+                None => result.push_str(&rest[start..start + end + 2]),
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_supported_source_extensions() {
+        let extensions = supported_source_extensions();
+        assert!(extensions.contains(&"pst"));
+        assert!(extensions.contains(&"mbox"));
+        assert!(extensions.contains(&"eml"));
+        assert!(extensions.contains(&"msg"));
+    }
+
+    #[test]
+    fn test_decode_modified_utf7() {
+        assert_eq!(decode_modified_utf7("Entw&APw-rfe"), "Entwürfe");
+        assert_eq!(decode_modified_utf7("Gel&APY-schte Elemente"), "Gelöschte Elemente");
+        assert_eq!(decode_modified_utf7("Tom &- Jerry"), "Tom & Jerry");
+        assert_eq!(decode_modified_utf7("&ZeVnLIqe-"), "日本語");
+        assert_eq!(decode_modified_utf7("kaputt&"), "kaputt&");
+    }
+
+    #[test]
+    fn test_open_unsupported_source() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "not a mailbox").unwrap();
+
+        match open_mail_source(&path) {
+            Err(MailSourceError::UnsupportedFormat(_)) => {}
+            other => panic!("Expected UnsupportedFormat error, got {:?}", other.map(|s| s.source_name())),
+        }
+    }
+
+    #[test]
+    fn test_open_mbox_source_sorts_chronologically() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("Archiv.mbox");
+        std::fs::write(
+            &path,
+            concat!(
+                "From a@example.com Tue Jan  2 10:00:00 2024\n",
+                "Subject: Zweite\n",
+                "Date: Tue, 2 Jan 2024 10:00:00 +0000\n",
+                "\n",
+                "B\n",
+                "\n",
+                "From a@example.com Mon Jan  1 10:00:00 2024\n",
+                "Subject: Erste\n",
+                "Date: Mon, 1 Jan 2024 10:00:00 +0000\n",
+                "\n",
+                "A\n",
+            ),
+        )
+        .unwrap();
+
+        let source = open_mail_source(&path).unwrap();
+        let emails = source.emails_chronological().unwrap();
+
+        assert_eq!(source.email_count().unwrap(), 2);
+        assert_eq!(emails[0].subject, "Erste");
+        assert_eq!(emails[1].subject, "Zweite");
+    }
+}
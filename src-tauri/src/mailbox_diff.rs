@@ -0,0 +1,366 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::dedup::{fields_hash, normalize_text, sender_address, Deduplicator, DuplicateMatch};
+use crate::errors::MailSourceResult;
+use crate::mail_source::MailSource;
+use crate::types::{Email, MessageFlags};
+
+/// How an item differs between the old and the new mailbox
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DiffStatus {
+    /// Only in the new mailbox
+    Added,
+    /// Only in the old mailbox
+    Removed,
+    /// In both mailboxes with different folder, content or flags
+    Modified,
+}
+
+/// A single changed item
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DiffItem {
+    /// Kind of change
+    pub status: DiffStatus,
+
+    /// Subject of the email
+    pub subject: String,
+
+    /// Sender of the email
+    pub sender: String,
+
+    /// Sent date of the email
+    pub date: DateTime<Utc>,
+
+    /// How the item was matched across the two mailboxes
+    pub matched_by: DuplicateMatch,
+
+    /// Changed properties of modified items (in German)
+    pub changes: Vec<String>,
+}
+
+/// Changed items of one folder
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FolderDiff {
+    /// Folder path; modified items are listed under their new folder, items in several folders under the first
+    pub folder: String,
+
+    /// Changed items, oldest first
+    pub items: Vec<DiffItem>,
+}
+
+impl FolderDiff {
+    /// Number of items in this folder with the given status
+    pub fn count(&self, status: DiffStatus) -> usize {
+        self.items.iter().filter(|item| item.status == status).count()
+    }
+}
+
+/// Differences between an old and a new copy of a mailbox
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MailboxDiff {
+    /// Display name of the old mailbox
+    pub old_source: String,
+
+    /// Display name of the new mailbox
+    pub new_source: String,
+
+    /// Number of items present unchanged in both mailboxes
+    pub unchanged: usize,
+
+    /// Folders with at least one change, sorted by path
+    pub folders: Vec<FolderDiff>,
+}
+
+/// What is compared of an item, without keeping the whole email in memory
+#[derive(Debug, Clone)]
+struct ItemFingerprint {
+    /// Every folder holding a copy of the item, sorted
+    folders: Vec<String>,
+    subject: String,
+    sender: String,
+    date: DateTime<Utc>,
+    body_hash: u64,
+    attachments: Vec<String>,
+    flags: MessageFlags,
+    matched_by: DuplicateMatch,
+}
+
+impl ItemFingerprint {
+    fn new(email: &Email, matched_by: DuplicateMatch) -> Self {
+        let mut hasher = DefaultHasher::new();
+        email.body.split_whitespace().for_each(|word| word.hash(&mut hasher));
+
+        Self {
+            folders: vec![email.folder.clone().unwrap_or_default()],
+            subject: email.subject.clone(),
+            sender: email.sender.clone(),
+            date: email.date,
+            body_hash: hasher.finish(),
+            attachments: email.attachments.iter().map(|attachment| attachment.name.clone()).collect(),
+            flags: email.flags,
+            matched_by,
+        }
+    }
+
+    /// Record another copy of the item
+    fn add_folder(&mut self, email: &Email) {
+        let folder = email.folder.clone().unwrap_or_default();
+        if let Err(position) = self.folders.binary_search(&folder) {
+            self.folders.insert(position, folder);
+        }
+    }
+
+    /// Folder the item is listed under in a diff
+    fn folder(&self) -> &str {
+        &self.folders[0]
+    }
+
+    /// Names of the properties that differ from `old`
+    fn changes_since(&self, old: &ItemFingerprint) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.folders != old.folders {
+            changes.push(format!("Ordner: {} → {}", display_folders(&old.folders), display_folders(&self.folders)));
+        }
+        if self.subject != old.subject {
+            changes.push("Betreff".to_string());
+        }
+        if self.body_hash != old.body_hash {
+            changes.push("Inhalt".to_string());
+        }
+        if self.attachments != old.attachments {
+            changes.push("Anhänge".to_string());
+        }
+        if self.flags != old.flags {
+            changes.push("Markierungen".to_string());
+        }
+        changes
+    }
+
+    fn diff_item(&self, status: DiffStatus, changes: Vec<String>) -> DiffItem {
+        DiffItem {
+            status,
+            subject: self.subject.clone(),
+            sender: self.sender.clone(),
+            date: self.date,
+            matched_by: self.matched_by,
+            changes,
+        }
+    }
+}
+
+/// Identity of an item across two copies of a mailbox
+///
+/// Items without Message-ID are matched by date, sender and subject rather than
+/// by a hash over the body, so an edited body shows as a modification instead
+/// of a removed and an added item. Different items that share all three
+/// headers are treated as copies of one item.
+fn item_identity(email: &Email) -> (String, DuplicateMatch) {
+    match Deduplicator::message_id_identity(email) {
+        Some(identity) => (identity, DuplicateMatch::MessageId),
+        None => {
+            let hash = fields_hash(email, &[sender_address(&email.sender), normalize_text(&email.subject)]);
+            (format!("headers:{}", hash), DuplicateMatch::Headers)
+        }
+    }
+}
+
+/// Fingerprints of all items of a mailbox, keyed by Message-ID or date, sender and subject
+pub struct MailboxSnapshot {
+    items: HashMap<String, ItemFingerprint>,
+}
+
+impl MailboxSnapshot {
+    /// Read all emails of `source`; unreadable messages are skipped like during archiving
+    /// Copies of an item in several folders are combined into one item
+    pub fn read(source: &dyn MailSource) -> MailSourceResult<Self> {
+        let mut items: HashMap<String, ItemFingerprint> = HashMap::new();
+        for email in source.emails_chronological()? {
+            let (identity, matched_by) = item_identity(&email);
+            items
+                .entry(identity)
+                .and_modify(|item| item.add_folder(&email))
+                .or_insert_with(|| ItemFingerprint::new(&email, matched_by));
+        }
+        Ok(Self { items })
+    }
+
+    /// Keep only the emails that are new or modified compared to this snapshot
+    pub fn delta(&self, emails: Vec<Email>) -> Vec<Email> {
+        emails
+            .into_iter()
+            .filter(|email| {
+                let (identity, matched_by) = item_identity(email);
+                match self.items.get(&identity) {
+                    // A copy in one of the known folders is unchanged even if the item has several
+                    Some(old) => {
+                        let mut fingerprint = ItemFingerprint::new(email, matched_by);
+                        if old.folders.contains(&fingerprint.folders[0]) {
+                            fingerprint.folders = old.folders.clone();
+                        }
+                        !fingerprint.changes_since(old).is_empty()
+                    }
+                    None => true,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Compare two mailboxes item by item
+pub fn compare_mailboxes(old: &dyn MailSource, new: &dyn MailSource) -> MailSourceResult<MailboxDiff> {
+    let old_snapshot = MailboxSnapshot::read(old)?;
+    let new_snapshot = MailboxSnapshot::read(new)?;
+
+    let mut folders: BTreeMap<String, Vec<DiffItem>> = BTreeMap::new();
+    let mut unchanged = 0;
+
+    for (identity, item) in &new_snapshot.items {
+        match old_snapshot.items.get(identity) {
+            None => folders.entry(item.folder().to_string()).or_default().push(item.diff_item(DiffStatus::Added, Vec::new())),
+            Some(old_item) => {
+                let changes = item.changes_since(old_item);
+                if changes.is_empty() {
+                    unchanged += 1;
+                } else {
+                    folders.entry(item.folder().to_string()).or_default().push(item.diff_item(DiffStatus::Modified, changes));
+                }
+            }
+        }
+    }
+
+    for (identity, item) in &old_snapshot.items {
+        if !new_snapshot.items.contains_key(identity) {
+            folders.entry(item.folder().to_string()).or_default().push(item.diff_item(DiffStatus::Removed, Vec::new()));
+        }
+    }
+
+    Ok(MailboxDiff {
+        old_source: old.source_name(),
+        new_source: new.source_name(),
+        unchanged,
+        folders: folders
+            .into_iter()
+            .map(|(folder, mut items)| {
+                items.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.subject.cmp(&b.subject)));
+                FolderDiff { folder, items }
+            })
+            .collect(),
+    })
+}
+
+fn display_folders(folders: &[String]) -> String {
+    folders
+        .iter()
+        .map(|folder| if folder.is_empty() { "(Stammordner)" } else { folder.as_str() })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eml_reader::EmlReader;
+    use tempfile::tempdir;
+
+    fn write_eml(dir: &std::path::Path, name: &str, message_id: &str, body: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join(name),
+            format!(
+                "Subject: {}\nMessage-ID: <{}>\nDate: Mon, 1 Jan 2024 10:00:00 +0000\n\n{}\n",
+                name, message_id, body
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_reports_added_removed_and_modified_per_folder() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        write_eml(old.path(), "gleich.eml", "same@example.com", "Text");
+        write_eml(old.path(), "geloescht.eml", "gone@example.com", "Text");
+        write_eml(old.path(), "verschoben.eml", "moved@example.com", "Text");
+        write_eml(new.path(), "gleich.eml", "same@example.com", "Text");
+        write_eml(new.path(), "neu.eml", "new@example.com", "Text");
+        write_eml(&new.path().join("Erledigt"), "verschoben.eml", "moved@example.com", "Text geändert");
+
+        let old_reader = EmlReader::new(old.path().to_path_buf()).unwrap();
+        let new_reader = EmlReader::new(new.path().to_path_buf()).unwrap();
+        let diff = compare_mailboxes(&old_reader, &new_reader).unwrap();
+
+        assert_eq!(diff.unchanged, 1);
+        let folders: Vec<&str> = diff.folders.iter().map(|folder| folder.folder.as_str()).collect();
+        assert_eq!(folders, vec!["", "Erledigt"]);
+
+        let root = &diff.folders[0];
+        assert_eq!(root.count(DiffStatus::Added), 1);
+        assert_eq!(root.count(DiffStatus::Removed), 1);
+
+        let moved = &diff.folders[1].items[0];
+        assert_eq!(moved.status, DiffStatus::Modified);
+        assert_eq!(moved.changes, vec!["Ordner: (Stammordner) → Erledigt".to_string(), "Inhalt".to_string()]);
+    }
+
+    #[test]
+    fn test_copies_in_several_folders_are_compared_as_a_set() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        write_eml(old.path(), "kopie.eml", "copy@example.com", "Text");
+        write_eml(&old.path().join("Archiv"), "kopie.eml", "copy@example.com", "Text");
+        write_eml(&new.path().join("Archiv"), "kopie.eml", "copy@example.com", "Text");
+        write_eml(new.path(), "kopie.eml", "copy@example.com", "Text");
+        write_eml(old.path(), "einmal.eml", "once@example.com", "Text");
+        write_eml(&old.path().join("Archiv"), "einmal.eml", "once@example.com", "Text");
+        write_eml(new.path(), "einmal.eml", "once@example.com", "Text");
+
+        let old_reader = EmlReader::new(old.path().to_path_buf()).unwrap();
+        let new_reader = EmlReader::new(new.path().to_path_buf()).unwrap();
+        let diff = compare_mailboxes(&old_reader, &new_reader).unwrap();
+
+        assert_eq!(diff.unchanged, 1);
+        let item = &diff.folders[0].items[0];
+        assert_eq!(item.subject, "einmal.eml");
+        assert_eq!(item.changes, vec!["Ordner: (Stammordner), Archiv → (Stammordner)".to_string()]);
+    }
+
+    #[test]
+    fn test_edited_body_without_message_id_is_modified() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        let raw = |body: &str| format!("Subject: Notiz\nFrom: a@example.com\nDate: Mon, 1 Jan 2024 10:00:00 +0000\n\n{}\n", body);
+        std::fs::write(old.path().join("notiz.eml"), raw("Entwurf")).unwrap();
+        std::fs::write(new.path().join("notiz.eml"), raw("Entwurf, ergänzt")).unwrap();
+
+        let old_reader = EmlReader::new(old.path().to_path_buf()).unwrap();
+        let new_reader = EmlReader::new(new.path().to_path_buf()).unwrap();
+        let diff = compare_mailboxes(&old_reader, &new_reader).unwrap();
+
+        let items = &diff.folders[0].items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].status, DiffStatus::Modified);
+        assert_eq!(items[0].matched_by, DuplicateMatch::Headers);
+        assert_eq!(items[0].changes, vec!["Inhalt".to_string()]);
+    }
+
+    #[test]
+    fn test_delta_keeps_new_and_modified_emails() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        write_eml(old.path(), "gleich.eml", "same@example.com", "Text");
+        write_eml(old.path(), "anders.eml", "changed@example.com", "Alt");
+        write_eml(new.path(), "gleich.eml", "same@example.com", "Text");
+        write_eml(new.path(), "anders.eml", "changed@example.com", "Neu");
+        write_eml(new.path(), "neu.eml", "new@example.com", "Text");
+
+        let snapshot = MailboxSnapshot::read(&EmlReader::new(old.path().to_path_buf()).unwrap()).unwrap();
+        let emails = EmlReader::new(new.path().to_path_buf()).unwrap().emails_chronological().unwrap();
+        let mut subjects: Vec<String> = snapshot.delta(emails).into_iter().map(|email| email.subject).collect();
+        subjects.sort();
+
+        assert_eq!(subjects, vec!["anders.eml", "neu.eml"]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::{decode_modified_utf7, EmailIterator, MailSource};
+use crate::mime_parser::MimeParser;
+use crate::types::{Email, MailFolder, MessageFlags};
+
+/// Folder name used for the messages in the Maildir root
+const INBOX: &str = "INBOX";
+
+/// A message file with the metadata encoded in its location and name
+#[derive(Debug, Clone)]
+struct MaildirMessage {
+    path: PathBuf,
+    folder: String,
+    flags: MessageFlags,
+    delivery_date: Option<DateTime<Utc>>,
+}
+
+/// Reader for Maildir and Maildir++ trees
+///
+/// The root maildir becomes "INBOX"; Maildir++ subfolders (".Work.Projects")
+/// become "/"-separated folder paths ("Work/Projects"). Messages in `tmp/` are
+/// still being delivered and are skipped.
+pub struct MaildirReader {
+    root: PathBuf,
+    messages: Vec<MaildirMessage>,
+}
+
+impl MaildirReader {
+    /// Open a Maildir and index the messages of all its folders
+    pub fn new(root: PathBuf) -> MailSourceResult<Self> {
+        if !root.exists() {
+            return Err(MailSourceError::NotFound(root.to_string_lossy().to_string()));
+        }
+        if !Self::is_maildir(&root) {
+            return Err(MailSourceError::InvalidFormat(
+                "Verzeichnis ist kein Maildir (cur/ und new/ fehlen)".to_string(),
+            ));
+        }
+
+        let mut messages = Vec::new();
+        Self::collect_messages(&root, INBOX, &mut messages)?;
+
+        let mut subfolders: Vec<(String, PathBuf)> = read_dir_sorted(&root)?
+            .into_iter()
+            .filter_map(|path| {
+                let name = path.file_name()?.to_string_lossy().to_string();
+                let folder = name.strip_prefix('.').filter(|folder| !folder.is_empty() && *folder != ".")?;
+                Self::is_maildir(&path).then(|| (Self::folder_path(folder), path))
+            })
+            .collect();
+        subfolders.sort();
+
+        for (folder, path) in subfolders {
+            Self::collect_messages(&path, &folder, &mut messages)?;
+        }
+
+        Ok(Self { root, messages })
+    }
+
+    /// Check whether `path` looks like a maildir (has `cur/` and `new/`)
+    pub fn is_maildir(path: &Path) -> bool {
+        path.join("cur").is_dir() && path.join("new").is_dir()
+    }
+
+    /// Convert a Maildir++ folder name (".Work.Projects" without the dot) to "Work/Projects"
+    fn folder_path(name: &str) -> String {
+        name.split('.').map(decode_modified_utf7).collect::<Vec<_>>().join("/")
+    }
+
+    fn collect_messages(maildir: &Path, folder: &str, messages: &mut Vec<MaildirMessage>) -> MailSourceResult<()> {
+        for subdirectory in ["new", "cur"] {
+            for path in read_dir_sorted(&maildir.join(subdirectory))? {
+                let Some(file_name) = path.file_name().map(|name| name.to_string_lossy().to_string()) else {
+                    continue;
+                };
+                if file_name.starts_with('.') || !path.is_file() {
+                    continue;
+                }
+
+                messages.push(MaildirMessage {
+                    flags: parse_flags(&file_name),
+                    delivery_date: parse_delivery_date(&file_name),
+                    folder: folder.to_string(),
+                    path,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn read_email(message: &MaildirMessage) -> MailSourceResult<Email> {
+        let raw = std::fs::read(&message.path)?;
+        let mut email = MimeParser::parse_email_with_fallback_date(&raw, message.delivery_date)?;
+        email.folder = Some(message.folder.clone());
+        email.flags = message.flags;
+        Ok(email)
+    }
+}
+
+impl MailSource for MaildirReader {
+    fn source_name(&self) -> String {
+        self.root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.root.to_string_lossy().to_string())
+    }
+
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(self.messages.len())
+    }
+
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for message in &self.messages {
+            if message.folder == INBOX {
+                continue;
+            }
+            let mut parent = String::new();
+            for segment in message.folder.split('/') {
+                if !parent.is_empty() {
+                    parent.push('/');
+                }
+                parent.push_str(segment);
+                counts.entry(parent.clone()).or_insert(0);
+            }
+            *counts.entry(message.folder.clone()).or_insert(0) += 1;
+        }
+
+        let inbox_count = self.messages.iter().filter(|message| message.folder == INBOX).count();
+        let mut folders = vec![MailFolder::new(INBOX.to_string(), INBOX.to_string(), inbox_count)];
+        folders.extend(counts.into_iter().map(|(path, count)| {
+            let name = path.rsplit('/').next().unwrap_or_default().to_string();
+            MailFolder::new(path, name, count)
+        }));
+        Ok(folders)
+    }
+
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        Ok(Box::new(self.messages.iter().map(Self::read_email)))
+    }
+}
+
+fn read_dir_sorted(directory: &Path) -> MailSourceResult<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(directory)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::PermissionDenied => MailSourceError::PermissionDenied(directory.to_string_lossy().to_string()),
+            _ => MailSourceError::IoError(e.to_string()),
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Parse the info suffix ":2,FRS" (or "!2," on Windows) of a maildir file name
+fn parse_flags(file_name: &str) -> MessageFlags {
+    let info = file_name
+        .rsplit_once(":2,")
+        .or_else(|| file_name.rsplit_once("!2,"))
+        .map(|(_, flags)| flags)
+        .unwrap_or_default();
+
+    MessageFlags {
+        seen: info.contains('S'),
+        replied: info.contains('R'),
+        flagged: info.contains('F'),
+        trashed: info.contains('T'),
+        draft: info.contains('D'),
+    }
+}
+
+/// Delivery time from the leading Unix timestamp of a maildir file name
+fn parse_delivery_date(file_name: &str) -> Option<DateTime<Utc>> {
+    let seconds = file_name.split('.').next()?.parse::<i64>().ok()?;
+    DateTime::from_timestamp(seconds, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn create_maildir(path: &Path) {
+        for subdirectory in ["cur", "new", "tmp"] {
+            std::fs::create_dir_all(path.join(subdirectory)).unwrap();
+        }
+    }
+
+    fn write_message(path: &Path, subject: &str) {
+        std::fs::write(path, format!("Subject: {}\n\nText\n", subject)).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)] // ':' is not allowed in Windows file names
+    fn test_folders_and_flags() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        create_maildir(root);
+        create_maildir(&root.join(".Kunden.Entw&APw-rfe"));
+        create_maildir(&root.join(".Sent"));
+
+        write_message(&root.join("new").join("1705311000.M1P1.host"), "Neu");
+        write_message(&root.join("cur").join("1705300000.M2P1.host:2,FRS"), "Gelesen");
+        write_message(&root.join("tmp").join("1705400000.M3P1.host"), "Unvollständig");
+        write_message(&root.join(".Kunden.Entw&APw-rfe").join("cur").join("1705000000.M4P1.host:2,DT"), "Entwurf");
+        write_message(&root.join(".Sent").join("cur").join("1705000001.M5P1.host:2,S"), "Gesendet");
+
+        let reader = MaildirReader::new(root.to_path_buf()).unwrap();
+        let emails: Vec<Email> = reader.emails().unwrap().map(|email| email.unwrap()).collect();
+        let find = |subject: &str| emails.iter().find(|email| email.subject == subject).unwrap();
+
+        assert_eq!(emails.len(), 4);
+        assert_eq!(find("Neu").flags, MessageFlags::default());
+        assert_eq!(find("Neu").date, Utc.timestamp_opt(1_705_311_000, 0).unwrap());
+        assert_eq!(find("Neu").folder.as_deref(), Some("INBOX"));
+
+        let seen = find("Gelesen").flags;
+        assert!(seen.seen && seen.replied && seen.flagged && !seen.trashed);
+
+        let draft = find("Entwurf");
+        assert_eq!(draft.folder.as_deref(), Some("Kunden/Entwürfe"));
+        assert!(draft.flags.draft && draft.flags.trashed);
+
+        let folders = reader.folders().unwrap();
+        let paths: Vec<&str> = folders.iter().map(|folder| folder.path.as_str()).collect();
+        assert_eq!(paths, vec!["INBOX", "Kunden", "Kunden/Entwürfe", "Sent"]);
+        assert_eq!(folders[0].email_count, 2);
+        assert_eq!(folders[1].email_count, 0);
+    }
+
+    #[test]
+    fn test_reject_directory_without_maildir_layout() {
+        let dir = tempdir().unwrap();
+
+        match MaildirReader::new(dir.path().to_path_buf()) {
+            Err(MailSourceError::InvalidFormat(_)) => {}
+            other => panic!("Expected InvalidFormat error, got {:?}", other.map(|reader| reader.source_name())),
+        }
+    }
+
+    #[test]
+    fn test_parse_windows_flag_separator() {
+        let flags = parse_flags("1705311000.M1P1.host!2,ST");
+        assert!(flags.seen && flags.trashed && !flags.replied);
+    }
+}
@@ -0,0 +1,466 @@
+//! MAPI property model shared by the Outlook formats (.msg, PST)
+//!
+//! Readers fill `MapiMessage` with raw property values; the conversion to
+//! `types::Email` lives here so every Outlook format maps properties the same way.
+
+use std::collections::HashMap;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use crate::mime_parser::{received_date, resolve_message_date};
+use crate::types::{Attachment, Email, EmailPriority};
+
+/// Property IDs (upper 16 bits of a property tag) used by the archiver
+pub mod prop {
+    pub const IMPORTANCE: u16 = 0x0017;
+    pub const PRIORITY: u16 = 0x0026;
+    pub const SUBJECT: u16 = 0x0037;
+    pub const CLIENT_SUBMIT_TIME: u16 = 0x0039;
+    pub const SENT_REPRESENTING_NAME: u16 = 0x0042;
+    pub const SENT_REPRESENTING_EMAIL_ADDRESS: u16 = 0x0065;
+    pub const CONVERSATION_INDEX: u16 = 0x0071;
+    pub const TRANSPORT_MESSAGE_HEADERS: u16 = 0x007D;
+    pub const RECIPIENT_TYPE: u16 = 0x0C15;
+    pub const SENDER_NAME: u16 = 0x0C1A;
+    pub const SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+    pub const DISPLAY_BCC: u16 = 0x0E02;
+    pub const DISPLAY_CC: u16 = 0x0E03;
+    pub const DISPLAY_TO: u16 = 0x0E04;
+    pub const MESSAGE_DELIVERY_TIME: u16 = 0x0E06;
+    pub const MESSAGE_SIZE: u16 = 0x0E08;
+    pub const BODY: u16 = 0x1000;
+    pub const HTML: u16 = 0x1013;
+    pub const INTERNET_MESSAGE_ID: u16 = 0x1035;
+    pub const INTERNET_REFERENCES: u16 = 0x1039;
+    pub const IN_REPLY_TO_ID: u16 = 0x1042;
+    pub const DISPLAY_NAME: u16 = 0x3001;
+    pub const EMAIL_ADDRESS: u16 = 0x3003;
+    pub const CREATION_TIME: u16 = 0x3007;
+    pub const ATTACH_DATA: u16 = 0x3701;
+    pub const ATTACH_FILENAME: u16 = 0x3704;
+    pub const ATTACH_METHOD: u16 = 0x3705;
+    pub const ATTACH_LONG_FILENAME: u16 = 0x3707;
+    pub const ATTACH_MIME_TAG: u16 = 0x370E;
+    pub const ATTACH_CONTENT_ID: u16 = 0x3712;
+    pub const ATTACH_FLAGS: u16 = 0x3714;
+    pub const SMTP_ADDRESS: u16 = 0x39FE;
+    pub const SENDER_SMTP_ADDRESS: u16 = 0x5D01;
+    pub const ATTACHMENT_HIDDEN: u16 = 0x7FFE;
+}
+
+/// Property types (lower 16 bits of a property tag)
+pub mod prop_type {
+    pub const INTEGER16: u16 = 0x0002;
+    pub const INTEGER32: u16 = 0x0003;
+    pub const BOOLEAN: u16 = 0x000B;
+    pub const OBJECT: u16 = 0x000D;
+    pub const INTEGER64: u16 = 0x0014;
+    pub const STRING8: u16 = 0x001E;
+    pub const UNICODE: u16 = 0x001F;
+    pub const SYSTIME: u16 = 0x0040;
+    pub const BINARY: u16 = 0x0102;
+}
+
+/// PR_RECIPIENT_TYPE values
+const RECIPIENT_CC: i64 = 2;
+const RECIPIENT_BCC: i64 = 3;
+
+/// PR_ATTACH_FLAGS bit for attachments referenced from the HTML body
+const ATT_MHTML_REF: i64 = 0x4;
+
+/// Nesting limit for messages embedded in messages
+pub const MAX_EMBEDDING_DEPTH: usize = 16;
+
+/// A single decoded property value
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Integer(i64),
+    Boolean(bool),
+    Time(DateTime<Utc>),
+    String(String),
+    Binary(Vec<u8>),
+}
+
+/// Properties of one MAPI object (message, recipient or attachment) keyed by property ID
+#[derive(Debug, Clone, Default)]
+pub struct PropertyBag {
+    values: HashMap<u16, PropertyValue>,
+}
+
+impl PropertyBag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: u16, value: PropertyValue) {
+        self.values.insert(id, value);
+    }
+
+    pub fn get(&self, id: u16) -> Option<&PropertyValue> {
+        self.values.get(&id)
+    }
+
+    /// String value; binary values are decoded as UTF-8 (used for PR_HTML)
+    pub fn string(&self, id: u16) -> Option<String> {
+        match self.get(id)? {
+            PropertyValue::String(value) => Some(value.clone()),
+            PropertyValue::Binary(value) => Some(String::from_utf8_lossy(value).to_string()),
+            _ => None,
+        }
+        .filter(|value| !value.is_empty())
+    }
+
+    pub fn integer(&self, id: u16) -> Option<i64> {
+        match self.get(id)? {
+            PropertyValue::Integer(value) => Some(*value),
+            PropertyValue::Boolean(value) => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn boolean(&self, id: u16) -> Option<bool> {
+        match self.get(id)? {
+            PropertyValue::Boolean(value) => Some(*value),
+            PropertyValue::Integer(value) => Some(*value != 0),
+            _ => None,
+        }
+    }
+
+    pub fn time(&self, id: u16) -> Option<DateTime<Utc>> {
+        match self.get(id)? {
+            PropertyValue::Time(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn binary(&self, id: u16) -> Option<&[u8]> {
+        match self.get(id)? {
+            PropertyValue::Binary(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Attachment object with an optional embedded message (PR_ATTACH_METHOD = 5)
+#[derive(Debug, Clone, Default)]
+pub struct MapiAttachment {
+    pub properties: PropertyBag,
+    pub embedded_message: Option<Box<MapiMessage>>,
+}
+
+/// Message object with its recipient and attachment tables
+#[derive(Debug, Clone, Default)]
+pub struct MapiMessage {
+    pub properties: PropertyBag,
+    pub recipients: Vec<PropertyBag>,
+    pub attachments: Vec<MapiAttachment>,
+}
+
+impl MapiMessage {
+    /// Map the MAPI properties onto an `Email`
+    pub fn to_email(&self) -> Email {
+        self.to_email_with_fallback_date(None)
+    }
+
+    /// Map the MAPI properties onto an `Email`, using `fallback_date` when neither
+    /// the MAPI times nor the Received headers of the transport headers give a date
+    pub fn to_email_with_fallback_date(&self, fallback_date: Option<DateTime<Utc>>) -> Email {
+        let props = &self.properties;
+
+        let sender = format_address(
+            props.string(prop::SENDER_NAME).or_else(|| props.string(prop::SENT_REPRESENTING_NAME)),
+            props
+                .string(prop::SENDER_SMTP_ADDRESS)
+                .or_else(|| props.string(prop::SENDER_EMAIL_ADDRESS))
+                .or_else(|| props.string(prop::SENT_REPRESENTING_EMAIL_ADDRESS)),
+        );
+
+        let mut to = Vec::new();
+        let mut cc = Vec::new();
+        let mut bcc = Vec::new();
+        for recipient in &self.recipients {
+            let address = format_address(
+                recipient.string(prop::DISPLAY_NAME),
+                recipient.string(prop::SMTP_ADDRESS).or_else(|| recipient.string(prop::EMAIL_ADDRESS)),
+            );
+            if address.is_empty() {
+                continue;
+            }
+            match recipient.integer(prop::RECIPIENT_TYPE) {
+                Some(RECIPIENT_CC) => cc.push(address),
+                Some(RECIPIENT_BCC) => bcc.push(address),
+                _ => to.push(address),
+            }
+        }
+
+        // Fall back to the display lists when the recipient table is missing
+        if self.recipients.is_empty() {
+            to = split_display_list(props.string(prop::DISPLAY_TO));
+            cc = split_display_list(props.string(prop::DISPLAY_CC));
+            bcc = split_display_list(props.string(prop::DISPLAY_BCC));
+        }
+
+        let subject = props.string(prop::SUBJECT).unwrap_or_default();
+        let received = props.string(prop::TRANSPORT_MESSAGE_HEADERS).and_then(|headers| {
+            mailparse::parse_headers(headers.as_bytes())
+                .ok()
+                .and_then(|(headers, _)| received_date(&headers))
+        });
+        let date = resolve_message_date(
+            &subject,
+            props
+                .time(prop::CLIENT_SUBMIT_TIME)
+                .or_else(|| props.time(prop::MESSAGE_DELIVERY_TIME))
+                .or_else(|| props.time(prop::CREATION_TIME)),
+            received,
+            fallback_date,
+        );
+
+        let (body, is_html) = match props.string(prop::HTML) {
+            Some(html) => (html, true),
+            None => (props.string(prop::BODY).unwrap_or_default(), false),
+        };
+
+        let mut email = Email::new(
+            subject,
+            sender,
+            to.join(", "),
+            date,
+            body,
+        );
+
+        email.cc_recipients = cc;
+        email.bcc_recipients = bcc;
+        email.is_html = is_html;
+        email.message_id = props.string(prop::INTERNET_MESSAGE_ID);
+        email.in_reply_to = props.string(prop::IN_REPLY_TO_ID);
+        email.references = props
+            .string(prop::INTERNET_REFERENCES)
+            .map(|references| references.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+        email.conversation_index = props
+            .binary(prop::CONVERSATION_INDEX)
+            .filter(|index| index.len() >= 22)
+            .map(|index| index.iter().map(|byte| format!("{:02x}", byte)).collect());
+        email.priority = match (props.integer(prop::PRIORITY), props.integer(prop::IMPORTANCE)) {
+            (Some(1), _) => EmailPriority::Urgent,
+            (_, Some(2)) => EmailPriority::High,
+            (_, Some(0)) => EmailPriority::Low,
+            _ => EmailPriority::Normal,
+        };
+        email.attachments = self
+            .attachments
+            .iter()
+            .enumerate()
+            .map(|(index, attachment)| attachment.to_attachment(index + 1))
+            .collect();
+        email.size = props
+            .integer(prop::MESSAGE_SIZE)
+            .map(|size| size.max(0) as usize)
+            .unwrap_or_else(|| email.body.len() + email.attachments.iter().map(|a| a.size).sum::<usize>());
+
+        email
+    }
+}
+
+impl MapiAttachment {
+    /// Map the attachment properties onto an `Attachment`
+    /// Embedded messages are converted to RFC 822 so they can be opened like EML attachments
+    pub fn to_attachment(&self, number: usize) -> Attachment {
+        let props = &self.properties;
+
+        if let Some(message) = &self.embedded_message {
+            let email = message.to_email();
+            let data = email_to_rfc822(&email);
+            let name = if email.subject.trim().is_empty() {
+                format!("Nachricht {}.eml", number)
+            } else {
+                format!("{}.eml", email.subject.trim())
+            };
+            let mut attachment = Attachment::new(name, data.len(), "message/rfc822".to_string());
+            attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(&data));
+            return attachment;
+        }
+
+        let name = props
+            .string(prop::ATTACH_LONG_FILENAME)
+            .or_else(|| props.string(prop::ATTACH_FILENAME))
+            .or_else(|| props.string(prop::DISPLAY_NAME))
+            .unwrap_or_else(|| format!("Anhang {}", number));
+        let data = props.binary(prop::ATTACH_DATA).unwrap_or_default();
+        let content_type = props
+            .string(prop::ATTACH_MIME_TAG)
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+
+        let mut attachment = Attachment::new(name, data.len(), content_type);
+        attachment.content_id = props
+            .string(prop::ATTACH_CONTENT_ID)
+            .map(|id| id.trim_start_matches('<').trim_end_matches('>').to_string());
+        let referenced_from_html = props.integer(prop::ATTACH_FLAGS).unwrap_or(0) & ATT_MHTML_REF != 0;
+        attachment.is_inline = attachment.content_id.is_some()
+            && (referenced_from_html || props.boolean(prop::ATTACHMENT_HIDDEN).unwrap_or(false));
+        attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(data));
+        attachment
+    }
+}
+
+/// Convert a Windows FILETIME (100 ns ticks since 1601-01-01) to UTC
+pub fn filetime_to_datetime(filetime: u64) -> Option<DateTime<Utc>> {
+    const TICKS_PER_SECOND: u64 = 10_000_000;
+    const SECONDS_1601_TO_1970: i64 = 11_644_473_600;
+
+    if filetime == 0 {
+        return None;
+    }
+    let seconds = (filetime / TICKS_PER_SECOND) as i64 - SECONDS_1601_TO_1970;
+    let nanos = ((filetime % TICKS_PER_SECOND) * 100) as u32;
+    DateTime::from_timestamp(seconds, nanos)
+}
+
+/// Decode a UTF-16LE string, dropping the terminating NUL
+pub fn decode_utf16(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units).trim_end_matches('\0').to_string()
+}
+
+/// Decode an 8-bit string; falls back to Latin-1 when it is not UTF-8
+pub fn decode_string8(data: &[u8]) -> String {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => data.iter().map(|&byte| byte as char).collect(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+fn format_address(name: Option<String>, address: Option<String>) -> String {
+    match (name, address) {
+        (Some(name), Some(address)) if name != address => format!("{} <{}>", name, address),
+        (_, Some(address)) => address,
+        (Some(name), None) => name,
+        (None, None) => String::new(),
+    }
+}
+
+fn split_display_list(list: Option<String>) -> Vec<String> {
+    list.map(|list| {
+        list.split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Serialize an email as a MIME message (used for embedded messages)
+fn email_to_rfc822(email: &Email) -> Vec<u8> {
+    let engine = &base64::engine::general_purpose::STANDARD;
+    let mut out = String::new();
+
+    out.push_str(&format!("From: {}\r\n", encode_header(&email.sender)));
+    out.push_str(&format!("To: {}\r\n", encode_header(&email.recipient)));
+    if !email.cc_recipients.is_empty() {
+        out.push_str(&format!("Cc: {}\r\n", encode_header(&email.cc_recipients.join(", "))));
+    }
+    out.push_str(&format!("Subject: {}\r\n", encode_header(&email.subject)));
+    out.push_str(&format!("Date: {}\r\n", email.date.to_rfc2822()));
+    if let Some(message_id) = &email.message_id {
+        out.push_str(&format!("Message-ID: {}\r\n", message_id));
+    }
+    out.push_str("MIME-Version: 1.0\r\n");
+
+    let body_type = if email.is_html { "text/html" } else { "text/plain" };
+    let body_part = format!(
+        "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        body_type,
+        wrap_base64(&engine.encode(email.body.as_bytes()))
+    );
+
+    if email.attachments.is_empty() {
+        out.push_str(&body_part);
+        return out.into_bytes();
+    }
+
+    let boundary = "----=_Embedded_Message_Boundary";
+    out.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+    out.push_str(&format!("--{}\r\n{}", boundary, body_part));
+    for attachment in &email.attachments {
+        out.push_str(&format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+            boundary,
+            attachment.content_type,
+            attachment.name.replace('"', "'"),
+            wrap_base64(attachment.data.as_deref().unwrap_or_default())
+        ));
+    }
+    out.push_str(&format!("--{}--\r\n", boundary));
+    out.into_bytes()
+}
+
+/// RFC 2047 encode header values that are not plain ASCII
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(value))
+    }
+}
+
+fn wrap_base64(encoded: &str) -> String {
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| String::from_utf8_lossy(line).to_string())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mime_parser::MimeParser;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_filetime_conversion() {
+        // 2024-01-15 09:30:00 UTC
+        let filetime = (1_705_311_000u64 + 11_644_473_600) * 10_000_000;
+        assert_eq!(filetime_to_datetime(filetime), Some(Utc.with_ymd_and_hms(2024, 1, 15, 9, 30, 0).unwrap()));
+        assert_eq!(filetime_to_datetime(0), None);
+    }
+
+    #[test]
+    fn test_embedded_message_round_trips_through_mime() {
+        let mut inner = MapiMessage::default();
+        inner.properties.insert(prop::SUBJECT, PropertyValue::String("Weitergeleitet: Büro".to_string()));
+        inner.properties.insert(prop::BODY, PropertyValue::String("Innen".to_string()));
+
+        let mut outer = MapiMessage::default();
+        outer.attachments.push(MapiAttachment { properties: PropertyBag::new(), embedded_message: Some(Box::new(inner)) });
+
+        let email = outer.to_email();
+        let attachment = &email.attachments[0];
+        assert_eq!(attachment.name, "Weitergeleitet: Büro.eml");
+        assert_eq!(attachment.content_type, "message/rfc822");
+
+        let raw = base64::engine::general_purpose::STANDARD.decode(attachment.data.as_deref().unwrap()).unwrap();
+        let parsed = MimeParser::parse_email(&raw).unwrap();
+        assert_eq!(parsed.subject, "Weitergeleitet: Büro");
+        assert_eq!(parsed.body, "Innen");
+    }
+
+    #[test]
+    fn test_missing_times_fall_back_to_received_then_file_date() {
+        let file_date = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap();
+        let mut message = MapiMessage::default();
+        assert_eq!(message.to_email_with_fallback_date(Some(file_date)).date, file_date);
+
+        message.properties.insert(
+            prop::TRANSPORT_MESSAGE_HEADERS,
+            PropertyValue::String("Received: from mx by mail; Tue, 14 May 2024 08:30:00 +0000\r\nSubject: Test\r\n".to_string()),
+        );
+        assert_eq!(message.to_email_with_fallback_date(Some(file_date)).date, Utc.with_ymd_and_hms(2024, 5, 14, 8, 30, 0).unwrap());
+    }
+}
@@ -0,0 +1,325 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::{EmailIterator, MailSource};
+use crate::mime_parser::MimeParser;
+use crate::types::{Email, MailFolder};
+
+/// Location of a single message inside the mbox file
+#[derive(Debug, Clone)]
+struct MboxEntry {
+    /// Offset of the first header line (after the "From " separator)
+    offset: u64,
+    /// Length of the message including the trailing separator newline
+    len: usize,
+    /// Date from the "From " separator line, used when the Date header is missing
+    separator_date: Option<DateTime<Utc>>,
+    /// Whether ">From " lines have to be unquoted (mboxrd); false for mboxcl2 messages
+    unquote: bool,
+}
+
+/// Reader for Unix mbox files (mboxo, mboxrd and mboxcl2)
+///
+/// The file is indexed once on open; messages are read and parsed lazily
+/// while iterating. All messages are placed in one folder named after the file.
+pub struct MboxReader {
+    file_path: PathBuf,
+    entries: Vec<MboxEntry>,
+}
+
+impl MboxReader {
+    /// Open an mbox file and index its messages
+    pub fn new(file_path: PathBuf) -> MailSourceResult<Self> {
+        if !file_path.exists() {
+            return Err(MailSourceError::NotFound(file_path.to_string_lossy().to_string()));
+        }
+
+        let entries = Self::scan(&file_path)?;
+        Ok(Self { file_path, entries })
+    }
+
+    /// Get supported mbox file extensions
+    pub fn supported_extensions() -> Vec<&'static str> {
+        vec!["mbox", "mbx"]
+    }
+
+    /// Name of the folder all messages are assigned to (the file stem)
+    fn folder_name(&self) -> String {
+        self.file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Posteingang".to_string())
+    }
+
+    /// Find all message boundaries in the file
+    fn scan(file_path: &PathBuf) -> MailSourceResult<Vec<MboxEntry>> {
+        let file = File::open(file_path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut entries: Vec<MboxEntry> = Vec::new();
+        let mut line = Vec::new();
+        let mut position = 0u64;
+        let mut previous_blank = true;
+
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            let line_start = position;
+            position += read as u64;
+
+            if !(previous_blank && line.starts_with(b"From ")) {
+                if entries.is_empty() {
+                    if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+                        continue;
+                    }
+                    return Err(MailSourceError::InvalidFormat(
+                        "Datei beginnt nicht mit einer mbox-Trennzeile (\"From \")".to_string(),
+                    ));
+                }
+                previous_blank = is_blank(&line);
+                continue;
+            }
+
+            // Close the previous message at this separator
+            if let Some(last) = entries.last_mut().filter(|last| last.unquote) {
+                last.len = (line_start - last.offset) as usize;
+            }
+
+            let separator_date = parse_separator_date(&String::from_utf8_lossy(&line));
+            let offset = position;
+
+            // Read the header block to look for a Content-Length (mboxcl2)
+            let mut content_length = None;
+            loop {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line)?;
+                if read == 0 {
+                    break;
+                }
+                position += read as u64;
+                if is_blank(&line) {
+                    break;
+                }
+                let text = String::from_utf8_lossy(&line);
+                if let Some((name, value)) = text.split_once(':') {
+                    if name.eq_ignore_ascii_case("Content-Length") {
+                        content_length = value.trim().parse::<u64>().ok();
+                    }
+                }
+            }
+
+            let body_end = content_length
+                .and_then(|len| position.checked_add(len))
+                .filter(|&end| Self::is_message_boundary(reader.get_mut(), end, file_size));
+
+            // The boundary check moved the file cursor, so resynchronise the buffered reader
+            match body_end {
+                Some(end) => {
+                    reader.seek(SeekFrom::Start(end))?;
+                    position = end;
+                    entries.push(MboxEntry { offset, len: (end - offset) as usize, separator_date, unquote: false });
+                }
+                None => {
+                    if content_length.is_some() {
+                        reader.seek(SeekFrom::Start(position))?;
+                    }
+                    entries.push(MboxEntry { offset, len: (position - offset) as usize, separator_date, unquote: true });
+                }
+            }
+            previous_blank = true;
+        }
+
+        if let Some(last) = entries.last_mut().filter(|last| last.unquote) {
+            last.len = (position - last.offset) as usize;
+        }
+
+        Ok(entries)
+    }
+
+    /// Check whether `offset` is end of file or the start of the next separator line
+    fn is_message_boundary(file: &mut File, offset: u64, file_size: u64) -> bool {
+        if offset == file_size {
+            return true;
+        }
+        if offset > file_size {
+            return false;
+        }
+
+        let mut buffer = [0u8; 7];
+        let available = ((file_size - offset) as usize).min(buffer.len());
+        if file.seek(SeekFrom::Start(offset)).is_err() || file.read_exact(&mut buffer[..available]).is_err() {
+            return false;
+        }
+
+        let rest = &buffer[..available];
+        rest.starts_with(b"From ") || rest.starts_with(b"\nFrom ") || rest.starts_with(b"\r\nFrom ")
+    }
+
+    /// Read and parse a single indexed message
+    fn read_email(&self, file: &mut File, entry: &MboxEntry) -> MailSourceResult<Email> {
+        let mut raw = vec![0u8; entry.len];
+        file.seek(SeekFrom::Start(entry.offset))?;
+        file.read_exact(&mut raw)?;
+
+        if entry.unquote {
+            // The blank line before the next separator belongs to the mbox framing
+            if raw.ends_with(b"\r\n\r\n") {
+                raw.truncate(raw.len() - 2);
+            } else if raw.ends_with(b"\n\n") {
+                raw.truncate(raw.len() - 1);
+            }
+            raw = unquote_from_lines(&raw);
+        }
+
+        let mut email = MimeParser::parse_email_with_fallback_date(&raw, entry.separator_date)?;
+        email.folder = Some(self.folder_name());
+        Ok(email)
+    }
+}
+
+impl MailSource for MboxReader {
+    fn source_name(&self) -> String {
+        self.file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(self.entries.len())
+    }
+
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        let name = self.folder_name();
+        Ok(vec![MailFolder::new(name.clone(), name, self.entries.len())])
+    }
+
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        let mut file = File::open(&self.file_path)?;
+        Ok(Box::new(self.entries.iter().map(move |entry| self.read_email(&mut file, entry))))
+    }
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line == b"\n" || line == b"\r\n"
+}
+
+/// Parse the asctime date of a "From sender Tue Jan  2 10:00:00 2024" separator line
+fn parse_separator_date(line: &str) -> Option<DateTime<Utc>> {
+    let tokens: Vec<&str> = line.split_whitespace().skip(2).collect();
+    if tokens.len() < 5 {
+        return None;
+    }
+
+    // Some writers put a time zone between the time and the year
+    let year = tokens.last()?;
+    let date = format!("{} {} {} {} {}", tokens[0], tokens[1], tokens[2], tokens[3], year);
+    NaiveDateTime::parse_from_str(&date, "%a %b %d %H:%M:%S %Y")
+        .ok()
+        .map(|naive| naive.and_utc())
+}
+
+/// Remove one level of ">" quoting from ">From " lines (mboxrd)
+fn unquote_from_lines(raw: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(raw.len());
+    for line in raw.split_inclusive(|&byte| byte == b'\n') {
+        let quotes = line.iter().take_while(|&&byte| byte == b'>').count();
+        if quotes > 0 && line[quotes..].starts_with(b"From ") {
+            result.extend_from_slice(&line[1..]);
+        } else {
+            result.extend_from_slice(line);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn write_mbox(content: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("Projekte.mbox");
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_mboxrd_unquoting_and_separator_date() {
+        let (_dir, path) = write_mbox(concat!(
+            "From sender@example.com Sat Mar  2 08:15:00 2024\n",
+            "Subject: Ohne Datum\n",
+            "\n",
+            "Zeile eins\n",
+            ">From the archive\n",
+            ">>From nested\n",
+            "\n",
+            "From other@example.com Sun Mar  3 09:00:00 2024\n",
+            "Subject: Zweite\n",
+            "\n",
+            "Text\n",
+        ));
+
+        let reader = MboxReader::new(path).unwrap();
+        let emails: Vec<Email> = reader.emails().unwrap().map(|email| email.unwrap()).collect();
+
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0].body, "Zeile eins\nFrom the archive\n>From nested\n");
+        assert_eq!(emails[0].date, Utc.with_ymd_and_hms(2024, 3, 2, 8, 15, 0).unwrap());
+        assert_eq!(emails[0].folder.as_deref(), Some("Projekte"));
+        assert_eq!(emails[1].body, "Text\n");
+    }
+
+    #[test]
+    fn test_mboxcl2_content_length_keeps_unquoted_from_lines() {
+        let (_dir, path) = write_mbox(concat!(
+            "From a@example.com Mon Jan  1 10:00:00 2024\n",
+            "Subject: Mit Laenge\n",
+            "Content-Length: 22\n",
+            "\n",
+            "Hallo\n",
+            "\n",
+            "From the start\n",
+            "\n",
+            "From b@example.com Mon Jan  1 11:00:00 2024\n",
+            "Subject: Danach\n",
+            "\n",
+            "Ende\n",
+        ));
+
+        let reader = MboxReader::new(path).unwrap();
+        let emails: Vec<Email> = reader.emails().unwrap().map(|email| email.unwrap()).collect();
+
+        assert_eq!(reader.email_count().unwrap(), 2);
+        assert_eq!(emails[0].body, "Hallo\n\nFrom the start\n");
+        assert_eq!(emails[1].subject, "Danach");
+    }
+
+    #[test]
+    fn test_reject_non_mbox_file() {
+        let (_dir, path) = write_mbox("Subject: kein mbox\n\nText\n");
+
+        match MboxReader::new(path) {
+            Err(MailSourceError::InvalidFormat(_)) => {}
+            other => panic!("Expected InvalidFormat error, got {:?}", other.map(|reader| reader.entries.len())),
+        }
+    }
+
+    #[test]
+    fn test_folders_use_file_stem() {
+        let (_dir, path) = write_mbox("From a@example.com Mon Jan  1 10:00:00 2024\nSubject: A\n\nText\n");
+        let reader = MboxReader::new(path).unwrap();
+
+        let folders = reader.folders().unwrap();
+        assert_eq!(folders.len(), 1);
+        assert_eq!(folders[0].name, "Projekte");
+        assert_eq!(folders[0].email_count, 1);
+    }
+}
@@ -0,0 +1,385 @@
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use mailparse::{DispositionType, MailAddr, MailHeader, MailHeaderMap, ParsedMail};
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::types::{Attachment, Email, EmailPriority};
+
+/// Maximum nesting depth of multipart bodies before parts are ignored
+const MAX_MULTIPART_DEPTH: usize = 32;
+
+/// Text and attachments collected while walking the MIME tree
+#[derive(Default)]
+struct BodyParts {
+    plain: Option<String>,
+    html: Option<String>,
+    attachments: Vec<Attachment>,
+}
+
+/// Date of the newest Received header, i.e. when the message reached the mailbox
+pub fn received_date(headers: &[MailHeader]) -> Option<DateTime<Utc>> {
+    headers
+        .get_first_value("Received")
+        .and_then(|value| value.rsplit_once(';').and_then(|(_, date)| parse_date(date)))
+}
+
+/// Date of a message whose own date may be missing
+/// Falls back to the Received date, then to `fallback_date` (e.g. the file modification time), and warns
+pub fn resolve_message_date(
+    subject: &str,
+    date: Option<DateTime<Utc>>,
+    received: Option<DateTime<Utc>>,
+    fallback_date: Option<DateTime<Utc>>,
+) -> DateTime<Utc> {
+    if let Some(date) = date {
+        return date;
+    }
+
+    match received.or(fallback_date) {
+        Some(fallback) => {
+            eprintln!("Warning: Email \"{}\" has no valid date, using {}", subject, fallback.to_rfc3339());
+            fallback
+        }
+        None => {
+            eprintln!("Warning: Email \"{}\" has no date, using 1970-01-01", subject);
+            DateTime::default()
+        }
+    }
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    mailparse::dateparse(value.trim())
+        .ok()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+}
+
+/// Parser for RFC 5322 / MIME messages shared by the MBOX, EML and Maildir sources
+pub struct MimeParser;
+
+impl MimeParser {
+    /// Parse a raw RFC 5322 message into an `Email`
+    pub fn parse_email(raw: &[u8]) -> MailSourceResult<Email> {
+        Self::parse_email_with_fallback_date(raw, None)
+    }
+
+    /// Parse a raw message, using `fallback_date` when the Date header is missing or invalid
+    pub fn parse_email_with_fallback_date(raw: &[u8], fallback_date: Option<DateTime<Utc>>) -> MailSourceResult<Email> {
+        let parsed = mailparse::parse_mail(raw)
+            .map_err(|e| MailSourceError::ParsingError(format!("MIME-Nachricht konnte nicht gelesen werden: {}", e)))?;
+        let headers = &parsed.headers;
+
+        let subject = headers.get_first_value("Subject").unwrap_or_default().trim().to_string();
+        let date = resolve_message_date(
+            &subject,
+            headers.get_first_value("Date").and_then(|value| parse_date(&value)),
+            received_date(headers),
+            fallback_date,
+        );
+
+        let sender = Self::address_list(&parsed, "From")
+            .into_iter()
+            .next()
+            .or_else(|| Self::address_list(&parsed, "Sender").into_iter().next())
+            .unwrap_or_default();
+
+        let mut body_parts = BodyParts::default();
+        Self::collect_parts(&parsed, &mut body_parts, 0);
+
+        let (body, is_html) = match (body_parts.html, body_parts.plain) {
+            (Some(html), _) => (html, true),
+            (None, Some(plain)) => (plain, false),
+            (None, None) => (String::new(), false),
+        };
+
+        let mut email = Email::new(
+            subject,
+            sender,
+            Self::address_list(&parsed, "To").join(", "),
+            date,
+            body,
+        );
+
+        email.cc_recipients = Self::address_list(&parsed, "Cc");
+        email.bcc_recipients = Self::address_list(&parsed, "Bcc");
+        email.is_html = is_html;
+        email.attachments = body_parts.attachments;
+        email.priority = Self::priority(&parsed);
+        email.message_id = headers
+            .get_first_value("Message-ID")
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+        email.in_reply_to = Self::message_ids(&parsed, "In-Reply-To").into_iter().next();
+        email.references = Self::message_ids(&parsed, "References");
+        email.conversation_index = headers
+            .get_first_value("Thread-Index")
+            .and_then(|value| Self::decode_thread_index(&value));
+        email.size = raw.len();
+
+        Ok(email)
+    }
+
+    /// Walk the MIME tree and sort parts into body text and attachments
+    fn collect_parts(part: &ParsedMail, out: &mut BodyParts, depth: usize) {
+        if depth > MAX_MULTIPART_DEPTH {
+            return;
+        }
+
+        let mimetype = part.ctype.mimetype.as_str();
+        if mimetype.starts_with("multipart/") {
+            for subpart in &part.subparts {
+                Self::collect_parts(subpart, out, depth + 1);
+            }
+            return;
+        }
+
+        let disposition = part.get_content_disposition();
+        let filename = disposition
+            .params
+            .get("filename")
+            .or_else(|| part.ctype.params.get("name"))
+            .cloned();
+        let is_text_body = (mimetype == "text/plain" || mimetype == "text/html")
+            && disposition.disposition != DispositionType::Attachment
+            && filename.is_none();
+
+        if is_text_body {
+            let text = part.get_body().unwrap_or_default();
+            let slot = if mimetype == "text/html" { &mut out.html } else { &mut out.plain };
+            match slot {
+                Some(existing) => {
+                    existing.push_str("\n\n");
+                    existing.push_str(&text);
+                }
+                None => *slot = Some(text),
+            }
+            return;
+        }
+
+        let data = part.get_body_raw().unwrap_or_default();
+        let name = filename.unwrap_or_else(|| Self::default_attachment_name(part, out.attachments.len() + 1));
+        let mut attachment = Attachment::new(name, data.len(), mimetype.to_string());
+        attachment.content_id = part
+            .headers
+            .get_first_value("Content-ID")
+            .map(|value| value.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+            .filter(|value| !value.is_empty());
+        attachment.is_inline = disposition.disposition == DispositionType::Inline && attachment.content_id.is_some();
+        attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(&data));
+
+        out.attachments.push(attachment);
+    }
+
+    /// Name for attachments without a filename; embedded messages use their subject
+    fn default_attachment_name(part: &ParsedMail, number: usize) -> String {
+        if part.ctype.mimetype == "message/rfc822" {
+            let subject = part
+                .get_body_raw()
+                .ok()
+                .and_then(|raw| {
+                    mailparse::parse_headers(&raw)
+                        .ok()
+                        .and_then(|(headers, _)| headers.get_first_value("Subject"))
+                })
+                .map(|subject| subject.trim().to_string())
+                .filter(|subject| !subject.is_empty());
+
+            if let Some(subject) = subject {
+                return format!("{}.eml", subject);
+            }
+            return format!("Nachricht {}.eml", number);
+        }
+
+        format!("Anhang {}", number)
+    }
+
+    /// Decode an address header into display strings ("Name <addr>" or "addr")
+    fn address_list(parsed: &ParsedMail, header: &str) -> Vec<String> {
+        let Some(mail_header) = parsed.headers.get_first_header(header) else {
+            return Vec::new();
+        };
+
+        match mailparse::addrparse_header(mail_header) {
+            Ok(addresses) => addresses
+                .iter()
+                .flat_map(|address| match address {
+                    MailAddr::Single(info) => vec![info.clone()],
+                    MailAddr::Group(group) => group.addrs.clone(),
+                })
+                .map(|info| match info.display_name {
+                    Some(name) if !name.trim().is_empty() => format!("{} <{}>", name.trim(), info.addr),
+                    _ => info.addr,
+                })
+                .collect(),
+            Err(_) => {
+                // Keep malformed headers readable instead of dropping them
+                let value = mail_header.get_value();
+                let value = value.trim();
+                if value.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![value.to_string()]
+                }
+            }
+        }
+    }
+
+    /// Parse a header containing message IDs into "<id>" strings
+    fn message_ids(parsed: &ParsedMail, header: &str) -> Vec<String> {
+        parsed
+            .headers
+            .get_first_value(header)
+            .and_then(|value| mailparse::msgidparse(&value).ok())
+            .map(|ids| ids.iter().map(|id| format!("<{}>", id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Map X-Priority, Importance and Priority headers onto `EmailPriority`
+    fn priority(parsed: &ParsedMail) -> EmailPriority {
+        let headers = &parsed.headers;
+
+        if let Some(value) = headers.get_first_value("X-Priority") {
+            match value.trim().chars().next() {
+                Some('1') => return EmailPriority::Urgent,
+                Some('2') => return EmailPriority::High,
+                Some('4') | Some('5') => return EmailPriority::Low,
+                _ => {}
+            }
+        }
+
+        let importance = headers
+            .get_first_value("Importance")
+            .or_else(|| headers.get_first_value("Priority"))
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+
+        match importance.as_str() {
+            "urgent" => EmailPriority::Urgent,
+            "high" => EmailPriority::High,
+            "low" | "non-urgent" => EmailPriority::Low,
+            _ => EmailPriority::Normal,
+        }
+    }
+
+    /// Convert Outlook's base64 Thread-Index header into a hex conversation index
+    fn decode_thread_index(value: &str) -> Option<String> {
+        let compact: String = value.split_whitespace().collect();
+        let bytes = base64::engine::general_purpose::STANDARD.decode(compact).ok()?;
+        if bytes.len() < 22 {
+            return None;
+        }
+        Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_simple_message() {
+        let raw = concat!(
+            "From: \"Müller, Anna\" <anna@example.com>\r\n",
+            "To: bob@example.com, Carol <carol@example.com>\r\n",
+            "Cc: dave@example.com\r\n",
+            "Subject: =?UTF-8?Q?Gr=C3=BC=C3=9Fe?=\r\n",
+            "Date: Mon, 15 Jan 2024 09:30:00 +0100\r\n",
+            "Message-ID: <abc@example.com>\r\n",
+            "In-Reply-To: <parent@example.com>\r\n",
+            "References: <root@example.com> <parent@example.com>\r\n",
+            "X-Priority: 2\r\n",
+            "\r\n",
+            "Hallo Bob\r\n",
+        );
+
+        let email = MimeParser::parse_email(raw.as_bytes()).unwrap();
+
+        assert_eq!(email.subject, "Grüße");
+        assert_eq!(email.sender, "Müller, Anna <anna@example.com>");
+        assert_eq!(email.recipient, "bob@example.com, Carol <carol@example.com>");
+        assert_eq!(email.cc_recipients, vec!["dave@example.com"]);
+        assert_eq!(email.date, Utc.with_ymd_and_hms(2024, 1, 15, 8, 30, 0).unwrap());
+        assert_eq!(email.message_id.as_deref(), Some("<abc@example.com>"));
+        assert_eq!(email.in_reply_to.as_deref(), Some("<parent@example.com>"));
+        assert_eq!(email.references, vec!["<root@example.com>", "<parent@example.com>"]);
+        assert_eq!(email.priority, EmailPriority::High);
+        assert_eq!(email.body.trim(), "Hallo Bob");
+        assert!(!email.is_html);
+    }
+
+    #[test]
+    fn test_parse_multipart_with_attachment() {
+        let raw = concat!(
+            "From: a@example.com\n",
+            "Subject: Bericht\n",
+            "Thread-Index: AdnFb3hqKbQm1Yc9SH6uAaBbCdEfGg==\n",
+            "Content-Type: multipart/mixed; boundary=\"outer\"\n",
+            "\n",
+            "--outer\n",
+            "Content-Type: multipart/alternative; boundary=\"inner\"\n",
+            "\n",
+            "--inner\n",
+            "Content-Type: text/plain; charset=utf-8\n",
+            "Content-Transfer-Encoding: quoted-printable\n",
+            "\n",
+            "Siehe Anhang =E2=82=AC\n",
+            "--inner\n",
+            "Content-Type: text/html; charset=utf-8\n",
+            "\n",
+            "<p>Siehe Anhang</p>\n",
+            "--inner--\n",
+            "--outer\n",
+            "Content-Type: application/pdf; name=\"bericht.pdf\"\n",
+            "Content-Disposition: attachment; filename=\"bericht.pdf\"\n",
+            "Content-Transfer-Encoding: base64\n",
+            "\n",
+            "JVBERi0xLjQK\n",
+            "--outer\n",
+            "Content-Type: image/png\n",
+            "Content-Disposition: inline\n",
+            "Content-ID: <logo@example.com>\n",
+            "Content-Transfer-Encoding: base64\n",
+            "\n",
+            "iVBORw0KGgo=\n",
+            "--outer--\n",
+        );
+
+        let email = MimeParser::parse_email(raw.as_bytes()).unwrap();
+
+        assert!(email.is_html);
+        assert!(email.body.contains("<p>Siehe Anhang</p>"));
+        assert_eq!(email.attachments.len(), 2);
+        assert_eq!(email.attachments[0].name, "bericht.pdf");
+        assert_eq!(email.attachments[0].size, 9);
+        assert_eq!(email.attachments[0].data.as_deref(), Some("JVBERi0xLjQK"));
+        assert!(!email.attachments[0].is_inline);
+        assert_eq!(email.attachments[1].content_id.as_deref(), Some("logo@example.com"));
+        assert!(email.attachments[1].is_inline);
+        assert_eq!(email.conversation_index.as_deref().map(str::len), Some(44));
+    }
+
+    #[test]
+    fn test_missing_date_uses_fallback() {
+        let fallback = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap();
+        let email = MimeParser::parse_email_with_fallback_date(b"Subject: Ohne Datum\n\nText", Some(fallback)).unwrap();
+
+        assert_eq!(email.date, fallback);
+        assert_eq!(email.sender, "");
+    }
+
+    #[test]
+    fn test_missing_date_prefers_newest_received_header() {
+        let raw = concat!(
+            "Received: from mx.example.com by mail.example.com;\n",
+            " Tue, 14 May 2024 08:30:00 +0200\n",
+            "Received: from client by mx.example.com; Tue, 14 May 2024 08:29:00 +0200\n",
+            "Subject: Ohne Datum\n",
+            "\n",
+            "Text",
+        );
+        let fallback = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap();
+        let email = MimeParser::parse_email_with_fallback_date(raw.as_bytes(), Some(fallback)).unwrap();
+
+        assert_eq!(email.date, Utc.with_ymd_and_hms(2024, 5, 14, 6, 30, 0).unwrap());
+    }
+}
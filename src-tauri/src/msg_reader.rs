@@ -0,0 +1,301 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use cfb::CompoundFile;
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::{file_modified_date, EmailIterator, MailSource};
+use crate::mapi::{decode_string8, decode_utf16, filetime_to_datetime, prop_type, MAX_EMBEDDING_DEPTH, MapiAttachment, MapiMessage, PropertyBag, PropertyValue};
+use crate::types::{Email, MailFolder};
+
+/// Prefix of streams holding variable-length properties ("__substg1.0_" + tag in hex)
+const SUBSTG_PREFIX: &str = "__substg1.0_";
+
+/// Stream with the fixed-length properties of a storage
+const PROPERTIES_STREAM: &str = "__properties_version1.0";
+
+const RECIPIENT_PREFIX: &str = "__recip_version1.0_#";
+const ATTACHMENT_PREFIX: &str = "__attach_version1.0_#";
+
+/// Storage holding an embedded message inside an attachment storage (PR_ATTACH_DATA_OBJ)
+const EMBEDDED_MESSAGE_STORAGE: &str = "__substg1.0_3701000D";
+
+/// Header sizes of the property stream, depending on the storage it belongs to
+const TOP_LEVEL_HEADER_LEN: usize = 32;
+const EMBEDDED_HEADER_LEN: usize = 24;
+const SUB_OBJECT_HEADER_LEN: usize = 8;
+
+/// Size of one entry in the property stream
+const PROPERTY_ENTRY_LEN: usize = 16;
+
+/// Reader for Outlook `.msg` files (MS-OXMSG, stored in an OLE compound file)
+pub struct MsgReader {
+    file_path: PathBuf,
+    message: MapiMessage,
+}
+
+impl MsgReader {
+    /// Open a `.msg` file and read its properties, recipients and attachments
+    pub fn new(file_path: PathBuf) -> MailSourceResult<Self> {
+        if !file_path.exists() {
+            return Err(MailSourceError::NotFound(file_path.to_string_lossy().to_string()));
+        }
+
+        let file = File::open(&file_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::PermissionDenied => MailSourceError::PermissionDenied(file_path.to_string_lossy().to_string()),
+            _ => MailSourceError::IoError(e.to_string()),
+        })?;
+        let mut compound_file = CompoundFile::open(file).map_err(|e| {
+            MailSourceError::InvalidFormat(format!("Keine gültige Outlook-Nachricht (OLE-Datei): {}", e))
+        })?;
+
+        let message = read_message(&mut compound_file, Path::new("/"), TOP_LEVEL_HEADER_LEN, 0)?;
+        Ok(Self { file_path, message })
+    }
+
+    /// Get supported Outlook message file extensions
+    pub fn supported_extensions() -> Vec<&'static str> {
+        vec!["msg"]
+    }
+
+    /// Raw MAPI properties of the message
+    pub fn message(&self) -> &MapiMessage {
+        &self.message
+    }
+}
+
+impl MailSource for MsgReader {
+    fn source_name(&self) -> String {
+        self.file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(1)
+    }
+
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        Ok(vec![MailFolder::new(String::new(), self.source_name(), 1)])
+    }
+
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        let email: Email = self.message.to_email_with_fallback_date(file_modified_date(&self.file_path));
+        Ok(Box::new(std::iter::once(Ok(email))))
+    }
+}
+
+/// Read the message stored in `storage` together with its recipients and attachments
+fn read_message(
+    compound_file: &mut CompoundFile<File>,
+    storage: &Path,
+    header_len: usize,
+    depth: usize,
+) -> MailSourceResult<MapiMessage> {
+    let mut message = MapiMessage {
+        properties: read_properties(compound_file, storage, header_len)?,
+        ..MapiMessage::default()
+    };
+
+    for name in storage_children(compound_file, storage, RECIPIENT_PREFIX) {
+        message.recipients.push(read_properties(compound_file, &storage.join(name), SUB_OBJECT_HEADER_LEN)?);
+    }
+
+    for name in storage_children(compound_file, storage, ATTACHMENT_PREFIX) {
+        let attachment_storage = storage.join(name);
+        let properties = read_properties(compound_file, &attachment_storage, SUB_OBJECT_HEADER_LEN)?;
+
+        let embedded_storage = attachment_storage.join(EMBEDDED_MESSAGE_STORAGE);
+        let embedded_message = if depth < MAX_EMBEDDING_DEPTH && compound_file.is_storage(&embedded_storage) {
+            Some(Box::new(read_message(compound_file, &embedded_storage, EMBEDDED_HEADER_LEN, depth + 1)?))
+        } else {
+            None
+        };
+
+        message.attachments.push(MapiAttachment { properties, embedded_message });
+    }
+
+    Ok(message)
+}
+
+/// Names of the child storages of `storage` starting with `prefix`, in numeric order
+fn storage_children(compound_file: &CompoundFile<File>, storage: &Path, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = compound_file
+        .read_storage(storage)
+        .map(|entries| {
+            entries
+                .filter(|entry| entry.is_storage() && entry.name().starts_with(prefix))
+                .map(|entry| entry.name().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Read the fixed-length and variable-length properties of a storage
+fn read_properties(
+    compound_file: &mut CompoundFile<File>,
+    storage: &Path,
+    header_len: usize,
+) -> MailSourceResult<PropertyBag> {
+    let mut properties = PropertyBag::new();
+
+    let streams: Vec<String> = compound_file
+        .read_storage(storage)
+        .map_err(|e| MailSourceError::ParsingError(format!("Speicher {} nicht lesbar: {}", storage.display(), e)))?
+        .filter(|entry| entry.is_stream())
+        .map(|entry| entry.name().to_string())
+        .collect();
+
+    // Fixed-length values live in the property stream
+    if streams.iter().any(|name| name == PROPERTIES_STREAM) {
+        let data = read_stream(compound_file, &storage.join(PROPERTIES_STREAM))?;
+        for entry in data.get(header_len..).unwrap_or_default().chunks_exact(PROPERTY_ENTRY_LEN) {
+            let tag = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let value = &entry[8..16];
+            if let Some(value) = decode_fixed_value((tag & 0xFFFF) as u16, value) {
+                properties.insert((tag >> 16) as u16, value);
+            }
+        }
+    }
+
+    // Variable-length values are stored in one stream per property
+    for name in streams.iter().filter(|name| name.starts_with(SUBSTG_PREFIX)) {
+        let Ok(tag) = u32::from_str_radix(&name[SUBSTG_PREFIX.len()..], 16) else {
+            continue;
+        };
+        let property_type = (tag & 0xFFFF) as u16;
+        if !matches!(property_type, prop_type::UNICODE | prop_type::STRING8 | prop_type::BINARY) {
+            continue;
+        }
+
+        let data = read_stream(compound_file, &storage.join(name))?;
+        let value = match property_type {
+            prop_type::UNICODE => PropertyValue::String(decode_utf16(&data)),
+            prop_type::STRING8 => PropertyValue::String(decode_string8(&data)),
+            _ => PropertyValue::Binary(data),
+        };
+        properties.insert((tag >> 16) as u16, value);
+    }
+
+    Ok(properties)
+}
+
+fn read_stream(compound_file: &mut CompoundFile<File>, path: &Path) -> MailSourceResult<Vec<u8>> {
+    let mut stream = compound_file
+        .open_stream(path)
+        .map_err(|e| MailSourceError::ParsingError(format!("Stream {} nicht lesbar: {}", path.display(), e)))?;
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Decode the 8-byte value field of a fixed-length property entry
+fn decode_fixed_value(property_type: u16, value: &[u8]) -> Option<PropertyValue> {
+    let bytes: [u8; 8] = value.try_into().ok()?;
+    match property_type {
+        prop_type::INTEGER16 => Some(PropertyValue::Integer(i16::from_le_bytes([bytes[0], bytes[1]]) as i64)),
+        prop_type::INTEGER32 => Some(PropertyValue::Integer(
+            i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64,
+        )),
+        prop_type::BOOLEAN => Some(PropertyValue::Boolean(bytes[0] != 0)),
+        prop_type::INTEGER64 => Some(PropertyValue::Integer(i64::from_le_bytes(bytes))),
+        prop_type::SYSTIME => filetime_to_datetime(u64::from_le_bytes(bytes)).map(PropertyValue::Time),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapi::prop;
+    use crate::test_support::{SyntheticMsg, SyntheticProperty};
+    use crate::types::EmailPriority;
+    use chrono::{TimeZone, Utc};
+    use tempfile::tempdir;
+
+    fn filetime(seconds: i64) -> u64 {
+        (seconds as u64 + 11_644_473_600) * 10_000_000
+    }
+
+    #[test]
+    fn test_read_message_with_recipients_and_attachment() {
+        let dir = tempdir().unwrap();
+        let path = SyntheticMsg::new()
+            .property(prop::SUBJECT, SyntheticProperty::Unicode("Quartalszahlen".to_string()))
+            .property(prop::SENDER_NAME, SyntheticProperty::Unicode("Anna Müller".to_string()))
+            .property(prop::SENDER_SMTP_ADDRESS, SyntheticProperty::Unicode("anna@example.com".to_string()))
+            .property(prop::BODY, SyntheticProperty::Unicode("Anbei die Zahlen".to_string()))
+            .property(prop::CLIENT_SUBMIT_TIME, SyntheticProperty::Time(filetime(1_705_311_000)))
+            .property(prop::IMPORTANCE, SyntheticProperty::Long(2))
+            .property(prop::INTERNET_MESSAGE_ID, SyntheticProperty::Unicode("<q1@example.com>".to_string()))
+            .recipient(vec![
+                (prop::DISPLAY_NAME, SyntheticProperty::Unicode("Bob".to_string())),
+                (prop::SMTP_ADDRESS, SyntheticProperty::Unicode("bob@example.com".to_string())),
+                (prop::RECIPIENT_TYPE, SyntheticProperty::Long(1)),
+            ])
+            .recipient(vec![
+                (prop::SMTP_ADDRESS, SyntheticProperty::Unicode("carol@example.com".to_string())),
+                (prop::RECIPIENT_TYPE, SyntheticProperty::Long(2)),
+            ])
+            .attachment(vec![
+                (prop::ATTACH_LONG_FILENAME, SyntheticProperty::Unicode("zahlen.csv".to_string())),
+                (prop::ATTACH_MIME_TAG, SyntheticProperty::Unicode("text/csv".to_string())),
+                (prop::ATTACH_DATA, SyntheticProperty::Binary(b"a;b\n1;2\n".to_vec())),
+            ])
+            .write_in(dir.path(), "zahlen.msg");
+
+        let reader = MsgReader::new(path).unwrap();
+        let emails: Vec<Email> = reader.emails().unwrap().map(|email| email.unwrap()).collect();
+        let email = &emails[0];
+
+        assert_eq!(email.subject, "Quartalszahlen");
+        assert_eq!(email.sender, "Anna Müller <anna@example.com>");
+        assert_eq!(email.recipient, "Bob <bob@example.com>");
+        assert_eq!(email.cc_recipients, vec!["carol@example.com"]);
+        assert_eq!(email.body, "Anbei die Zahlen");
+        assert_eq!(email.date, Utc.with_ymd_and_hms(2024, 1, 15, 9, 30, 0).unwrap());
+        assert_eq!(email.priority, EmailPriority::High);
+        assert_eq!(email.message_id.as_deref(), Some("<q1@example.com>"));
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].name, "zahlen.csv");
+        assert_eq!(email.attachments[0].size, 8);
+        assert_eq!(email.attachments[0].content_type, "text/csv");
+    }
+
+    #[test]
+    fn test_read_embedded_message() {
+        let dir = tempdir().unwrap();
+        let inner = SyntheticMsg::new()
+            .property(prop::SUBJECT, SyntheticProperty::Unicode("Original".to_string()))
+            .property(prop::BODY, SyntheticProperty::Unicode("Ursprünglicher Text".to_string()));
+        let path = SyntheticMsg::new()
+            .property(prop::SUBJECT, SyntheticProperty::Unicode("WG: Original".to_string()))
+            .property(prop::HTML, SyntheticProperty::Binary(b"<p>siehe unten</p>".to_vec()))
+            .embedded_message(vec![(prop::ATTACH_METHOD, SyntheticProperty::Long(5))], inner)
+            .write_in(dir.path(), "weiterleitung.msg");
+
+        let reader = MsgReader::new(path).unwrap();
+        let email = reader.emails().unwrap().next().unwrap().unwrap();
+
+        assert!(email.is_html);
+        assert_eq!(email.body, "<p>siehe unten</p>");
+        let embedded = reader.message().attachments[0].embedded_message.as_ref().unwrap();
+        assert_eq!(embedded.properties.string(prop::SUBJECT).as_deref(), Some("Original"));
+        assert_eq!(email.attachments[0].name, "Original.eml");
+        assert_eq!(email.attachments[0].content_type, "message/rfc822");
+    }
+
+    #[test]
+    fn test_reject_non_ole_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kaputt.msg");
+        std::fs::write(&path, "keine OLE-Datei").unwrap();
+
+        match MsgReader::new(path) {
+            Err(MailSourceError::InvalidFormat(_)) => {}
+            other => panic!("Expected InvalidFormat error, got {:?}", other.map(|reader| reader.source_name())),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use crate::types::{Email, NearDuplicateMode};
+
+/// Number of hash functions in a MinHash signature
+const SIGNATURE_LEN: usize = 64;
+
+/// Signature rows per LSH band; bands of equal rows make two emails candidates
+const BAND_ROWS: usize = 4;
+
+/// Number of consecutive words forming one shingle
+const SHINGLE_WORDS: usize = 3;
+
+/// Following bucket members each email is compared with
+/// Bounds the quadratic cost when many emails share a band, e.g. newsletters;
+/// copies still end up in one cluster because matches are chained
+const MAX_BUCKET_COMPARISONS: usize = 32;
+
+/// Clusters emails with nearly identical bodies using MinHash over word shingles
+///
+/// Bodies are lowercased and reduced to alphanumeric words first, so copies that
+/// differ in whitespace, punctuation or a short signature still match. Quoted
+/// lines are left out, so a reply is not mistaken for the message it quotes. Candidate
+/// pairs come from locality-sensitive hashing and are confirmed by the estimated
+/// Jaccard similarity of their signatures.
+pub struct NearDuplicateDetector {
+    threshold: f64,
+}
+
+impl NearDuplicateDetector {
+    /// Create a detector; `threshold` is the minimum estimated similarity (0-1)
+    pub fn new(threshold: f64) -> Self {
+        Self { threshold }
+    }
+
+    /// Indices of emails forming clusters of two or more near-duplicates
+    pub fn clusters(&self, emails: &[Email]) -> Vec<Vec<usize>> {
+        let signatures: Vec<Option<[u64; SIGNATURE_LEN]>> = emails
+            .iter()
+            .map(|email| signature(&shingles(&normalized_words(&email.body))))
+            .collect();
+
+        // Emails sharing any LSH band are candidates
+        let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+        for (index, signature) in signatures.iter().enumerate() {
+            let Some(signature) = signature else { continue };
+            for (band, rows) in signature.chunks(BAND_ROWS).enumerate() {
+                let mut hasher = DefaultHasher::new();
+                rows.hash(&mut hasher);
+                buckets.entry((band, hasher.finish())).or_default().push(index);
+            }
+        }
+
+        let mut parents: Vec<usize> = (0..emails.len()).collect();
+        for members in buckets.values() {
+            for (position, &first) in members.iter().enumerate() {
+                for &second in members[position + 1..].iter().take(MAX_BUCKET_COMPARISONS) {
+                    if find(&mut parents, first) == find(&mut parents, second) {
+                        continue;
+                    }
+                    if let (Some(a), Some(b)) = (&signatures[first], &signatures[second]) {
+                        if similarity(a, b) >= self.threshold {
+                            let (root_a, root_b) = (find(&mut parents, first), find(&mut parents, second));
+                            parents[root_b] = root_a;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+        for index in 0..emails.len() {
+            let root = find(&mut parents, index);
+            clusters.entry(root).or_default().push(index);
+        }
+        let mut clusters: Vec<Vec<usize>> = clusters.into_values().filter(|members| members.len() > 1).collect();
+        clusters.sort();
+        clusters
+    }
+
+    /// Drop or mark near-duplicates according to `mode`
+    ///
+    /// The representative of each cluster is the email with the longest body
+    /// (the earliest on ties); the order of the remaining emails is preserved.
+    pub fn apply(&self, mut emails: Vec<Email>, mode: NearDuplicateMode) -> Vec<Email> {
+        if mode == NearDuplicateMode::Disabled {
+            return emails;
+        }
+
+        let mut representative_of: HashMap<usize, usize> = HashMap::new();
+        for members in self.clusters(&emails) {
+            let representative = members
+                .iter()
+                .copied()
+                .max_by_key(|&index| (emails[index].body.trim().len(), std::cmp::Reverse(emails[index].date)))
+                .unwrap_or(members[0]);
+            for index in members.into_iter().filter(|&index| index != representative) {
+                representative_of.insert(index, representative);
+            }
+        }
+
+        match mode {
+            NearDuplicateMode::KeepLongest => emails
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| !representative_of.contains_key(index))
+                .map(|(_, email)| email)
+                .collect(),
+            NearDuplicateMode::Mark => {
+                for (&index, &representative) in &representative_of {
+                    let original = &emails[representative];
+                    emails[index].near_duplicate_of = Some(format!("{} ({})", original.subject, original.formatted_date()));
+                }
+                emails
+            }
+            NearDuplicateMode::Disabled => emails,
+        }
+    }
+}
+
+/// Lowercased alphanumeric words of a body without quoted ("> ") lines; punctuation is dropped
+fn normalized_words(body: &str) -> Vec<String> {
+    body.lines()
+        .filter(|line| !line.trim_start().starts_with('>'))
+        .flat_map(|line| line.split(|ch: char| !ch.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Hashes of all word shingles; short texts form a single shingle
+fn shingles(words: &[String]) -> Vec<u64> {
+    let hash = |words: &[String]| {
+        let mut hasher = DefaultHasher::new();
+        words.hash(&mut hasher);
+        hasher.finish()
+    };
+
+    if words.len() <= SHINGLE_WORDS {
+        return if words.is_empty() { Vec::new() } else { vec![hash(words)] };
+    }
+    words.windows(SHINGLE_WORDS).map(hash).collect()
+}
+
+/// MinHash signature of a shingle set; None for empty bodies, which are never clustered
+fn signature(shingles: &[u64]) -> Option<[u64; SIGNATURE_LEN]> {
+    if shingles.is_empty() {
+        return None;
+    }
+
+    let mut signature = [u64::MAX; SIGNATURE_LEN];
+    for &shingle in shingles {
+        for (seed, slot) in signature.iter_mut().enumerate() {
+            let mut hasher = DefaultHasher::new();
+            (seed, shingle).hash(&mut hasher);
+            *slot = (*slot).min(hasher.finish());
+        }
+    }
+    Some(signature)
+}
+
+/// Estimated Jaccard similarity of two signatures
+fn similarity(a: &[u64; SIGNATURE_LEN], b: &[u64; SIGNATURE_LEN]) -> f64 {
+    let equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    equal as f64 / SIGNATURE_LEN as f64
+}
+
+fn find(parents: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    // Path compression
+    let mut current = index;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    const BODY: &str = "Hallo Bernd, anbei wie besprochen das Angebot für die Wartung der Anlagen im zweiten Quartal. \
+        Die Preise gelten bis Ende Juni, danach müssen wir neu kalkulieren. Bitte gib mir bis Freitag Bescheid, \
+        ob wir den Termin für die Abnahme am Montag halten können.";
+
+    fn email(subject: &str, body: &str, day: u32) -> Email {
+        Email::new(
+            subject.to_string(),
+            "anna@example.com".to_string(),
+            "bernd@example.com".to_string(),
+            Utc.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap(),
+            body.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_keep_longest_drops_copies_with_signature_and_whitespace_changes() {
+        let emails = vec![
+            email("Angebot", BODY, 1),
+            email("WG: Angebot", &format!("{}\n\n--\nAnna Schmidt\nVertrieb", BODY.replace(' ', "  ")), 2),
+            email("Urlaub", "Ich bin bis zum 15. März im Urlaub und lese meine Mails danach.", 3),
+        ];
+
+        let kept = NearDuplicateDetector::new(0.8).apply(emails, NearDuplicateMode::KeepLongest);
+
+        let subjects: Vec<&str> = kept.iter().map(|email| email.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["WG: Angebot", "Urlaub"]);
+    }
+
+    #[test]
+    fn test_mark_keeps_all_emails() {
+        let quoted = BODY.lines().map(|line| format!("> {}", line)).collect::<Vec<_>>().join("\n");
+        let emails = vec![
+            email("Angebot", BODY, 1),
+            email("WG: Angebot", &format!("{}\n\n--\nAnna Schmidt", BODY), 2),
+            email("AW: Angebot", &format!("Passt, danke!\n\n{}", quoted), 3),
+        ];
+
+        let marked = NearDuplicateDetector::new(0.8).apply(emails, NearDuplicateMode::Mark);
+
+        // The forwarded copy is longer and becomes the representative; the reply only quotes the original
+        assert_eq!(marked.len(), 3);
+        assert!(marked[0].near_duplicate_of.as_deref().unwrap().starts_with("WG: Angebot ("));
+        assert_eq!(marked[1].near_duplicate_of, None);
+        assert_eq!(marked[2].near_duplicate_of, None);
+    }
+
+    #[test]
+    fn test_many_identical_emails_form_one_cluster() {
+        let emails: Vec<Email> = (0..500).map(|index| email(&format!("Newsletter {}", index), BODY, 1 + index % 28)).collect();
+
+        let clusters = NearDuplicateDetector::new(0.8).clusters(&emails);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].len(), 500);
+    }
+
+    #[test]
+    fn test_different_and_empty_bodies_are_not_clustered() {
+        let emails = vec![
+            email("A", "", 1),
+            email("B", "", 2),
+            email("C", "Die Rechnung für Februar ist beglichen.", 3),
+            email("D", "Das Meeting am Dienstag fällt leider aus.", 4),
+        ];
+
+        assert!(NearDuplicateDetector::new(0.8).clusters(&emails).is_empty());
+    }
+}
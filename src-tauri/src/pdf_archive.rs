@@ -0,0 +1,808 @@
+use std::collections::BTreeMap;
+use printpdf::lopdf::{self, Dictionary, Object, Stream};
+use printpdf::lopdf::xref::XrefEntry;
+use printpdf::{CustomPdfConformance, PdfConformance};
+use crate::errors::{PdfError, PdfResult};
+use crate::pdf_attachments::text_string;
+use crate::types::PdfStandard;
+
+/// Comment with binary bytes that PDF/A requires on the line after the header
+const BINARY_COMMENT: &[u8] = b"%\xe2\xe3\xcf\xd3\n";
+
+/// Trailer keys that only belong to a cross-reference stream
+const XREF_STREAM_KEYS: [&[u8]; 8] = [b"Type", b"W", b"Index", b"Length", b"Filter", b"DecodeParms", b"Prev", b"XRefStm"];
+
+/// Output condition of the sRGB output intent
+const SRGB_CONDITION: &str = "sRGB IEC61966-2.1";
+
+/// Info dictionary keys written by printpdf that PDF/A has no XMP counterpart for
+const UNSUPPORTED_INFO_KEYS: [&[u8]; 3] = [b"Trapped", b"GTS_PDFXVersion", b"Identifier"];
+
+/// Info dictionary keys with a counterpart in a standard XMP schema
+const STANDARD_INFO_KEYS: [&[u8]; 8] = [
+    b"Title", b"Author", b"Subject", b"Keywords", b"Creator", b"Producer", b"CreationDate", b"ModDate",
+];
+
+/// XMP namespace and prefix of the Info entries beyond the standard ones
+const ARCHIVE_NAMESPACE: &str = "http://ns.pbl.ch/outlook-archiver/archive/1.0/";
+const ARCHIVE_PREFIX: &str = "archive";
+
+/// Actions that PDF/A forbids, as they run code or depend on the viewer
+const FORBIDDEN_ACTIONS: [&str; 11] = [
+    "JavaScript", "Launch", "Sound", "Movie", "ResetForm", "ImportData", "Hide", "SetOCGState", "Rendition", "Trans", "GoTo3DView",
+];
+
+/// Annotation types that PDF/A forbids
+const FORBIDDEN_ANNOTATIONS: [&str; 5] = ["Sound", "Movie", "Screen", "3D", "TrapNet"];
+
+/// Annotation flags Invisible, Hidden and NoView
+const HIDING_ANNOTATION_FLAGS: i64 = 1 | 2 | 32;
+
+/// Annotation flag Print
+const PRINT_ANNOTATION_FLAG: i64 = 4;
+
+/// Conformance handed to printpdf when the output is converted to PDF/A afterwards
+///
+/// printpdf only writes the PDF/X flavour of XMP metadata and a CMYK output
+/// intent, so both are left out here and added by `convert_to_pdfa`.
+pub fn printpdf_conformance() -> PdfConformance {
+    PdfConformance::Custom(CustomPdfConformance {
+        identifier: String::new(),
+        requires_xmp_metadata: false,
+        requires_icc_profile: false,
+        allows_default_fonts: false,
+        ..Default::default()
+    })
+}
+
+/// Bring a document written by printpdf in line with a PDF/A standard
+///
+/// Adds XMP metadata mirroring the Info dictionary and an sRGB output intent,
+/// and removes the features PDF/A forbids. Fonts have to be embedded already.
+pub fn convert_to_pdfa(document: &mut lopdf::Document, standard: PdfStandard) -> PdfResult<()> {
+    let Some(part) = standard.pdfa_part() else {
+        return Ok(());
+    };
+
+    // The binary comment after the header is added to the saved bytes by `add_binary_comment`
+    document.version = "1.7".to_string();
+
+    for key in UNSUPPORTED_INFO_KEYS {
+        info_dictionary(document)?.remove(key);
+    }
+    add_xmp_metadata(document, Some(part))?;
+
+    let profile = document.add_object(Stream::new(
+        Dictionary::from_iter(vec![("N", Object::Integer(3))]),
+        srgb_icc_profile(),
+    ));
+    let output_intent = Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"OutputIntent".to_vec())),
+        ("S", Object::Name(b"GTS_PDFA1".to_vec())),
+        ("OutputConditionIdentifier", Object::string_literal(SRGB_CONDITION)),
+        ("Info", Object::string_literal(SRGB_CONDITION)),
+        ("RegistryName", Object::string_literal("http://www.color.org")),
+        ("DestOutputProfile", Object::Reference(profile)),
+    ]);
+
+    let catalog = document.catalog_mut().map_err(conversion_error)?;
+    catalog.set("OutputIntents", Object::Array(vec![Object::Dictionary(output_intent)]));
+
+    // Optional content configurations need a name and must not switch layers automatically
+    if let Ok(configuration) = catalog
+        .get_mut(b"OCProperties")
+        .and_then(Object::as_dict_mut)
+        .and_then(|properties| properties.get_mut(b"D"))
+        .and_then(Object::as_dict_mut)
+    {
+        configuration.set("Name", Object::string_literal("Layers"));
+        configuration.remove(b"AS");
+    }
+
+    for object in document.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        match name(dict, b"Subtype") {
+            // Interpolated images look different depending on the viewer
+            Some("Image") => {
+                dict.remove(b"Interpolate");
+            }
+            // printpdf uses glyph IDs as CIDs without saying so
+            Some("CIDFontType2") if !dict.has(b"CIDToGIDMap") => {
+                dict.set("CIDToGIDMap", Object::Name(b"Identity".to_vec()));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Insert the binary comment PDF/A requires after the header line of a saved document
+///
+/// lopdf only writes the header line itself. The saved file is parsed again, the comment is
+/// inserted after the header and a new cross-reference table, trailer and `startxref` are
+/// written from the object offsets lopdf read, replacing the original table or stream.
+pub fn add_binary_comment(bytes: &[u8]) -> PdfResult<Vec<u8>> {
+    let document = lopdf::Document::load_mem(bytes).map_err(conversion_error)?;
+    let header_end = bytes.iter().position(|&byte| byte == b'\n').map_or(0, |index| index + 1);
+    let body_end = document.xref_start;
+    if header_end == 0 || body_end < header_end || body_end > bytes.len() {
+        return Err(PdfError::GenerationFailed("Failed to convert PDF to PDF/A: unreadable file structure".to_string()));
+    }
+
+    // Every object follows the header, so all of them move by the length of the comment
+    let shift = BINARY_COMMENT.len();
+    let mut offsets = BTreeMap::new();
+    for (&id, entry) in &document.reference_table.entries {
+        match *entry {
+            // A cross-reference stream of the input starts at `body_end` and is replaced below
+            XrefEntry::Normal { offset, generation } if (offset as usize) < body_end => {
+                offsets.insert(id, (offset as usize + shift, generation));
+            }
+            XrefEntry::Normal { .. } | XrefEntry::Free | XrefEntry::UnusableFree => {}
+            XrefEntry::Compressed { .. } => {
+                return Err(PdfError::GenerationFailed(
+                    "Failed to convert PDF to PDF/A: objects in object streams are not supported".to_string(),
+                ));
+            }
+        }
+    }
+    let size = offsets.keys().next_back().map_or(1, |id| id + 1);
+
+    let mut output = Vec::with_capacity(bytes.len() + shift);
+    output.extend_from_slice(&bytes[..header_end]);
+    output.extend_from_slice(BINARY_COMMENT);
+    output.extend_from_slice(&bytes[header_end..body_end]);
+
+    let xref_start = output.len();
+    output.extend_from_slice(format!("xref\n0 {}\n", size).as_bytes());
+    for id in 0..size {
+        let entry = match offsets.get(&id) {
+            Some((offset, generation)) => format!("{:010} {:05} n \n", offset, generation),
+            None => "0000000000 65535 f \n".to_string(),
+        };
+        output.extend_from_slice(entry.as_bytes());
+    }
+
+    let mut trailer = document.trailer.clone();
+    for key in XREF_STREAM_KEYS {
+        trailer.remove(key);
+    }
+    trailer.set("Size", Object::Integer(size as i64));
+    output.extend_from_slice(b"trailer\n");
+    write_object(&mut output, &Object::Dictionary(trailer));
+    output.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", xref_start).as_bytes());
+    Ok(output)
+}
+
+/// Serialize a direct object, as found in the trailer; strings are written in hex
+fn write_object(output: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null | Object::Stream(_) => output.extend_from_slice(b"null"),
+        Object::Boolean(value) => output.extend_from_slice(value.to_string().as_bytes()),
+        Object::Integer(value) => output.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => output.extend_from_slice(value.to_string().as_bytes()),
+        Object::Name(name) => write_name(output, name),
+        Object::String(text, _) => {
+            output.push(b'<');
+            output.extend(text.iter().flat_map(|byte| format!("{:02X}", byte).into_bytes()));
+            output.push(b'>');
+        }
+        Object::Array(items) => {
+            output.push(b'[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    output.push(b' ');
+                }
+                write_object(output, item);
+            }
+            output.push(b']');
+        }
+        Object::Dictionary(dict) => {
+            output.extend_from_slice(b"<<");
+            for (key, value) in dict.iter() {
+                write_name(output, key);
+                output.push(b' ');
+                write_object(output, value);
+            }
+            output.extend_from_slice(b">>");
+        }
+        Object::Reference((id, generation)) => output.extend_from_slice(format!("{} {} R", id, generation).as_bytes()),
+    }
+}
+
+/// Write a name, escaping delimiters and bytes outside printable ASCII as `#xx`
+fn write_name(output: &mut Vec<u8>, name: &[u8]) {
+    output.push(b'/');
+    for &byte in name {
+        if byte.is_ascii_graphic() && !b"#%()/<>[]{}".contains(&byte) {
+            output.push(byte);
+        } else {
+            output.extend_from_slice(format!("#{:02X}", byte).as_bytes());
+        }
+    }
+}
+
+/// Check a saved document against the structural requirements of a PDF/A standard
+///
+/// This is not a full validator; it covers what the generator is responsible for:
+/// metadata, output intent, embedded fonts, annotations, actions and embedded files.
+/// Returns a description of every violation found.
+pub fn check_pdfa(document: &lopdf::Document, standard: PdfStandard) -> Vec<String> {
+    let Some(part) = standard.pdfa_part() else {
+        return Vec::new();
+    };
+    let mut violations = Vec::new();
+
+    if document.trailer.has(b"Encrypt") {
+        violations.push("document is encrypted".to_string());
+    }
+    if !document.trailer.has(b"ID") {
+        violations.push("trailer has no file identifier".to_string());
+    }
+
+    let Ok(catalog) = document.catalog() else {
+        violations.push("document has no catalog".to_string());
+        return violations;
+    };
+
+    match catalog.get_deref(b"Metadata", document).and_then(Object::as_stream) {
+        Ok(metadata) => {
+            let xmp = String::from_utf8_lossy(&metadata.content);
+            if metadata.dict.has(b"Filter") {
+                violations.push("XMP metadata is compressed".to_string());
+            }
+            if !xmp.contains(&format!("<pdfaid:part>{}</pdfaid:part>", part)) || !xmp.contains("<pdfaid:conformance>B</pdfaid:conformance>") {
+                violations.push(format!("XMP metadata does not declare PDF/A-{}b", part));
+            }
+        }
+        Err(_) => violations.push("catalog has no XMP metadata".to_string()),
+    }
+
+    let has_rgb_intent = catalog
+        .get_deref(b"OutputIntents", document)
+        .and_then(Object::as_array)
+        .map(|intents| {
+            intents.iter().any(|intent| {
+                let Ok((_, intent)) = document.dereference(intent) else { return false };
+                let Ok(intent) = intent.as_dict() else { return false };
+                let profile_components = intent
+                    .get_deref(b"DestOutputProfile", document)
+                    .and_then(Object::as_stream)
+                    .and_then(|profile| profile.dict.get(b"N"))
+                    .and_then(Object::as_i64);
+                name(intent, b"S") == Some("GTS_PDFA1") && profile_components.ok() == Some(3)
+            })
+        })
+        .unwrap_or(false);
+    if !has_rgb_intent {
+        violations.push("no PDF/A output intent with an RGB profile".to_string());
+    }
+
+    let configuration_named = catalog
+        .get_deref(b"OCProperties", document)
+        .and_then(Object::as_dict)
+        .and_then(|properties| properties.get_deref(b"D", document))
+        .and_then(Object::as_dict)
+        .map(|configuration| configuration.has(b"Name") && !configuration.has(b"AS"));
+    if configuration_named.is_ok_and(|named| !named) {
+        violations.push("optional content configuration has no name or uses AS".to_string());
+    }
+
+    if catalog
+        .get_deref(b"Names", document)
+        .and_then(Object::as_dict)
+        .is_ok_and(|names| names.has(b"JavaScript"))
+    {
+        violations.push("document contains JavaScript".to_string());
+    }
+
+    for object in document.objects.values() {
+        let (dict, is_stream) = match object {
+            Object::Dictionary(dict) => (dict, false),
+            Object::Stream(stream) => (&stream.dict, true),
+            _ => continue,
+        };
+        check_object(document, dict, is_stream, part, &mut violations);
+    }
+
+    violations.sort();
+    violations.dedup();
+    violations
+}
+
+/// Check a single dictionary or stream dictionary of a document
+fn check_object(document: &lopdf::Document, dict: &Dictionary, is_stream: bool, part: u8, violations: &mut Vec<String>) {
+    let subtype = name(dict, b"Subtype");
+
+    if is_stream {
+        let filters: Vec<&str> = match dict.get(b"Filter") {
+            Ok(Object::Name(filter)) => vec![std::str::from_utf8(filter).unwrap_or_default()],
+            Ok(Object::Array(filters)) => filters.iter().filter_map(|filter| filter.as_name_str().ok()).collect(),
+            _ => Vec::new(),
+        };
+        if filters.contains(&"LZWDecode") {
+            violations.push("stream uses LZW compression".to_string());
+        }
+        if dict.has(b"F") {
+            violations.push("stream refers to an external file".to_string());
+        }
+    }
+
+    if let Some(action) = name(dict, b"S").filter(|action| FORBIDDEN_ACTIONS.contains(action)) {
+        if dict.type_is(b"Action") || !dict.has(b"Type") {
+            violations.push(format!("forbidden {} action", action));
+        }
+    }
+
+    match (name(dict, b"Type"), subtype) {
+        (Some("Font"), Some(font_type)) if font_type != "Type0" && font_type != "Type3" => {
+            let base_font = name(dict, b"BaseFont").unwrap_or("unnamed");
+            let embedded = dict
+                .get_deref(b"FontDescriptor", document)
+                .and_then(Object::as_dict)
+                .is_ok_and(|descriptor| [b"FontFile".as_slice(), b"FontFile2", b"FontFile3"].iter().any(|key| descriptor.has(key)));
+            if !embedded {
+                violations.push(format!("font {} is not embedded", base_font));
+            }
+            if font_type == "CIDFontType2" && !dict.has(b"CIDToGIDMap") {
+                violations.push(format!("font {} has no CIDToGIDMap", base_font));
+            }
+        }
+        (Some("Annot"), Some(annotation_type)) => {
+            if FORBIDDEN_ANNOTATIONS.contains(&annotation_type) {
+                violations.push(format!("forbidden {} annotation", annotation_type));
+            }
+            let flags = dict.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+            if annotation_type != "Popup" && (flags & PRINT_ANNOTATION_FLAG == 0 || flags & HIDING_ANNOTATION_FLAGS != 0) {
+                violations.push(format!("{} annotation is not printable", annotation_type));
+            }
+            let has_appearance = dict
+                .get_deref(b"AP", document)
+                .and_then(Object::as_dict)
+                .is_ok_and(|appearance| appearance.has(b"N"));
+            if annotation_type != "Link" && annotation_type != "Popup" && !has_appearance {
+                violations.push(format!("{} annotation has no appearance", annotation_type));
+            }
+        }
+        (Some("EmbeddedFile"), _) if part < 3 => {
+            violations.push(format!("PDF/A-{} does not allow embedded files", part));
+        }
+        (Some("EmbeddedFile"), None) => {
+            violations.push("embedded file has no MIME type".to_string());
+        }
+        (Some("Filespec"), _) if part >= 3 && dict.has(b"EF") => {
+            if !dict.has(b"F") || !dict.has(b"UF") {
+                violations.push("embedded file specification lacks F or UF".to_string());
+            }
+            if !dict.has(b"AFRelationship") {
+                violations.push("embedded file is not an associated file".to_string());
+            }
+        }
+        _ => {}
+    }
+
+    if subtype == Some("Image") && dict.get(b"Interpolate").and_then(Object::as_bool).unwrap_or(false) {
+        violations.push("image is interpolated".to_string());
+    }
+}
+
+/// Replace the XMP metadata of a saved document with one mirroring its Info dictionary
+///
+/// `part` declares PDF/A conformance; Info entries beyond the standard ones are
+/// then described by an extension schema, as PDF/A requires for custom properties.
+pub fn add_xmp_metadata(document: &mut lopdf::Document, part: Option<u8>) -> PdfResult<()> {
+    let info = normalize_info(document)?;
+    let metadata = document.add_object(Stream::new(
+        Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Metadata".to_vec())),
+            ("Subtype", Object::Name(b"XML".to_vec())),
+        ]),
+        xmp_packet(&info, part).into_bytes(),
+    ));
+
+    let catalog = document.catalog_mut().map_err(conversion_error)?;
+    let previous = catalog.get(b"Metadata").and_then(Object::as_reference).ok();
+    catalog.set("Metadata", Object::Reference(metadata));
+    if let Some(previous) = previous {
+        document.objects.remove(&previous);
+    }
+    Ok(())
+}
+
+fn info_dictionary(document: &mut lopdf::Document) -> PdfResult<&mut Dictionary> {
+    let info_id = document
+        .trailer
+        .get(b"Info")
+        .and_then(Object::as_reference)
+        .map_err(conversion_error)?;
+    document.get_dictionary_mut(info_id).map_err(conversion_error)
+}
+
+/// Store the Info texts as proper PDF text strings and drop empty entries
+fn normalize_info(document: &mut lopdf::Document) -> PdfResult<Dictionary> {
+    let info = info_dictionary(document)?;
+    let keys: Vec<Vec<u8>> = info.iter().map(|(key, _)| key.clone()).collect();
+    for key in keys {
+        if key.ends_with(b"Date") {
+            continue;
+        }
+        match text_value(info, &key) {
+            Some(text) if !text.is_empty() => info.set(key, text_string(&text)),
+            _ => {
+                info.remove(&key);
+            }
+        }
+    }
+    Ok(info.clone())
+}
+
+/// XMP packet with the same values as the Info dictionary, declaring the PDF/A part if any
+fn xmp_packet(info: &Dictionary, part: Option<u8>) -> String {
+    let mut properties = String::new();
+    let mut property = |element: &str, value: String| {
+        properties.push_str(&format!("   <{element}>{value}</{element}>\n"));
+    };
+
+    let language_alternative = |text: &str| format!("<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>", xml_escape(text));
+    if let Some(title) = text_value(info, b"Title") {
+        property("dc:title", language_alternative(&title));
+    }
+    if let Some(author) = text_value(info, b"Author") {
+        property("dc:creator", format!("<rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq>", xml_escape(&author)));
+    }
+    if let Some(subject) = text_value(info, b"Subject") {
+        property("dc:description", language_alternative(&subject));
+    }
+    if let Some(keywords) = text_value(info, b"Keywords") {
+        property("pdf:Keywords", xml_escape(&keywords));
+    }
+    if let Some(producer) = text_value(info, b"Producer") {
+        property("pdf:Producer", xml_escape(&producer));
+    }
+    if let Some(creator) = text_value(info, b"Creator") {
+        property("xmp:CreatorTool", xml_escape(&creator));
+    }
+    if let Some(created) = text_value(info, b"CreationDate").as_deref().and_then(xmp_date) {
+        property("xmp:CreateDate", created);
+    }
+    if let Some(modified) = text_value(info, b"ModDate").as_deref().and_then(xmp_date) {
+        property("xmp:ModifyDate", modified.clone());
+        property("xmp:MetadataDate", modified);
+    }
+    if let Some(part) = part {
+        property("pdfaid:part", part.to_string());
+        property("pdfaid:conformance", "B".to_string());
+    }
+
+    // Info entries beyond the standard ones go into the archive namespace
+    let custom: Vec<(String, String)> = info
+        .iter()
+        .filter(|(key, _)| !STANDARD_INFO_KEYS.contains(&key.as_slice()) && !UNSUPPORTED_INFO_KEYS.contains(&key.as_slice()))
+        .filter_map(|(key, _)| {
+            let key = String::from_utf8(key.clone()).ok().filter(|key| key.chars().all(|c| c.is_ascii_alphanumeric()))?;
+            Some((key.clone(), text_value(info, key.as_bytes())?))
+        })
+        .collect();
+    for (key, value) in &custom {
+        property(&format!("{ARCHIVE_PREFIX}:{key}"), xml_escape(value));
+    }
+    let extension = match part {
+        Some(_) if !custom.is_empty() => extension_schema(custom.iter().map(|(key, _)| key.as_str())),
+        _ => String::new(),
+    };
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\"\n\
+         \x20  xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\"\n\
+         \x20  xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n\
+         \x20  xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n\
+         \x20  xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n\
+         \x20  xmlns:{ARCHIVE_PREFIX}=\"{ARCHIVE_NAMESPACE}\">\n\
+         {properties}\
+         </rdf:Description>\n\
+         {extension}\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>"
+    )
+}
+
+/// PDF/A extension schema declaring the text properties `names` of the archive namespace
+fn extension_schema<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    let properties: String = names
+        .map(|name| {
+            format!(
+                "      <rdf:li rdf:parseType=\"Resource\">\n\
+                 \x20       <pdfaProperty:name>{name}</pdfaProperty:name>\n\
+                 \x20       <pdfaProperty:valueType>Text</pdfaProperty:valueType>\n\
+                 \x20       <pdfaProperty:category>external</pdfaProperty:category>\n\
+                 \x20       <pdfaProperty:description>Archive property {name}</pdfaProperty:description>\n\
+                 \x20     </rdf:li>\n"
+            )
+        })
+        .collect();
+
+    format!(
+        "<rdf:Description rdf:about=\"\"\n\
+         \x20  xmlns:pdfaExtension=\"http://www.aiim.org/pdfa/ns/extension/\"\n\
+         \x20  xmlns:pdfaSchema=\"http://www.aiim.org/pdfa/ns/schema#\"\n\
+         \x20  xmlns:pdfaProperty=\"http://www.aiim.org/pdfa/ns/property#\">\n\
+         \x20  <pdfaExtension:schemas>\n\
+         \x20   <rdf:Bag>\n\
+         \x20    <rdf:li rdf:parseType=\"Resource\">\n\
+         \x20     <pdfaSchema:schema>Email archive properties</pdfaSchema:schema>\n\
+         \x20     <pdfaSchema:namespaceURI>{ARCHIVE_NAMESPACE}</pdfaSchema:namespaceURI>\n\
+         \x20     <pdfaSchema:prefix>{ARCHIVE_PREFIX}</pdfaSchema:prefix>\n\
+         \x20     <pdfaSchema:property>\n\
+         \x20      <rdf:Seq>\n\
+         {properties}\
+         \x20      </rdf:Seq>\n\
+         \x20     </pdfaSchema:property>\n\
+         \x20    </rdf:li>\n\
+         \x20   </rdf:Bag>\n\
+         \x20  </pdfaExtension:schemas>\n\
+         </rdf:Description>\n"
+    )
+}
+
+/// Text of a string entry, decoding UTF-16BE strings with byte order mark
+/// Other strings are read as UTF-8, which printpdf writes and which covers ASCII
+fn text_value(dict: &Dictionary, key: &[u8]) -> Option<String> {
+    let bytes = dict.get(key).and_then(Object::as_str).ok()?;
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        None => Some(String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
+
+/// Convert a PDF date ("D:20240131143000+01'00'") to an XMP date ("2024-01-31T14:30:00+01:00")
+fn xmp_date(pdf_date: &str) -> Option<String> {
+    let date = pdf_date.strip_prefix("D:").unwrap_or(pdf_date);
+    let digits: String = date.chars().take_while(char::is_ascii_digit).collect();
+    if digits.len() < 14 {
+        return None;
+    }
+    let offset = match date[14..].trim_end_matches('\'').replace('\'', ":").as_str() {
+        "" | "Z" => "Z".to_string(),
+        offset if offset.starts_with(['+', '-']) && offset.len() == 6 => offset.to_string(),
+        _ => return None,
+    };
+    Some(format!(
+        "{}-{}-{}T{}:{}:{}{}",
+        &digits[0..4], &digits[4..6], &digits[6..8], &digits[8..10], &digits[10..12], &digits[12..14], offset
+    ))
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn name<'a>(dict: &'a Dictionary, key: &[u8]) -> Option<&'a str> {
+    dict.get(key).and_then(Object::as_name_str).ok()
+}
+
+fn conversion_error(error: lopdf::Error) -> PdfError {
+    PdfError::GenerationFailed(format!("Failed to convert PDF to PDF/A: {}", error))
+}
+
+/// A minimal ICC v2 display profile for sRGB (IEC 61966-2.1)
+///
+/// Built in code so no profile file has to be shipped: D50-adapted primaries,
+/// the D65 media white point and the exact sRGB tone curve as a sampled table.
+pub fn srgb_icc_profile() -> Vec<u8> {
+    const CURVE_POINTS: usize = 1024;
+
+    let s15_fixed16 = |value: f64| ((value * 65536.0).round() as i32).to_be_bytes();
+    let xyz = |x: f64, y: f64, z: f64| {
+        let mut data = b"XYZ \0\0\0\0".to_vec();
+        for value in [x, y, z] {
+            data.extend(s15_fixed16(value));
+        }
+        data
+    };
+
+    let description = "sRGB IEC61966-2.1";
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend((description.len() as u32 + 1).to_be_bytes());
+    desc.extend(description.as_bytes());
+    desc.push(0);
+    // Empty Unicode and ScriptCode descriptions
+    desc.extend([0u8; 4 + 4 + 2 + 1 + 67]);
+
+    let mut copyright = b"text\0\0\0\0".to_vec();
+    copyright.extend(b"No copyright, use freely\0");
+
+    let mut curve = b"curv\0\0\0\0".to_vec();
+    curve.extend((CURVE_POINTS as u32).to_be_bytes());
+    for index in 0..CURVE_POINTS {
+        let encoded = index as f64 / (CURVE_POINTS - 1) as f64;
+        let linear = if encoded <= 0.04045 { encoded / 12.92 } else { ((encoded + 0.055) / 1.055).powf(2.4) };
+        curve.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+
+    let elements: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", desc),
+        (b"cprt", copyright),
+        (b"wtpt", xyz(0.95045, 1.0, 1.08905)),
+        (b"rXYZ", xyz(0.43607, 0.22249, 0.01392)),
+        (b"gXYZ", xyz(0.38515, 0.71687, 0.09708)),
+        (b"bXYZ", xyz(0.14307, 0.06061, 0.71410)),
+        (b"rTRC", curve),
+    ];
+    // The three channels share one tone curve
+    let tags: Vec<(&[u8; 4], usize)> = vec![
+        (b"desc", 0), (b"cprt", 1), (b"wtpt", 2), (b"rXYZ", 3), (b"gXYZ", 4), (b"bXYZ", 5), (b"rTRC", 6), (b"gTRC", 6), (b"bTRC", 6),
+    ];
+
+    let mut data_offset = 128 + 4 + 12 * tags.len();
+    let mut element_positions = Vec::with_capacity(elements.len());
+    let mut element_data = Vec::new();
+    for (_, data) in &elements {
+        element_positions.push((data_offset, data.len()));
+        element_data.extend(data);
+        while element_data.len() % 4 != 0 {
+            element_data.push(0);
+        }
+        data_offset = 128 + 4 + 12 * tags.len() + element_data.len();
+    }
+
+    let mut profile = Vec::with_capacity(data_offset);
+    profile.extend((data_offset as u32).to_be_bytes());
+    profile.extend([0u8; 4]);
+    profile.extend(0x0210_0000u32.to_be_bytes());
+    profile.extend(b"mntrRGB XYZ ");
+    // Creation date 2024-01-01 00:00:00
+    for value in [2024u16, 1, 1, 0, 0, 0] {
+        profile.extend(value.to_be_bytes());
+    }
+    profile.extend(b"acsp");
+    // Platform, flags, manufacturer, model, attributes and rendering intent
+    profile.extend([0u8; 4 + 4 + 4 + 4 + 8 + 4]);
+    profile.extend(s15_fixed16(0.9642));
+    profile.extend(s15_fixed16(1.0));
+    profile.extend(s15_fixed16(0.8249));
+    // Creator, profile ID and reserved bytes
+    profile.extend([0u8; 4 + 16 + 28]);
+
+    profile.extend((tags.len() as u32).to_be_bytes());
+    for (signature, element) in &tags {
+        let (offset, size) = element_positions[*element];
+        profile.extend(*signature);
+        profile.extend((offset as u32).to_be_bytes());
+        profile.extend((size as u32).to_be_bytes());
+    }
+    profile.extend(element_data);
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_profile_structure() {
+        let profile = srgb_icc_profile();
+        let read_u32 = |offset: usize| u32::from_be_bytes(profile[offset..offset + 4].try_into().unwrap());
+
+        assert_eq!(read_u32(0) as usize, profile.len());
+        assert_eq!(&profile[12..24], b"mntrRGB XYZ ");
+        assert_eq!(&profile[36..40], b"acsp");
+        assert_eq!(read_u32(128), 9);
+
+        // Every tag points at data of its type within the profile
+        for tag in 0..9 {
+            let entry = 132 + tag * 12;
+            let (offset, size) = (read_u32(entry + 4) as usize, read_u32(entry + 8) as usize);
+            assert_eq!(offset % 4, 0);
+            assert!(offset + size <= profile.len());
+            let expected: &[u8] = match &profile[entry..entry + 4] {
+                b"desc" => b"desc",
+                b"cprt" => b"text",
+                b"rTRC" | b"gTRC" | b"bTRC" => b"curv",
+                _ => b"XYZ ",
+            };
+            assert_eq!(&profile[offset..offset + 4], expected);
+        }
+    }
+
+    #[test]
+    fn test_binary_comment_keeps_every_object_resolvable() {
+        use printpdf::lopdf::xref::XrefType;
+
+        let mut document = lopdf::Document::with_version("1.7");
+        let pages = document.new_object_id();
+        let catalog = document.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Pages", Object::Reference(pages)),
+        ]));
+        document.objects.insert(pages, Object::Dictionary(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Pages".to_vec())),
+            ("Kids", Object::Array(Vec::new())),
+            ("Count", Object::Integer(0)),
+        ])));
+        // Stream content that looks like file structure must not confuse the patching
+        let content = b"xref\n0 1\n0000000000 65535 f \ntrailer\nstartxref\n0\n%%EOF".to_vec();
+        let stream = document.add_object(Stream::new(Dictionary::new(), content.clone()));
+        document.trailer.set("Root", Object::Reference(catalog));
+        document.trailer.set("ID", Object::Array(vec![Object::string_literal("a\u{0}b"), Object::string_literal("a\u{0}b")]));
+
+        for cross_reference_type in [XrefType::CrossReferenceTable, XrefType::CrossReferenceStream] {
+            document.reference_table.cross_reference_type = cross_reference_type;
+            let mut bytes = Vec::new();
+            document.save_to(&mut bytes).unwrap();
+            let patched = add_binary_comment(&bytes).unwrap();
+            assert!(patched.starts_with(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n"));
+
+            let reloaded = lopdf::Document::load_mem(&patched).unwrap();
+            assert_eq!(reloaded.objects.len(), 3);
+            for (id, entry) in &reloaded.reference_table.entries {
+                if let XrefEntry::Normal { offset, generation } = entry {
+                    assert!(patched[*offset as usize..].starts_with(format!("{} {} obj", id, generation).as_bytes()));
+                }
+            }
+            assert_eq!(reloaded.catalog().unwrap().get(b"Type").and_then(Object::as_name_str).unwrap(), "Catalog");
+            assert_eq!(reloaded.get_dictionary(pages).unwrap().get(b"Count").and_then(Object::as_i64).unwrap(), 0);
+            assert_eq!(reloaded.get_object(stream).and_then(Object::as_stream).unwrap().content, content);
+            let id = reloaded.trailer.get(b"ID").and_then(Object::as_array).unwrap();
+            assert_eq!(id[0].as_str().unwrap(), b"a\0b");
+        }
+    }
+
+    #[test]
+    fn test_xmp_mirrors_info_dictionary() {
+        let info = Dictionary::from_iter(vec![
+            ("Title", text_string("Archiv <Müller & Co>")),
+            ("Producer", Object::string_literal("outlook-archiver")),
+            ("CreationDate", Object::string_literal("D:20240131143000+01'00'")),
+            ("SessionID", Object::string_literal("4f2c")),
+        ]);
+        let xmp = xmp_packet(&info, Some(3));
+
+        assert!(xmp.contains("<pdfaid:part>3</pdfaid:part>"));
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Archiv &lt;Müller &amp; Co&gt;</rdf:li>"));
+        assert!(xmp.contains("<pdf:Producer>outlook-archiver</pdf:Producer>"));
+        assert!(xmp.contains("<xmp:CreateDate>2024-01-31T14:30:00+01:00</xmp:CreateDate>"));
+        assert!(!xmp.contains("dc:creator"));
+
+        // Custom entries need an extension schema in PDF/A only
+        assert!(xmp.contains("<archive:SessionID>4f2c</archive:SessionID>"));
+        assert!(xmp.contains("<pdfaProperty:name>SessionID</pdfaProperty:name>"));
+        let plain = xmp_packet(&info, None);
+        assert!(plain.contains("<archive:SessionID>4f2c</archive:SessionID>"));
+        assert!(!plain.contains("pdfaid:part") && !plain.contains("pdfaExtension:schemas"));
+
+        assert_eq!(xmp_date("D:20240131143000Z").as_deref(), Some("2024-01-31T14:30:00Z"));
+        assert_eq!(xmp_date("D:2024"), None);
+    }
+
+    #[test]
+    fn test_check_reports_missing_requirements() {
+        let mut document = lopdf::Document::with_version("1.7");
+        let font = document.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Font".to_vec())),
+            ("Subtype", Object::Name(b"Type1".to_vec())),
+            ("BaseFont", Object::Name(b"Helvetica".to_vec())),
+        ]));
+        let catalog = document.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Fonts", Object::Reference(font)),
+        ]));
+        document.trailer.set("Root", Object::Reference(catalog));
+
+        let violations = check_pdfa(&document, PdfStandard::PdfA3b);
+        assert!(violations.contains(&"catalog has no XMP metadata".to_string()));
+        assert!(violations.contains(&"no PDF/A output intent with an RGB profile".to_string()));
+        assert!(violations.contains(&"font Helvetica is not embedded".to_string()));
+        assert!(check_pdfa(&document, PdfStandard::Plain).is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+use std::fmt;
+use base64::Engine;
+use printpdf::lopdf::{self, Dictionary, Object, ObjectId, Stream, StringFormat};
+use crate::errors::{PdfError, PdfResult};
+use crate::types::{Attachment, Email, DEFAULT_MAX_EMBEDDED_FILE_MB, DEFAULT_MAX_EMBEDDED_PDF_MB};
+
+/// Default size limit for a single embedded attachment in bytes
+pub const DEFAULT_MAX_EMBEDDED_FILE_SIZE: usize = DEFAULT_MAX_EMBEDDED_FILE_MB as usize * 1024 * 1024;
+
+/// Default size limit for all attachments embedded into one PDF in bytes
+pub const DEFAULT_MAX_EMBEDDED_PDF_SIZE: usize = DEFAULT_MAX_EMBEDDED_PDF_MB as usize * 1024 * 1024;
+
+/// Edge length of the paperclip icon of an embedded file in mm
+const ICON_SIZE: f32 = 4.0;
+
+/// Icons placed side by side before a new row starts
+const ICONS_PER_ROW: usize = 4;
+
+/// Conversion factor from mm to PDF points
+pub(crate) const POINTS_PER_MM: f32 = 72.0 / 25.4;
+
+/// Size limits for attachments embedded as files into a PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddingLimits {
+    /// Largest single attachment in bytes
+    pub max_file_size: usize,
+    /// Largest total size of all attachments in one PDF in bytes
+    pub max_pdf_size: usize,
+}
+
+impl Default for EmbeddingLimits {
+    fn default() -> Self {
+        Self {
+            max_file_size: DEFAULT_MAX_EMBEDDED_FILE_SIZE,
+            max_pdf_size: DEFAULT_MAX_EMBEDDED_PDF_SIZE,
+        }
+    }
+}
+
+/// Why an attachment was not embedded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The mail source did not provide the attachment's content
+    NoData,
+    /// The attachment alone exceeds the per-file limit
+    FileTooLarge { limit: usize },
+    /// Embedding it would exceed the limit for the whole PDF
+    PdfLimitReached { limit: usize },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NoData => write!(f, "content not available"),
+            SkipReason::FileTooLarge { limit } => write!(f, "larger than the limit of {} per file", format_megabytes(*limit)),
+            SkipReason::PdfLimitReached { limit } => write!(f, "limit of {} per PDF reached", format_megabytes(*limit)),
+        }
+    }
+}
+
+fn format_megabytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// An attachment queued for embedding, with the position of its icon
+#[derive(Debug)]
+struct EmbeddedFile {
+    name: String,
+    content_type: String,
+    data: Vec<u8>,
+    /// Page index and lower left corner of the icon in mm, once placed
+    position: Option<(usize, f32, f32)>,
+}
+
+/// Collects the attachments embedded into one PDF while its pages are laid out
+///
+/// Attachments are queued per email and placed as paperclip icons next to that
+/// email's header. After the document is saved, `embed` writes them into the
+/// PDF's `EmbeddedFiles` name tree with a `FileAttachment` annotation each, and
+/// associates them with the document as their source (PDF/A-3 associated files).
+#[derive(Debug)]
+pub struct AttachmentEmbedder {
+    limits: EmbeddingLimits,
+    total_size: usize,
+    files: Vec<EmbeddedFile>,
+}
+
+impl AttachmentEmbedder {
+    pub fn new(limits: EmbeddingLimits) -> Self {
+        Self { limits, total_size: 0, files: Vec::new() }
+    }
+
+    /// Queue the attachments of an email and return the ones that cannot be embedded
+    pub fn add_email<'a>(&mut self, email: &'a Email) -> Vec<(&'a Attachment, SkipReason)> {
+        let mut skipped = Vec::new();
+        for attachment in &email.attachments {
+            let data = attachment
+                .data
+                .as_deref()
+                .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok());
+            let Some(data) = data else {
+                skipped.push((attachment, SkipReason::NoData));
+                continue;
+            };
+
+            if data.len() > self.limits.max_file_size {
+                skipped.push((attachment, SkipReason::FileTooLarge { limit: self.limits.max_file_size }));
+            } else if self.total_size + data.len() > self.limits.max_pdf_size {
+                skipped.push((attachment, SkipReason::PdfLimitReached { limit: self.limits.max_pdf_size }));
+            } else {
+                self.total_size += data.len();
+                self.files.push(EmbeddedFile {
+                    name: attachment.name.clone(),
+                    content_type: attachment.content_type.clone(),
+                    data,
+                    position: None,
+                });
+            }
+        }
+        skipped
+    }
+
+    /// Place the icons of the attachments queued since the last call in rows
+    /// starting at `x`/`y` (top edge, in mm) on the page with index `page`
+    pub fn place(&mut self, page: usize, x: f32, y: f32) {
+        let unplaced = self.files.iter_mut().filter(|file| file.position.is_none());
+        for (index, file) in unplaced.enumerate() {
+            let column = (index % ICONS_PER_ROW) as f32;
+            let row = (index / ICONS_PER_ROW) as f32;
+            let step = ICON_SIZE + 1.0;
+            file.position = Some((page, x + column * step, y - ICON_SIZE - row * step));
+        }
+    }
+
+    /// Whether no attachment has been queued
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Add the queued attachments to a saved PDF
+    pub fn embed(&self, document: &mut lopdf::Document) -> PdfResult<()> {
+        let pages = document.get_pages();
+        let appearance = add_icon_appearance(document);
+
+        let mut names = Vec::with_capacity(self.files.len() * 2);
+        let mut file_specs = Vec::with_capacity(self.files.len());
+        for (index, file) in self.files.iter().enumerate() {
+            let file_spec = add_file_spec(document, file);
+            file_specs.push(Object::Reference(file_spec));
+
+            // Keys of a name tree must be unique and sorted
+            names.push(Object::string_literal(format!("{:05} {}", index + 1, ascii_file_name(&file.name))));
+            names.push(Object::Reference(file_spec));
+
+            let Some((page, x, y)) = file.position else { continue };
+            let Some(&page_id) = pages.get(&(page as u32 + 1)) else { continue };
+            let rect = [x, y, x + ICON_SIZE, y + ICON_SIZE]
+                .iter()
+                .map(|mm| Object::Real(mm * POINTS_PER_MM))
+                .collect::<Vec<_>>();
+            let annotation = document.add_object(Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"Annot".to_vec())),
+                ("Subtype", Object::Name(b"FileAttachment".to_vec())),
+                ("Rect", Object::Array(rect)),
+                ("FS", Object::Reference(file_spec)),
+                ("Contents", text_string(&file.name)),
+                ("Name", Object::Name(b"Paperclip".to_vec())),
+                ("AP", Object::Dictionary(Dictionary::from_iter(vec![("N", Object::Reference(appearance))]))),
+                // Printable
+                ("F", Object::Integer(4)),
+            ]));
+            add_annotation(document, page_id, annotation)?;
+        }
+
+        let embedded_files = Dictionary::from_iter(vec![("Names", Object::Array(names))]);
+        let catalog = document.catalog_mut().map_err(embedding_error)?;
+        match catalog.get_mut(b"Names").and_then(Object::as_dict_mut) {
+            Ok(name_dictionary) => name_dictionary.set("EmbeddedFiles", embedded_files),
+            Err(_) => catalog.set("Names", Dictionary::from_iter(vec![("EmbeddedFiles", Object::Dictionary(embedded_files))])),
+        }
+        catalog.set("AF", Object::Array(file_specs));
+        Ok(())
+    }
+}
+
+/// Add the appearance shared by all attachment icons: a framed paperclip
+fn add_icon_appearance(document: &mut lopdf::Document) -> ObjectId {
+    let size = ICON_SIZE * POINTS_PER_MM;
+    let content = format!(
+        "0.2 0.3 0.6 RG 0.8 w 0.5 0.5 {inner} {inner} re S {a} {b} m {a} {c} l {d} {c} l {d} {e} l S",
+        inner = size - 1.0,
+        a = size * 0.35,
+        b = size * 0.75,
+        c = size * 0.2,
+        d = size * 0.65,
+        e = size * 0.6,
+    );
+    document.add_object(Stream::new(
+        Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"XObject".to_vec())),
+            ("Subtype", Object::Name(b"Form".to_vec())),
+            ("BBox", Object::Array(vec![0.into(), 0.into(), Object::Real(size), Object::Real(size)])),
+        ]),
+        content.into_bytes(),
+    ))
+}
+
+/// Add the content stream and file specification of an embedded file
+fn add_file_spec(document: &mut lopdf::Document, file: &EmbeddedFile) -> ObjectId {
+    let mut stream = Stream::new(
+        Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"EmbeddedFile".to_vec())),
+            ("Subtype", Object::Name(mime_type(&file.content_type).into_bytes())),
+            ("Params", Object::Dictionary(Dictionary::from_iter(vec![("Size", Object::Integer(file.data.len() as i64))]))),
+        ]),
+        file.data.clone(),
+    );
+    // Already compressed formats keep their size, so the stream stays as is
+    let _ = stream.compress();
+    let stream = document.add_object(stream);
+
+    document.add_object(Dictionary::from_iter(vec![
+        ("Type", Object::Name(b"Filespec".to_vec())),
+        ("F", Object::string_literal(ascii_file_name(&file.name))),
+        ("UF", text_string(&file.name)),
+        ("Desc", text_string(&file.name)),
+        ("AFRelationship", Object::Name(b"Source".to_vec())),
+        ("EF", Object::Dictionary(Dictionary::from_iter(vec![
+            ("F", Object::Reference(stream)),
+            ("UF", Object::Reference(stream)),
+        ]))),
+    ]))
+}
+
+/// Append an annotation to the `Annots` array of a page
+pub(crate) fn add_annotation(document: &mut lopdf::Document, page_id: ObjectId, annotation: ObjectId) -> PdfResult<()> {
+    let page = document
+        .get_dictionary_mut(page_id)
+        .map_err(|e| PdfError::GenerationFailed(format!("Failed to add annotation: {}", e)))?;
+    match page.get_mut(b"Annots").and_then(Object::as_array_mut) {
+        Ok(annotations) => annotations.push(Object::Reference(annotation)),
+        Err(_) => page.set("Annots", Object::Array(vec![Object::Reference(annotation)])),
+    }
+    Ok(())
+}
+
+/// The MIME type of an attachment in lower case, or the generic binary type when it has none
+fn mime_type(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let valid = essence.split_once('/').is_some_and(|(kind, subtype)| !kind.is_empty() && !subtype.is_empty());
+    if valid { essence } else { "application/octet-stream".to_string() }
+}
+
+/// A PDF text string: literal for ASCII, UTF-16BE with byte order mark otherwise
+pub(crate) fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::string_literal(text)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+}
+
+/// File name for the legacy `F` entry, which readers expect in ASCII
+fn ascii_file_name(name: &str) -> String {
+    name.chars().map(|ch| if ch.is_ascii() && !ch.is_ascii_control() { ch } else { '_' }).collect()
+}
+
+fn embedding_error(error: lopdf::Error) -> PdfError {
+    PdfError::GenerationFailed(format!("Failed to embed attachments: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn email_with_attachments(sizes: &[usize]) -> Email {
+        let mut email = Email::new(String::new(), String::new(), String::new(), Utc::now(), String::new());
+        for (index, size) in sizes.iter().enumerate() {
+            let mut attachment = Attachment::new(format!("Anlage {}.bin", index + 1), *size, "application/octet-stream".to_string());
+            attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(vec![b'x'; *size]));
+            email.attachments.push(attachment);
+        }
+        email
+    }
+
+    #[test]
+    fn test_limits_per_file_and_per_pdf() {
+        let mut embedder = AttachmentEmbedder::new(EmbeddingLimits { max_file_size: 100, max_pdf_size: 150 });
+
+        let mut email = email_with_attachments(&[80, 120, 90]);
+        email.attachments.push(Attachment::new("ohne Daten.txt".to_string(), 10, "text/plain".to_string()));
+        let skipped = embedder.add_email(&email);
+
+        let reasons: Vec<(&str, &SkipReason)> = skipped.iter().map(|(attachment, reason)| (attachment.name.as_str(), reason)).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("Anlage 2.bin", &SkipReason::FileTooLarge { limit: 100 }),
+                ("Anlage 3.bin", &SkipReason::PdfLimitReached { limit: 150 }),
+                ("ohne Daten.txt", &SkipReason::NoData),
+            ]
+        );
+        assert_eq!(embedder.files.len(), 1);
+        assert_eq!(SkipReason::FileTooLarge { limit: 25 * 1024 * 1024 }.to_string(), "larger than the limit of 25.0 MB per file");
+    }
+
+    #[test]
+    fn test_places_icons_in_rows() {
+        let mut embedder = AttachmentEmbedder::new(EmbeddingLimits::default());
+        embedder.add_email(&email_with_attachments(&[1, 1, 1, 1, 1]));
+        embedder.place(2, 190.0, 250.0);
+        embedder.add_email(&email_with_attachments(&[1]));
+        embedder.place(3, 190.0, 100.0);
+
+        let positions: Vec<(usize, f32, f32)> = embedder.files.iter().filter_map(|file| file.position).collect();
+        assert_eq!(positions[0], (2, 190.0, 246.0));
+        assert_eq!(positions[3], (2, 205.0, 246.0));
+        assert_eq!(positions[4], (2, 190.0, 241.0));
+        assert_eq!(positions[5], (3, 190.0, 96.0));
+    }
+
+    #[test]
+    fn test_text_strings_use_utf16_for_non_ascii() {
+        assert!(matches!(text_string("a.pdf"), Object::String(bytes, StringFormat::Literal) if bytes == b"a.pdf"));
+        assert!(matches!(text_string("ü"), Object::String(bytes, StringFormat::Hexadecimal) if bytes == [0xFE, 0xFF, 0x00, 0xFC]));
+        assert_eq!(ascii_file_name("Übersicht.pdf"), "_bersicht.pdf");
+        assert_eq!(mime_type("Text/Plain; charset=utf-8"), "text/plain");
+        assert_eq!(mime_type(""), "application/octet-stream");
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use printpdf::{BuiltinFont, IndirectFontRef, PdfDocumentReference};
+use crate::errors::{PdfError, PdfResult};
+
+/// Directory in the application resources that holds the fonts shipped with the application
+pub const BUNDLED_FONT_DIR: &str = "fonts";
+
+/// Resource directory of the running application, set once at startup
+static RESOURCE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Bundled fonts, loaded on first use and shared by every generator
+static BUNDLED_FONTS: OnceLock<FontChain> = OnceLock::new();
+
+/// Record the resource directory the bundled fonts are loaded from
+/// Must be called before the first PDF is generated; later calls are ignored
+pub fn set_resource_dir(directory: PathBuf) {
+    let _ = RESOURCE_DIR.set(directory);
+}
+
+/// A TrueType/OpenType font file and the characters it can draw
+#[derive(Debug)]
+pub struct FontFile {
+    path: PathBuf,
+    data: Vec<u8>,
+    /// Advance width of every covered character, as a fraction of the font size
+    advances: HashMap<char, f32>,
+    /// Bold face found next to the regular one ("Name-Bold.ttf")
+    bold: Option<Arc<FontFile>>,
+}
+
+impl FontFile {
+    /// Load a font file, together with its bold sibling if one exists
+    pub fn load(path: &Path) -> PdfResult<Self> {
+        let mut font = Self::load_face(path)?;
+
+        let bold_path = path.with_file_name(format!(
+            "{}-Bold.{}",
+            path.file_stem().unwrap_or_default().to_string_lossy(),
+            path.extension().unwrap_or_default().to_string_lossy()
+        ));
+        if bold_path != path && bold_path.is_file() {
+            font.bold = Self::load_face(&bold_path).ok().map(Arc::new);
+        }
+
+        Ok(font)
+    }
+
+    fn load_face(path: &Path) -> PdfResult<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| PdfError::FormattingError(format!("Failed to read font {}: {}", path.display(), e)))?;
+        let face = ttf_parser::Face::from_slice(&data, 0)
+            .map_err(|e| PdfError::FormattingError(format!("Invalid font {}: {}", path.display(), e)))?;
+
+        let units_per_em = face.units_per_em().unwrap_or(1000) as f32;
+        let mut advances = HashMap::new();
+        for subtable in face.character_mapping_subtables().filter(|subtable| subtable.is_unicode()) {
+            subtable.codepoints(|codepoint| {
+                let glyph = subtable.glyph_index(codepoint).filter(|glyph| glyph.0 != 0);
+                if let (Some(ch), Some(glyph)) = (char::from_u32(codepoint), glyph) {
+                    let advance = face.glyph_hor_advance(glyph).unwrap_or(0) as f32;
+                    advances.insert(ch, advance / units_per_em);
+                }
+            });
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            data,
+            advances,
+            bold: None,
+        })
+    }
+
+    /// Path the font was loaded from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the font has a glyph for `ch`
+    pub fn covers(&self, ch: char) -> bool {
+        self.advances.contains_key(&ch)
+    }
+
+    /// Advance width of `ch` as a fraction of the font size (0 if not covered)
+    pub fn advance(&self, ch: char) -> f32 {
+        self.advances.get(&ch).copied().unwrap_or(0.0)
+    }
+}
+
+/// Typeface variant requested for a piece of text
+///
+/// Embedded fonts only provide regular and bold faces; italic text that is drawn
+/// with an embedded font stays upright.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+    /// Fixed-width text, drawn with the built-in Courier where it has the glyphs
+    pub monospace: bool,
+}
+
+impl FontStyle {
+    pub const REGULAR: Self = Self { bold: false, italic: false, monospace: false };
+    pub const BOLD: Self = Self { bold: true, italic: false, monospace: false };
+
+    /// Index into the built-in fonts registered by `FontChain::register`
+    fn builtin_index(self) -> usize {
+        self.bold as usize | (self.italic as usize) << 1 | (self.monospace as usize) << 2
+    }
+}
+
+/// Built-in fonts in the order of `FontStyle::builtin_index`
+const BUILTIN_FONTS: [BuiltinFont; 8] = [
+    BuiltinFont::Helvetica,
+    BuiltinFont::HelveticaBold,
+    BuiltinFont::HelveticaOblique,
+    BuiltinFont::HelveticaBoldOblique,
+    BuiltinFont::Courier,
+    BuiltinFont::CourierBold,
+    BuiltinFont::CourierOblique,
+    BuiltinFont::CourierBoldOblique,
+];
+
+/// Fonts used for PDF text in fallback order
+///
+/// Each character is drawn with the first font that has a glyph for it. The
+/// built-in Helvetica (WinAnsi only) is always the last entry, so documents
+/// can still be generated when no font files are available.
+#[derive(Debug, Clone, Default)]
+pub struct FontChain {
+    fonts: Vec<Arc<FontFile>>,
+}
+
+impl FontChain {
+    /// Load the given font files in order
+    pub fn load(paths: &[PathBuf]) -> PdfResult<Self> {
+        let fonts = paths
+            .iter()
+            .map(|path| FontFile::load(path).map(Arc::new))
+            .collect::<PdfResult<Vec<_>>>()?;
+        Ok(Self { fonts })
+    }
+
+    /// Fonts shipped in `BUNDLED_FONT_DIR` of the application resources, sorted by file name
+    ///
+    /// Without a resource directory (tests, development builds) the directory next to the
+    /// executable is used. The files are read once per process; later calls share them.
+    pub fn bundled() -> Self {
+        BUNDLED_FONTS
+            .get_or_init(|| {
+                let directory = RESOURCE_DIR.get().cloned().or_else(|| {
+                    std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf))
+                });
+                match directory {
+                    Some(directory) => Self::from_directory(&directory.join(BUNDLED_FONT_DIR)),
+                    None => Self::default(),
+                }
+            })
+            .clone()
+    }
+
+    /// All regular font files in `directory`, sorted by file name
+    /// Bold faces are picked up through their regular sibling; unreadable files are skipped
+    pub fn from_directory(directory: &Path) -> Self {
+        let Ok(entries) = std::fs::read_dir(directory) else {
+            return Self::default();
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_font_file(path) && !is_bold_variant(path))
+            .collect();
+        paths.sort();
+
+        let fonts = paths
+            .iter()
+            .filter_map(|path| match FontFile::load(path) {
+                Ok(font) => Some(Arc::new(font)),
+                Err(e) => {
+                    eprintln!("Warning: Skipping font {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Self { fonts }
+    }
+
+    /// Append the fonts of `fallback` after the fonts of this chain
+    pub fn followed_by(mut self, fallback: FontChain) -> Self {
+        self.fonts.extend(fallback.fonts);
+        self
+    }
+
+    /// Font files of the chain, without the built-in fallback
+    pub fn fonts(&self) -> &[Arc<FontFile>] {
+        &self.fonts
+    }
+
+    /// Add the fonts to a document; embedded fonts are subset to the glyphs used
+    pub fn register(&self, doc: &PdfDocumentReference) -> PdfResult<DocumentFonts> {
+        let embedded = self.embed(doc)?;
+        let builtin = BUILTIN_FONTS
+            .iter()
+            .map(|font| {
+                doc.add_builtin_font(*font)
+                    .map_err(|e| PdfError::FormattingError(format!("Failed to load font {:?}: {}", font, e)))
+            })
+            .collect::<PdfResult<Vec<_>>>()?;
+
+        Ok(DocumentFonts { embedded, builtin })
+    }
+
+    /// Add only the font files to a document, without the built-in fallback
+    ///
+    /// PDF/A requires every font to be embedded, so characters none of the files
+    /// can draw are replaced with `MISSING_GLYPH_SUBSTITUTE` instead.
+    pub fn register_embedded(&self, doc: &PdfDocumentReference) -> PdfResult<DocumentFonts> {
+        if self.fonts.is_empty() {
+            return Err(PdfError::FormattingError("No font file available for embedding".to_string()));
+        }
+        Ok(DocumentFonts { embedded: self.embed(doc)?, builtin: Vec::new() })
+    }
+
+    fn embed(&self, doc: &PdfDocumentReference) -> PdfResult<Vec<EmbeddedFont>> {
+        let embed = |font: &FontFile| {
+            doc.add_external_font_with_subsetting(Cursor::new(font.data.clone()), true)
+                .map_err(|e| PdfError::FormattingError(format!("Failed to embed font {}: {}", font.path.display(), e)))
+        };
+
+        let mut embedded = Vec::with_capacity(self.fonts.len());
+        for font in &self.fonts {
+            let regular = embed(font)?;
+            let bold = match &font.bold {
+                Some(bold) => Some(embed(bold)?),
+                None => None,
+            };
+            embedded.push(EmbeddedFont { file: font.clone(), regular, bold });
+        }
+        Ok(embedded)
+    }
+}
+
+/// Drawn in place of characters without a glyph when no built-in fonts are available
+const MISSING_GLYPH_SUBSTITUTE: char = '?';
+
+/// Millimetres per PDF point
+const MM_PER_POINT: f32 = 25.4 / 72.0;
+
+/// Which font of a `DocumentFonts` draws a character
+#[derive(Debug, Clone, Copy)]
+enum FontSelection {
+    Embedded(usize),
+    Builtin,
+}
+
+#[derive(Debug)]
+struct EmbeddedFont {
+    file: Arc<FontFile>,
+    regular: IndirectFontRef,
+    bold: Option<IndirectFontRef>,
+}
+
+/// The fonts of a font chain as registered in one PDF document
+#[derive(Debug)]
+pub struct DocumentFonts {
+    embedded: Vec<EmbeddedFont>,
+    /// Built-in fonts indexed by `FontStyle::builtin_index`; empty when only embedded fonts may be used
+    builtin: Vec<IndirectFontRef>,
+}
+
+impl DocumentFonts {
+    /// Split `text` into runs that are each drawn with a single font
+    /// Characters no font can draw are kept with the first font of the chain
+    pub fn runs(&self, text: &str, style: FontStyle) -> Vec<(&IndirectFontRef, String)> {
+        let mut runs: Vec<(&IndirectFontRef, String)> = Vec::new();
+        for ch in text.chars().map(|ch| self.drawable(ch)) {
+            // Spaces continue the current run so runs are not split needlessly
+            if let (' ', Some((_, run))) = (ch, runs.last_mut()) {
+                run.push(ch);
+                continue;
+            }
+
+            let font = self.font_for(ch, style);
+            match runs.last_mut() {
+                Some((current, run)) if std::ptr::eq(*current, font) => run.push(ch),
+                _ => runs.push((font, ch.to_string())),
+            }
+        }
+        runs
+    }
+
+    /// Rendered width of `text` in millimetres, measured with the font that draws each character
+    pub fn text_width(&self, text: &str, style: FontStyle, font_size: f32) -> f32 {
+        let mut em_width = 0.0;
+        let mut current: Option<FontSelection> = None;
+        for ch in text.chars().map(|ch| self.drawable(ch)) {
+            // Spaces are drawn with the font of the current run, like in `runs`
+            let selection = match (ch, current) {
+                (' ', Some(selection)) => selection,
+                _ => self.select(ch, style),
+            };
+            em_width += match selection {
+                FontSelection::Embedded(index) => self.embedded[index].advance(ch, style.bold),
+                FontSelection::Builtin if style.monospace => COURIER_WIDTH as f32 / 1000.0,
+                FontSelection::Builtin => helvetica_width(ch, style.bold) as f32 / 1000.0,
+            };
+            current = Some(selection);
+        }
+        em_width * font_size * MM_PER_POINT
+    }
+
+    fn font_for(&self, ch: char, style: FontStyle) -> &IndirectFontRef {
+        match self.select(ch, style) {
+            FontSelection::Embedded(index) => self.embedded[index].face(style.bold),
+            FontSelection::Builtin => &self.builtin[style.builtin_index()],
+        }
+    }
+
+    /// First font that can draw `ch`; the first embedded font when none can
+    /// Monospace text prefers Courier, since the embedded fonts are proportional
+    fn select(&self, ch: char, style: FontStyle) -> FontSelection {
+        let builtin_covers = !self.builtin.is_empty() && is_win_ansi(ch);
+        if style.monospace && builtin_covers {
+            return FontSelection::Builtin;
+        }
+
+        let fallback = if self.embedded.is_empty() { FontSelection::Builtin } else { FontSelection::Embedded(0) };
+        self.embedded
+            .iter()
+            .position(|font| font.file.covers(ch))
+            .map(FontSelection::Embedded)
+            .or_else(|| builtin_covers.then_some(FontSelection::Builtin))
+            .unwrap_or(fallback)
+    }
+
+    /// `ch`, or its substitute when only embedded fonts are available and none has a glyph for it
+    fn drawable(&self, ch: char) -> char {
+        let missing = self.builtin.is_empty() && !self.embedded.iter().any(|font| font.file.covers(ch));
+        if missing { MISSING_GLYPH_SUBSTITUTE } else { ch }
+    }
+}
+
+impl EmbeddedFont {
+    /// Advance width of `ch` in the face used for regular or bold text
+    fn advance(&self, ch: char, bold: bool) -> f32 {
+        match (&self.file.bold, &self.bold, bold) {
+            (Some(bold_file), Some(_), true) if bold_file.covers(ch) => bold_file.advance(ch),
+            _ => self.file.advance(ch),
+        }
+    }
+
+    fn face(&self, bold: bool) -> &IndirectFontRef {
+        match (&self.bold, bold) {
+            (Some(bold_face), true) => bold_face,
+            _ => &self.regular,
+        }
+    }
+}
+
+fn is_font_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| ext == "ttf" || ext == "otf")
+}
+
+fn is_bold_variant(path: &Path) -> bool {
+    path.file_stem()
+        .is_some_and(|stem| stem.to_string_lossy().ends_with("-Bold"))
+}
+
+/// Whether the built-in fonts can draw `ch` (Windows-1252 / WinAnsiEncoding)
+fn is_win_ansi(ch: char) -> bool {
+    const CP1252_EXTRAS: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+    matches!(ch, ' '..='~' | '\u{a0}'..='\u{ff}') || CP1252_EXTRAS.contains(ch)
+}
+
+/// Helvetica and Helvetica-Bold advance widths (1/1000 em) of the printable ASCII characters
+const HELVETICA_ASCII_WIDTHS: [[u16; 95]; 2] = [
+    [
+        278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+        556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+        1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+        667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+        333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+        556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+    ],
+    [
+        278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+        556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+        975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+        667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+        333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+        611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+    ],
+];
+
+/// Advance width (1/1000 em) of every Courier character
+const COURIER_WIDTH: u16 = 600;
+
+/// Latin-1 letters with diacritics and the base letter sharing their Helvetica width
+const HELVETICA_ACCENTED: &[(&str, char)] = &[
+    ("ÀÁÂÃÄÅ", 'A'), ("Ç", 'C'), ("ÈÉÊË", 'E'), ("ÌÍÎÏ", 'I'), ("Ñ", 'N'), ("ÒÓÔÕÖØ", 'O'),
+    ("ÙÚÛÜ", 'U'), ("ÝŸ", 'Y'), ("Š", 'S'), ("Ž", 'Z'), ("àáâãäå", 'a'), ("ç", 'c'),
+    ("èéêë", 'e'), ("ìíîï", 'i'), ("ñ", 'n'), ("òóôõöø", 'o'), ("ùúûü", 'u'), ("ýÿ", 'y'),
+    ("š", 's'), ("ž", 'z'),
+];
+
+/// Advance width (1/1000 em) of a WinAnsi character in Helvetica or Helvetica-Bold
+/// The oblique faces share the widths of the upright ones
+fn helvetica_width(ch: char, bold: bool) -> u16 {
+    let widths = &HELVETICA_ASCII_WIDTHS[bold as usize];
+    let ascii_width = |ch: char| widths[ch as usize - 0x20];
+
+    match ch {
+        ' '..='~' => ascii_width(ch),
+        'ß' => 611,
+        'Æ' | 'Œ' => 1000,
+        'æ' | 'œ' => 889,
+        '€' | '•' | '–' => 556,
+        '—' | '…' | '‰' | '™' => 1000,
+        '\u{a0}' => 278,
+        _ => HELVETICA_ACCENTED
+            .iter()
+            .find(|(accented, _)| accented.contains(ch))
+            .map(|(_, base)| ascii_width(*base))
+            .unwrap_or(556),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printpdf::{Mm, PdfDocument};
+    use crate::test_support::bundled_font;
+
+    /// DejaVu fonts are installed on most Linux systems; tests that need real fonts skip without them
+    const SYSTEM_FONT_DIR: &str = "/usr/share/fonts/truetype/dejavu";
+
+    #[test]
+    fn test_win_ansi_coverage() {
+        assert!(is_win_ansi('a'));
+        assert!(is_win_ansi('ü'));
+        assert!(is_win_ansi('€'));
+        assert!(!is_win_ansi('ł'));
+        assert!(!is_win_ansi('Ж'));
+    }
+
+    #[test]
+    fn test_builtin_only_chain_uses_helvetica() {
+        let (doc, _, _) = PdfDocument::new("test", Mm(210.0), Mm(297.0), "Layer 1");
+        let fonts = FontChain::default().register(&doc).unwrap();
+
+        let runs = fonts.runs("Grüße", FontStyle::REGULAR);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].1, "Grüße");
+
+        // "W" is 944/1000 em, "i" 222/1000 em; 10pt is 3.5278 mm
+        let width = |text: &str, style: FontStyle| fonts.text_width(text, style, 10.0);
+        assert!((width("W", FontStyle::REGULAR) - 3.330).abs() < 0.001);
+        assert!(width("WWW", FontStyle::REGULAR) > width("iii", FontStyle::REGULAR) * 4.0);
+        assert_eq!(width("ü", FontStyle::REGULAR), width("u", FontStyle::REGULAR));
+        assert!(width("a", FontStyle::BOLD) == width("a", FontStyle::REGULAR));
+        assert!(width("b", FontStyle::BOLD) > width("b", FontStyle::REGULAR));
+
+        // Courier is fixed-width
+        let monospace = FontStyle { monospace: true, ..FontStyle::REGULAR };
+        assert_eq!(width("WWW", monospace), width("iii", monospace));
+    }
+
+    #[test]
+    fn test_fallback_splits_runs_by_coverage() {
+        let math = PathBuf::from(SYSTEM_FONT_DIR).join("DejaVuMathTeXGyre.ttf");
+        let sans = PathBuf::from(SYSTEM_FONT_DIR).join("DejaVuSans.ttf");
+        if !math.exists() || !sans.exists() {
+            return;
+        }
+
+        let chain = FontChain::load(&[math, sans]).unwrap();
+        assert!(!chain.fonts()[0].covers('Ж'));
+        assert!(chain.fonts()[1].covers('Ж'));
+
+        let (doc, _, _) = PdfDocument::new("test", Mm(210.0), Mm(297.0), "Layer 1");
+        let fonts = chain.register(&doc).unwrap();
+
+        let runs = fonts.runs("abc Жук 中", FontStyle::REGULAR);
+        let texts: Vec<&str> = runs.iter().map(|(_, run)| run.as_str()).collect();
+        assert_eq!(texts, vec!["abc ", "Жук ", "中"]);
+        assert!(std::ptr::eq(runs[1].0, fonts.embedded[1].face(false)));
+        // No font has CJK glyphs, so the first font of the chain is used
+        assert!(std::ptr::eq(runs[2].0, fonts.embedded[0].face(false)));
+    }
+
+    #[test]
+    fn test_embedded_only_fonts_substitute_missing_glyphs() {
+        let (doc, _, _) = PdfDocument::new("test", Mm(210.0), Mm(297.0), "Layer 1");
+        assert!(FontChain::default().register_embedded(&doc).is_err());
+
+        let fonts = FontChain::load(&[bundled_font("DejaVuSans.ttf")]).unwrap().register_embedded(&doc).unwrap();
+
+        // Monospace text stays with the embedded font instead of Courier
+        let monospace = FontStyle { monospace: true, ..FontStyle::REGULAR };
+        let runs = fonts.runs("Grüße 中", monospace);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].1, "Grüße ?");
+        assert_eq!(fonts.text_width("中", FontStyle::REGULAR, 10.0), fonts.text_width("?", FontStyle::REGULAR, 10.0));
+    }
+}
@@ -1,10 +1,366 @@
 use std::path::PathBuf;
-use std::fs::File;
-use std::io::BufWriter;
 use chrono::{DateTime, Local};
 use printpdf::*;
 use crate::errors::{PdfError, PdfResult};
-use crate::types::Email;
+use crate::pdf_archive::{add_binary_comment, add_xmp_metadata, check_pdfa, convert_to_pdfa, printpdf_conformance};
+use crate::pdf_attachments::{AttachmentEmbedder, EmbeddingLimits};
+use crate::pdf_metadata::{ArchiveProvenance, DocumentMetadata};
+use crate::pdf_outline::{add_internal_links, add_outline, Destination, InternalLink, OutlineItem};
+use crate::html_layout::{parse_html, BlockKind, HtmlBlock, HtmlImage, HtmlTable, StyledSpan, TextStyle};
+use crate::pdf_fonts::{DocumentFonts, FontChain, FontStyle};
+use crate::pdf_images::{find_content_id, is_renderable_image, PdfImage};
+use crate::text_layout::{truncate_graphemes, truncate_to_width, wrap_spans, wrap_text, Span};
+use crate::types::{Attachment, Email, PageFrame, PdfStandard};
+
+/// A4 page width
+const PAGE_WIDTH: Mm = Mm(210.0);
+
+/// A4 page height
+const PAGE_HEIGHT: Mm = Mm(297.0);
+
+/// Baseline of the first line on each page
+const CONTENT_TOP: Mm = Mm(280.0);
+
+/// Lowest baseline for content; the area below is reserved for the page footer
+const CONTENT_BOTTOM: Mm = Mm(25.0);
+
+/// Body lines that must fit on the page together with an email header
+const MIN_BODY_LINES_WITH_HEADER: usize = 3;
+
+/// Font size of email body text in points
+const BODY_FONT_SIZE: f32 = 9.0;
+
+/// Line height of email body text
+const BODY_LINE_HEIGHT: Mm = Mm(4.0);
+
+/// Indentation per list level, in mm
+const LIST_INDENT: f32 = 6.0;
+
+/// Indentation per block quote level, in mm
+const QUOTE_INDENT: f32 = 5.0;
+
+/// Space between the text of a table cell and the column edges, in mm
+const CELL_PADDING: f32 = 1.5;
+
+/// Table rows up to this many lines are moved to the next page as a whole
+const MAX_ROW_LINES_KEPT_TOGETHER: usize = 10;
+
+/// Distance from the baseline of a body line to the top of its text, in mm
+const BODY_ASCENT: f32 = 3.0;
+
+/// Longest attachment name shown in an email header, in grapheme clusters
+const MAX_ATTACHMENT_NAME_LENGTH: usize = 60;
+
+/// Width of a row of embedded attachment icons at the right edge of an email title
+const EMBEDDED_ICONS_WIDTH: Mm = Mm(19.0);
+
+/// Line height of table of contents entries
+const CONTENTS_LINE_HEIGHT: Mm = Mm(5.0);
+
+/// Longest bookmark title, in grapheme clusters
+const MAX_OUTLINE_TITLE_LENGTH: usize = 120;
+
+/// Font size of the running header and footer in points
+const FRAME_FONT_SIZE: f32 = 7.5;
+
+/// Line height of the running header and footer
+const FRAME_LINE_HEIGHT: Mm = Mm(3.5);
+
+/// Baseline of the running header and the rule below it
+const HEADER_BASELINE: Mm = Mm(289.0);
+const HEADER_RULE: Mm = Mm(287.0);
+
+/// Rule above the running footer and the baseline of its first line
+const FOOTER_RULE: Mm = Mm(20.0);
+const FOOTER_BASELINE: Mm = Mm(16.5);
+
+/// Footer lines available for the legal notice; longer notices are shortened
+const MAX_LEGAL_NOTICE_LINES: usize = 2;
+
+/// Current page and vertical position while laying out a document
+#[derive(Clone)]
+struct PageCursor<'a> {
+    doc: &'a PdfDocumentReference,
+    page: PdfPageIndex,
+    /// Zero-based number of the current page
+    page_number: usize,
+    layer: PdfLayerIndex,
+    y: Mm,
+    /// All pages added so far, for drawing on them once the layout is complete
+    pages: Vec<(PdfPageIndex, PdfLayerIndex)>,
+}
+
+impl<'a> PageCursor<'a> {
+    fn new(doc: &'a PdfDocumentReference, page: PdfPageIndex, layer: PdfLayerIndex) -> Self {
+        Self { doc, page, page_number: 0, layer, y: CONTENT_TOP, pages: vec![(page, layer)] }
+    }
+
+    /// A cursor at the top of an earlier page
+    fn on_page(&self, page_number: usize) -> Self {
+        let (page, layer) = self.pages[page_number];
+        Self { doc: self.doc, page, page_number, layer, y: CONTENT_TOP, pages: Vec::new() }
+    }
+
+    fn layer(&self) -> PdfLayerReference {
+        self.doc.get_page(self.page).get_layer(self.layer)
+    }
+
+    /// Whether `height` fits between the current position and the footer area
+    fn fits(&self, height: Mm) -> bool {
+        self.y - height >= CONTENT_BOTTOM
+    }
+
+    /// Continue on a new page unless `height` still fits on the current one
+    fn ensure_space(&mut self, height: Mm) {
+        if !self.fits(height) && self.y < CONTENT_TOP {
+            self.new_page();
+        }
+    }
+
+    /// Continue at the top of a new page
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(PAGE_WIDTH, PAGE_HEIGHT, "Layer 1");
+        self.page = page;
+        self.page_number += 1;
+        self.layer = layer;
+        self.y = CONTENT_TOP;
+        self.pages.push((page, layer));
+    }
+
+    fn advance(&mut self, height: Mm) {
+        self.y -= height;
+    }
+
+    /// Draw a line of text at the current position and move down by `advance`
+    /// Each character is drawn with the first font of the chain that has a glyph for it
+    fn text(&mut self, text: &str, font_size: f32, x: Mm, fonts: &DocumentFonts, style: FontStyle, advance: Mm) {
+        let layer = self.layer();
+        layer.begin_text_section();
+        layer.set_text_cursor(x, self.y);
+        for (font, run) in fonts.runs(text, style) {
+            layer.set_font(font, font_size);
+            layer.write_text(run, font);
+        }
+        layer.end_text_section();
+        self.y -= advance;
+    }
+
+    /// Draw styled spans side by side on the current line without moving down
+    fn spans(&self, spans: &[StyledSpan], font_size: f32, x: Mm, fonts: &DocumentFonts) {
+        let layer = self.layer();
+        let mut x = x;
+        for span in spans {
+            let size = font_size * span.style.scale;
+            let width = Mm(fonts.text_width(&span.text, span.style.font, size));
+            let color = span.style.color.map(|[r, g, b]| rgb(r, g, b));
+            if let Some(color) = &color {
+                layer.set_fill_color(color.clone());
+                layer.set_outline_color(color.clone());
+            }
+
+            layer.begin_text_section();
+            layer.set_text_cursor(x, self.y);
+            for (font, run) in fonts.runs(&span.text, span.style.font) {
+                layer.set_font(font, size);
+                layer.write_text(run, font);
+            }
+            layer.end_text_section();
+
+            if span.style.underline {
+                let underline_y = self.y - Mm(0.7);
+                self.line(Point::new(x, underline_y), Point::new(x + width, underline_y));
+            }
+            if color.is_some() {
+                layer.set_fill_color(rgb(0, 0, 0));
+                layer.set_outline_color(rgb(0, 0, 0));
+            }
+            x += width;
+        }
+    }
+
+    /// Draw the blocks of an HTML body, continuing on new pages as needed
+    /// Embedded images are looked up among the attachments of `email`
+    fn html(&mut self, blocks: &[HtmlBlock], email: &Email, x: Mm, width: f32, fonts: &DocumentFonts) {
+        let measure = |text: &str, style: &TextStyle| fonts.text_width(text, style.font, BODY_FONT_SIZE * style.scale);
+
+        for block in blocks {
+            let indent = block.quote_depth as f32 * QUOTE_INDENT + block.indent as f32 * LIST_INDENT;
+            let left = x + Mm(indent);
+            let width = width - indent;
+
+            let lines = match &block.kind {
+                BlockKind::Rule => {
+                    self.ensure_space(BODY_LINE_HEIGHT);
+                    self.separator(left, left + Mm(width));
+                    self.advance(BODY_LINE_HEIGHT);
+                    continue;
+                }
+                BlockKind::Table(table) => {
+                    self.table(table, left, width, fonts);
+                    self.advance(BODY_LINE_HEIGHT * 0.5);
+                    continue;
+                }
+                BlockKind::Image(image) => match decode_html_image(image, email) {
+                    Ok(decoded) => {
+                        self.image(&decoded, (image.width, image.height), left, width);
+                        continue;
+                    }
+                    // Images that cannot be drawn are replaced by their alternative text
+                    Err(_) => match &image.alt {
+                        Some(alt) => vec![vec![Span::new(format!("[{}]", alt), TextStyle::default())]],
+                        None => continue,
+                    },
+                },
+                BlockKind::Heading(_) => {
+                    self.advance(BODY_LINE_HEIGHT * 0.5);
+                    wrap_spans(&block.spans, width, measure)
+                }
+                _ => wrap_spans(&block.spans, width, measure),
+            };
+
+            for (index, line) in lines.iter().enumerate() {
+                let height = BODY_LINE_HEIGHT * line_scale(line);
+                let below_baseline = BODY_LINE_HEIGHT * line_scale(line).min(1.0);
+                self.ensure_space(height);
+                // Larger text needs more room above its baseline
+                self.advance(height - below_baseline);
+                self.quote_bars(x, block.quote_depth, height);
+
+                if let (0, Some(marker)) = (index, &block.marker) {
+                    let marker = [Span::new(marker.as_str(), TextStyle::default())];
+                    self.spans(&marker, BODY_FONT_SIZE, left - Mm(LIST_INDENT - 1.5), fonts);
+                }
+                self.spans(line, BODY_FONT_SIZE, left, fonts);
+                self.advance(below_baseline);
+            }
+
+            if matches!(block.kind, BlockKind::Paragraph | BlockKind::Heading(_) | BlockKind::Preformatted) {
+                self.advance(BODY_LINE_HEIGHT * 0.5);
+            }
+        }
+    }
+
+    /// Draw a table; columns get their natural width, shrunk proportionally when the table is too wide
+    fn table(&mut self, table: &HtmlTable, x: Mm, width: f32, fonts: &DocumentFonts) {
+        let measure = |text: &str, style: &TextStyle| fonts.text_width(text, style.font, BODY_FONT_SIZE * style.scale);
+        let line_width = |line: &[StyledSpan]| line.iter().map(|span| measure(&span.text, &span.style)).sum::<f32>();
+
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut natural = vec![0.0_f32; columns];
+        for row in &table.rows {
+            for (column, cell) in row.iter().enumerate() {
+                // Without a width limit, lines only break at explicit line breaks
+                let widest = wrap_spans(cell, f32::INFINITY, measure)
+                    .iter()
+                    .map(|line| line_width(line))
+                    .fold(0.0, f32::max);
+                natural[column] = natural[column].max(widest + 2.0 * CELL_PADDING);
+            }
+        }
+
+        let total: f32 = natural.iter().sum();
+        let widths: Vec<f32> = if total <= width {
+            natural
+        } else {
+            let minimum = width / columns as f32 / 2.0;
+            let shrunk: Vec<f32> = natural.iter().map(|natural| (natural * width / total).max(minimum)).collect();
+            let factor = width / shrunk.iter().sum::<f32>();
+            shrunk.into_iter().map(|column| column * factor).collect()
+        };
+        let table_right = x + Mm(widths.iter().sum());
+
+        if table.bordered {
+            self.ensure_space(BODY_LINE_HEIGHT);
+            self.line(Point::new(x, self.y + Mm(3.5)), Point::new(table_right, self.y + Mm(3.5)));
+        }
+
+        for row in &table.rows {
+            let cells: Vec<Vec<Vec<StyledSpan>>> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, column_width)| wrap_spans(cell, column_width - 2.0 * CELL_PADDING, measure))
+                .collect();
+            let row_lines = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+            self.ensure_space(BODY_LINE_HEIGHT * row_lines.min(MAX_ROW_LINES_KEPT_TOGETHER) as f32);
+
+            for line_index in 0..row_lines {
+                let lines: Vec<&[StyledSpan]> = cells
+                    .iter()
+                    .map(|lines| lines.get(line_index).map_or(&[][..], Vec::as_slice))
+                    .collect();
+                let scale = lines.iter().map(|line| line_scale(line)).fold(1.0, f32::max);
+                self.ensure_space(BODY_LINE_HEIGHT * scale);
+                self.advance(BODY_LINE_HEIGHT * (scale - 1.0));
+
+                let mut column_x = x;
+                for (line, column_width) in lines.iter().zip(&widths) {
+                    self.spans(line, BODY_FONT_SIZE, column_x + Mm(CELL_PADDING), fonts);
+                    column_x += Mm(*column_width);
+                }
+                self.advance(BODY_LINE_HEIGHT);
+            }
+
+            if table.bordered {
+                self.line(Point::new(x, self.y + Mm(2.5)), Point::new(table_right, self.y + Mm(2.5)));
+            }
+        }
+    }
+
+    /// Draw an image where the next line of text would start, scaled to `max_width` and the page height
+    fn image(&mut self, image: &PdfImage, requested: (Option<f32>, Option<f32>), x: Mm, max_width: f32) {
+        let (width, height) = image.display_size(requested, max_width, (CONTENT_TOP - CONTENT_BOTTOM).0);
+        self.ensure_space(Mm(height));
+
+        let bottom = self.y + Mm(BODY_ASCENT) - Mm(height);
+        image.draw(self.layer(), x, bottom, width, height);
+        self.y = bottom - BODY_LINE_HEIGHT;
+    }
+
+    /// Vertical bars left of quoted text, one per quote level
+    fn quote_bars(&self, x: Mm, depth: usize, height: Mm) {
+        if depth == 0 {
+            return;
+        }
+        let layer = self.layer();
+        layer.set_outline_color(rgb(153, 153, 153));
+        for level in 0..depth {
+            let bar_x = x + Mm(level as f32 * QUOTE_INDENT + 1.0);
+            self.line(Point::new(bar_x, self.y + height * 0.75), Point::new(bar_x, self.y - height * 0.25));
+        }
+        layer.set_outline_color(rgb(0, 0, 0));
+    }
+
+    /// Draw a horizontal rule at the current position
+    fn separator(&self, from: Mm, to: Mm) {
+        self.line(Point::new(from, self.y), Point::new(to, self.y));
+    }
+
+    fn line(&self, from: Point, to: Point) {
+        self.layer().add_line(Line {
+            points: vec![(from, false), (to, false)],
+            is_closed: false,
+        });
+    }
+}
+
+/// Decode an image of an HTML body from a `cid:` attachment or a data URI
+fn decode_html_image(image: &HtmlImage, email: &Email) -> PdfResult<PdfImage> {
+    if image.source.starts_with("data:") {
+        return PdfImage::from_data_uri(&image.source);
+    }
+    let attachment = find_content_id(email, &image.source)
+        .ok_or_else(|| PdfError::FormattingError(format!("No attachment for {}", image.source)))?;
+    PdfImage::from_attachment(attachment)
+}
+
+/// Font size of the largest text on a line relative to the body text
+fn line_scale(line: &[StyledSpan]) -> f32 {
+    line.iter().map(|span| span.style.scale).reduce(f32::max).unwrap_or(1.0)
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb(Rgb::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, None))
+}
 
 /// PDF generator for converting emails to PDF format
 #[derive(Debug)]
@@ -12,6 +368,21 @@ pub struct PdfGenerator {
     output_dir: PathBuf,
     base_name: String,
     session_timestamp: DateTime<Local>,
+    fonts: FontChain,
+    /// Whether image attachments are drawn on pages after their email
+    image_attachments: bool,
+    /// Size limits for embedding attachments as files, if they are embedded at all
+    embedding: Option<EmbeddingLimits>,
+    /// Archiving standard the output conforms to
+    standard: PdfStandard,
+    /// Whether the first page lists all emails with their page numbers
+    table_of_contents: bool,
+    /// Running header and footer of every page
+    page_frame: PageFrame,
+    /// Name of the mailbox the emails come from, for the running header
+    source_name: Option<String>,
+    /// Owner, source file and session, recorded in the document properties
+    provenance: ArchiveProvenance,
 }
 
 impl PdfGenerator {
@@ -50,9 +421,67 @@ impl PdfGenerator {
             output_dir,
             base_name,
             session_timestamp,
+            fonts: FontChain::bundled(),
+            image_attachments: false,
+            embedding: None,
+            standard: PdfStandard::default(),
+            table_of_contents: false,
+            page_frame: PageFrame::default(),
+            source_name: None,
+            provenance: ArchiveProvenance::default(),
         })
     }
 
+    /// Use the given fonts instead of the bundled ones
+    pub fn with_fonts(mut self, fonts: FontChain) -> Self {
+        self.fonts = fonts;
+        self
+    }
+
+    /// Also draw image attachments that are not part of the body, on pages after each email
+    pub fn with_image_attachments(mut self, enabled: bool) -> Self {
+        self.image_attachments = enabled;
+        self
+    }
+
+    /// Embed the original attachments as files next to the email that carries them
+    /// Ignored for PDF/A-2b, which does not allow embedded files
+    pub fn with_embedded_attachments(mut self, limits: EmbeddingLimits) -> Self {
+        self.embedding = Some(limits);
+        self
+    }
+
+    /// Write PDFs that conform to an archiving standard such as PDF/A-3b
+    /// Only the configured font files are used then, as PDF/A requires every font to be embedded
+    pub fn with_pdf_standard(mut self, standard: PdfStandard) -> Self {
+        self.standard = standard;
+        self
+    }
+
+    /// List all emails with their page numbers and links to them on the first page
+    pub fn with_table_of_contents(mut self, enabled: bool) -> Self {
+        self.table_of_contents = enabled;
+        self
+    }
+
+    /// Print a running header and footer on every page
+    pub fn with_page_frame(mut self, page_frame: PageFrame) -> Self {
+        self.page_frame = page_frame;
+        self
+    }
+
+    /// Name of the mailbox the emails come from, shown in the running header
+    pub fn with_source_name(mut self, source_name: impl Into<String>) -> Self {
+        self.source_name = Some(source_name.into());
+        self
+    }
+
+    /// Record the mailbox owner, source file hash and session in the document properties
+    pub fn with_provenance(mut self, provenance: ArchiveProvenance) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
     /// Generate a PDF file from a collection of emails
     pub fn generate_pdf(&self, emails: Vec<Email>, sequence: u32) -> PdfResult<PathBuf> {
         if emails.is_empty() {
@@ -61,34 +490,42 @@ impl PdfGenerator {
             ));
         }
 
-        let filename = self.generate_filename(sequence);
+        let filename = self.generate_filename(sequence, emails.iter().all(|email| email.recovered));
         let output_path = self.output_dir.join(&filename);
+        let metadata = DocumentMetadata::for_emails(
+            format!("Email Archive - {}", self.base_name),
+            self.source_name.as_deref(),
+            &emails,
+            &self.provenance,
+        );
         
         // Create PDF document
         let (doc, page1, layer1) = PdfDocument::new(
-            &format!("Email Archive - {}", self.base_name),
-            Mm(210.0), // A4 width
-            Mm(297.0), // A4 height
+            &metadata.title,
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
             "Layer 1"
         );
+        let is_pdfa = self.standard.pdfa_part().is_some();
+        let doc = if is_pdfa { doc.with_conformance(printpdf_conformance()) } else { doc };
 
-        let current_layer = doc.get_page(page1).get_layer(layer1);
-        
         // Set up fonts
-        let font = doc.add_builtin_font(BuiltinFont::Helvetica)
-            .map_err(|e| PdfError::FormattingError(format!("Failed to load font: {}", e)))?;
-        let font_bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)
-            .map_err(|e| PdfError::FormattingError(format!("Failed to load bold font: {}", e)))?;
+        let fonts = if is_pdfa { self.fonts.register_embedded(&doc)? } else { self.fonts.register(&doc)? };
 
         // Start writing content
-        let mut current_y = Mm(280.0); // Start near top of page
+        let mut cursor = PageCursor::new(&doc, page1, layer1);
         let margin_left = Mm(20.0);
         let margin_right = Mm(190.0);
         let line_height = Mm(6.0);
 
+        let content_width = (margin_right - margin_left).0;
+        let body_width = content_width - 5.0;
+
         // Add title
-        current_layer.use_text(&format!("Email Archive - {}", self.base_name), 16.0, margin_left, current_y, &font_bold);
-        current_y -= line_height * 2.0;
+        let title = truncate_to_width(&format!("Email Archive - {}", self.base_name), content_width, |text| {
+            fonts.text_width(text, FontStyle::BOLD, 16.0)
+        });
+        cursor.text(&title, 16.0, margin_left, &fonts, FontStyle::BOLD, line_height * 2.0);
 
         // Add generation info
         let generation_info = format!(
@@ -97,128 +534,356 @@ impl PdfGenerator {
             emails.len(),
             sequence
         );
-        current_layer.use_text(&generation_info, 10.0, margin_left, current_y, &font);
-        current_y -= line_height * 2.0;
+        cursor.text(&generation_info, 10.0, margin_left, &fonts, FontStyle::REGULAR, line_height * 2.0);
 
         // Add separator line
-        let line_points = vec![
-            (Point::new(margin_left, current_y), false),
-            (Point::new(margin_right, current_y), false)
-        ];
-        let line = Line {
-            points: line_points,
-            is_closed: false,
-        };
-        current_layer.add_line(line);
-        current_y -= line_height;
+        cursor.separator(margin_left, margin_right);
+        cursor.advance(line_height);
+
+        // Reserve a line per email for the table of contents, filled in once page numbers are known
+        let mut contents_slots = Vec::new();
+        if self.table_of_contents {
+            cursor.text("Contents", 12.0, margin_left, &fonts, FontStyle::BOLD, line_height);
+            for _ in &emails {
+                cursor.ensure_space(CONTENTS_LINE_HEIGHT);
+                contents_slots.push(cursor.clone());
+                cursor.advance(CONTENTS_LINE_HEIGHT);
+            }
+            cursor.new_page();
+        }
+
+        let mut outline = Vec::with_capacity(emails.len());
+        let mut embedder = self
+            .embedding
+            .filter(|_| self.standard.allows_embedded_files())
+            .map(AttachmentEmbedder::new);
 
-        // Keep track of current page and layer indices
-        let mut current_page_index = page1;
-        let mut current_layer_index = layer1;
+        // Header lines leave room for the icons of embedded attachments at the right edge
+        let header_width = match embedder {
+            Some(_) => content_width - EMBEDDED_ICONS_WIDTH.0,
+            None => content_width,
+        };
 
         // Process each email
         for (index, email) in emails.iter().enumerate() {
-            // Check if we need a new page
-            if current_y < Mm(50.0) {
-                let (new_page, new_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Layer 1");
-                current_page_index = new_page;
-                current_layer_index = new_layer;
-                current_y = Mm(280.0);
-            }
+            let mut header_lines = self.header_lines(email, &fonts, header_width);
 
-            let current_layer = doc.get_page(current_page_index).get_layer(current_layer_index);
-
-            // Email header
-            current_layer.use_text(&format!("Email {} of {}", index + 1, emails.len()), 12.0, margin_left, current_y, &font_bold);
-            current_y -= line_height;
-
-            // Subject
-            let subject = self.truncate_text(&email.subject, 80);
-            current_layer.use_text(&format!("Subject: {}", subject), 10.0, margin_left, current_y, &font_bold);
-            current_y -= line_height;
-
-            // From
-            let sender = self.truncate_text(&email.sender, 80);
-            current_layer.use_text(&format!("From: {}", sender), 10.0, margin_left, current_y, &font);
-            current_y -= line_height;
-
-            // To
-            let recipient = self.truncate_text(&email.recipient, 80);
-            current_layer.use_text(&format!("To: {}", recipient), 10.0, margin_left, current_y, &font);
-            current_y -= line_height;
-
-            // CC recipients if any
-            if !email.cc_recipients.is_empty() {
-                let cc_list = email.cc_recipients.join(", ");
-                let cc_truncated = self.truncate_text(&cc_list, 80);
-                current_layer.use_text(&format!("CC: {}", cc_truncated), 10.0, margin_left, current_y, &font);
-                current_y -= line_height;
+            // Attachments that exceed the embedding limits are noted below the header
+            if let Some(embedder) = &mut embedder {
+                for (attachment, reason) in embedder.add_email(email) {
+                    let note = format!("Not embedded: {} ({})", self.truncate_text(&attachment.name, MAX_ATTACHMENT_NAME_LENGTH), reason);
+                    let lines = wrap_text(&note, header_width, |text| fonts.text_width(text, FontStyle::REGULAR, 10.0));
+                    header_lines.extend(lines.into_iter().map(|line| (line, FontStyle::REGULAR)));
+                }
             }
 
-            // Date
-            current_layer.use_text(&format!("Date: {}", email.formatted_date()), 10.0, margin_left, current_y, &font);
-            current_y -= line_height;
+            // HTML bodies keep their structure; plain text keeps its line breaks
+            let html_blocks = email.is_html.then(|| parse_html(&email.body));
+            let body_lines = match &html_blocks {
+                Some(_) => Vec::new(),
+                None => self.prepare_body_text(&email.body, body_width, |text| {
+                    fonts.text_width(text, FontStyle::REGULAR, BODY_FONT_SIZE)
+                }),
+            };
 
-            // Attachments if any
-            if email.has_attachments() {
-                let attachment_names: Vec<String> = email.attachments.iter()
-                    .map(|a| format!("{} ({})", a.name, self.format_file_size(a.size)))
-                    .collect();
-                let attachments_text = attachment_names.join(", ");
-                let attachments_truncated = self.truncate_text(&attachments_text, 80);
-                current_layer.use_text(&format!("Attachments: {}", attachments_truncated), 10.0, margin_left, current_y, &font);
-                current_y -= line_height;
+            // Keep the header block together with the first body lines
+            let header_height = line_height * (header_lines.len() as f32 + 1.5);
+            let kept_body_lines = match &html_blocks {
+                Some(blocks) if !blocks.is_empty() => MIN_BODY_LINES_WITH_HEADER,
+                Some(_) => 0,
+                None => body_lines.len().min(MIN_BODY_LINES_WITH_HEADER),
+            };
+            cursor.ensure_space(header_height + BODY_LINE_HEIGHT * kept_body_lines as f32);
+
+            // Email header, with the icons of embedded attachments at its right edge
+            let anchor = Destination { page: cursor.page_number, top: (cursor.y + Mm(5.0)).0 };
+            outline.push(self.outline_item(email, anchor));
+            if let Some(embedder) = &mut embedder {
+                embedder.place(cursor.page_number, (margin_right - EMBEDDED_ICONS_WIDTH).0, (cursor.y + Mm(BODY_ASCENT)).0);
+            }
+            cursor.text(&format!("Email {} of {}", index + 1, emails.len()), 12.0, margin_left, &fonts, FontStyle::BOLD, line_height);
+            for (line, style) in &header_lines {
+                cursor.text(line, 10.0, margin_left, &fonts, *style, line_height);
             }
+            cursor.advance(line_height * 0.5);
 
-            current_y -= line_height * 0.5;
+            // Email body, continued on as many pages as needed
+            if let Some(blocks) = &html_blocks {
+                cursor.html(blocks, email, margin_left + Mm(5.0), body_width, &fonts);
+            }
+            for line in &body_lines {
+                cursor.ensure_space(BODY_LINE_HEIGHT);
+                cursor.text(line, BODY_FONT_SIZE, margin_left + Mm(5.0), &fonts, FontStyle::REGULAR, BODY_LINE_HEIGHT);
+            }
 
-            // Email body (truncated and cleaned)
-            let body_lines = self.prepare_body_text(&email.body, 90);
-            for line in body_lines.iter().take(10) { // Limit to 10 lines per email
-                if current_y < Mm(30.0) {
-                    let (new_page, new_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Layer 1");
-                    current_page_index = new_page;
-                    current_layer_index = new_layer;
-                    current_y = Mm(280.0);
+            // Inline images the body does not show, e.g. of plain text emails
+            let referenced: Vec<&Attachment> = html_blocks
+                .iter()
+                .flatten()
+                .filter_map(|block| match &block.kind {
+                    BlockKind::Image(image) => find_content_id(email, &image.source),
+                    _ => None,
+                })
+                .collect();
+            for attachment in email.attachments.iter().filter(|attachment| attachment.is_inline && is_renderable_image(attachment)) {
+                if !referenced.iter().any(|shown| std::ptr::eq(*shown, attachment)) {
+                    if let Ok(image) = PdfImage::from_attachment(attachment) {
+                        cursor.image(&image, (None, None), margin_left + Mm(5.0), body_width);
+                    }
                 }
-                let current_layer = doc.get_page(current_page_index).get_layer(current_layer_index);
-                current_layer.use_text(line, 9.0, margin_left + Mm(5.0), current_y, &font);
-                current_y -= Mm(4.0);
+            }
+
+            if self.image_attachments {
+                self.image_attachment_pages(&mut cursor, email, &fonts, margin_left, content_width);
             }
 
             // Add separator between emails
-            current_y -= line_height;
-            if current_y > Mm(30.0) {
-                let current_layer = doc.get_page(current_page_index).get_layer(current_layer_index);
-                let separator_points = vec![
-                    (Point::new(margin_left, current_y), false),
-                    (Point::new(margin_right, current_y), false)
-                ];
-                let separator = Line {
-                    points: separator_points,
-                    is_closed: false,
-                };
-                current_layer.add_line(separator);
+            cursor.advance(line_height);
+            if cursor.fits(line_height) {
+                cursor.separator(margin_left, margin_right);
             }
-            current_y -= line_height;
+            cursor.advance(line_height);
         }
 
+        // Table of contents, linking each entry to the first page of its email
+        let mut links = Vec::with_capacity(contents_slots.len());
+        for (index, (slot, item)) in contents_slots.iter_mut().zip(&outline).enumerate() {
+            let page_label = (item.destination.page + 1).to_string();
+            let label_width = fonts.text_width(&page_label, FontStyle::REGULAR, 10.0);
+            let entry = truncate_to_width(&format!("{}. {}", index + 1, item.title), content_width - label_width - 5.0, |text| {
+                fonts.text_width(text, FontStyle::REGULAR, 10.0)
+            });
+
+            let baseline = slot.y.0;
+            links.push(InternalLink {
+                page: slot.page_number,
+                rect: [margin_left.0, baseline - 1.5, margin_right.0, baseline + BODY_ASCENT + 1.0],
+                destination: item.destination,
+            });
+            slot.text(&entry, 10.0, margin_left, &fonts, FontStyle::REGULAR, Mm(0.0));
+            slot.text(&page_label, 10.0, margin_right - Mm(label_width), &fonts, FontStyle::REGULAR, Mm(0.0));
+        }
+
+        // Second pass: headers and footers, now that the page count is known
+        self.page_frames(&cursor, &fonts, sequence, margin_left, margin_right);
+
         // Save PDF to file
-        let file = File::create(&output_path)
-            .map_err(|e| PdfError::FileWriteError(format!("Failed to create PDF file: {}", e)))?;
-        let mut writer = BufWriter::new(file);
-        
-        doc.save(&mut writer)
+        let bytes = doc.save_to_bytes()
             .map_err(|e| PdfError::GenerationFailed(format!("Failed to save PDF: {}", e)))?;
+        let embedder = embedder.filter(|embedder| !embedder.is_empty());
+        let bytes = self.finish_document(bytes, &metadata, embedder, &outline, &links)?;
+        std::fs::write(&output_path, bytes)
+            .map_err(|e| PdfError::FileWriteError(format!("Failed to create PDF file: {}", e)))?;
 
         Ok(output_path)
     }
 
+    /// Add the document properties, outline, internal links and embedded files, and convert to the configured PDF/A standard
+    /// PDF/A output is checked after writing and rejected if it violates the standard
+    fn finish_document(
+        &self,
+        bytes: Vec<u8>,
+        metadata: &DocumentMetadata,
+        embedder: Option<AttachmentEmbedder>,
+        outline: &[OutlineItem],
+        links: &[InternalLink],
+    ) -> PdfResult<Vec<u8>> {
+        let is_pdfa = self.standard.pdfa_part().is_some();
+        let load = |bytes: &[u8]| {
+            lopdf::Document::load_mem(bytes)
+                .map_err(|e| PdfError::GenerationFailed(format!("Failed to read generated PDF: {}", e)))
+        };
+        let mut document = load(&bytes)?;
+        metadata.apply(&mut document)?;
+        if let Some(embedder) = &embedder {
+            embedder.embed(&mut document)?;
+        }
+        add_outline(&mut document, outline)?;
+        add_internal_links(&mut document, links)?;
+        if is_pdfa {
+            convert_to_pdfa(&mut document, self.standard)?;
+        } else {
+            add_xmp_metadata(&mut document, None)?;
+        }
+
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes)
+            .map_err(|e| PdfError::GenerationFailed(format!("Failed to save PDF: {}", e)))?;
+
+        if is_pdfa {
+            bytes = add_binary_comment(&bytes)?;
+            let violations = check_pdfa(&load(&bytes)?, self.standard);
+            if !violations.is_empty() {
+                return Err(PdfError::ConformanceViolation(violations.join("; ")));
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Draw the configured running header and footer on every page of the document
+    fn page_frames(&self, cursor: &PageCursor, fonts: &DocumentFonts, sequence: u32, left: Mm, right: Mm) {
+        let frame = &self.page_frame;
+        let width = (right - left).0;
+        let measure = |text: &str| fonts.text_width(text, FontStyle::REGULAR, FRAME_FONT_SIZE);
+
+        let sequence_label = frame.sequence_number.then(|| format!("Archiv-PDF Nr. {}", sequence));
+        let source_label = self.source_name.as_ref().filter(|_| frame.source_name).map(|name| {
+            let available = width - sequence_label.as_deref().map_or(0.0, measure) - 5.0;
+            truncate_to_width(&format!("Quelle: {}", name), available, measure)
+        });
+        let date_label = frame.archive_date.then(|| format!("Archiviert am {}", self.session_timestamp.format("%d.%m.%Y")));
+
+        let mut notice_lines = frame
+            .legal_notice
+            .as_deref()
+            .map(|notice| wrap_text(notice.trim(), width, measure))
+            .unwrap_or_default();
+        if notice_lines.len() > MAX_LEGAL_NOTICE_LINES {
+            let remainder = notice_lines[MAX_LEGAL_NOTICE_LINES - 1..].join(" ");
+            notice_lines.truncate(MAX_LEGAL_NOTICE_LINES - 1);
+            notice_lines.push(truncate_to_width(&remainder, width, measure));
+        }
+
+        let page_count = cursor.pages.len();
+        for page_number in 0..page_count {
+            let mut page = cursor.on_page(page_number);
+            let layer = page.layer();
+            layer.set_fill_color(rgb(90, 90, 90));
+            layer.set_outline_color(rgb(160, 160, 160));
+
+            if source_label.is_some() || sequence_label.is_some() {
+                page.y = HEADER_BASELINE;
+                if let Some(label) = &source_label {
+                    page.text(label, FRAME_FONT_SIZE, left, fonts, FontStyle::REGULAR, Mm(0.0));
+                }
+                if let Some(label) = &sequence_label {
+                    page.text(label, FRAME_FONT_SIZE, right - Mm(measure(label)), fonts, FontStyle::REGULAR, Mm(0.0));
+                }
+                page.y = HEADER_RULE;
+                page.separator(left, right);
+            }
+
+            let page_label = frame.page_numbers.then(|| format!("Seite {} von {}", page_number + 1, page_count));
+            if date_label.is_some() || page_label.is_some() || !notice_lines.is_empty() {
+                page.y = FOOTER_RULE;
+                page.separator(left, right);
+                page.y = FOOTER_BASELINE;
+                if let Some(label) = &date_label {
+                    page.text(label, FRAME_FONT_SIZE, left, fonts, FontStyle::REGULAR, Mm(0.0));
+                }
+                if let Some(label) = &page_label {
+                    page.text(label, FRAME_FONT_SIZE, right - Mm(measure(label)), fonts, FontStyle::REGULAR, Mm(0.0));
+                }
+                if date_label.is_some() || page_label.is_some() {
+                    page.advance(FRAME_LINE_HEIGHT);
+                }
+                for line in &notice_lines {
+                    page.text(line, FRAME_FONT_SIZE, left, fonts, FontStyle::REGULAR, FRAME_LINE_HEIGHT);
+                }
+            }
+
+            layer.set_fill_color(rgb(0, 0, 0));
+            layer.set_outline_color(rgb(0, 0, 0));
+        }
+    }
+
+    /// Bookmark of an email (date, sender, subject) with an entry for each of its attachments
+    fn outline_item(&self, email: &Email, destination: Destination) -> OutlineItem {
+        let subject = if email.subject.trim().is_empty() { "(No subject)" } else { email.subject.trim() };
+        let mut title = format!("{} | {} | {}", email.formatted_date(), email.sender, subject);
+        if email.recovered {
+            title.insert_str(0, "Wiederhergestellt | ");
+        }
+
+        let mut item = OutlineItem::new(truncate_graphemes(&title, MAX_OUTLINE_TITLE_LENGTH), destination);
+        item.children = email
+            .attachments
+            .iter()
+            .filter(|attachment| !attachment.is_inline)
+            .map(|attachment| {
+                let title = format!("{} ({})", self.truncate_text(&attachment.name, MAX_ATTACHMENT_NAME_LENGTH), self.format_file_size(attachment.size));
+                OutlineItem::new(title, destination)
+            })
+            .collect();
+        item
+    }
+
+    /// Draw the image attachments of an email, starting on a new page, each below its file name
+    fn image_attachment_pages(&self, cursor: &mut PageCursor, email: &Email, fonts: &DocumentFonts, x: Mm, width: f32) {
+        let images: Vec<&Attachment> = email
+            .attachments
+            .iter()
+            .filter(|attachment| !attachment.is_inline && is_renderable_image(attachment))
+            .collect();
+        if images.is_empty() {
+            return;
+        }
+
+        cursor.new_page();
+        for attachment in images {
+            let caption = truncate_to_width(&format!("Attachment: {}", attachment.name), width, |text| {
+                fonts.text_width(text, FontStyle::BOLD, 10.0)
+            });
+            cursor.ensure_space(Mm(6.0) * 2.0);
+            cursor.text(&caption, 10.0, x, fonts, FontStyle::BOLD, Mm(6.0));
+
+            match PdfImage::from_attachment(attachment) {
+                Ok(image) => cursor.image(&image, (None, None), x, width),
+                Err(e) => cursor.text(&format!("Image could not be displayed: {}", e), BODY_FONT_SIZE, x, fonts, FontStyle::REGULAR, Mm(6.0)),
+            }
+        }
+    }
+
+    /// Header lines of an email below its title, with the style each is set in
+    /// Fields wider than `max_width` (in mm) continue on the following lines
+    fn header_lines(&self, email: &Email, fonts: &DocumentFonts, max_width: f32) -> Vec<(String, FontStyle)> {
+        let mut fields = vec![
+            (format!("Subject: {}", email.subject), FontStyle::BOLD),
+            (format!("From: {}", email.sender), FontStyle::REGULAR),
+            (format!("To: {}", email.recipient), FontStyle::REGULAR),
+        ];
+
+        // CC recipients if any
+        if !email.cc_recipients.is_empty() {
+            fields.push((format!("CC: {}", email.cc_recipients.join(", ")), FontStyle::REGULAR));
+        }
+
+        fields.push((format!("Date: {}", email.formatted_date()), FontStyle::REGULAR));
+
+        // Attachments if any
+        if email.has_attachments() {
+            let attachment_names: Vec<String> = email.attachments.iter()
+                .map(|a| format!("{} ({})", self.truncate_text(&a.name, MAX_ATTACHMENT_NAME_LENGTH), self.format_file_size(a.size)))
+                .collect();
+            fields.push((format!("Attachments: {}", attachment_names.join(", ")), FontStyle::REGULAR));
+        }
+
+        // Marker for near-duplicates of another archived email
+        if let Some(original) = &email.near_duplicate_of {
+            fields.push((format!("Near duplicate of: {}", original), FontStyle::BOLD));
+        }
+
+        // Marker for items found by the recovery scan instead of in a folder
+        if email.recovered {
+            fields.push(("Wiederhergestellt: gelöschtes oder verwaistes Element".to_string(), FontStyle::BOLD));
+        }
+
+        fields
+            .into_iter()
+            .flat_map(|(field, style)| {
+                let lines = wrap_text(&field, max_width, |text| fonts.text_width(text, style, 10.0));
+                lines.into_iter().map(move |line| (line, style))
+            })
+            .collect()
+    }
+
     /// Generate timestamp-prefixed filename with sequence number
-    fn generate_filename(&self, sequence: u32) -> String {
-        // Format: YYYY-MM-DDTHH-mm-ss_{base_name}_{sequence}.pdf
+    /// PDFs holding only recovered items are marked so they sort apart from the regular archive
+    fn generate_filename(&self, sequence: u32, recovered: bool) -> String {
+        // Format: YYYY-MM-DDTHH-mm-ss_{base_name}[_Wiederhergestellt]_{sequence}.pdf
         let timestamp = self.session_timestamp.format("%Y-%m-%dT%H-%M-%S");
-        format!("{}_{}_{}.pdf", timestamp, self.base_name, sequence)
+        let section = if recovered { "_Wiederhergestellt" } else { "" };
+        format!("{}_{}{}_{}.pdf", timestamp, self.base_name, section, sequence)
     }
 
     /// Validate that the output directory is writable
@@ -240,53 +905,37 @@ impl PdfGenerator {
         self.session_timestamp
     }
 
-    /// Truncate text to specified length with ellipsis
+    /// Truncate text to at most `max_length` characters with ellipsis, never splitting a grapheme cluster
     fn truncate_text(&self, text: &str, max_length: usize) -> String {
-        if text.len() <= max_length {
-            text.to_string()
-        } else {
-            format!("{}...", &text[..max_length.saturating_sub(3)])
-        }
+        truncate_graphemes(text, max_length)
     }
 
     /// Prepare email body text for PDF display
-    fn prepare_body_text(&self, body: &str, max_line_length: usize) -> Vec<String> {
-        // Remove HTML tags if present and clean up text
-        let cleaned_body = self.strip_html_tags(body);
-        
-        // Split into words and wrap into lines
-        let words: Vec<&str> = cleaned_body.split_whitespace().collect();
+    /// Line breaks are kept, runs of spaces and tabs collapse and at most one blank line
+    /// separates paragraphs. Lines are wrapped so that `measure` reports no more than
+    /// `max_width` for each
+    fn prepare_body_text(&self, body: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+        // Remove HTML tags on the whole body first, as tags may span several lines
+        let body = self.strip_html_tags(body);
         let mut lines = Vec::new();
-        let mut current_line = String::new();
-        
-        for word in words {
-            // Check if adding this word would exceed the line length
-            let test_line = if current_line.is_empty() {
-                word.to_string()
-            } else {
-                format!("{} {}", current_line, word)
-            };
-            
-            if test_line.len() <= max_line_length {
-                current_line = test_line;
-            } else {
-                // Start a new line
-                if !current_line.is_empty() {
-                    lines.push(current_line);
-                }
-                current_line = word.to_string();
+        let mut pending_blank = false;
+        for line in body.lines() {
+            let text = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+            if text.is_empty() {
+                pending_blank = !lines.is_empty();
+                continue;
             }
+            if pending_blank {
+                lines.push(String::new());
+                pending_blank = false;
+            }
+            lines.extend(wrap_text(&text, max_width, &measure));
         }
-        
-        // Add the last line if not empty
-        if !current_line.is_empty() {
-            lines.push(current_line);
-        }
-        
         lines
     }
 
     /// Strip HTML tags from text (basic implementation)
+    /// Whitespace and line breaks outside of tags are kept as they are
     fn strip_html_tags(&self, html: &str) -> String {
         let mut result = String::new();
         let mut in_tag = false;
@@ -313,12 +962,8 @@ impl PdfGenerator {
                 _ => {}
             }
         }
-        
-        // Clean up multiple whitespaces and newlines
+
         result
-            .split_whitespace()
-            .collect::<Vec<&str>>()
-            .join(" ")
     }
 
     /// Format file size in human-readable format
@@ -345,7 +990,8 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
     use chrono::Utc;
-    use crate::types::{Email, Attachment, EmailPriority};
+    use crate::types::{Email, Attachment, EmailPriority, MessageFlags};
+    use crate::test_support::bundled_font;
 
     fn create_test_email(subject: &str, sender: &str, recipient: &str) -> Email {
         Email {
@@ -361,6 +1007,12 @@ mod tests {
             priority: EmailPriority::Normal,
             message_id: Some("test@example.com".to_string()),
             in_reply_to: None,
+            references: vec![],
+            conversation_index: None,
+            folder: None,
+            flags: MessageFlags::default(),
+            near_duplicate_of: None,
+            recovered: false,
             size: 1024,
         }
     }
@@ -428,8 +1080,8 @@ mod tests {
             "test_emails".to_string()
         ).unwrap();
         
-        let filename1 = generator.generate_filename(1);
-        let filename2 = generator.generate_filename(2);
+        let filename1 = generator.generate_filename(1, false);
+        let filename2 = generator.generate_filename(2, false);
         
         // Both should have same timestamp but different sequence numbers
         assert!(filename1.contains("test_emails"));
@@ -441,6 +1093,26 @@ mod tests {
         let dash_count = filename1.matches('-').count();
         assert!(dash_count == 4); // Exactly 4 dashes in ISO format YYYY-MM-DDTHH-MM-SS
         assert!(filename1.contains('T')); // ISO format separator
+        assert!(generator.generate_filename(3, true).ends_with("_test_emails_Wiederhergestellt_3.pdf"));
+    }
+
+    #[test]
+    fn test_recovered_email_is_labelled() {
+        use printpdf::lopdf::{self, Object};
+
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(temp_dir.path().to_path_buf(), "recovered".to_string()).unwrap();
+        let mut email = create_test_email("Entwurf", "anna@example.com", "recipient@example.com");
+        email.recovered = true;
+
+        let pdf_path = generator.generate_pdf(vec![email], 1).unwrap();
+        assert!(pdf_path.file_name().unwrap().to_string_lossy().contains("_Wiederhergestellt_1"));
+
+        let document = lopdf::Document::load(&pdf_path).unwrap();
+        let reference = |dict: &lopdf::Dictionary, key: &[u8]| dict.get(key).and_then(Object::as_reference).unwrap();
+        let root = document.get_dictionary(reference(document.catalog().unwrap(), b"Outlines")).unwrap();
+        let item = document.get_dictionary(reference(root, b"First")).unwrap();
+        assert!(item.get(b"Title").and_then(Object::as_str).unwrap().starts_with(b"Wiederhergestellt | "));
     }
 
     #[test]
@@ -498,6 +1170,267 @@ mod tests {
         assert!(pdf_path.file_name().unwrap().to_str().unwrap().contains("multi_test"));
     }
 
+    fn count_pages(pdf_path: &std::path::Path) -> usize {
+        let bytes = std::fs::read(pdf_path).unwrap();
+        let content = String::from_utf8_lossy(&bytes);
+        content.matches("/Type/Page").count() - content.matches("/Type/Pages").count()
+    }
+
+    #[test]
+    fn test_generate_pdf_renders_long_body_on_following_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(
+            temp_dir.path().to_path_buf(),
+            "long".to_string()
+        ).unwrap();
+
+        let mut email = create_test_email("Long Email", "sender@example.com", "recipient@example.com");
+        email.body = "Lorem ipsum dolor sit amet consectetur ".repeat(400);
+        // A body line holds fewer than 110 characters of this text in 9pt Helvetica
+        let lines = generator.prepare_body_text(&email.body, 110.0, |text| text.chars().count() as f32);
+        assert!(lines.len() > 100);
+
+        let pdf_path = generator.generate_pdf(vec![email], 1).unwrap();
+
+        // 4 mm per body line leaves room for roughly 60 lines per page
+        assert!(count_pages(&pdf_path) > lines.len() / 64);
+    }
+
+    #[test]
+    fn test_generate_pdf_renders_html_body() {
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(
+            temp_dir.path().to_path_buf(),
+            "html".to_string()
+        ).unwrap();
+
+        let mut email = create_test_email("Angebot", "sender@example.com", "recipient@example.com");
+        email.is_html = true;
+        email.body = format!(
+            "<h1>Angebot</h1><p>Sehr geehrte Damen und Herren, <b>anbei</b> <i>unser</i> <u>Angebot</u>.</p>\
+             <ul><li>Position eins<ol><li>Detail</li></ol></li></ul>\
+             <table border=1><tr><th>Artikel</th><th>Preis</th></tr>{}</table>\
+             <blockquote><p style=\"color: #336699; font-size: 18px\">Zitat</p></blockquote><hr><pre>  eingerückt</pre>",
+            "<tr><td>Stuhl mit sehr langer Beschreibung, die umbrochen wird</td><td>49,90 €</td></tr>".repeat(80)
+        );
+
+        let pdf_path = generator.generate_pdf(vec![email], 1).unwrap();
+
+        // 80 table rows do not fit on one page
+        assert!(count_pages(&pdf_path) > 1);
+    }
+
+    fn image_attachment(name: &str, content_id: Option<&str>) -> Attachment {
+        use base64::Engine;
+        use printpdf::image_crate::{DynamicImage, ImageOutputFormat, RgbImage};
+
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(300, 100, printpdf::image_crate::Rgb([0, 90, 160])))
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+
+        let mut attachment = Attachment::new(name.to_string(), png.len(), "image/png".to_string());
+        attachment.content_id = content_id.map(str::to_string);
+        attachment.is_inline = content_id.is_some();
+        attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(&png));
+        attachment
+    }
+
+    #[test]
+    fn test_generate_pdf_renders_inline_images_and_image_attachments() {
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(
+            temp_dir.path().to_path_buf(),
+            "images".to_string()
+        ).unwrap().with_image_attachments(true);
+
+        let mut email = create_test_email("Screenshot", "sender@example.com", "recipient@example.com");
+        email.is_html = true;
+        email.body = "<p>Siehe Bild:</p><img src=\"cid:shot@example.com\"><img src=\"cid:missing\" alt=\"Fehlt\">".to_string();
+        email.attachments = vec![image_attachment("shot.png", Some("shot@example.com")), image_attachment("scan.png", None)];
+
+        let pdf_path = generator.generate_pdf(vec![email], 1).unwrap();
+        let content = String::from_utf8_lossy(&std::fs::read(&pdf_path).unwrap()).into_owned();
+
+        // One image in the body and the attachment on a page of its own
+        assert_eq!(content.matches("/Subtype/Image").count(), 2);
+        assert_eq!(count_pages(&pdf_path), 2);
+    }
+
+    #[test]
+    fn test_generate_pdf_writes_document_properties() {
+        use printpdf::lopdf::{self, Object};
+
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(temp_dir.path().to_path_buf(), "properties".to_string())
+            .unwrap()
+            .with_source_name("Postfach.pst")
+            .with_provenance(ArchiveProvenance {
+                owner: Some("Anna Example".to_string()),
+                source_hash: Some("9f86d081".to_string()),
+                session_id: Some("session-42".to_string()),
+            });
+
+        let mut email = create_test_email("Angebot", "anna@example.com", "recipient@example.com");
+        email.message_id = Some("<angebot@example.com>".to_string());
+        let pdf_path = generator.generate_pdf(vec![email], 1).unwrap();
+
+        let document = lopdf::Document::load(&pdf_path).unwrap();
+        let info = document.get_dictionary(document.trailer.get(b"Info").and_then(Object::as_reference).unwrap()).unwrap();
+        let text = |key: &[u8]| String::from_utf8_lossy(info.get(key).and_then(Object::as_str).unwrap()).into_owned();
+        assert_eq!(text(b"Title"), "Email Archive - properties");
+        assert_eq!(text(b"Author"), "Anna Example");
+        assert!(text(b"Subject").starts_with("1 email from "));
+        assert_eq!(text(b"Keywords"), "Email archive, Postfach.pst");
+        assert_eq!(text(b"SourceFileHash"), "9f86d081");
+        assert_eq!(text(b"FirstMessageID"), "<angebot@example.com>");
+
+        // The XMP metadata mirrors the custom properties
+        let metadata = document.catalog().unwrap().get(b"Metadata").and_then(Object::as_reference).unwrap();
+        let xmp = String::from_utf8(document.get_object(metadata).and_then(Object::as_stream).unwrap().content.clone()).unwrap();
+        assert!(xmp.contains("<dc:creator><rdf:Seq><rdf:li>Anna Example</rdf:li></rdf:Seq></dc:creator>"));
+        assert!(xmp.contains("<archive:SessionID>session-42</archive:SessionID>"));
+        assert!(xmp.contains("<archive:EmailCount>1</archive:EmailCount>"));
+    }
+
+    #[test]
+    fn test_generate_pdf_embeds_attachments_within_limits() {
+        use base64::Engine;
+        use printpdf::lopdf::{self, Object};
+
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(
+            temp_dir.path().to_path_buf(),
+            "embedded".to_string()
+        ).unwrap().with_embedded_attachments(EmbeddingLimits { max_file_size: 1000, max_pdf_size: 5000 });
+
+        let mut email = create_test_email("Vertrag", "sender@example.com", "recipient@example.com");
+        for (name, size) in [("Vertrag.txt", 200), ("Video.mp4", 2000)] {
+            let mut attachment = Attachment::new(name.to_string(), size, "text/plain".to_string());
+            attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(vec![b'a'; size]));
+            email.attachments.push(attachment);
+        }
+
+        let pdf_path = generator.generate_pdf(vec![email], 1).unwrap();
+        let document = lopdf::Document::load(&pdf_path).unwrap();
+
+        // Only the attachment below the per-file limit is embedded
+        let names = document.catalog().unwrap()
+            .get(b"Names").and_then(Object::as_dict).unwrap()
+            .get(b"EmbeddedFiles").and_then(Object::as_dict).unwrap()
+            .get(b"Names").and_then(Object::as_array).unwrap();
+        assert_eq!(names.len(), 2);
+
+        let page_id = document.get_pages()[&1];
+        let annotations = document.get_dictionary(page_id).unwrap().get(b"Annots").and_then(Object::as_array).unwrap();
+        let annotation = document.get_dictionary(annotations[0].as_reference().unwrap()).unwrap();
+        assert_eq!(annotation.get(b"Subtype").and_then(Object::as_name_str).unwrap(), "FileAttachment");
+    }
+
+    #[test]
+    fn test_generate_pdfa_passes_self_check() {
+        use base64::Engine;
+
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(temp_dir.path().to_path_buf(), "archiv".to_string())
+            .unwrap()
+            .with_fonts(FontChain::default())
+            .with_pdf_standard(PdfStandard::PdfA3b);
+
+        // Built-in fonts cannot be embedded, so PDF/A needs font files
+        let email = create_test_email("Prüfung", "sender@example.com", "recipient@example.com");
+        assert!(generator.generate_pdf(vec![email.clone()], 1).is_err());
+
+        let generator = generator
+            .with_fonts(FontChain::load(&[bundled_font("DejaVuSans.ttf")]).unwrap())
+            .with_embedded_attachments(EmbeddingLimits::default());
+
+        let mut html_email = email.clone();
+        html_email.is_html = true;
+        html_email.body = "<p>Siehe <b>Anhang</b> und Bild:</p><img src=\"cid:shot@example.com\"><pre>code 中</pre>".to_string();
+        html_email.attachments.push(image_attachment("shot.png", Some("shot@example.com")));
+        let mut attachment = Attachment::new("Vertrag.txt".to_string(), 5, "text/plain".to_string());
+        attachment.data = Some(base64::engine::general_purpose::STANDARD.encode(b"Hallo"));
+        html_email.attachments.push(attachment);
+
+        let pdf_path = generator.generate_pdf(vec![email, html_email], 2).unwrap();
+        let bytes = std::fs::read(&pdf_path).unwrap();
+        assert!(bytes.starts_with(b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n"));
+
+        let document = lopdf::Document::load_mem(&bytes).unwrap();
+        assert_eq!(check_pdfa(&document, PdfStandard::PdfA3b), Vec::<String>::new());
+        // PDF/A-2b would reject the embedded files
+        assert!(!check_pdfa(&document, PdfStandard::PdfA2b).is_empty());
+    }
+
+    #[test]
+    fn test_generate_pdf_adds_outline_and_table_of_contents() {
+        use printpdf::lopdf::{self, Object};
+
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(
+            temp_dir.path().to_path_buf(),
+            "navigation".to_string()
+        ).unwrap().with_table_of_contents(true);
+
+        let mut first = create_test_email("Angebot", "anna@example.com", "recipient@example.com");
+        first.attachments.push(Attachment::new("Angebot.pdf".to_string(), 2048, "application/pdf".to_string()));
+        let second = create_test_email("Antwort", "ben@example.com", "recipient@example.com");
+
+        let pdf_path = generator.generate_pdf(vec![first, second], 1).unwrap();
+        let document = lopdf::Document::load(&pdf_path).unwrap();
+        let pages: Vec<_> = document.get_pages().into_values().collect();
+        let dictionary = |id| document.get_dictionary(id).unwrap();
+        let reference = |dict: &lopdf::Dictionary, key: &[u8]| dict.get(key).and_then(Object::as_reference).unwrap();
+
+        // One bookmark per email, the attachment nested below the first
+        let root = dictionary(reference(document.catalog().unwrap(), b"Outlines"));
+        assert_eq!(root.get(b"Count").and_then(Object::as_i64).unwrap(), 2);
+        let first_item = dictionary(reference(root, b"First"));
+        assert_eq!(first_item.get(b"Count").and_then(Object::as_i64).unwrap(), -1);
+        assert!(first_item.get(b"Title").and_then(Object::as_str).unwrap().ends_with(b"| anna@example.com | Angebot"));
+
+        // The contents on the first page link to the emails, which start on the second page
+        let annotations = dictionary(pages[0]).get(b"Annots").and_then(Object::as_array).unwrap();
+        assert_eq!(annotations.len(), 2);
+        let link = dictionary(annotations[0].as_reference().unwrap());
+        let destination = link.get(b"Dest").and_then(Object::as_array).unwrap();
+        assert_eq!(destination[0].as_reference().unwrap(), pages[1]);
+    }
+
+    #[test]
+    fn test_generate_pdf_draws_page_frame_on_every_page() {
+        use printpdf::lopdf;
+
+        let temp_dir = TempDir::new().unwrap();
+        let generator = PdfGenerator::new(
+            temp_dir.path().to_path_buf(),
+            "frame".to_string()
+        ).unwrap()
+            .with_table_of_contents(true)
+            .with_source_name("Postfach.pst")
+            .with_page_frame(PageFrame {
+                page_numbers: true,
+                sequence_number: true,
+                source_name: true,
+                archive_date: true,
+                legal_notice: Some("Vertraulich. Nur für den internen Gebrauch.".to_string()),
+            });
+
+        let email = create_test_email("Angebot", "anna@example.com", "recipient@example.com");
+        let pdf_path = generator.generate_pdf(vec![email], 7).unwrap();
+        let document = lopdf::Document::load(&pdf_path).unwrap();
+        assert_eq!(document.get_pages().len(), 2);
+
+        let second_page = document.extract_text(&[2]).unwrap();
+        assert!(second_page.contains("Seite 2 von 2"));
+        assert!(second_page.contains("Archiv-PDF Nr. 7"));
+        assert!(second_page.contains("Quelle: Postfach.pst"));
+        assert!(second_page.contains("den internen Gebrauch."));
+        let first_page = document.extract_text(&[1]).unwrap();
+        assert!(first_page.contains("Seite 1 von 2"));
+    }
+
     #[test]
     fn test_truncate_text() {
         let temp_dir = TempDir::new().unwrap();
@@ -554,11 +1487,16 @@ mod tests {
         ).unwrap();
         
         let body_text = "Line 1\n\nLine 2 with some content\n   \nLine 3";
-        let lines = generator.prepare_body_text(body_text, 20);
+        let lines = generator.prepare_body_text(body_text, 20.0, |text| text.chars().count() as f32);
         
-        assert_eq!(lines.len(), 2);
-        assert_eq!(lines[0], "Line 1 Line 2 with");
-        assert_eq!(lines[1], "some content Line 3");
+        assert_eq!(lines, vec!["Line 1", "", "Line 2 with some", "content", "", "Line 3"]);
+
+        let lines = generator.prepare_body_text("Hallo\t\tBernd,\r\n\r\n\r\n\r\nbis  morgen\n\n", 40.0, |text| text.chars().count() as f32);
+        assert_eq!(lines, vec!["Hallo Bernd,", "", "bis morgen"]);
+
+        let html_body = "<table><tr><td\n   style=\"color: red\"\n   class=\"cell\">Zelle 1</td>\n\n\n<td>Zelle 2</td></tr></table>";
+        let lines = generator.prepare_body_text(html_body, 40.0, |text| text.chars().count() as f32);
+        assert_eq!(lines, vec!["Zelle 1", "", "Zelle 2"]);
     }
 
     #[test]
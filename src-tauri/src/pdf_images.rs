@@ -0,0 +1,185 @@
+use base64::Engine;
+use printpdf::image_crate::imageops::FilterType;
+use printpdf::image_crate::{self, DynamicImage, GenericImageView, RgbImage};
+use printpdf::{Image, ImageTransform, Mm, PdfLayerReference};
+use crate::errors::{PdfError, PdfResult};
+use crate::types::{Attachment, Email};
+
+/// Longest side in pixels; larger images are scaled down before embedding
+const MAX_IMAGE_PIXELS: u32 = 1600;
+
+/// Resolution at which image pixels are laid out, like in a mail client
+const SCREEN_DPI: f32 = 96.0;
+
+/// File extensions of image formats that can be rendered
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp"];
+
+/// A decoded raster image ready to be placed in a PDF
+#[derive(Debug, Clone)]
+pub struct PdfImage {
+    pixels: RgbImage,
+}
+
+impl PdfImage {
+    /// Decode a PNG, JPEG, GIF (first frame) or BMP image
+    /// Transparent areas are composed onto white, as PDF images have no alpha channel here
+    pub fn decode(data: &[u8]) -> PdfResult<Self> {
+        let image = image_crate::load_from_memory(data)
+            .map_err(|e| PdfError::FormattingError(format!("Unsupported image: {}", e)))?;
+
+        let (width, height) = image.dimensions();
+        let image = if width.max(height) > MAX_IMAGE_PIXELS {
+            image.resize(MAX_IMAGE_PIXELS, MAX_IMAGE_PIXELS, FilterType::Triangle)
+        } else {
+            image
+        };
+
+        let rgba = image.to_rgba8();
+        let pixels = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let over_white = |channel: u8| ((channel as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+            image_crate::Rgb([over_white(r), over_white(g), over_white(b)])
+        });
+        Ok(Self { pixels })
+    }
+
+    /// Decode the data of an image attachment
+    pub fn from_attachment(attachment: &Attachment) -> PdfResult<Self> {
+        let data = attachment
+            .data
+            .as_deref()
+            .ok_or_else(|| PdfError::FormattingError(format!("No data for image {}", attachment.name)))?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|e| PdfError::FormattingError(format!("Invalid data for image {}: {}", attachment.name, e)))?;
+        Self::decode(&bytes)
+    }
+
+    /// Decode a `data:image/...;base64,` URI
+    pub fn from_data_uri(uri: &str) -> PdfResult<Self> {
+        let encoded = uri
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+            .map(|(_, encoded)| encoded)
+            .ok_or_else(|| PdfError::FormattingError("Only base64 data URIs are supported".to_string()))?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| PdfError::FormattingError(format!("Invalid image data URI: {}", e)))?;
+        Self::decode(&bytes)
+    }
+
+    /// Size in mm when shown like a mail client at 96 dpi, or at the requested size in CSS pixels
+    /// The result is scaled down proportionally to fit `max_width` and `max_height`
+    pub fn display_size(&self, requested: (Option<f32>, Option<f32>), max_width: f32, max_height: f32) -> (f32, f32) {
+        let (pixel_width, pixel_height) = (self.pixels.width() as f32, self.pixels.height() as f32);
+        let (width, height) = match requested {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, width * pixel_height / pixel_width),
+            (None, Some(height)) => (height * pixel_width / pixel_height, height),
+            (None, None) => (pixel_width, pixel_height),
+        };
+
+        let to_mm = 25.4 / SCREEN_DPI;
+        let (width, height) = (width * to_mm, height * to_mm);
+        let fit = (max_width / width).min(max_height / height).min(1.0);
+        (width * fit, height * fit)
+    }
+
+    /// Draw the image with its lower left corner at `x`/`y`, stretched to `width` x `height` mm
+    pub fn draw(&self, layer: PdfLayerReference, x: Mm, y: Mm, width: f32, height: f32) {
+        const DPI: f32 = 300.0;
+        let natural_mm = |pixels: u32| pixels as f32 / DPI * 25.4;
+
+        Image::from_dynamic_image(&DynamicImage::ImageRgb8(self.pixels.clone())).add_to_layer(
+            layer,
+            ImageTransform {
+                translate_x: Some(x),
+                translate_y: Some(y),
+                scale_x: Some(width / natural_mm(self.pixels.width())),
+                scale_y: Some(height / natural_mm(self.pixels.height())),
+                dpi: Some(DPI),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// Whether an attachment is an image in a format that can be rendered
+pub fn is_renderable_image(attachment: &Attachment) -> bool {
+    let extension = attachment.extension().map(str::to_ascii_lowercase);
+    let by_extension = extension.as_deref().is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension));
+    let by_type = matches!(
+        attachment.content_type.to_ascii_lowercase().as_str(),
+        "image/png" | "image/jpeg" | "image/jpg" | "image/pjpeg" | "image/gif" | "image/bmp" | "image/x-ms-bmp"
+    );
+    attachment.data.is_some() && (by_type || by_extension)
+}
+
+/// The inline attachment an `<img src="cid:...">` refers to
+pub fn find_content_id<'a>(email: &'a Email, source: &str) -> Option<&'a Attachment> {
+    let content_id = source.strip_prefix("cid:")?.trim().trim_start_matches('<').trim_end_matches('>');
+    email
+        .attachments
+        .iter()
+        .find(|attachment| attachment.content_id.as_deref().is_some_and(|id| id.eq_ignore_ascii_case(content_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use chrono::Utc;
+
+    /// A 4x2 PNG with a fully transparent right half
+    fn test_png() -> Vec<u8> {
+        let image = image_crate::RgbaImage::from_fn(4, 2, |x, _| {
+            if x < 2 { image_crate::Rgba([255, 0, 0, 255]) } else { image_crate::Rgba([0, 0, 0, 0]) }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut bytes), image_crate::ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_decode_composes_transparency_onto_white() {
+        let image = PdfImage::decode(&test_png()).unwrap();
+
+        assert_eq!(image.pixels.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.pixels.get_pixel(3, 1).0, [255, 255, 255]);
+        assert!(PdfImage::decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn test_display_size_fits_available_space() {
+        let image = PdfImage::decode(&test_png()).unwrap();
+
+        // 4 px at 96 dpi
+        let (width, height) = image.display_size((None, None), 100.0, 100.0);
+        assert!((width - 1.058).abs() < 0.01 && (height - width / 2.0).abs() < 0.001);
+
+        let (width, height) = image.display_size((Some(960.0), None), 100.0, 100.0);
+        assert_eq!((width, height), (100.0, 50.0));
+    }
+
+    #[test]
+    fn test_resolves_content_ids_and_data_uris() {
+        let png = test_png();
+        let mut logo = Attachment::new("logo.png".to_string(), png.len(), "application/octet-stream".to_string());
+        logo.content_id = Some("Logo@Example.com".to_string());
+        logo.is_inline = true;
+        logo.data = Some(base64::engine::general_purpose::STANDARD.encode(&png));
+
+        let mut email = Email::new(String::new(), String::new(), String::new(), Utc::now(), String::new());
+        email.attachments.push(logo);
+
+        let found = find_content_id(&email, "cid:logo@example.com").unwrap();
+        assert!(is_renderable_image(found));
+        assert!(PdfImage::from_attachment(found).is_ok());
+        assert!(find_content_id(&email, "https://example.com/logo.png").is_none());
+
+        let uri = format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(&png));
+        assert!(PdfImage::from_data_uri(&uri).is_ok());
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use chrono::SecondsFormat;
+use printpdf::lopdf::{self, Object};
+use sha2::{Digest, Sha256};
+use crate::dedup::sender_address;
+use crate::errors::{PdfError, PdfResult};
+use crate::pdf_attachments::text_string;
+use crate::types::Email;
+
+/// Most folder names listed in the keywords of a PDF
+const MAX_FOLDER_KEYWORDS: usize = 20;
+
+/// Where the emails of an archive come from, recorded in the properties of every PDF
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArchiveProvenance {
+    /// Owner of the mailbox, written as the author
+    pub owner: Option<String>,
+    /// Hex SHA-256 of the source file; merged archives list one per source, separated by commas
+    pub source_hash: Option<String>,
+    /// Processing session that wrote the PDF
+    pub session_id: Option<String>,
+}
+
+/// Document properties of a single archive PDF
+///
+/// Written to the Info dictionary; the XMP metadata mirrors them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentMetadata {
+    pub title: String,
+    pub author: Option<String>,
+    pub subject: String,
+    pub keywords: Vec<String>,
+    /// Info entries beyond the standard ones, by key
+    pub custom: Vec<(&'static str, String)>,
+}
+
+impl DocumentMetadata {
+    /// Properties of a PDF containing `emails` in document order
+    pub fn for_emails(title: String, source_name: Option<&str>, emails: &[Email], provenance: &ArchiveProvenance) -> Self {
+        let first_date = emails.iter().map(|email| email.date).min();
+        let last_date = emails.iter().map(|email| email.date).max();
+
+        let subject = match (first_date, last_date) {
+            (Some(first), Some(last)) if first.date_naive() != last.date_naive() => format!(
+                "{} emails from {} to {}",
+                emails.len(),
+                first.format("%d.%m.%Y"),
+                last.format("%d.%m.%Y")
+            ),
+            (Some(first), _) if emails.len() == 1 => format!("1 email from {}", first.format("%d.%m.%Y")),
+            (Some(first), _) => format!("{} emails from {}", emails.len(), first.format("%d.%m.%Y")),
+            _ => "No emails".to_string(),
+        };
+
+        let mut keywords = vec!["Email archive".to_string()];
+        keywords.extend(source_name.map(str::to_string));
+        for folder in emails.iter().filter_map(|email| email.folder.as_deref()) {
+            if keywords.len() >= MAX_FOLDER_KEYWORDS + 2 {
+                break;
+            }
+            if !keywords.iter().any(|keyword| keyword == folder) {
+                keywords.push(folder.to_string());
+            }
+        }
+
+        let mut custom = Vec::new();
+        if let Some(hash) = &provenance.source_hash {
+            custom.push(("SourceFileHash", hash.clone()));
+        }
+        if let Some(session_id) = &provenance.session_id {
+            custom.push(("SessionID", session_id.clone()));
+        }
+        custom.push(("EmailCount", emails.len().to_string()));
+        if let (Some(first), Some(last)) = (first_date, last_date) {
+            custom.push(("EmailsFrom", first.to_rfc3339_opts(SecondsFormat::Secs, true)));
+            custom.push(("EmailsTo", last.to_rfc3339_opts(SecondsFormat::Secs, true)));
+        }
+        if let Some(message_id) = emails.first().and_then(|email| email.message_id.clone()) {
+            custom.push(("FirstMessageID", message_id));
+        }
+        if let Some(message_id) = emails.last().and_then(|email| email.message_id.clone()) {
+            custom.push(("LastMessageID", message_id));
+        }
+
+        Self {
+            title,
+            author: provenance.owner.clone().or_else(|| mailbox_owner(emails)),
+            subject,
+            keywords,
+            custom,
+        }
+    }
+
+    /// Write the properties to the Info dictionary of a saved document
+    pub fn apply(&self, document: &mut lopdf::Document) -> PdfResult<()> {
+        let info_id = document.trailer.get(b"Info").and_then(Object::as_reference).map_err(metadata_error)?;
+        let info = document.get_dictionary_mut(info_id).map_err(metadata_error)?;
+
+        info.set("Title", text_string(&self.title));
+        match &self.author {
+            Some(author) => info.set("Author", text_string(author)),
+            None => {
+                info.remove(b"Author");
+            }
+        }
+        info.set("Subject", text_string(&self.subject));
+        info.set("Keywords", text_string(&self.keywords.join(", ")));
+        for (key, value) in &self.custom {
+            info.set(*key, text_string(value));
+        }
+        Ok(())
+    }
+}
+
+/// The address taking part in the most emails, which is the owner's in a personal mailbox
+/// Ties go to the address seen first
+pub fn mailbox_owner(emails: &[Email]) -> Option<String> {
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for email in emails {
+        let mut addresses: Vec<String> = std::iter::once(email.sender.as_str())
+            .chain(email.recipient.split(','))
+            .chain(email.cc_recipients.iter().map(String::as_str))
+            .map(sender_address)
+            .filter(|address| address.contains('@'))
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        for address in addresses {
+            let first_seen = counts.len();
+            counts.entry(address).or_insert((0, first_seen)).0 += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by(|(_, (count_a, seen_a)), (_, (count_b, seen_b))| count_a.cmp(count_b).then(seen_b.cmp(seen_a)))
+        .map(|(address, _)| address)
+}
+
+/// Hex SHA-256 of a file's content
+pub fn file_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn metadata_error(error: lopdf::Error) -> PdfError {
+    PdfError::GenerationFailed(format!("Failed to write document properties: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn email(sender: &str, recipient: &str, day: u32, message_id: &str) -> Email {
+        let date = Utc.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap();
+        let mut email = Email::new("Angebot".to_string(), sender.to_string(), recipient.to_string(), date, "Text".to_string());
+        email.message_id = Some(message_id.to_string());
+        email.folder = Some("Inbox".to_string());
+        email
+    }
+
+    #[test]
+    fn test_metadata_describes_contained_emails() {
+        let emails = vec![
+            email("Anna <anna@example.com>", "me@example.com", 4, "<first@example.com>"),
+            email("me@example.com", "Ben <ben@example.com>, anna@example.com", 2, "<second@example.com>"),
+            email("ben@example.com", "Me <ME@example.com>", 9, "<third@example.com>"),
+        ];
+        let provenance = ArchiveProvenance { owner: None, source_hash: Some("ab12".to_string()), session_id: Some("session-1".to_string()) };
+        let metadata = DocumentMetadata::for_emails("Email Archive - test".to_string(), Some("Postfach.pst"), &emails, &provenance);
+
+        assert_eq!(metadata.author.as_deref(), Some("me@example.com"));
+        assert_eq!(metadata.subject, "3 emails from 02.03.2024 to 09.03.2024");
+        assert_eq!(metadata.keywords, vec!["Email archive", "Postfach.pst", "Inbox"]);
+
+        let custom: HashMap<_, _> = metadata.custom.iter().cloned().collect();
+        assert_eq!(custom["SourceFileHash"], "ab12");
+        assert_eq!(custom["SessionID"], "session-1");
+        assert_eq!(custom["EmailCount"], "3");
+        assert_eq!(custom["EmailsFrom"], "2024-03-02T09:00:00Z");
+        assert_eq!(custom["EmailsTo"], "2024-03-09T09:00:00Z");
+        assert_eq!(custom["FirstMessageID"], "<first@example.com>");
+        assert_eq!(custom["LastMessageID"], "<third@example.com>");
+    }
+
+    #[test]
+    fn test_configured_owner_wins_over_derived_one() {
+        let emails = vec![email("anna@example.com", "ben@example.com", 1, "<a@example.com>")];
+        let provenance = ArchiveProvenance { owner: Some("Chris Example".to_string()), ..Default::default() };
+        let metadata = DocumentMetadata::for_emails("Archive".to_string(), None, &emails, &provenance);
+
+        assert_eq!(metadata.author.as_deref(), Some("Chris Example"));
+        assert_eq!(metadata.subject, "1 email from 01.03.2024");
+        assert!(!metadata.custom.iter().any(|(key, _)| *key == "SourceFileHash"));
+        // Sender and recipient take part equally often; the first one seen wins
+        assert_eq!(mailbox_owner(&emails).as_deref(), Some("anna@example.com"));
+    }
+
+    #[test]
+    fn test_file_hash() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("source.mbox");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(file_hash(&path).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
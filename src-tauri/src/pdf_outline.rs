@@ -0,0 +1,215 @@
+use printpdf::lopdf::{self, Dictionary, Object, ObjectId};
+use crate::errors::{PdfError, PdfResult};
+use crate::pdf_attachments::{add_annotation, text_string, POINTS_PER_MM};
+
+/// A position in the document that bookmarks and links jump to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Destination {
+    /// Zero-based page index
+    pub page: usize,
+    /// Distance of the target's top edge from the bottom of the page in mm
+    pub top: f32,
+}
+
+/// An entry of the bookmark outline, with the entries nested below it
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineItem {
+    pub title: String,
+    pub destination: Destination,
+    pub children: Vec<OutlineItem>,
+}
+
+impl OutlineItem {
+    pub fn new(title: impl Into<String>, destination: Destination) -> Self {
+        Self { title: title.into(), destination, children: Vec::new() }
+    }
+}
+
+/// A clickable area that jumps to another place in the same document
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InternalLink {
+    /// Zero-based index of the page the area is on
+    pub page: usize,
+    /// Left, bottom, right and top edge of the area in mm
+    pub rect: [f32; 4],
+    pub destination: Destination,
+}
+
+/// Replace the outline of a saved document with `items`
+///
+/// Top-level entries are shown expanded, their children collapsed.
+pub fn add_outline(document: &mut lopdf::Document, items: &[OutlineItem]) -> PdfResult<()> {
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    let pages = page_ids(document);
+    let root = document.new_object_id();
+    let (first, last) = add_items(document, &pages, items, root);
+    document.objects.insert(
+        root,
+        Object::Dictionary(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Outlines".to_vec())),
+            ("First", Object::Reference(first)),
+            ("Last", Object::Reference(last)),
+            ("Count", Object::Integer(items.len() as i64)),
+        ])),
+    );
+
+    let catalog = document.catalog_mut().map_err(outline_error)?;
+    catalog.set("Outlines", Object::Reference(root));
+    catalog.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+    Ok(())
+}
+
+/// Add link annotations that jump to places within the document
+pub fn add_internal_links(document: &mut lopdf::Document, links: &[InternalLink]) -> PdfResult<()> {
+    let pages = page_ids(document);
+    for link in links {
+        let (Some(&page), Some(destination)) = (pages.get(link.page), destination(&pages, link.destination)) else {
+            continue;
+        };
+        let rect = link.rect.iter().map(|mm| Object::Real(mm * POINTS_PER_MM)).collect();
+        let annotation = document.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Annot".to_vec())),
+            ("Subtype", Object::Name(b"Link".to_vec())),
+            ("Rect", Object::Array(rect)),
+            ("Border", Object::Array(vec![0.into(), 0.into(), 0.into()])),
+            ("Dest", destination),
+            // Printable
+            ("F", Object::Integer(4)),
+        ]));
+        add_annotation(document, page, annotation)?;
+    }
+    Ok(())
+}
+
+/// Write the outline dictionaries of `items` as children of `parent`
+/// Returns the first and last of them
+fn add_items(document: &mut lopdf::Document, pages: &[ObjectId], items: &[OutlineItem], parent: ObjectId) -> (ObjectId, ObjectId) {
+    let ids: Vec<ObjectId> = items.iter().map(|_| document.new_object_id()).collect();
+
+    for (index, item) in items.iter().enumerate() {
+        let mut dict = Dictionary::from_iter(vec![
+            ("Title", text_string(&item.title)),
+            ("Parent", Object::Reference(parent)),
+        ]);
+        if let Some(destination) = destination(pages, item.destination) {
+            dict.set("Dest", destination);
+        }
+        if index > 0 {
+            dict.set("Prev", Object::Reference(ids[index - 1]));
+        }
+        if let Some(next) = ids.get(index + 1) {
+            dict.set("Next", Object::Reference(*next));
+        }
+        if !item.children.is_empty() {
+            let (first, last) = add_items(document, pages, &item.children, ids[index]);
+            dict.set("First", Object::Reference(first));
+            dict.set("Last", Object::Reference(last));
+            // A negative count shows the entry collapsed
+            dict.set("Count", Object::Integer(-(item.children.len() as i64)));
+        }
+        document.objects.insert(ids[index], Object::Dictionary(dict));
+    }
+
+    (ids[0], ids[ids.len() - 1])
+}
+
+/// Object IDs of the pages in page order
+fn page_ids(document: &lopdf::Document) -> Vec<ObjectId> {
+    document.get_pages().into_values().collect()
+}
+
+/// An explicit destination showing the target's top edge at the top of the window
+fn destination(pages: &[ObjectId], destination: Destination) -> Option<Object> {
+    let page = pages.get(destination.page)?;
+    Some(Object::Array(vec![
+        Object::Reference(*page),
+        Object::Name(b"XYZ".to_vec()),
+        Object::Null,
+        Object::Real(destination.top * POINTS_PER_MM),
+        Object::Null,
+    ]))
+}
+
+fn outline_error(error: lopdf::Error) -> PdfError {
+    PdfError::GenerationFailed(format!("Failed to add outline: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A document with `count` empty pages
+    fn document_with_pages(count: usize) -> lopdf::Document {
+        let mut document = lopdf::Document::with_version("1.7");
+        let pages_id = document.new_object_id();
+        let kids: Vec<Object> = (0..count)
+            .map(|_| {
+                Object::Reference(document.add_object(Dictionary::from_iter(vec![
+                    ("Type", Object::Name(b"Page".to_vec())),
+                    ("Parent", Object::Reference(pages_id)),
+                ])))
+            })
+            .collect();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(Dictionary::from_iter(vec![
+                ("Type", Object::Name(b"Pages".to_vec())),
+                ("Count", Object::Integer(count as i64)),
+                ("Kids", Object::Array(kids)),
+            ])),
+        );
+        let catalog = document.add_object(Dictionary::from_iter(vec![
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Pages", Object::Reference(pages_id)),
+        ]));
+        document.trailer.set("Root", Object::Reference(catalog));
+        document
+    }
+
+    fn title(document: &lopdf::Document, item: ObjectId) -> String {
+        let bytes = document.get_dictionary(item).unwrap().get(b"Title").and_then(Object::as_str).unwrap();
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    #[test]
+    fn test_outline_nests_children() {
+        let mut document = document_with_pages(3);
+        let mut first = OutlineItem::new("01.02.2024 Anna: Angebot", Destination { page: 0, top: 250.0 });
+        first.children.push(OutlineItem::new("Angebot.pdf", Destination { page: 0, top: 250.0 }));
+        first.children.push(OutlineItem::new("Preise.xlsx", Destination { page: 0, top: 250.0 }));
+        let second = OutlineItem::new("02.02.2024 Ben: Antwort", Destination { page: 2, top: 100.0 });
+        add_outline(&mut document, &[first, second]).unwrap();
+
+        let catalog = document.catalog().unwrap();
+        let root = document.get_dictionary(catalog.get(b"Outlines").and_then(Object::as_reference).unwrap()).unwrap();
+        assert_eq!(root.get(b"Count").and_then(Object::as_i64).unwrap(), 2);
+
+        let first_id = root.get(b"First").and_then(Object::as_reference).unwrap();
+        let first = document.get_dictionary(first_id).unwrap();
+        assert_eq!(title(&document, first_id), "01.02.2024 Anna: Angebot");
+        assert_eq!(first.get(b"Count").and_then(Object::as_i64).unwrap(), -2);
+        let child = first.get(b"Last").and_then(Object::as_reference).unwrap();
+        assert_eq!(title(&document, child), "Preise.xlsx");
+
+        let second_id = first.get(b"Next").and_then(Object::as_reference).unwrap();
+        assert_eq!(root.get(b"Last").and_then(Object::as_reference).unwrap(), second_id);
+        let destination = document.get_dictionary(second_id).unwrap().get(b"Dest").and_then(Object::as_array).unwrap();
+        assert_eq!(destination[0].as_reference().unwrap(), page_ids(&document)[2]);
+    }
+
+    #[test]
+    fn test_links_are_added_to_their_page() {
+        let mut document = document_with_pages(2);
+        let link = InternalLink { page: 0, rect: [20.0, 200.0, 190.0, 205.0], destination: Destination { page: 1, top: 280.0 } };
+        add_internal_links(&mut document, &[link]).unwrap();
+
+        let pages = page_ids(&document);
+        let annotations = document.get_dictionary(pages[0]).unwrap().get(b"Annots").and_then(Object::as_array).unwrap();
+        let annotation = document.get_dictionary(annotations[0].as_reference().unwrap()).unwrap();
+        assert_eq!(annotation.get(b"Subtype").and_then(Object::as_name_str).unwrap(), "Link");
+        assert!(!document.get_dictionary(pages[1]).unwrap().has(b"Annots"));
+    }
+}
@@ -0,0 +1,341 @@
+//! Lists, tables and properties layer of the PST format (MS-PST 2.3)
+//!
+//! Decodes the heap-on-node (HN), the B-tree-on-heap (BTH) and the property
+//! and table contexts stored in PST nodes into `mapi::PropertyBag`s.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::errors::{PstError, PstResult};
+use crate::mapi::{decode_string8, decode_utf16, filetime_to_datetime, prop_type, PropertyBag, PropertyValue};
+use crate::pst_ndb::{read_bytes, read_u16, read_u32, read_u64, NodeDatabase, Subnodes};
+
+/// Heap signature bSig
+const HEAP_SIGNATURE: u8 = 0xEC;
+
+/// Client signatures (bClientSig) of the heap users
+const CLIENT_TABLE_CONTEXT: u8 = 0x7C;
+const CLIENT_BTREE_ON_HEAP: u8 = 0xB5;
+const CLIENT_PROPERTY_CONTEXT: u8 = 0xBC;
+
+/// Deepest BTH index a heap may contain
+const MAX_BTH_LEVELS: u8 = 8;
+
+/// Property ID of the row ID column every table context has
+pub const LTP_ROW_ID: u16 = 0x67F2;
+
+/// Key and data of one BTH record
+type BthRecord<'a> = (&'a [u8], &'a [u8]);
+
+/// Heap-on-node: the data blocks of a node split into allocations
+struct Heap {
+    blocks: Vec<Arc<Vec<u8>>>,
+    client_signature: u8,
+    user_root: u32,
+}
+
+impl Heap {
+    fn new(blocks: Vec<Arc<Vec<u8>>>) -> PstResult<Self> {
+        // HNHDR: ibHnpm, bSig, bClientSig, hidUserRoot, rgbFillLevel
+        let first = blocks.first().ok_or_else(|| heap_error("Knoten enthält keine Daten"))?;
+        let first = read_bytes(first, 0, 12)?;
+        if first[2] != HEAP_SIGNATURE {
+            return Err(heap_error("ungültige Heap-Signatur"));
+        }
+        Ok(Self {
+            client_signature: first[3],
+            user_root: read_u32(first, 4)?,
+            blocks,
+        })
+    }
+
+    /// Bytes of the allocation `hid`
+    fn item(&self, hid: u32) -> PstResult<&[u8]> {
+        // HID: hidType (5 bits, always 0), hidIndex (11 bits, 1-based), hidBlockIndex (16 bits)
+        let index = ((hid >> 5) & 0x7FF) as usize;
+        let block = self
+            .blocks
+            .get((hid >> 16) as usize)
+            .filter(|_| hid & 0x1F == 0 && index > 0)
+            .ok_or_else(|| heap_error(&format!("ungültige Heap-ID {:#x}", hid)))?;
+
+        // HNPAGEMAP: cAlloc, cFree, rgibAlloc[cAlloc + 1]
+        let page_map = read_u16(block, 0)? as usize;
+        if index > read_u16(block, page_map)? as usize {
+            return Err(heap_error(&format!("Heap-ID {:#x} nicht belegt", hid)));
+        }
+        let start = read_u16(block, page_map + 4 + (index - 1) * 2)? as usize;
+        let end = read_u16(block, page_map + 4 + index * 2)? as usize;
+        if start > end {
+            return Err(heap_error(&format!("Heap-ID {:#x} hat eine negative Länge", hid)));
+        }
+        read_bytes(block, start, end - start)
+    }
+
+    /// Records of the BTH whose header is at `hid`, in key order
+    fn bth_records(&self, hid: u32) -> PstResult<(usize, Vec<BthRecord<'_>>)> {
+        // BTHHEADER: bType, cbKey, cbEnt, bIdxLevels, hidRoot
+        let header = read_bytes(self.item(hid)?, 0, 8)?;
+        if header[0] != CLIENT_BTREE_ON_HEAP {
+            return Err(heap_error("ungültiger BTH-Kopf"));
+        }
+        let key_len = header[1] as usize;
+        let data_len = header[2] as usize;
+        let levels = header[3];
+        let root = read_u32(header, 4)?;
+        if key_len == 0 || levels > MAX_BTH_LEVELS {
+            return Err(heap_error("ungültiger BTH-Kopf"));
+        }
+
+        let mut records = Vec::new();
+        let mut pending = vec![(root, levels)];
+        while let Some((hid, level)) = pending.pop() {
+            if hid == 0 {
+                continue;
+            }
+            // Index records point to the next level with a 4-byte HID
+            let record_len = key_len + if level == 0 { data_len } else { 4 };
+            let items = self.item(hid)?;
+            if level == 0 {
+                records.extend(items.chunks_exact(record_len).map(|record| record.split_at(key_len)));
+            } else {
+                for record in items.chunks_exact(record_len).rev() {
+                    pending.push((read_u32(record, key_len)?, level - 1));
+                }
+            }
+        }
+        Ok((data_len, records))
+    }
+}
+
+/// Properties of a property context with object references kept apart
+#[derive(Debug, Default)]
+pub struct PropertyContext {
+    pub properties: PropertyBag,
+    /// Subnode IDs of PtypObject properties (e.g. embedded messages) by property ID
+    pub objects: HashMap<u16, u32>,
+}
+
+/// Heap and subnodes of one node, used to resolve HNIDs
+struct NodeHeap<'a> {
+    db: &'a NodeDatabase,
+    heap: Heap,
+    subnodes: &'a Subnodes,
+}
+
+impl<'a> NodeHeap<'a> {
+    fn open(db: &'a NodeDatabase, bid_data: u64, subnodes: &'a Subnodes, client_signature: u8) -> PstResult<Self> {
+        let heap = Heap::new(db.data_blocks(bid_data)?)?;
+        if heap.client_signature != client_signature {
+            return Err(heap_error(&format!(
+                "unerwarteter Heap-Typ {:#04x} statt {:#04x}",
+                heap.client_signature, client_signature
+            )));
+        }
+        Ok(Self { db, heap, subnodes })
+    }
+
+    /// Data behind an HNID: a heap allocation, or a subnode if the low five bits are set
+    fn hnid_data(&self, hnid: u32) -> PstResult<Vec<u8>> {
+        if hnid == 0 {
+            return Ok(Vec::new());
+        }
+        if hnid & 0x1F == 0 {
+            return Ok(self.heap.item(hnid)?.to_vec());
+        }
+        let subnode = self
+            .subnodes
+            .get(&hnid)
+            .ok_or_else(|| PstError::CorruptedFile(format!("Unterknoten {:#x} nicht gefunden", hnid)))?;
+        self.db.data(subnode.bid_data)
+    }
+
+    /// Decode a variable-length value stored behind an HNID
+    fn variable_value(&self, property_type: u16, hnid: u32) -> PstResult<Option<PropertyValue>> {
+        let data = self.hnid_data(hnid)?;
+        Ok(match property_type {
+            prop_type::UNICODE => Some(PropertyValue::String(decode_utf16(&data))),
+            prop_type::STRING8 => Some(PropertyValue::String(decode_string8(&data))),
+            prop_type::BINARY => Some(PropertyValue::Binary(data)),
+            _ => None,
+        })
+    }
+}
+
+/// Read the property context stored in a node
+pub fn read_properties(db: &NodeDatabase, bid_data: u64, subnodes: &Subnodes) -> PstResult<PropertyContext> {
+    let node = NodeHeap::open(db, bid_data, subnodes, CLIENT_PROPERTY_CONTEXT)?;
+    let (data_len, records) = node.heap.bth_records(node.heap.user_root)?;
+    if data_len != 6 {
+        return Err(heap_error("ungültiger Eigenschaftskontext"));
+    }
+
+    // PC records: wPropId -> wPropType, dwValueHnid
+    let mut context = PropertyContext::default();
+    for (key, data) in records {
+        let id = read_u16(key, 0)?;
+        let property_type = read_u16(data, 0)?;
+        let value = read_u32(data, 2)?;
+        let decoded = match property_type {
+            prop_type::INTEGER16 => Some(PropertyValue::Integer(value as u16 as i16 as i64)),
+            prop_type::INTEGER32 => Some(PropertyValue::Integer(value as i32 as i64)),
+            prop_type::BOOLEAN => Some(PropertyValue::Boolean(value & 0xFF != 0)),
+            prop_type::INTEGER64 => Some(PropertyValue::Integer(read_u64(node.heap.item(value)?, 0)? as i64)),
+            prop_type::SYSTIME => filetime_to_datetime(read_u64(node.heap.item(value)?, 0)?).map(PropertyValue::Time),
+            prop_type::OBJECT => {
+                // The heap allocation holds the subnode ID and the object size
+                let object = node.hnid_data(value)?;
+                context.objects.insert(id, read_u32(&object, 0)?);
+                None
+            }
+            _ => node.variable_value(property_type, value)?,
+        };
+        if let Some(decoded) = decoded {
+            context.properties.insert(id, decoded);
+        }
+    }
+    Ok(context)
+}
+
+/// Read the rows of the table context stored in a node; every row includes the `LTP_ROW_ID` column
+pub fn read_table(db: &NodeDatabase, bid_data: u64, subnodes: &Subnodes) -> PstResult<Vec<PropertyBag>> {
+    let node = NodeHeap::open(db, bid_data, subnodes, CLIENT_TABLE_CONTEXT)?;
+
+    // TCINFO: bType, cCols, rgib[4], hidRowIndex, hnidRows, hidIndex, rgTCOLDESC
+    let info = node.heap.item(node.heap.user_root)?;
+    if read_bytes(info, 0, 22)?[0] != CLIENT_TABLE_CONTEXT {
+        return Err(heap_error("ungültiger Tabellenkontext"));
+    }
+    let column_count = info[1] as usize;
+    let ceb_start = read_u16(info, 6)? as usize;
+    let row_len = read_u16(info, 8)? as usize;
+    let row_index = read_u32(info, 10)?;
+    let rows_hnid = read_u32(info, 14)?;
+
+    let mut columns = Vec::with_capacity(column_count);
+    for index in 0..column_count {
+        // TCOLDESC: tag, ibData, cbData, iBit
+        let column = read_bytes(info, 22 + index * 8, 8)?;
+        let tag = read_u32(column, 0)?;
+        columns.push((tag, read_u16(column, 4)? as usize, column[6] as usize, column[7] as usize));
+    }
+
+    // Row index BTH: dwRowID -> dwRowIndex (2 bytes in ANSI files)
+    let (index_len, records) = node.heap.bth_records(row_index)?;
+    let mut positions = Vec::with_capacity(records.len());
+    for (_, data) in records {
+        positions.push(match index_len {
+            2 => read_u16(data, 0)? as usize,
+            _ => read_u32(data, 0)? as usize,
+        });
+    }
+    positions.sort_unstable();
+    if positions.is_empty() || row_len == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Row matrix: a heap allocation, or a subnode whose blocks each hold whole rows
+    let matrix: Vec<Arc<Vec<u8>>> = if rows_hnid & 0x1F == 0 {
+        vec![Arc::new(node.heap.item(rows_hnid)?.to_vec())]
+    } else {
+        let subnode = subnodes
+            .get(&rows_hnid)
+            .ok_or_else(|| PstError::CorruptedFile(format!("Zeilen-Unterknoten {:#x} nicht gefunden", rows_hnid)))?;
+        db.data_blocks(subnode.bid_data)?
+    };
+    let rows_per_block = match matrix.len() {
+        1 => usize::MAX,
+        _ => (db.format().max_block_data() / row_len).max(1),
+    };
+
+    let mut rows = Vec::with_capacity(positions.len());
+    for position in positions {
+        let block = matrix
+            .get(position / rows_per_block)
+            .ok_or_else(|| heap_error(&format!("Zeile {} fehlt", position)))?;
+        let row = read_bytes(block, (position % rows_per_block) * row_len, row_len)?;
+        let cell_exists = read_bytes(row, ceb_start, row_len.saturating_sub(ceb_start))?;
+
+        let mut properties = PropertyBag::new();
+        for &(tag, offset, len, bit) in &columns {
+            if cell_exists.get(bit / 8).is_none_or(|byte| byte & (0x80 >> (bit % 8)) == 0) {
+                continue;
+            }
+            let cell = read_bytes(row, offset, len)?;
+            let property_type = (tag & 0xFFFF) as u16;
+            let value = match (property_type, len) {
+                (prop_type::INTEGER16, 2) => Some(PropertyValue::Integer(read_u16(cell, 0)? as i16 as i64)),
+                (prop_type::INTEGER32, 4) => Some(PropertyValue::Integer(read_u32(cell, 0)? as i32 as i64)),
+                (prop_type::BOOLEAN, 1) => Some(PropertyValue::Boolean(cell[0] != 0)),
+                (prop_type::INTEGER64, 8) => Some(PropertyValue::Integer(read_u64(cell, 0)? as i64)),
+                (prop_type::SYSTIME, 8) => filetime_to_datetime(read_u64(cell, 0)?).map(PropertyValue::Time),
+                (_, 4) => node.variable_value(property_type, read_u32(cell, 0)?)?,
+                _ => None,
+            };
+            if let Some(value) = value {
+                properties.insert((tag >> 16) as u16, value);
+            }
+        }
+        rows.push(properties);
+    }
+    Ok(rows)
+}
+
+/// Row IDs of a table context, e.g. the node IDs listed in a hierarchy or contents table
+pub fn read_row_ids(db: &NodeDatabase, bid_data: u64, subnodes: &Subnodes) -> PstResult<Vec<u32>> {
+    Ok(read_table(db, bid_data, subnodes)?
+        .iter()
+        .filter_map(|row| row.integer(LTP_ROW_ID))
+        .map(|id| id as u32)
+        .collect())
+}
+
+fn heap_error(reason: &str) -> PstError {
+    PstError::CorruptedFile(format!("Heap: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapi::prop;
+    use crate::pst_reader::PstReader;
+    use crate::test_support::{SyntheticFolder, SyntheticMsg, SyntheticProperty, SyntheticPst};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_property_context_and_tables() {
+        let dir = tempdir().unwrap();
+        let path = SyntheticPst::new()
+            .folder(SyntheticFolder::new("Posteingang").message(
+                SyntheticMsg::new()
+                    .property(prop::SUBJECT, SyntheticProperty::Unicode("Angebot".to_string()))
+                    .recipient(vec![(prop::DISPLAY_NAME, SyntheticProperty::Unicode("Anna".to_string()))]),
+            ))
+            .write_in(dir.path(), "test.pst");
+        let db = NodeDatabase::open(PstReader::open(&path).unwrap()).unwrap();
+
+        // Message store, then the message listed in the contents table of the only folder
+        let store = db.node(0x21).unwrap();
+        let store_properties = read_properties(&db, store.bid_data, &Subnodes::new()).unwrap().properties;
+        assert!(store_properties.binary(0x35E0).is_some());
+
+        let message = db.nodes().unwrap().into_iter().find(|node| node.nid & 0x1F == 0x04).unwrap();
+        let contents = db.node((message.nid_parent & !0x1F) | 0x0E).unwrap();
+        assert_eq!(read_row_ids(&db, contents.bid_data, &db.subnodes(contents.bid_sub).unwrap()).unwrap(), vec![message.nid]);
+
+        let subnodes = db.subnodes(message.bid_sub).unwrap();
+        let properties = read_properties(&db, message.bid_data, &subnodes).unwrap().properties;
+        assert_eq!(properties.string(prop::SUBJECT).as_deref(), Some("Angebot"));
+
+        let recipients = &subnodes[&0x692];
+        let rows = read_table(&db, recipients.bid_data, &db.subnodes(recipients.bid_sub).unwrap()).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].string(prop::DISPLAY_NAME).as_deref(), Some("Anna"));
+    }
+
+    #[test]
+    fn test_heap_rejects_bad_signature() {
+        let mut block = vec![0u8; 16];
+        block[2] = 0xAB;
+        assert!(matches!(Heap::new(vec![Arc::new(block)]), Err(PstError::CorruptedFile(_))));
+    }
+}
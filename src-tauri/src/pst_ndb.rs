@@ -0,0 +1,762 @@
+//! Node database layer of the PST format (MS-PST 2.2)
+//!
+//! Resolves node IDs through the node B-tree (NBT) and block IDs through the
+//! block B-tree (BBT), checks block trailers and CRCs and undoes the permutative
+//! encryption. The lists, tables and properties layer is built on top of this in
+//! `pst_ltp`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::errors::{PstError, PstResult};
+use crate::pst_reader::{CacheStats, LruCache, PstReader, DEFAULT_CACHE_BUDGET, PAGE_SIZE};
+
+/// Header signature "!BDN"
+const SIGNATURE: [u8; 4] = [0x21, 0x42, 0x44, 0x4E];
+
+/// Number of header bytes covered by dwCRCPartial (starting at wMagicClient)
+const PARTIAL_CRC_LEN: usize = 471;
+
+/// Number of header bytes covered by dwCRCFull in Unicode files (starting at wMagicClient)
+const FULL_CRC_LEN: usize = 516;
+
+/// Page types of the B-tree pages
+const PAGE_TYPE_BBT: u8 = 0x80;
+const PAGE_TYPE_NBT: u8 = 0x81;
+
+/// Block types of internal blocks
+const BLOCK_TYPE_XBLOCK: u8 = 0x01;
+const BLOCK_TYPE_SLBLOCK: u8 = 0x02;
+
+/// Highest level a B-tree may have; real files use three or four
+const MAX_BTREE_LEVEL: u8 = 8;
+
+/// Decoding table of the permutative encryption (mpbbI in MS-PST 5.1)
+const PERMUTE_DECODE: [u8; 256] = [
+    0x47, 0xf1, 0xb4, 0xe6, 0x0b, 0x6a, 0x72, 0x48, 0x85, 0x4e, 0x9e, 0xeb, 0xe2, 0xf8, 0x94, 0x53,
+    0xe0, 0xbb, 0xa0, 0x02, 0xe8, 0x5a, 0x09, 0xab, 0xdb, 0xe3, 0xba, 0xc6, 0x7c, 0xc3, 0x10, 0xdd,
+    0x39, 0x05, 0x96, 0x30, 0xf5, 0x37, 0x60, 0x82, 0x8c, 0xc9, 0x13, 0x4a, 0x6b, 0x1d, 0xf3, 0xfb,
+    0x8f, 0x26, 0x97, 0xca, 0x91, 0x17, 0x01, 0xc4, 0x32, 0x2d, 0x6e, 0x31, 0x95, 0xff, 0xd9, 0x23,
+    0xd1, 0x00, 0x5e, 0x79, 0xdc, 0x44, 0x3b, 0x1a, 0x28, 0xc5, 0x61, 0x57, 0x20, 0x90, 0x3d, 0x83,
+    0xb9, 0x43, 0xbe, 0x67, 0xd2, 0x46, 0x42, 0x76, 0xc0, 0x6d, 0x5b, 0x7e, 0xb2, 0x0f, 0x16, 0x29,
+    0x3c, 0xa9, 0x03, 0x54, 0x0d, 0xda, 0x5d, 0xdf, 0xf6, 0xb7, 0xc7, 0x62, 0xcd, 0x8d, 0x06, 0xd3,
+    0x69, 0x5c, 0x86, 0xd6, 0x14, 0xf7, 0xa5, 0x66, 0x75, 0xac, 0xb1, 0xe9, 0x45, 0x21, 0x70, 0x0c,
+    0x87, 0x9f, 0x74, 0xa4, 0x22, 0x4c, 0x6f, 0xbf, 0x1f, 0x56, 0xaa, 0x2e, 0xb3, 0x78, 0x33, 0x50,
+    0xb0, 0xa3, 0x92, 0xbc, 0xcf, 0x19, 0x1c, 0xa7, 0x63, 0xcb, 0x1e, 0x4d, 0x3e, 0x4b, 0x1b, 0x9b,
+    0x4f, 0xe7, 0xf0, 0xee, 0xad, 0x3a, 0xb5, 0x59, 0x04, 0xea, 0x40, 0x55, 0x25, 0x51, 0xe5, 0x7a,
+    0x89, 0x38, 0x68, 0x52, 0x7b, 0xfc, 0x27, 0xae, 0xd7, 0xbd, 0xfa, 0x07, 0xf4, 0xcc, 0x8e, 0x5f,
+    0xef, 0x35, 0x9c, 0x84, 0x2b, 0x15, 0xd5, 0x77, 0x34, 0x49, 0xb6, 0x12, 0x0a, 0x7f, 0x71, 0x88,
+    0xfd, 0x9d, 0x18, 0x41, 0x7d, 0x93, 0xd8, 0x58, 0x2c, 0xce, 0xfe, 0x24, 0xaf, 0xde, 0xb8, 0x36,
+    0xc8, 0xa1, 0x80, 0xa6, 0x99, 0x98, 0xa8, 0x2f, 0x0e, 0x81, 0x65, 0x73, 0xe4, 0xc2, 0xa2, 0x8a,
+    0xd4, 0xe1, 0x11, 0xd0, 0x08, 0x8b, 0x2a, 0xf2, 0xed, 0x9a, 0x64, 0x3f, 0xc1, 0x6c, 0xf9, 0xec,
+];
+
+/// Lookup table for `compute_crc`
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// On-disk layout variant, selected by wVer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PstFormat {
+    Ansi,    // ANSI PST (Outlook 97-2002)
+    Unicode, // Unicode PST (Outlook 2003+)
+}
+
+impl PstFormat {
+    /// Format for a wVer value; 14 and 15 are ANSI, 23 is Unicode
+    pub fn from_version(version: u16) -> PstResult<Self> {
+        match version {
+            14 | 15 => Ok(PstFormat::Ansi),
+            23 => Ok(PstFormat::Unicode),
+            _ => Err(PstError::InvalidFormat(format!(
+                "Unbekannte PST-Version: {}. Unterstützte Versionen: ANSI (14, 15) und Unicode (23)",
+                version
+            ))),
+        }
+    }
+
+    /// Size of the file header in bytes
+    pub fn header_len(self) -> usize {
+        match self {
+            PstFormat::Ansi => 512,
+            PstFormat::Unicode => 564,
+        }
+    }
+
+    /// Size of node and block IDs and file offsets in bytes
+    pub fn id_len(self) -> usize {
+        match self {
+            PstFormat::Ansi => 4,
+            PstFormat::Unicode => 8,
+        }
+    }
+
+    /// Most data bytes a single block can hold
+    pub fn max_block_data(self) -> usize {
+        8192 - self.block_trailer_len()
+    }
+
+    fn block_trailer_len(self) -> usize {
+        match self {
+            PstFormat::Ansi => 12,
+            PstFormat::Unicode => 16,
+        }
+    }
+
+    /// Bytes of a B-tree page available for entries (before cEnt)
+    fn page_entries_len(self) -> usize {
+        match self {
+            PstFormat::Ansi => 496,
+            PstFormat::Unicode => 488,
+        }
+    }
+
+    fn crypt_method_offset(self) -> usize {
+        match self {
+            PstFormat::Ansi => 461,
+            PstFormat::Unicode => 513,
+        }
+    }
+
+    /// Offset of the ROOT structure in the header
+    fn root_offset(self) -> usize {
+        match self {
+            PstFormat::Ansi => 164,
+            PstFormat::Unicode => 180,
+        }
+    }
+}
+
+/// Value of bCryptMethod
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CryptMethod {
+    None,
+    Permute,
+}
+
+/// Block ID and file offset of a page or block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockRef {
+    pub bid: u64,
+    pub ib: u64,
+}
+
+/// The parts of the file header needed to read the node database
+#[derive(Debug, Clone, PartialEq)]
+pub struct NdbHeader {
+    pub format: PstFormat,
+    pub crypt_method: CryptMethod,
+    pub nbt_root: BlockRef,
+    pub bbt_root: BlockRef,
+}
+
+impl NdbHeader {
+    /// Read and check the file header: signature, version, CRCs and encryption
+    pub fn read(reader: &PstReader) -> PstResult<Self> {
+        if reader.file_size() < 512 {
+            return Err(PstError::InvalidFormat("Datei ist zu klein für eine gültige PST-Datei".to_string()));
+        }
+        let bytes = reader.read_at(0, 512)?;
+        if bytes[0..4] != SIGNATURE {
+            return Err(PstError::InvalidFormat(
+                "Ungültige PST-Datei-Signatur - Datei ist möglicherweise beschädigt".to_string(),
+            ));
+        }
+
+        let format = PstFormat::from_version(u16::from_le_bytes([bytes[10], bytes[11]]))?;
+        let bytes = if format.header_len() > bytes.len() {
+            if reader.file_size() < format.header_len() as u64 {
+                return Err(PstError::InvalidFormat("Datei ist zu klein für eine gültige PST-Datei".to_string()));
+            }
+            reader.read_at(0, format.header_len())?
+        } else {
+            bytes
+        };
+        Self::parse(&bytes, format)
+    }
+
+    fn parse(bytes: &[u8], format: PstFormat) -> PstResult<Self> {
+        let stored_crc = read_u32(bytes, 4)?;
+        if stored_crc != compute_crc(&bytes[8..8 + PARTIAL_CRC_LEN]) {
+            return Err(PstError::CorruptedFile("Prüfsumme des PST-Headers ist ungültig".to_string()));
+        }
+        if format == PstFormat::Unicode && read_u32(bytes, 524)? != compute_crc(&bytes[8..8 + FULL_CRC_LEN]) {
+            return Err(PstError::CorruptedFile("Prüfsumme des PST-Headers ist ungültig".to_string()));
+        }
+
+        let crypt_method = match bytes[format.crypt_method_offset()] {
+            0x00 => CryptMethod::None,
+            0x01 => CryptMethod::Permute,
+            0x02 => {
+                return Err(PstError::InvalidFormat(
+                    "Zyklisch verschlüsselte PST-Dateien werden nicht unterstützt".to_string(),
+                ))
+            }
+            other => {
+                return Err(PstError::InvalidFormat(format!("Unbekannte PST-Verschlüsselung: {:#04x}", other)))
+            }
+        };
+
+        // ROOT: dwReserved, ibFileEof, ibAMapLast, cbAMapFree, cbPMapFree, BREFNBT, BREFBBT
+        let id_len = format.id_len();
+        let nbt_offset = format.root_offset() + 4 + 4 * id_len;
+        let bbt_offset = nbt_offset + 2 * id_len;
+        Ok(Self {
+            format,
+            crypt_method,
+            nbt_root: read_block_ref(bytes, nbt_offset, format)?,
+            bbt_root: read_block_ref(bytes, bbt_offset, format)?,
+        })
+    }
+}
+
+/// Entry of the node B-tree or of a subnode tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeEntry {
+    pub nid: u32,
+    /// Block holding the node data (0 = none)
+    pub bid_data: u64,
+    /// Subnode tree of the node (0 = none)
+    pub bid_sub: u64,
+    /// Parent folder; only set for NBT entries
+    pub nid_parent: u32,
+}
+
+/// Subnodes of a node by node ID
+pub type Subnodes = BTreeMap<u32, NodeEntry>;
+
+/// Entries of one B-tree page
+#[derive(Debug)]
+struct BTreePage {
+    page_type: u8,
+    level: u8,
+    entry_len: usize,
+    entries: Vec<u8>,
+}
+
+impl BTreePage {
+    fn entries(&self) -> impl Iterator<Item = &[u8]> {
+        self.entries.chunks_exact(self.entry_len)
+    }
+}
+
+/// Structures kept in the cache of a `NodeDatabase`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum CacheKey {
+    /// Checked and decrypted block by block ID
+    Block(u64),
+    /// Checked B-tree page by file offset
+    Page(u64),
+    /// NBT lookup result by node ID
+    Node(u32),
+}
+
+#[derive(Debug, Clone)]
+enum CachedValue {
+    Block(Arc<Vec<u8>>),
+    Page(Arc<BTreePage>),
+    Node(Option<NodeEntry>),
+}
+
+/// Node database of an opened PST file
+///
+/// Decoded blocks, B-tree pages and node lookups are kept in an LRU cache, so
+/// repeated reads skip the CRC check, decryption and B-tree descent.
+#[derive(Debug)]
+pub struct NodeDatabase {
+    reader: PstReader,
+    header: NdbHeader,
+    cache: Mutex<LruCache<CacheKey, CachedValue>>,
+}
+
+impl NodeDatabase {
+    /// Read the header of the file behind `reader`
+    pub fn open(reader: PstReader) -> PstResult<Self> {
+        Self::with_cache_budget(reader, DEFAULT_CACHE_BUDGET)
+    }
+
+    /// Read the header and keep at most `cache_budget` bytes of decoded structures
+    pub fn with_cache_budget(reader: PstReader, cache_budget: usize) -> PstResult<Self> {
+        let header = NdbHeader::read(&reader)?;
+        Ok(Self { reader, header, cache: Mutex::new(LruCache::new(cache_budget)) })
+    }
+
+    /// Current statistics of the block and node cache
+    pub fn cache_stats(&self) -> CacheStats {
+        self.lock_cache().stats()
+    }
+
+    /// Drop all cached blocks and nodes while keeping hit/miss counters
+    pub fn clear_cache(&self) {
+        self.lock_cache().clear();
+    }
+
+    pub fn reader(&self) -> &PstReader {
+        &self.reader
+    }
+
+    pub fn format(&self) -> PstFormat {
+        self.header.format
+    }
+
+    /// Look up a node in the NBT
+    pub fn node(&self, nid: u32) -> PstResult<NodeEntry> {
+        self.find_node(nid)?
+            .ok_or_else(|| PstError::CorruptedFile(format!("Knoten {:#x} nicht gefunden", nid)))
+    }
+
+    /// Look up a node in the NBT; missing nodes are not an error
+    pub fn find_node(&self, nid: u32) -> PstResult<Option<NodeEntry>> {
+        if let Some(CachedValue::Node(node)) = self.lock_cache().get(&CacheKey::Node(nid)) {
+            return Ok(node);
+        }
+
+        let entry = self.find_entry(self.header.nbt_root, PAGE_TYPE_NBT, nid as u64)?;
+        let node = entry.map(|entry| self.parse_node_entry(&entry)).transpose()?;
+        self.lock_cache().insert(CacheKey::Node(nid), CachedValue::Node(node), std::mem::size_of::<NodeEntry>());
+        Ok(node)
+    }
+
+    /// All entries of the NBT in key order
+    pub fn nodes(&self) -> PstResult<Vec<NodeEntry>> {
+        let mut nodes = Vec::new();
+        self.walk_leaves(self.header.nbt_root, PAGE_TYPE_NBT, |entry| {
+            nodes.push(self.parse_node_entry(entry)?);
+            Ok(())
+        })?;
+        Ok(nodes)
+    }
+
+    /// Data blocks of a node in order; XBLOCK and XXBLOCK trees are expanded
+    pub fn data_blocks(&self, bid: u64) -> PstResult<Vec<Arc<Vec<u8>>>> {
+        let mut blocks = Vec::new();
+        if bid != 0 {
+            self.collect_data_blocks(bid, None, &mut blocks)?;
+        }
+        Ok(blocks)
+    }
+
+    /// Data of a node as one buffer
+    pub fn data(&self, bid: u64) -> PstResult<Vec<u8>> {
+        Ok(self.data_blocks(bid)?.iter().flat_map(|block| block.iter().copied()).collect())
+    }
+
+    /// Subnodes of a node; SIBLOCK trees are expanded
+    pub fn subnodes(&self, bid: u64) -> PstResult<Subnodes> {
+        let mut subnodes = Subnodes::new();
+        if bid != 0 {
+            self.collect_subnodes(bid, None, &mut subnodes)?;
+        }
+        Ok(subnodes)
+    }
+
+    fn collect_data_blocks(&self, bid: u64, expected_level: Option<u8>, blocks: &mut Vec<Arc<Vec<u8>>>) -> PstResult<()> {
+        let block = self.block(bid)?;
+        if !is_internal(bid) {
+            if expected_level.is_some_and(|level| level != 0) {
+                return Err(corrupted_block(bid, "Datenblock an Stelle eines XBLOCK"));
+            }
+            blocks.push(block);
+            return Ok(());
+        }
+
+        // XBLOCK (cLevel 1) or XXBLOCK (cLevel 2): btype, cLevel, cEnt, lcbTotal, rgbid
+        let level = block.get(1).copied().unwrap_or_default();
+        if block.first() != Some(&BLOCK_TYPE_XBLOCK) || !(1..=2).contains(&level) || expected_level.is_some_and(|expected| expected != level) {
+            return Err(corrupted_block(bid, "ungültiger XBLOCK"));
+        }
+        let id_len = self.format().id_len();
+        let count = read_u16(&block, 2)? as usize;
+        for index in 0..count {
+            let child = read_id(&block, 8 + index * id_len, self.format())?;
+            self.collect_data_blocks(child, Some(level - 1), blocks)?;
+        }
+        Ok(())
+    }
+
+    fn collect_subnodes(&self, bid: u64, expected_level: Option<u8>, subnodes: &mut Subnodes) -> PstResult<()> {
+        let block = self.block(bid)?;
+        let level = block.get(1).copied().unwrap_or_default();
+        if !is_internal(bid) || block.first() != Some(&BLOCK_TYPE_SLBLOCK) || level > 1 || expected_level.is_some_and(|expected| expected != level) {
+            return Err(corrupted_block(bid, "ungültiger Unterknoten-Block"));
+        }
+
+        // SLBLOCK (cLevel 0) holds SLENTRYs, SIBLOCK (cLevel 1) points to further SLBLOCKs
+        let format = self.format();
+        let id_len = format.id_len();
+        let header_len = if format == PstFormat::Unicode { 8 } else { 4 };
+        let count = read_u16(&block, 2)? as usize;
+        for index in 0..count {
+            if level == 1 {
+                let offset = header_len + index * 2 * id_len;
+                self.collect_subnodes(read_id(&block, offset + id_len, format)?, Some(0), subnodes)?;
+            } else {
+                let offset = header_len + index * 3 * id_len;
+                let nid = read_id(&block, offset, format)? as u32;
+                subnodes.insert(nid, NodeEntry {
+                    nid,
+                    bid_data: read_id(&block, offset + id_len, format)?,
+                    bid_sub: read_id(&block, offset + 2 * id_len, format)?,
+                    nid_parent: 0,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a block, check its trailer and CRC and decrypt external blocks
+    fn block(&self, bid: u64) -> PstResult<Arc<Vec<u8>>> {
+        let key = CacheKey::Block(bid & !1);
+        if let Some(CachedValue::Block(block)) = self.lock_cache().get(&key) {
+            return Ok(block);
+        }
+
+        let block = Arc::new(self.decode_block(bid)?);
+        self.lock_cache().insert(key, CachedValue::Block(Arc::clone(&block)), block.len());
+        Ok(block)
+    }
+
+    fn decode_block(&self, bid: u64) -> PstResult<Vec<u8>> {
+        let format = self.format();
+        let entry = self
+            .find_entry(self.header.bbt_root, PAGE_TYPE_BBT, bid & !1)?
+            .ok_or_else(|| corrupted_block(bid, "nicht im Block-Index"))?;
+        let location = read_block_ref(&entry, 0, format)?;
+        let size = read_u16(&entry, 2 * format.id_len())? as usize;
+        if size > format.max_block_data() {
+            return Err(corrupted_block(bid, "Blockgröße zu groß"));
+        }
+
+        let trailer_len = format.block_trailer_len();
+        let total_len = (size + trailer_len).div_ceil(64) * 64;
+        let raw = self.reader.read_at(location.ib, total_len)?;
+        let trailer = &raw[total_len - trailer_len..];
+        let (stored_size, stored_crc, stored_bid) = match format {
+            PstFormat::Unicode => (read_u16(trailer, 0)?, read_u32(trailer, 4)?, read_u64(trailer, 8)?),
+            PstFormat::Ansi => (read_u16(trailer, 0)?, read_u32(trailer, 8)?, read_u32(trailer, 4)? as u64),
+        };
+        if stored_size as usize != size || stored_bid & !1 != bid & !1 {
+            return Err(corrupted_block(bid, "Blockkopf passt nicht zum Block-Index"));
+        }
+
+        let mut data = raw[..size].to_vec();
+        if compute_crc(&data) != stored_crc {
+            return Err(corrupted_block(bid, "Prüfsumme ist ungültig"));
+        }
+        if !is_internal(bid) && self.header.crypt_method == CryptMethod::Permute {
+            decrypt_permute(&mut data);
+        }
+        Ok(data)
+    }
+
+    /// Find the leaf entry with `key` in the B-tree starting at `root`
+    fn find_entry(&self, root: BlockRef, page_type: u8, key: u64) -> PstResult<Option<Vec<u8>>> {
+        let format = self.format();
+        let mut location = root;
+        let mut expected_level = None;
+        loop {
+            let page = self.page(location, page_type, expected_level)?;
+            if page.level == 0 {
+                return page
+                    .entries()
+                    .find_map(|entry| match read_id(entry, 0, format) {
+                        Ok(entry_key) if entry_key == key => Some(Ok(entry.to_vec())),
+                        Ok(_) => None,
+                        Err(e) => Some(Err(e)),
+                    })
+                    .transpose();
+            }
+
+            // Intermediate entries hold the smallest key of their child page
+            let mut child = None;
+            for entry in page.entries() {
+                if read_id(entry, 0, format)? > key {
+                    break;
+                }
+                child = Some(read_block_ref(entry, format.id_len(), format)?);
+            }
+            let Some(child) = child else { return Ok(None) };
+            location = child;
+            expected_level = Some(page.level - 1);
+        }
+    }
+
+    /// Call `visit` for every leaf entry of the B-tree starting at `root`
+    fn walk_leaves(&self, root: BlockRef, page_type: u8, mut visit: impl FnMut(&[u8]) -> PstResult<()>) -> PstResult<()> {
+        let format = self.format();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(root, None)];
+        while let Some((location, expected_level)) = pending.pop() {
+            if !visited.insert(location.ib) {
+                continue;
+            }
+            let page = self.page(location, page_type, expected_level)?;
+            if page.level == 0 {
+                for entry in page.entries() {
+                    visit(entry)?;
+                }
+                continue;
+            }
+            for entry in page.entries().collect::<Vec<_>>().into_iter().rev() {
+                pending.push((read_block_ref(entry, format.id_len(), format)?, Some(page.level - 1)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch a B-tree page and check that it has the expected type and level
+    fn page(&self, location: BlockRef, page_type: u8, expected_level: Option<u8>) -> PstResult<Arc<BTreePage>> {
+        let key = CacheKey::Page(location.ib);
+        let page = match self.lock_cache().get(&key) {
+            Some(CachedValue::Page(page)) => Some(page),
+            _ => None,
+        };
+        let page = match page {
+            Some(page) => page,
+            None => {
+                let page = Arc::new(self.decode_page(location)?);
+                self.lock_cache().insert(key, CachedValue::Page(Arc::clone(&page)), PAGE_SIZE);
+                page
+            }
+        };
+
+        if page.page_type != page_type {
+            return Err(corrupted_page(location, "falscher Seitentyp"));
+        }
+        let min_entry_len = match (page.level, page_type) {
+            (0, PAGE_TYPE_NBT) => 4 * self.format().id_len(),
+            _ => 3 * self.format().id_len(),
+        };
+        if page.entry_len < min_entry_len || expected_level.is_some_and(|expected| expected != page.level) {
+            return Err(corrupted_page(location, "ungültiger Seitenaufbau"));
+        }
+        Ok(page)
+    }
+
+    /// Read a B-tree page and check its trailer, CRC and layout
+    fn decode_page(&self, location: BlockRef) -> PstResult<BTreePage> {
+        let format = self.format();
+        let data = self.reader.read_at(location.ib, PAGE_SIZE)?;
+        let entries_len = format.page_entries_len();
+        // The page trailer has the same size as a block trailer; the CRC covers everything before it
+        let crc_len = PAGE_SIZE - format.block_trailer_len();
+        let trailer = &data[crc_len..];
+        let stored_crc = match format {
+            PstFormat::Unicode => read_u32(trailer, 4)?,
+            PstFormat::Ansi => read_u32(trailer, 8)?,
+        };
+        let page_type = trailer[0];
+        if !matches!(page_type, PAGE_TYPE_BBT | PAGE_TYPE_NBT) || trailer[1] != page_type {
+            return Err(corrupted_page(location, "falscher Seitentyp"));
+        }
+        if compute_crc(&data[..crc_len]) != stored_crc {
+            return Err(corrupted_page(location, "Prüfsumme ist ungültig"));
+        }
+
+        let count = data[entries_len] as usize;
+        let entry_len = data[entries_len + 2] as usize;
+        let level = data[entries_len + 3];
+        if entry_len == 0 || count * entry_len > entries_len || level > MAX_BTREE_LEVEL {
+            return Err(corrupted_page(location, "ungültiger Seitenaufbau"));
+        }
+
+        Ok(BTreePage { page_type, level, entry_len, entries: data[..count * entry_len].to_vec() })
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, LruCache<CacheKey, CachedValue>> {
+        // A poisoned cache only holds immutable decoded copies, so it is safe to keep using
+        self.cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn parse_node_entry(&self, entry: &[u8]) -> PstResult<NodeEntry> {
+        let format = self.format();
+        let id_len = format.id_len();
+        Ok(NodeEntry {
+            nid: read_id(entry, 0, format)? as u32,
+            bid_data: read_id(entry, id_len, format)?,
+            bid_sub: read_id(entry, 2 * id_len, format)?,
+            nid_parent: read_u32(entry, 3 * id_len)?,
+        })
+    }
+}
+
+/// Internal blocks (XBLOCK, SLBLOCK, ...) have bit 1 of the block ID set
+pub fn is_internal(bid: u64) -> bool {
+    bid & 0x2 != 0
+}
+
+/// Undo the permutative encryption of an external block
+pub fn decrypt_permute(data: &mut [u8]) {
+    for byte in data {
+        *byte = PERMUTE_DECODE[*byte as usize];
+    }
+}
+
+/// Apply the permutative encryption (mpbbR in MS-PST 5.1)
+pub fn encrypt_permute(data: &mut [u8]) {
+    let mut encode = [0u8; 256];
+    for (plain, &decoded) in PERMUTE_DECODE.iter().enumerate() {
+        encode[decoded as usize] = plain as u8;
+    }
+    for byte in data {
+        *byte = encode[*byte as usize];
+    }
+}
+
+/// CRC-32 as used by the PST format (reflected 0xEDB88320, seed 0, no final XOR)
+pub fn compute_crc(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+/// `len` bytes at `offset`, failing instead of panicking on truncated structures
+pub fn read_bytes(data: &[u8], offset: usize, len: usize) -> PstResult<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| PstError::CorruptedFile(format!(
+            "Struktur reicht über das Ende der Daten hinaus (Offset {}, {} Bytes)",
+            offset, len
+        )))
+}
+
+pub fn read_u16(data: &[u8], offset: usize) -> PstResult<u16> {
+    read_bytes(data, offset, 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_u32(data: &[u8], offset: usize) -> PstResult<u32> {
+    read_bytes(data, offset, 4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn read_u64(data: &[u8], offset: usize) -> PstResult<u64> {
+    read_bytes(data, offset, 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+}
+
+/// Node ID, block ID or file offset; 4 bytes in ANSI files, 8 in Unicode files
+fn read_id(data: &[u8], offset: usize, format: PstFormat) -> PstResult<u64> {
+    match format {
+        PstFormat::Ansi => read_u32(data, offset).map(u64::from),
+        PstFormat::Unicode => read_u64(data, offset),
+    }
+}
+
+fn read_block_ref(data: &[u8], offset: usize, format: PstFormat) -> PstResult<BlockRef> {
+    Ok(BlockRef {
+        bid: read_id(data, offset, format)?,
+        ib: read_id(data, offset + format.id_len(), format)?,
+    })
+}
+
+fn corrupted_block(bid: u64, reason: &str) -> PstError {
+    PstError::CorruptedFile(format!("Block {:#x}: {}", bid, reason))
+}
+
+fn corrupted_page(location: BlockRef, reason: &str) -> PstError {
+    PstError::CorruptedFile(format!("B-Baum-Seite bei Offset {:#x}: {}", location.ib, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{SyntheticCorruption, SyntheticFolder, SyntheticMsg, SyntheticPst};
+    use tempfile::tempdir;
+
+    fn open(pst: SyntheticPst) -> PstResult<NodeDatabase> {
+        let dir = tempdir().unwrap();
+        let path = pst.write_in(dir.path(), "test.pst");
+        NodeDatabase::open(PstReader::open(&path)?)
+    }
+
+    #[test]
+    fn test_permute_tables_are_inverse() {
+        let mut seen = [false; 256];
+        for &byte in PERMUTE_DECODE.iter() {
+            assert!(!seen[byte as usize], "{:#04x} appears twice", byte);
+            seen[byte as usize] = true;
+        }
+
+        let plain: Vec<u8> = (0..=255).collect();
+        let mut data = plain.clone();
+        encrypt_permute(&mut data);
+        assert_ne!(data, plain);
+        decrypt_permute(&mut data);
+        assert_eq!(data, plain);
+    }
+
+    #[test]
+    fn test_crc_table_matches_bitwise_crc() {
+        let data = b"Persoenliche Ordner";
+        let mut crc = 0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+        }
+        assert_eq!(compute_crc(data), crc);
+    }
+
+    #[test]
+    fn test_lookup_in_multi_level_btrees() {
+        let mut folder = SyntheticFolder::new("Posteingang");
+        for _ in 0..60 {
+            folder = folder.message(SyntheticMsg::new());
+        }
+        let db = open(SyntheticPst::new().folder(folder)).unwrap();
+
+        let nodes = db.nodes().unwrap();
+        assert!(nodes.len() > 60);
+        assert!(nodes.windows(2).all(|pair| pair[0].nid < pair[1].nid));
+        for node in &nodes {
+            assert_eq!(db.node(node.nid).unwrap(), *node);
+        }
+        assert!(db.find_node(0x7FFF_FFE4).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cache_keeps_decoded_blocks_within_budget() {
+        let dir = tempdir().unwrap();
+        let mut folder = SyntheticFolder::new("Posteingang");
+        for _ in 0..20 {
+            folder = folder.message(SyntheticMsg::new());
+        }
+        let path = SyntheticPst::new().folder(folder).write_in(dir.path(), "test.pst");
+
+        let db = NodeDatabase::open(PstReader::open(&path).unwrap()).unwrap();
+        let node = db.node(0x21).unwrap();
+        let first = db.data(node.bid_data).unwrap();
+        let misses = db.cache_stats().misses;
+        assert_eq!(db.data(db.node(0x21).unwrap().bid_data).unwrap(), first);
+        assert_eq!(db.cache_stats().misses, misses);
+
+        let small = NodeDatabase::with_cache_budget(PstReader::open(&path).unwrap(), 1024).unwrap();
+        for node in small.nodes().unwrap() {
+            small.data(node.bid_data).unwrap();
+        }
+        let stats = small.cache_stats();
+        assert!(stats.evictions > 0);
+        assert!(stats.cached_bytes <= 1024);
+    }
+
+    #[test]
+    fn test_reject_bad_header_crc() {
+        assert!(matches!(
+            open(SyntheticPst::new().corruption(SyntheticCorruption::BadCrc)),
+            Err(PstError::CorruptedFile(_))
+        ));
+    }
+}
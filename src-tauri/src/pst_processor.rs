@@ -1,70 +1,100 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::fs::File;
 use std::io::Read;
-use std::collections::HashMap;
-use chrono::{DateTime, Utc, TimeZone};
-use crate::errors::{PstError, PstResult};
-use crate::types::{Email, PstInfo, Attachment, EmailPriority};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::{DateTime, Utc};
+use crate::errors::{MailSourceResult, PstError, PstResult};
+use crate::mail_source::{file_modified_date, EmailIterator, MailSource};
+use crate::mapi::{prop, MapiAttachment, MapiMessage, MAX_EMBEDDING_DEPTH};
+use crate::pst_ltp::{read_properties, read_row_ids, read_table, PropertyContext};
+use crate::pst_ndb::{NodeDatabase, PstFormat};
+use crate::pst_reader::{CacheStats, PstReader, DEFAULT_CACHE_BUDGET};
+use crate::types::{Email, MailFolder, PstInfo};
+
+/// Well-known node IDs (MS-PST 2.4.1)
+pub const NID_MESSAGE_STORE: u32 = 0x21;
+pub const NID_ROOT_FOLDER: u32 = 0x122;
+
+/// Subnodes of a message holding its recipient and attachment tables
+pub const NID_RECIPIENT_TABLE: u32 = 0x692;
+pub const NID_ATTACHMENT_TABLE: u32 = 0x671;
+
+/// Node types, stored in the low five bits of a node ID
+pub const NID_TYPE_FOLDER: u32 = 0x02;
+pub const NID_TYPE_NORMAL_MESSAGE: u32 = 0x04;
+pub const NID_TYPE_ATTACHMENT: u32 = 0x05;
+pub const NID_TYPE_HIERARCHY_TABLE: u32 = 0x0D;
+pub const NID_TYPE_CONTENTS_TABLE: u32 = 0x0E;
+pub const NID_TYPE_LTP: u32 = 0x1F;
+
+/// PR_IPM_SUBTREE_ENTRYID of the message store; the node ID sits after the 20-byte entry ID prefix
+pub const IPM_SUBTREE_ENTRY_ID: u16 = 0x35E0;
+
+/// Folder the recovery scan files deleted and orphaned messages under
+pub const RECOVERED_FOLDER: &str = "Wiederhergestellt";
 
 /// PST processor for handling PST file operations
-/// This implementation provides basic PST parsing capabilities for email extraction
+///
+/// Opens the node database, walks the folder hierarchy below the IPM subtree
+/// once and decodes messages (properties, recipients, attachments and
+/// embedded messages) on demand.
 pub struct PstProcessor {
     file_path: PathBuf,
-    email_cache: HashMap<usize, Email>,
-    total_emails: Option<usize>,
-    pst_format: PstFormat,
+    db: NodeDatabase,
+    messages: Vec<MessageEntry>,
+    folders: Vec<MailFolder>,
+    thread_count: usize,
+    /// Modification time of the file, used for messages without any date
+    modified: Option<DateTime<Utc>>,
 }
 
-/// PST file format variants
-#[derive(Debug, Clone, PartialEq)]
-enum PstFormat {
-    Ansi,    // ANSI PST (Outlook 97-2002)
-    Unicode, // Unicode PST (Outlook 2003+)
+/// Message node found in a folder's contents table
+#[derive(Debug, Clone)]
+struct MessageEntry {
+    nid: u32,
+    /// "/" separated folder path, empty for the top of the IPM subtree
+    folder: String,
+    /// Found by the recovery scan instead of in a contents table
+    recovered: bool,
 }
 
-/// PST file header structure for parsing
-#[derive(Debug)]
-struct PstHeader {
-    signature: [u8; 4],
-    #[allow(dead_code)]
-    crc: u32,
-    #[allow(dead_code)]
-    version: u16,
-    format: PstFormat,
-    #[allow(dead_code)]
-    file_size: u64,
-}
+/// Number of messages a worker claims from the shared queue at once
+const EXTRACTION_BATCH_SIZE: usize = 32;
 
 impl PstProcessor {
     /// Create a new PST processor for the given file path
-    /// Validates the PST file and initializes the processor
+    /// Validates the PST file and reads its folder hierarchy
     pub fn new(file_path: PathBuf) -> PstResult<Self> {
+        Self::with_cache_budget(file_path, DEFAULT_CACHE_BUDGET)
+    }
+
+    /// Create a new PST processor whose cache of decoded blocks and nodes may hold at most `cache_budget` bytes
+    pub fn with_cache_budget(file_path: PathBuf, cache_budget: usize) -> PstResult<Self> {
         if !file_path.exists() {
             return Err(PstError::FileNotFound(file_path.to_string_lossy().to_string()));
         }
 
+        let db = NodeDatabase::with_cache_budget(PstReader::open(&file_path)?, cache_budget)?;
         let mut processor = Self {
+            modified: file_modified_date(&file_path),
             file_path,
-            email_cache: HashMap::new(),
-            total_emails: None,
-            pst_format: PstFormat::Unicode, // Default, will be determined during validation
+            db,
+            messages: Vec::new(),
+            folders: Vec::new(),
+            thread_count: 0,
         };
 
-        // Validate the PST file and determine format
-        processor.validate_and_initialize()?;
-        
+        let (folders, messages) = processor.read_folder_tree()?;
+        processor.folders = folders;
+        processor.messages = messages;
+
         Ok(processor)
     }
 
     /// Get the total number of emails in the PST file
-    /// Uses cached value if available, otherwise scans the file
     pub fn get_email_count(&self) -> PstResult<usize> {
-        if let Some(count) = self.total_emails {
-            Ok(count)
-        } else {
-            // If not cached, perform a scan to count emails
-            self.scan_email_count()
-        }
+        Ok(self.messages.len())
     }
 
     /// Extract a range of emails from the PST file in chronological order
@@ -79,11 +109,8 @@ impl PstProcessor {
         let end = (start + count).min(total_emails);
         let mut emails = Vec::new();
 
-        // Extract emails from the PST file
-        // This is a simplified implementation that demonstrates the structure
-        // In a real implementation, this would use a proper PST parsing library
-        for index in start..end {
-            match self.extract_single_email(index) {
+        for (index, result) in self.extract_range(start, end)? {
+            match result {
                 Ok(email) => emails.push(email),
                 Err(e) => {
                     // Log the error but continue processing other emails
@@ -99,150 +126,193 @@ impl PstProcessor {
         Ok(emails)
     }
 
-    /// Validate the PST file and initialize processor state
-    fn validate_and_initialize(&mut self) -> PstResult<()> {
-        let header = self.read_pst_header()?;
-        
-        // Validate PST signature
-        if header.signature != [0x21, 0x42, 0x44, 0x4E] { // "!BDN"
-            return Err(PstError::InvalidFormat(
-                "Ungültige PST-Datei-Signatur - Datei ist möglicherweise beschädigt".to_string()
-            ));
-        }
-
-        // Set PST format based on version
-        self.pst_format = header.format;
-
-        // Initialize email count
-        self.total_emails = Some(self.scan_email_count()?);
+    /// Set the number of worker threads used for extraction (0 = one per CPU core)
+    pub fn set_thread_count(&mut self, threads: usize) {
+        self.thread_count = threads;
+    }
 
-        Ok(())
+    /// Number of worker threads that extraction will actually use
+    pub fn effective_thread_count(&self) -> usize {
+        if self.thread_count > 0 {
+            self.thread_count
+        } else {
+            std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        }
     }
 
-    /// Read and parse the PST file header
-    fn read_pst_header(&self) -> PstResult<PstHeader> {
-        let mut file = File::open(&self.file_path)?;
-        let mut header_bytes = [0u8; 512];
-        
-        file.read_exact(&mut header_bytes)?;
-
-        let signature = [header_bytes[0], header_bytes[1], header_bytes[2], header_bytes[3]];
-        let crc = u32::from_le_bytes([header_bytes[4], header_bytes[5], header_bytes[6], header_bytes[7]]);
-        let version = u16::from_le_bytes([header_bytes[8], header_bytes[9]]);
-
-        let format = match version {
-            14 | 15 => PstFormat::Ansi,
-            23 => PstFormat::Unicode,
-            _ => return Err(PstError::InvalidFormat(
-                format!("Unbekannte PST-Version: {}. Unterstützte Versionen: ANSI (14, 15) und Unicode (23)", version)
-            )),
-        };
+    /// Decode the messages in `start..end` on a worker pool
+    /// Results are returned in index order regardless of which worker decoded them
+    fn extract_range(&self, start: usize, end: usize) -> PstResult<Vec<(usize, PstResult<Email>)>> {
+        let total = end.saturating_sub(start);
+        let workers = self.effective_thread_count().min(total.div_ceil(EXTRACTION_BATCH_SIZE)).max(1);
 
-        // Get file size
-        let metadata = std::fs::metadata(&self.file_path)?;
-        let file_size = metadata.len();
+        if workers == 1 {
+            return Ok((start..end).map(|index| (index, self.extract_single_email(index))).collect());
+        }
 
-        Ok(PstHeader {
-            signature,
-            crc,
-            version,
-            format,
-            file_size,
-        })
-    }
+        // Workers claim small batches from a shared counter so uneven messages balance out
+        let next = AtomicUsize::new(start);
+        let mut results = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut decoded = Vec::new();
+                        loop {
+                            let batch_start = next.fetch_add(EXTRACTION_BATCH_SIZE, Ordering::Relaxed);
+                            if batch_start >= end {
+                                break;
+                            }
+                            let batch_end = (batch_start + EXTRACTION_BATCH_SIZE).min(end);
+                            for index in batch_start..batch_end {
+                                decoded.push((index, self.extract_single_email(index)));
+                            }
+                        }
+                        decoded
+                    })
+                })
+                .collect();
+
+            let mut results = Vec::with_capacity(total);
+            for handle in handles {
+                let decoded = handle.join().map_err(|_| {
+                    PstError::ExtractionFailed("Ein Extraktions-Thread ist unerwartet abgebrochen".to_string())
+                })?;
+                results.extend(decoded);
+            }
+            Ok::<_, PstError>(results)
+        })?;
 
-    /// Scan the PST file to count total emails
-    /// This is a simplified implementation for demonstration
-    fn scan_email_count(&self) -> PstResult<usize> {
-        // In a real implementation, this would parse the PST structure
-        // For now, we'll provide a reasonable estimate based on file analysis
-        
-        let metadata = std::fs::metadata(&self.file_path)?;
-        let file_size = metadata.len();
+        results.sort_by_key(|(index, _)| *index);
+        Ok(results)
+    }
 
-        // Estimate based on file size and format
-        let estimated_count = match self.pst_format {
-            PstFormat::Ansi => {
-                // ANSI PST files are generally smaller per email
-                (file_size / 80_000).max(1) as usize
+    /// Walk the folder hierarchy below the IPM subtree and collect the message nodes
+    ///
+    /// The top of the IPM subtree becomes the root folder named after the file.
+    /// Folders that cannot be read are skipped with a warning; a cycle in the
+    /// hierarchy is followed only once.
+    fn read_folder_tree(&self) -> PstResult<(Vec<MailFolder>, Vec<MessageEntry>)> {
+        let ipm_subtree = read_node_properties(&self.db, NID_MESSAGE_STORE)
+            .ok()
+            .and_then(|store| {
+                let entry_id = store.properties.binary(IPM_SUBTREE_ENTRY_ID)?.get(20..24)?.try_into().ok()?;
+                Some(u32::from_le_bytes(entry_id))
+            })
+            .unwrap_or(NID_ROOT_FOLDER);
+
+        let mut folders = Vec::new();
+        let mut messages = Vec::new();
+        let mut visited = HashSet::new();
+        let mut pending = vec![(ipm_subtree, String::new(), self.source_name())];
+        while let Some((nid, path, name)) = pending.pop() {
+            if !visited.insert(nid) {
+                continue;
             }
-            PstFormat::Unicode => {
-                // Unicode PST files have more overhead
-                (file_size / 120_000).max(1) as usize
+
+            let contents = match folder_table(&self.db, nid, NID_TYPE_CONTENTS_TABLE) {
+                Ok(contents) => contents,
+                Err(e) if !path.is_empty() => {
+                    eprintln!("Warning: Skipping folder {}: {}", path, e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let children = folder_table(&self.db, nid, NID_TYPE_HIERARCHY_TABLE).unwrap_or_else(|e| {
+                eprintln!("Warning: Skipping subfolders of {}: {}", name, e);
+                Vec::new()
+            });
+
+            folders.push(MailFolder::new(path.clone(), name.clone(), contents.len()));
+            messages.extend(contents.into_iter().map(|nid| MessageEntry { nid, folder: path.clone(), recovered: false }));
+
+            // Push in reverse so the folders come out in hierarchy table order
+            for child in children.into_iter().rev() {
+                let child_name = match read_node_properties(&self.db, child) {
+                    Ok(context) => context.properties.string(prop::DISPLAY_NAME).unwrap_or_default(),
+                    Err(e) => {
+                        eprintln!("Warning: Skipping folder {:#x} in {}: {}", child, name, e);
+                        continue;
+                    }
+                };
+                let segment = child_name.replace('/', "_");
+                let child_path = if path.is_empty() { segment } else { format!("{}/{}", path, segment) };
+                pending.push((child, child_path, child_name));
             }
-        };
+        }
 
-        // Cap the estimate at reasonable bounds
-        Ok(estimated_count.min(50000).max(1))
+        Ok((folders, messages))
     }
 
-    /// Extract a single email at the specified index
-    /// This is a simplified implementation for demonstration
-    fn extract_single_email(&self, index: usize) -> PstResult<Email> {
-        // Check cache first
-        if let Some(email) = self.email_cache.get(&index) {
-            return Ok(email.clone());
+    /// Search the node B-tree for messages that no folder lists and add them as recovered items
+    ///
+    /// Deleted messages often survive as nodes that no contents table refers to
+    /// any more, and Recoverable Items folders live outside the IPM subtree. Each
+    /// such message is filed under `RECOVERED_FOLDER`, below the name of its former
+    /// parent folder if that folder still exists. Returns the number of messages found.
+    pub fn recover_orphaned_items(&mut self) -> PstResult<usize> {
+        let reachable: HashSet<u32> = self.messages.iter().map(|entry| entry.nid).collect();
+        let mut parent_names: HashMap<u32, Option<String>> = HashMap::new();
+        let mut recovered = Vec::new();
+        for node in self.db.nodes()? {
+            if node.nid & 0x1F != NID_TYPE_NORMAL_MESSAGE || reachable.contains(&node.nid) {
+                continue;
+            }
+            let parent_name = parent_names.entry(node.nid_parent).or_insert_with(|| {
+                (node.nid_parent & 0x1F == NID_TYPE_FOLDER)
+                    .then(|| read_node_properties(&self.db, node.nid_parent).ok())
+                    .flatten()
+                    .and_then(|context| context.properties.string(prop::DISPLAY_NAME))
+                    .filter(|name| !name.trim().is_empty())
+            });
+            let folder = match parent_name {
+                Some(name) => format!("{}/{}", RECOVERED_FOLDER, name.replace('/', "_")),
+                None => RECOVERED_FOLDER.to_string(),
+            };
+            recovered.push(MessageEntry { nid: node.nid, folder, recovered: true });
         }
 
-        // In a real implementation, this would:
-        // 1. Navigate to the email entry in the PST structure
-        // 2. Parse the email properties and content
-        // 3. Extract attachments if present
-        // 4. Handle different encoding formats
-
-        // For demonstration, create a sample email structure
-        // This would be replaced with actual PST parsing logic
-        let email = self.create_sample_email(index)?;
+        if !recovered.is_empty() {
+            let mut counts = BTreeMap::new();
+            for entry in &recovered {
+                *counts.entry(entry.folder.clone()).or_insert(0) += 1;
+            }
+            let direct = counts.remove(RECOVERED_FOLDER).unwrap_or(0);
+            self.folders.push(MailFolder::new(RECOVERED_FOLDER.to_string(), RECOVERED_FOLDER.to_string(), direct));
+            for (path, count) in counts {
+                let name = path[RECOVERED_FOLDER.len() + 1..].to_string();
+                self.folders.push(MailFolder::new(path, name, count));
+            }
+        }
 
-        Ok(email)
+        let count = recovered.len();
+        self.messages.extend(recovered);
+        Ok(count)
     }
 
-    /// Create a sample email for demonstration purposes
-    /// In a real implementation, this would parse actual PST data
-    fn create_sample_email(&self, index: usize) -> PstResult<Email> {
-        use chrono::Duration;
-
-        // Create a sample email with realistic data
-        let base_date = Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
-        let email_date = base_date + Duration::days(index as i64);
-
-        let mut email = Email::new(
-            format!("Email Subject {}", index + 1),
-            format!("sender{}@example.com", index + 1),
-            "recipient@example.com".to_string(),
-            email_date,
-            format!("This is the body content of email number {}.\n\nThis email contains sample content for testing purposes.", index + 1),
-        );
-
-        // Add some variety to the sample data
-        email.cc_recipients = if index % 3 == 0 {
-            vec!["cc@example.com".to_string()]
-        } else {
-            Vec::new()
-        };
-
-        email.is_html = index % 2 == 0;
-        email.priority = match index % 4 {
-            0 => EmailPriority::Low,
-            1 => EmailPriority::Normal,
-            2 => EmailPriority::High,
-            3 => EmailPriority::Urgent,
-            _ => EmailPriority::Normal,
+    /// Decode the message at `index` with its recipients, attachments and embedded messages
+    fn extract_single_email(&self, index: usize) -> PstResult<Email> {
+        let entry = self
+            .messages
+            .get(index)
+            .ok_or_else(|| PstError::ExtractionFailed(format!("Keine Nachricht an Position {}", index)))?;
+        let node = self.db.node(entry.nid)?;
+        let message = match read_message(&self.db, node.bid_data, node.bid_sub, 0) {
+            Ok(message) => message,
+            // Recovered items often lost parts of their subnodes; keep what the property context still holds
+            Err(e) if entry.recovered => {
+                eprintln!("Warning: Recovering only the properties of message {:#x}: {}", entry.nid, e);
+                let subnodes = self.db.subnodes(node.bid_sub).unwrap_or_default();
+                MapiMessage {
+                    properties: read_properties(&self.db, node.bid_data, &subnodes)?.properties,
+                    ..MapiMessage::default()
+                }
+            }
+            Err(e) => return Err(e),
         };
 
-        email.message_id = Some(format!("<message-{}@example.com>", index + 1));
-        email.size = email.body.len() + email.subject.len() + 200; // Approximate size
-
-        // Add sample attachment for some emails
-        if index % 5 == 0 {
-            let attachment = Attachment::new(
-                format!("document_{}.pdf", index + 1),
-                1024 * (index % 10 + 1), // Variable size
-                "application/pdf".to_string(),
-            );
-            email.attachments.push(attachment);
-        }
-
+        let mut email = message.to_email_with_fallback_date(self.modified);
+        email.folder = (!entry.folder.is_empty()).then(|| entry.folder.clone());
+        email.recovered = entry.recovered;
         Ok(email)
     }
 
@@ -275,21 +345,13 @@ impl PstProcessor {
                 .as_secs() as i64, 0
         ).unwrap_or_else(|| Utc::now());
 
-        // Validate PST header
-        match self.read_pst_header() {
-            Ok(_header) => {
-                // File is valid, get email count
-                match self.get_email_count() {
-                    Ok(email_count) => {
-                        pst_info.mark_valid(email_count, file_size, last_modified);
-                    }
-                    Err(e) => {
-                        pst_info.mark_invalid(vec![format!("Fehler beim Zählen der E-Mails: {}", e)]);
-                    }
-                }
+        // The header and the folder hierarchy were validated when the processor was opened
+        match self.get_email_count() {
+            Ok(email_count) => {
+                pst_info.mark_valid(email_count, file_size, last_modified);
             }
             Err(e) => {
-                pst_info.mark_invalid(vec![e.to_string()]);
+                pst_info.mark_invalid(vec![format!("Fehler beim Zählen der E-Mails: {}", e)]);
             }
         }
         
@@ -305,25 +367,47 @@ impl PstProcessor {
 
     /// Check if the processor can handle the PST file format
     pub fn is_supported_format(&self) -> bool {
-        matches!(self.pst_format, PstFormat::Ansi | PstFormat::Unicode)
+        matches!(self.db.format(), PstFormat::Ansi | PstFormat::Unicode)
     }
 
     /// Get PST format information
     pub fn get_format_info(&self) -> String {
-        match self.pst_format {
+        match self.db.format() {
             PstFormat::Ansi => "ANSI PST (Outlook 97-2002)".to_string(),
             PstFormat::Unicode => "Unicode PST (Outlook 2003+)".to_string(),
         }
     }
 
-    /// Clear the email cache to free memory
-    pub fn clear_cache(&mut self) {
-        self.email_cache.clear();
+    /// Clear the cache of decoded blocks and nodes to free memory
+    pub fn clear_cache(&self) {
+        self.db.clear_cache();
+    }
+
+    /// Get cache statistics (hits, misses, memory usage)
+    pub fn get_cache_stats(&self) -> CacheStats {
+        self.db.cache_stats()
+    }
+}
+
+impl MailSource for PstProcessor {
+    fn source_name(&self) -> String {
+        self.file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(self.get_email_count()?)
+    }
+
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        Ok(self.folders.clone())
     }
 
-    /// Get cache statistics for debugging
-    pub fn get_cache_stats(&self) -> (usize, usize) {
-        (self.email_cache.len(), self.total_emails.unwrap_or(0))
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        let emails = self.get_all_emails_chronological()?;
+        Ok(Box::new(emails.into_iter().map(Ok)))
     }
 }
 
@@ -387,18 +471,116 @@ impl PstProcessor {
     }
 }
 
+/// Property context of the NBT node `nid`
+fn read_node_properties(db: &NodeDatabase, nid: u32) -> PstResult<PropertyContext> {
+    let node = db.node(nid)?;
+    read_properties(db, node.bid_data, &db.subnodes(node.bid_sub)?)
+}
+
+/// Row IDs of a folder's hierarchy or contents table, i.e. the node IDs of its subfolders or messages
+fn folder_table(db: &NodeDatabase, folder: u32, table_type: u32) -> PstResult<Vec<u32>> {
+    let node = db.node((folder & !0x1F) | table_type)?;
+    read_row_ids(db, node.bid_data, &db.subnodes(node.bid_sub)?)
+}
+
+/// Decode a message node or an embedded message subnode into MAPI properties
+pub(crate) fn read_message(db: &NodeDatabase, bid_data: u64, bid_sub: u64, depth: usize) -> PstResult<MapiMessage> {
+    let subnodes = db.subnodes(bid_sub)?;
+    let mut message = MapiMessage {
+        properties: read_properties(db, bid_data, &subnodes)?.properties,
+        ..MapiMessage::default()
+    };
+
+    if let Some(table) = subnodes.get(&NID_RECIPIENT_TABLE) {
+        message.recipients = read_table(db, table.bid_data, &db.subnodes(table.bid_sub)?)?;
+    }
+
+    if let Some(table) = subnodes.get(&NID_ATTACHMENT_TABLE) {
+        // The row IDs of the attachment table are the subnode IDs of the attachments
+        for nid in read_row_ids(db, table.bid_data, &db.subnodes(table.bid_sub)?)? {
+            let node = subnodes
+                .get(&nid)
+                .ok_or_else(|| PstError::CorruptedFile(format!("Anhang {:#x} nicht gefunden", nid)))?;
+            let attachment_subnodes = db.subnodes(node.bid_sub)?;
+            let attachment = read_properties(db, node.bid_data, &attachment_subnodes)?;
+
+            let embedded = attachment.objects.get(&prop::ATTACH_DATA).and_then(|nid| attachment_subnodes.get(nid));
+            let embedded_message = match embedded {
+                Some(embedded) if depth < MAX_EMBEDDING_DEPTH => {
+                    Some(Box::new(read_message(db, embedded.bid_data, embedded.bid_sub, depth + 1)?))
+                }
+                _ => None,
+            };
+
+            message.attachments.push(MapiAttachment { properties: attachment.properties, embedded_message });
+        }
+    }
+
+    Ok(message)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs::File;
     use std::io::Write;
     use tempfile::tempdir;
+    use chrono::TimeZone;
+    use crate::mapi::prop;
+    use crate::test_support::{
+        SyntheticCorruption, SyntheticEncryption, SyntheticFolder, SyntheticFormat, SyntheticMsg, SyntheticProperty, SyntheticPst,
+    };
+
+    /// FILETIME of 2024-01-01 00:00 UTC
+    const JANUARY_2024: u64 = 133_485_408_000_000_000;
+
+    /// FILETIME ticks per hour
+    const HOUR: u64 = 36_000_000_000;
+
+    /// Most messages put into one folder; keeps each contents table on a single heap block
+    const MESSAGES_PER_FOLDER: usize = 300;
+
+    fn message(subject: &str, hours: u64) -> SyntheticMsg {
+        SyntheticMsg::new()
+            .property(prop::SUBJECT, SyntheticProperty::Unicode(subject.to_string()))
+            .property(prop::SENDER_NAME, SyntheticProperty::Unicode("Jürgen Weiß".to_string()))
+            .property(prop::SENDER_EMAIL_ADDRESS, SyntheticProperty::Unicode("juergen@example.com".to_string()))
+            .property(prop::CLIENT_SUBMIT_TIME, SyntheticProperty::Time(JANUARY_2024 + hours * HOUR))
+            .property(prop::BODY, SyntheticProperty::Unicode(format!("Inhalt von {}", subject)))
+            .recipient(vec![
+                (prop::DISPLAY_NAME, SyntheticProperty::Unicode("Anna".to_string())),
+                (prop::SMTP_ADDRESS, SyntheticProperty::Unicode("anna@example.com".to_string())),
+                (prop::RECIPIENT_TYPE, SyntheticProperty::Long(1)),
+            ])
+    }
 
-    #[test]
-    fn test_pst_format_detection() {
-        // Test PST format enum
-        assert_eq!(PstFormat::Ansi, PstFormat::Ansi);
-        assert_ne!(PstFormat::Ansi, PstFormat::Unicode);
+    /// Mailbox with two folders, a subfolder, an attachment and an embedded message
+    fn sample_pst() -> SyntheticPst {
+        let forwarded = message("Weitergeleitet", 1).attachment(vec![
+            (prop::ATTACH_LONG_FILENAME, SyntheticProperty::Unicode("Preisliste.pdf".to_string())),
+            (prop::ATTACH_DATA, SyntheticProperty::Binary(vec![0x25; 5000])),
+            (prop::ATTACH_METHOD, SyntheticProperty::Long(1)),
+        ]);
+        let inbox = SyntheticFolder::new("Posteingang")
+            .message(message("Angebot", 2).property(prop::BODY, SyntheticProperty::Unicode("x".repeat(6000))))
+            .message(message("Rückfrage", 3).embedded_message(
+                vec![(prop::DISPLAY_NAME, SyntheticProperty::Unicode("Weitergeleitet".to_string()))],
+                forwarded,
+            ))
+            .subfolder(SyntheticFolder::new("Kunden/Alt").message(message("Archiv", 0)));
+        SyntheticPst::new().folder(inbox).folder(SyntheticFolder::new("Gesendete Elemente"))
+    }
+
+    /// PST with `count` messages spread over folders of at most `MESSAGES_PER_FOLDER`
+    fn create_pst_file(dir: &std::path::Path, count: usize) -> PathBuf {
+        let mut pst = SyntheticPst::new();
+        for (number, chunk) in (0..count).collect::<Vec<_>>().chunks(MESSAGES_PER_FOLDER).enumerate() {
+            let folder = chunk.iter().fold(SyntheticFolder::new(&format!("Ordner {}", number)), |folder, index| {
+                folder.message(message(&format!("Nachricht {}", index), *index as u64))
+            });
+            pst = pst.folder(folder);
+        }
+        pst.write_in(dir, "test.pst")
     }
 
     #[test]
@@ -459,6 +641,190 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_open_unicode_pst() {
+        let dir = tempdir().unwrap();
+        let file_path = sample_pst().file_size(240_000).write_in(dir.path(), "unicode.pst");
+
+        let processor = PstProcessor::new(file_path.clone()).unwrap();
+        let info = processor.validate().unwrap();
+
+        assert!(info.is_valid);
+        assert_eq!(info.email_count, 3);
+        assert_eq!(info.file_size, 240_000);
+        assert_eq!(processor.get_format_info(), "Unicode PST (Outlook 2003+)");
+        assert!(PstProcessor::quick_validate(&file_path).unwrap());
+
+        let folders: Vec<(String, usize)> = processor.folders().unwrap().into_iter().map(|f| (f.path, f.email_count)).collect();
+        assert_eq!(
+            folders,
+            vec![
+                (String::new(), 0),
+                ("Posteingang".to_string(), 2),
+                ("Posteingang/Kunden_Alt".to_string(), 1),
+                ("Gesendete Elemente".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extract_message_content() {
+        let dir = tempdir().unwrap();
+        let file_path = sample_pst().write_in(dir.path(), "unicode.pst");
+
+        let processor = PstProcessor::new(file_path).unwrap();
+        let emails = processor.get_all_emails_chronological().unwrap();
+        let subjects: Vec<&str> = emails.iter().map(|email| email.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["Archiv", "Angebot", "Rückfrage"]);
+
+        let offer = &emails[1];
+        assert_eq!(offer.sender, "Jürgen Weiß <juergen@example.com>");
+        assert_eq!(offer.recipient, "Anna <anna@example.com>");
+        assert_eq!(offer.date, Utc.with_ymd_and_hms(2024, 1, 1, 2, 0, 0).unwrap());
+        assert_eq!(offer.body, "x".repeat(6000));
+        assert_eq!(offer.folder.as_deref(), Some("Posteingang"));
+        assert_eq!(emails[0].folder.as_deref(), Some("Posteingang/Kunden_Alt"));
+
+        let forwarded = &emails[2].attachments[0];
+        assert_eq!(forwarded.name, "Weitergeleitet.eml");
+        assert_eq!(forwarded.content_type, "message/rfc822");
+    }
+
+    #[test]
+    fn test_open_encrypted_ansi_pst() {
+        let dir = tempdir().unwrap();
+        let file_path = sample_pst()
+            .format(SyntheticFormat::Ansi)
+            .encryption(SyntheticEncryption::Permute)
+            .file_size(160_000)
+            .write_in(dir.path(), "ansi.pst");
+
+        let processor = PstProcessor::new(file_path).unwrap();
+
+        assert_eq!(processor.get_format_info(), "ANSI PST (Outlook 97-2002)");
+        assert_eq!(processor.get_email_count().unwrap(), 3);
+        let emails = processor.get_all_emails_chronological().unwrap();
+        assert_eq!(emails[2].subject, "R\u{fc}ckfrage");
+        assert_eq!(emails[2].sender, "J\u{fc}rgen Wei\u{df} <juergen@example.com>");
+    }
+
+    #[test]
+    fn test_reject_corrupted_pst() {
+        let dir = tempdir().unwrap();
+        let bad_signature = SyntheticPst::new()
+            .corruption(SyntheticCorruption::BadSignature)
+            .write_in(dir.path(), "signature.pst");
+        let unknown_version = SyntheticPst::new()
+            .corruption(SyntheticCorruption::UnknownVersion(99))
+            .write_in(dir.path(), "version.pst");
+        let truncated = SyntheticPst::new()
+            .corruption(SyntheticCorruption::Truncated(300))
+            .write_in(dir.path(), "truncated.pst");
+        let bad_crc = sample_pst()
+            .corruption(SyntheticCorruption::BadCrc)
+            .write_in(dir.path(), "crc.pst");
+        let cyclic = sample_pst()
+            .encryption(SyntheticEncryption::Cyclic)
+            .write_in(dir.path(), "cyclic.pst");
+
+        assert!(matches!(PstProcessor::new(bad_signature.clone()), Err(PstError::InvalidFormat(_))));
+        assert!(matches!(PstProcessor::quick_validate(&bad_signature), Err(PstError::InvalidFormat(_))));
+        assert!(matches!(PstProcessor::new(unknown_version), Err(PstError::InvalidFormat(_))));
+        assert!(matches!(PstProcessor::new(truncated), Err(PstError::InvalidFormat(_))));
+        assert!(matches!(PstProcessor::new(bad_crc), Err(PstError::CorruptedFile(_))));
+        assert!(matches!(PstProcessor::new(cyclic), Err(PstError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn test_skip_message_with_bad_block_crc() {
+        let dir = tempdir().unwrap();
+        let file_path = sample_pst()
+            .corruption(SyntheticCorruption::BadBlockCrc)
+            .write_in(dir.path(), "block.pst");
+
+        let processor = PstProcessor::new(file_path).unwrap();
+        let emails = processor.get_all_emails_chronological().unwrap();
+
+        assert_eq!(processor.get_email_count().unwrap(), 3);
+        let subjects: Vec<&str> = emails.iter().map(|email| email.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["Archiv", "Rückfrage"]);
+    }
+
+    #[test]
+    fn test_recover_deleted_messages() {
+        let dir = tempdir().unwrap();
+        let inbox = SyntheticFolder::new("Posteingang")
+            .message(message("Angebot", 1))
+            .deleted_message(message("Gelöscht", 2));
+        let file_path = SyntheticPst::new().folder(inbox).write_in(dir.path(), "deleted.pst");
+
+        let mut processor = PstProcessor::new(file_path).unwrap();
+        assert_eq!(processor.get_email_count().unwrap(), 1);
+        assert_eq!(processor.recover_orphaned_items().unwrap(), 1);
+        assert_eq!(processor.recover_orphaned_items().unwrap(), 0);
+
+        let emails = processor.get_all_emails_chronological().unwrap();
+        assert!(!emails[0].recovered);
+        assert_eq!(emails[1].subject, "Gelöscht");
+        assert!(emails[1].recovered);
+        assert_eq!(emails[1].folder.as_deref(), Some("Wiederhergestellt/Posteingang"));
+
+        let folders: Vec<(String, usize)> = processor.folders().unwrap().into_iter().map(|f| (f.path, f.email_count)).collect();
+        assert!(folders.contains(&("Wiederhergestellt".to_string(), 0)));
+        assert!(folders.contains(&("Wiederhergestellt/Posteingang".to_string(), 1)));
+    }
+
+    #[test]
+    fn test_cache_stats_reported_after_open() {
+        let dir = tempdir().unwrap();
+        let file_path = sample_pst().write_in(dir.path(), "test.pst");
+
+        let processor = PstProcessor::with_cache_budget(file_path, 4096).unwrap();
+        processor.get_all_emails_chronological().unwrap();
+
+        let stats = processor.get_cache_stats();
+        assert_eq!(stats.budget_bytes, 4096);
+        assert!(stats.misses > 0);
+        assert!(stats.hits > 0);
+        assert!(stats.cached_bytes <= 4096);
+    }
+
+    #[test]
+    fn test_parallel_extraction_matches_sequential() {
+        let dir = tempdir().unwrap();
+        let file_path = create_pst_file(dir.path(), 500);
+
+        let mut processor = PstProcessor::new(file_path).unwrap();
+        processor.set_thread_count(1);
+        let sequential = processor.extract_emails(0, 500).unwrap();
+        processor.set_thread_count(4);
+        let parallel = processor.extract_emails(0, 500).unwrap();
+
+        assert_eq!(sequential.len(), 500);
+        assert_eq!(sequential, parallel);
+    }
+
+    #[test]
+    fn test_parallel_extraction_is_complete_and_ordered() {
+        let dir = tempdir().unwrap();
+        let file_path = create_pst_file(dir.path(), 400);
+        let mut processor = PstProcessor::new(file_path).unwrap();
+        let expected: Vec<String> = (0..400).map(|index| format!("Nachricht {}", index)).collect();
+
+        for threads in [2, 3, 8] {
+            processor.set_thread_count(threads);
+
+            let results = processor.extract_range(0, 400).unwrap();
+            let indices: Vec<usize> = results.iter().map(|(index, _)| *index).collect();
+            assert_eq!(indices, (0..400).collect::<Vec<_>>());
+            let subjects: Vec<String> = results.into_iter().map(|(_, email)| email.unwrap().subject).collect();
+            assert_eq!(subjects, expected);
+
+            let window: Vec<String> = processor.extract_emails(150, 100).unwrap().into_iter().map(|email| email.subject).collect();
+            assert_eq!(window, expected[150..250]);
+        }
+    }
+
     #[test]
     fn test_email_chronological_sorting() {
         // Test that emails are sorted chronologically
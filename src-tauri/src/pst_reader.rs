@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::errors::{PstError, PstResult};
+use crate::types::DEFAULT_PST_CACHE_BUDGET_MB;
+
+/// Size of a PST page in bytes; all NDB structures are aligned to this
+pub const PAGE_SIZE: usize = 512;
+
+/// Default memory budget for the cache of decoded blocks and nodes
+pub const DEFAULT_CACHE_BUDGET: usize = DEFAULT_PST_CACHE_BUDGET_MB as usize * 1024 * 1024;
+
+/// Cache statistics reported by the PST processor
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CacheStats {
+    /// Lookups of blocks, B-tree pages and nodes served from the cache
+    pub hits: u64,
+
+    /// Lookups that had to read and decode from disk
+    pub misses: u64,
+
+    /// Entries dropped to stay within the memory budget
+    pub evictions: u64,
+
+    /// Entries currently held in the cache
+    pub cached_entries: usize,
+
+    /// Bytes currently held in the cache
+    pub cached_bytes: usize,
+
+    /// Maximum number of bytes the cache may hold
+    pub budget_bytes: usize,
+}
+
+impl CacheStats {
+    /// Fraction of lookups served from the cache (0.0-1.0)
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+/// Least-recently-used cache bounded by a byte budget
+///
+/// Every entry is inserted with its size in bytes; the least recently used
+/// entries are dropped until the total fits into the budget again.
+#[derive(Debug)]
+pub(crate) struct LruCache<K, V> {
+    entries: HashMap<K, (V, usize, u64)>,
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    budget_bytes: usize,
+    stats: CacheStats,
+}
+
+impl<K: Copy + Eq + Hash, V: Clone> LruCache<K, V> {
+    pub(crate) fn new(budget_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            budget_bytes,
+            stats: CacheStats {
+                budget_bytes,
+                ..CacheStats::default()
+            },
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &K) -> Option<V> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        match self.entries.get_mut(key) {
+            Some((value, _, last_used)) => {
+                self.recency.remove(last_used);
+                self.recency.insert(tick, *key);
+                *last_used = tick;
+                self.stats.hits += 1;
+                Some(value.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Store `value`, which occupies `weight` bytes, and evict old entries to stay within the budget
+    pub(crate) fn insert(&mut self, key: K, value: V, weight: usize) {
+        if weight > self.budget_bytes {
+            return;
+        }
+
+        self.tick += 1;
+        if let Some((_, old_weight, last_used)) = self.entries.insert(key, (value, weight, self.tick)) {
+            self.recency.remove(&last_used);
+            self.stats.cached_bytes -= old_weight;
+        }
+        self.recency.insert(self.tick, key);
+        self.stats.cached_bytes += weight;
+
+        while self.stats.cached_bytes > self.budget_bytes {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            if let Some((_, evicted_weight, _)) = self.entries.remove(&oldest) {
+                self.stats.cached_bytes -= evicted_weight;
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.stats.cached_entries = self.entries.len();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.stats.cached_entries = 0;
+        self.stats.cached_bytes = 0;
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.stats.clone()
+    }
+}
+
+/// Shared random-access reader for PST files
+///
+/// The file is opened once and read with positioned reads, so a single
+/// reader can be used from several threads without seeking. Caching happens
+/// one layer up, where `NodeDatabase` keeps decoded blocks and nodes.
+#[derive(Debug)]
+pub struct PstReader {
+    file_path: PathBuf,
+    file: File,
+    file_size: u64,
+}
+
+impl PstReader {
+    /// Open a PST file for reading
+    pub fn open(file_path: &Path) -> PstResult<Self> {
+        let file = File::open(file_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => PstError::FileNotFound(file_path.to_string_lossy().to_string()),
+            std::io::ErrorKind::PermissionDenied => PstError::PermissionDenied(file_path.to_string_lossy().to_string()),
+            _ => PstError::IoError(e.to_string()),
+        })?;
+        let file_size = file.metadata()?.len();
+
+        Ok(Self {
+            file_path: file_path.to_path_buf(),
+            file,
+            file_size,
+        })
+    }
+
+    /// Path of the underlying PST file
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
+    }
+
+    /// Size of the PST file in bytes
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    /// Read `len` bytes starting at `offset`
+    pub fn read_at(&self, offset: u64, len: usize) -> PstResult<Vec<u8>> {
+        offset.checked_add(len as u64).filter(|&end| end <= self.file_size).ok_or_else(|| {
+            PstError::CorruptedFile(format!(
+                "Lesezugriff außerhalb der Datei: Offset {} + {} Bytes, Dateigröße {}",
+                offset, len, self.file_size
+            ))
+        })?;
+
+        let mut buffer = vec![0u8; len];
+        read_exact_at(&self.file, &mut buffer, offset)?;
+        Ok(buffer)
+    }
+}
+
+/// Positioned read that does not move a shared file cursor
+#[cfg(unix)]
+fn read_exact_at(file: &File, buffer: &mut [u8], offset: u64) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buffer, offset)
+}
+
+/// Positioned read that does not move a shared file cursor
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buffer: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn create_test_file(len: usize) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        file.write_all(&data).unwrap();
+        file.flush().unwrap();
+        file
+    }
+
+    #[test]
+    fn test_read_across_page_boundaries() {
+        let file = create_test_file(PAGE_SIZE * 3 + 100);
+        let reader = PstReader::open(file.path()).unwrap();
+
+        let data = reader.read_at(PAGE_SIZE as u64 - 10, PAGE_SIZE + 20).unwrap();
+
+        assert_eq!(data.len(), PAGE_SIZE + 20);
+        assert_eq!(data[0], ((PAGE_SIZE - 10) % 251) as u8);
+        assert_eq!(data[PAGE_SIZE + 19], ((2 * PAGE_SIZE + 9) % 251) as u8);
+    }
+
+    #[test]
+    fn test_read_past_end_fails() {
+        let file = create_test_file(PAGE_SIZE);
+        let reader = PstReader::open(file.path()).unwrap();
+
+        assert!(reader.read_at(PAGE_SIZE as u64 - 4, 8).is_err());
+        assert_eq!(reader.read_at(PAGE_SIZE as u64 - 4, 4).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_cache_hits_and_misses() {
+        let mut cache = LruCache::new(1024);
+
+        assert_eq!(cache.get(&1), None);
+        cache.insert(1, "Block", 100);
+        cache.insert(2, "Knoten", 32);
+        assert_eq!(cache.get(&1), Some("Block"));
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.cached_entries, 2);
+        assert_eq!(stats.cached_bytes, 132);
+    }
+
+    #[test]
+    fn test_cache_respects_budget_and_evicts_least_recent() {
+        let mut cache = LruCache::new(PAGE_SIZE * 2);
+
+        cache.insert(0, 0, PAGE_SIZE);
+        cache.insert(1, 1, PAGE_SIZE);
+        cache.get(&0); // entry 0 is now most recent
+        cache.insert(2, 2, PAGE_SIZE); // evicts entry 1
+        cache.insert(3, 3, PAGE_SIZE * 3); // larger than the budget, not cached
+
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 1);
+        assert!(stats.cached_bytes <= stats.budget_bytes);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.get(&0), Some(0));
+    }
+
+    #[test]
+    fn test_clear_cache_keeps_counters() {
+        let mut cache = LruCache::new(1024);
+
+        cache.get(&0);
+        cache.insert(0, vec![0u8; 16], 16);
+        cache.clear();
+
+        let stats = cache.stats();
+        assert_eq!(stats.cached_entries, 0);
+        assert_eq!(stats.cached_bytes, 0);
+        assert_eq!(stats.misses, 1);
+    }
+}
@@ -0,0 +1,203 @@
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Marker appended to truncated text
+const ELLIPSIS: &str = "...";
+
+/// A piece of text drawn in a single style
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span<S> {
+    pub text: String,
+    pub style: S,
+}
+
+impl<S> Span<S> {
+    pub fn new(text: impl Into<String>, style: S) -> Self {
+        Self { text: text.into(), style }
+    }
+}
+
+/// Wrap text into lines no wider than `max_width`
+///
+/// Lines break at Unicode line-break opportunities (UAX #14); newlines are
+/// mandatory breaks. A word wider than a whole line is split between grapheme
+/// clusters, never inside one. `measure` returns the rendered width of a string
+/// in the same unit as `max_width`.
+pub fn wrap_text(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    wrap_spans(&[Span::new(text, ())], max_width, |text, _| measure(text))
+        .into_iter()
+        .map(|line| line.into_iter().map(|span| span.text).collect())
+        .collect()
+}
+
+/// Wrap text made of differently styled spans, like `wrap_text`
+///
+/// Break opportunities are found across span boundaries, and the width of a
+/// line is the sum of its pieces measured in their own style.
+pub fn wrap_spans<S: Clone>(spans: &[Span<S>], max_width: f32, measure: impl Fn(&str, &S) -> f32) -> Vec<Vec<Span<S>>> {
+    let text = StyledText::new(spans);
+    if text.text.is_empty() {
+        return Vec::new();
+    }
+
+    let width = |start: usize, end: usize| -> f32 {
+        let end = start + text.text[start..end].trim_end().len();
+        text.pieces(start, end).map(|(piece, style)| measure(piece, style)).sum()
+    };
+
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut segment_start = 0;
+
+    for (index, opportunity) in linebreaks(&text.text) {
+        if width(line_start, index) > max_width {
+            if !text.text[line_start..segment_start].trim_end().is_empty() {
+                lines.push(text.line(line_start, segment_start));
+                line_start = segment_start;
+            }
+
+            // A segment wider than a whole line is split between grapheme clusters
+            if width(line_start, index) > max_width {
+                let overlong_start = line_start;
+                for (offset, grapheme) in text.text[overlong_start..index].grapheme_indices(true) {
+                    let position = overlong_start + offset;
+                    if position > line_start && width(line_start, position + grapheme.len()) > max_width {
+                        lines.push(text.line(line_start, position));
+                        line_start = position;
+                    }
+                }
+            }
+        }
+        segment_start = index;
+
+        if opportunity == BreakOpportunity::Mandatory {
+            lines.push(text.line(line_start, index));
+            line_start = index;
+        }
+    }
+    lines
+}
+
+/// The concatenated text of styled spans with the byte range of each span
+struct StyledText<'a, S> {
+    text: String,
+    ranges: Vec<(usize, usize, &'a S)>,
+}
+
+impl<'a, S: Clone> StyledText<'a, S> {
+    fn new(spans: &'a [Span<S>]) -> Self {
+        let mut text = String::new();
+        let mut ranges = Vec::with_capacity(spans.len());
+        for span in spans {
+            ranges.push((text.len(), text.len() + span.text.len(), &span.style));
+            text.push_str(&span.text);
+        }
+        Self { text, ranges }
+    }
+
+    /// Parts of `start..end` together with the style of the span they belong to
+    fn pieces(&self, start: usize, end: usize) -> impl Iterator<Item = (&str, &'a S)> + '_ {
+        self.ranges
+            .iter()
+            .filter(move |(span_start, span_end, _)| *span_start < end && *span_end > start)
+            .map(move |(span_start, span_end, style)| {
+                (&self.text[(*span_start).max(start)..(*span_end).min(end)], *style)
+            })
+    }
+
+    /// Spans of the line `start..end` without the line break and trailing whitespace
+    fn line(&self, start: usize, end: usize) -> Vec<Span<S>> {
+        let end = start + self.text[start..end].trim_end_matches(['\r', '\n']).trim_end().len();
+        self.pieces(start, end)
+            .filter(|(piece, _)| !piece.is_empty())
+            .map(|(piece, style)| Span::new(piece, style.clone()))
+            .collect()
+    }
+}
+
+/// Shorten text to at most `max_graphemes` grapheme clusters, ending in "..." when cut
+pub fn truncate_graphemes(text: &str, max_graphemes: usize) -> String {
+    if text.graphemes(true).count() <= max_graphemes {
+        return text.to_string();
+    }
+
+    let kept: String = text
+        .graphemes(true)
+        .take(max_graphemes.saturating_sub(ELLIPSIS.len()))
+        .collect();
+    format!("{}{}", kept, ELLIPSIS)
+}
+
+/// Shorten text so that it including "..." is no wider than `max_width`
+pub fn truncate_to_width(text: &str, max_width: f32, measure: impl Fn(&str) -> f32) -> String {
+    if measure(text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut kept = String::new();
+    for grapheme in text.graphemes(true) {
+        let candidate = format!("{}{}", kept, grapheme);
+        if measure(&format!("{}{}", candidate, ELLIPSIS)) > max_width {
+            break;
+        }
+        kept = candidate;
+    }
+    format!("{}{}", kept.trim_end(), ELLIPSIS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn char_count(text: &str) -> f32 {
+        text.graphemes(true).count() as f32
+    }
+
+    #[test]
+    fn test_wrap_at_break_opportunities() {
+        let lines = wrap_text("Sehr geehrte Damen und Herren, anbei die Unterlagen.", 20.0, char_count);
+
+        assert_eq!(lines, vec!["Sehr geehrte Damen", "und Herren, anbei", "die Unterlagen."]);
+    }
+
+    #[test]
+    fn test_wrap_keeps_hard_breaks_and_splits_long_words() {
+        let lines = wrap_text("Zeile eins\nDonaudampfschifffahrt", 10.0, char_count);
+
+        assert_eq!(lines, vec!["Zeile eins", "Donaudampf", "schifffahr", "t"]);
+    }
+
+    #[test]
+    fn test_never_splits_grapheme_clusters() {
+        // "e" + combining acute accent and a family emoji are single grapheme clusters
+        let text = "Caf\u{65}\u{301}\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}xyz";
+        let lines = wrap_text(text, 2.0, char_count);
+
+        assert_eq!(
+            lines,
+            vec!["Ca", "f\u{65}\u{301}", "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}", "xy", "z"]
+        );
+    }
+
+    #[test]
+    fn test_wrap_spans_across_styles() {
+        let spans = vec![Span::new("Bitte ", false), Span::new("unbedingt", true), Span::new(" bis Freitag antworten", false)];
+        // Bold characters count double
+        let lines = wrap_spans(&spans, 25.0, |text, bold| char_count(text) * if *bold { 2.0 } else { 1.0 });
+
+        assert_eq!(
+            lines,
+            vec![
+                vec![Span::new("Bitte ", false), Span::new("unbedingt", true)],
+                vec![Span::new("bis Freitag antworten", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncate_is_grapheme_safe() {
+        assert_eq!(truncate_graphemes("Grüße aus Köln", 8), "Grüße...");
+        assert_eq!(truncate_graphemes("Kurz", 8), "Kurz");
+        assert_eq!(truncate_to_width("Übersicht März", 10.0, char_count), "Übersic...");
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::types::Email;
+
+/// Length of the PidTagConversationIndex header block in hex characters (22 bytes)
+const CONVERSATION_HEADER_HEX_LEN: usize = 44;
+
+/// Length of a single PidTagConversationIndex child block in hex characters (5 bytes)
+const CONVERSATION_CHILD_HEX_LEN: usize = 10;
+
+/// Subject prefixes added by mail clients when replying or forwarding
+const SUBJECT_PREFIXES: &[&str] = &["re", "aw", "antw", "fw", "fwd", "wg", "tr", "sv", "vs"];
+
+/// A single message within a conversation tree
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ThreadNode {
+    /// The email at this position in the conversation
+    pub email: Email,
+
+    /// Direct replies to this email, oldest first
+    pub replies: Vec<ThreadNode>,
+}
+
+impl ThreadNode {
+    /// Count this node and all of its replies
+    pub fn email_count(&self) -> usize {
+        let mut count = 0;
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            count += 1;
+            pending.extend(&node.replies);
+        }
+        count
+    }
+
+    /// Walk the tree depth-first with an explicit stack, as reply chains can be arbitrarily long
+    fn collect_in_reply_order<'a>(&'a self, emails: &mut Vec<&'a Email>) {
+        let mut pending = vec![self];
+        while let Some(node) = pending.pop() {
+            emails.push(&node.email);
+            pending.extend(node.replies.iter().rev());
+        }
+    }
+}
+
+/// A reconstructed conversation consisting of one or more root messages
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConversationThread {
+    /// Stable key identifying the conversation
+    pub thread_id: String,
+
+    /// Subject of the first message in the conversation
+    pub subject: String,
+
+    /// Root messages of the conversation, oldest first
+    pub roots: Vec<ThreadNode>,
+}
+
+impl ConversationThread {
+    /// Total number of emails in the conversation
+    pub fn email_count(&self) -> usize {
+        self.roots.iter().map(|root| root.email_count()).sum()
+    }
+
+    /// Date of the earliest email in the conversation
+    pub fn started_at(&self) -> Option<DateTime<Utc>> {
+        self.emails_in_reply_order().iter().map(|email| email.date).min()
+    }
+
+    /// Flatten the tree depth-first so every reply follows the message it answers
+    pub fn emails_in_reply_order(&self) -> Vec<&Email> {
+        let mut emails = Vec::with_capacity(self.email_count());
+        for root in &self.roots {
+            root.collect_in_reply_order(&mut emails);
+        }
+        emails
+    }
+
+    /// Consume the thread and return owned emails in reply order
+    pub fn into_emails(self) -> Vec<Email> {
+        let mut emails = Vec::new();
+        let mut pending: Vec<ThreadNode> = self.roots.into_iter().rev().collect();
+        while let Some(node) = pending.pop() {
+            emails.push(node.email);
+            pending.extend(node.replies.into_iter().rev());
+        }
+        emails
+    }
+}
+
+/// Reconstructs conversation threads from a flat list of emails
+///
+/// Messages are linked by PidTagConversationIndex first, then by the
+/// References and In-Reply-To headers. Messages that still have no parent
+/// are grouped by their normalized subject.
+pub struct ThreadBuilder;
+
+impl ThreadBuilder {
+    /// Group emails into conversation threads, ordered by their first email
+    pub fn build_threads(emails: Vec<Email>) -> Vec<ConversationThread> {
+        let parents = Self::resolve_parents(&emails);
+
+        // Collect children per parent and the thread key of every root
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); emails.len()];
+        let mut roots_by_key: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut key_order: Vec<String> = Vec::new();
+
+        for (index, parent) in parents.iter().enumerate() {
+            match parent {
+                Some(parent) => children[*parent].push(index),
+                None => {
+                    let key = Self::thread_key(&emails[index], index);
+                    let roots = roots_by_key.entry(key.clone()).or_insert_with(|| {
+                        key_order.push(key);
+                        Vec::new()
+                    });
+                    roots.push(index);
+                }
+            }
+        }
+
+        for siblings in &mut children {
+            siblings.sort_by_key(|&index| (emails[index].date, index));
+        }
+
+        let mut slots: Vec<Option<Email>> = emails.into_iter().map(Some).collect();
+        let mut threads: Vec<ConversationThread> = key_order
+            .into_iter()
+            .map(|key| {
+                let mut root_indices = roots_by_key.remove(&key).unwrap_or_default();
+                root_indices.sort_by_key(|&index| {
+                    (slots[index].as_ref().map(|email| email.date), index)
+                });
+
+                let roots: Vec<ThreadNode> = root_indices
+                    .into_iter()
+                    .filter_map(|index| Self::build_node(index, &children, &mut slots))
+                    .collect();
+                let subject = roots
+                    .first()
+                    .map(|root| root.email.subject.clone())
+                    .unwrap_or_default();
+
+                ConversationThread {
+                    thread_id: key,
+                    subject,
+                    roots,
+                }
+            })
+            .collect();
+
+        threads.sort_by_key(|thread| thread.started_at());
+        threads
+    }
+
+    /// Normalize a subject for thread grouping by removing reply/forward prefixes
+    pub fn normalize_subject(subject: &str) -> String {
+        let mut rest = subject.trim();
+
+        while let Some(colon) = rest.find(':') {
+            let prefix = rest[..colon].trim();
+
+            // Accept counted prefixes such as "Re[2]" or "AW(3)"
+            let word = prefix
+                .split(['[', '('])
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_lowercase();
+
+            if SUBJECT_PREFIXES.contains(&word.as_str()) {
+                rest = rest[colon + 1..].trim_start();
+            } else {
+                break;
+            }
+        }
+
+        rest.split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    /// Determine the parent index of every email, if any
+    fn resolve_parents(emails: &[Email]) -> Vec<Option<usize>> {
+        let mut by_message_id: HashMap<String, usize> = HashMap::new();
+        let mut by_conversation_index: HashMap<String, usize> = HashMap::new();
+
+        for (index, email) in emails.iter().enumerate() {
+            if let Some(message_id) = email.message_id.as_deref() {
+                by_message_id.entry(Self::normalize_message_id(message_id)).or_insert(index);
+            }
+            if let Some(conversation_index) = Self::valid_conversation_index(email) {
+                by_conversation_index.entry(conversation_index).or_insert(index);
+            }
+        }
+
+        let mut parents: Vec<Option<usize>> = emails
+            .iter()
+            .enumerate()
+            .map(|(index, email)| {
+                Self::parent_by_conversation_index(email, &by_conversation_index)
+                    .or_else(|| Self::parent_by_headers(email, &by_message_id))
+                    .filter(|&parent| parent != index)
+            })
+            .collect();
+
+        // Break reference cycles so every chain ends at a root
+        // Each email is walked once: chains already checked end the walk
+        let mut checked = vec![false; parents.len()];
+        for index in 0..parents.len() {
+            let mut path = HashSet::new();
+            let mut current = index;
+            while !checked[current] && path.insert(current) {
+                match parents[current] {
+                    Some(parent) if path.contains(&parent) => parents[current] = None,
+                    Some(parent) => current = parent,
+                    None => {}
+                }
+            }
+            for visited in path {
+                checked[visited] = true;
+            }
+        }
+
+        parents
+    }
+
+    /// Find the parent by stripping child blocks from the conversation index
+    fn parent_by_conversation_index(email: &Email, index: &HashMap<String, usize>) -> Option<usize> {
+        let mut candidate = Self::valid_conversation_index(email)?;
+
+        while candidate.len() > CONVERSATION_HEADER_HEX_LEN {
+            candidate.truncate(candidate.len() - CONVERSATION_CHILD_HEX_LEN);
+            if let Some(&parent) = index.get(&candidate) {
+                return Some(parent);
+            }
+        }
+
+        None
+    }
+
+    /// Find the parent via In-Reply-To, falling back to the closest known reference
+    fn parent_by_headers(email: &Email, by_message_id: &HashMap<String, usize>) -> Option<usize> {
+        email
+            .in_reply_to
+            .iter()
+            .chain(email.references.iter().rev())
+            .find_map(|id| by_message_id.get(&Self::normalize_message_id(id)).copied())
+    }
+
+    /// Thread key for a root message: conversation header if present, else normalized subject
+    /// Roots without a subject are not grouped; they are keyed by Message-ID or position
+    fn thread_key(email: &Email, index: usize) -> String {
+        if let Some(conversation_index) = Self::valid_conversation_index(email) {
+            return format!("ci:{}", &conversation_index[..CONVERSATION_HEADER_HEX_LEN]);
+        }
+
+        let subject = Self::normalize_subject(&email.subject);
+        if !subject.is_empty() {
+            return format!("subject:{}", subject);
+        }
+        match email.message_id.as_deref().map(Self::normalize_message_id).filter(|id| !id.is_empty()) {
+            Some(message_id) => format!("id:{}", message_id),
+            None => format!("index:{}", index),
+        }
+    }
+
+    /// Return the normalized conversation index if it has a well-formed length
+    fn valid_conversation_index(email: &Email) -> Option<String> {
+        let conversation_index = email.conversation_index.as_deref()?.trim().to_lowercase();
+
+        let well_formed = conversation_index.len() >= CONVERSATION_HEADER_HEX_LEN
+            && (conversation_index.len() - CONVERSATION_HEADER_HEX_LEN).is_multiple_of(CONVERSATION_CHILD_HEX_LEN)
+            && conversation_index.chars().all(|c| c.is_ascii_hexdigit());
+
+        well_formed.then_some(conversation_index)
+    }
+
+    fn normalize_message_id(message_id: &str) -> String {
+        message_id
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .to_lowercase()
+    }
+
+    /// Build the tree below `index` with an explicit stack, as reply chains can be arbitrarily long
+    fn build_node(index: usize, children: &[Vec<usize>], slots: &mut [Option<Email>]) -> Option<ThreadNode> {
+        let email = slots[index].take()?;
+        // Nodes whose replies are still being built, with the children left to visit
+        let mut open = vec![(ThreadNode { email, replies: Vec::new() }, children[index].iter())];
+
+        loop {
+            let (_, pending) = open.last_mut()?;
+            match pending.next() {
+                Some(&child) => {
+                    if let Some(email) = slots[child].take() {
+                        open.push((ThreadNode { email, replies: Vec::new() }, children[child].iter()));
+                    }
+                }
+                None => {
+                    let (node, _) = open.pop()?;
+                    match open.last_mut() {
+                        Some((parent, _)) => parent.replies.push(node),
+                        None => return Some(node),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const HEADER: &str = "01d7a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4";
+
+    fn email(subject: &str, day: u32, message_id: &str) -> Email {
+        let mut email = Email::new(
+            subject.to_string(),
+            "sender@example.com".to_string(),
+            "recipient@example.com".to_string(),
+            Utc.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap(),
+            "Body".to_string(),
+        );
+        email.message_id = Some(message_id.to_string());
+        email
+    }
+
+    #[test]
+    fn test_normalize_subject() {
+        assert_eq!(ThreadBuilder::normalize_subject("Re: AW: Projekt  Status"), "projekt status");
+        assert_eq!(ThreadBuilder::normalize_subject("WG: Re[2]: Rechnung"), "rechnung");
+        assert_eq!(ThreadBuilder::normalize_subject("Termin: Montag"), "termin: montag");
+    }
+
+    #[test]
+    fn test_threads_by_in_reply_to_and_references() {
+        let original = email("Angebot", 1, "<a@example.com>");
+        let mut reply = email("Re: Angebot", 2, "<b@example.com>");
+        reply.in_reply_to = Some("<a@example.com>".to_string());
+        let mut reply_to_reply = email("Re: Re: Angebot", 3, "<c@example.com>");
+        reply_to_reply.references = vec!["<a@example.com>".to_string(), "<b@example.com>".to_string()];
+        let unrelated = email("Urlaub", 4, "<d@example.com>");
+
+        let threads = ThreadBuilder::build_threads(vec![reply_to_reply, unrelated, reply, original]);
+
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].email_count(), 3);
+        assert_eq!(threads[0].roots[0].replies[0].replies[0].email.subject, "Re: Re: Angebot");
+        let ids: Vec<_> = threads[0]
+            .emails_in_reply_order()
+            .iter()
+            .map(|e| e.message_id.clone().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["<a@example.com>", "<b@example.com>", "<c@example.com>"]);
+    }
+
+    #[test]
+    fn test_threads_by_conversation_index() {
+        let mut original = email("Budget", 1, "<x@example.com>");
+        original.conversation_index = Some(HEADER.to_string());
+        let mut first_reply = email("AW: Budget", 2, "<y@example.com>");
+        first_reply.conversation_index = Some(format!("{}0000000001", HEADER));
+        let mut second_reply = email("Something else entirely", 3, "<z@example.com>");
+        second_reply.conversation_index = Some(format!("{}00000000010000000002", HEADER));
+
+        let threads = ThreadBuilder::build_threads(vec![second_reply, first_reply, original]);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].thread_id, format!("ci:{}", HEADER));
+        assert_eq!(threads[0].roots.len(), 1);
+        assert_eq!(threads[0].roots[0].email.subject, "Budget");
+        assert_eq!(threads[0].roots[0].replies[0].replies[0].email.subject, "Something else entirely");
+    }
+
+    #[test]
+    fn test_falls_back_to_normalized_subject() {
+        let first = email("Quartalsbericht", 1, "<m1@example.com>");
+        let second = email("AW: Quartalsbericht", 2, "<m2@example.com>");
+
+        let threads = ThreadBuilder::build_threads(vec![second, first]);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].roots.len(), 2);
+        assert_eq!(threads[0].subject, "Quartalsbericht");
+        let emails = threads[0].clone().into_emails();
+        assert_eq!(emails[1].subject, "AW: Quartalsbericht");
+    }
+
+    #[test]
+    fn test_reference_cycle_does_not_lose_emails() {
+        let mut a = email("Loop", 1, "<a@example.com>");
+        a.in_reply_to = Some("<b@example.com>".to_string());
+        let mut b = email("Re: Loop", 2, "<b@example.com>");
+        b.in_reply_to = Some("<a@example.com>".to_string());
+
+        let threads = ThreadBuilder::build_threads(vec![a, b]);
+
+        assert_eq!(threads.iter().map(|t| t.email_count()).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn test_long_reply_chain_does_not_overflow_the_stack() {
+        let depth = 100_000;
+        let emails: Vec<Email> = (0..depth)
+            .map(|index| {
+                let mut email = email("Kette", 1, &format!("<{}@example.com>", index));
+                if index > 0 {
+                    email.in_reply_to = Some(format!("<{}@example.com>", index - 1));
+                }
+                email
+            })
+            .collect();
+
+        let mut threads = ThreadBuilder::build_threads(emails);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].email_count(), depth);
+        assert_eq!(threads[0].emails_in_reply_order()[1].message_id.as_deref(), Some("<1@example.com>"));
+        let emails = threads.remove(0).into_emails();
+        assert_eq!(emails.len(), depth);
+        assert_eq!(emails[depth - 1].message_id.as_deref(), Some("<99999@example.com>"));
+    }
+
+    #[test]
+    fn test_empty_subjects_do_not_merge_unrelated_emails() {
+        let first = email("", 1, "<e1@example.com>");
+        let second = email("Re: ", 2, "<e2@example.com>");
+        let mut third = email("", 3, "");
+        third.message_id = None;
+        let mut fourth = email("", 4, "");
+        fourth.message_id = None;
+
+        let threads = ThreadBuilder::build_threads(vec![first, second, third, fourth]);
+
+        assert_eq!(threads.len(), 4);
+        assert!(threads.iter().all(|thread| thread.email_count() == 1));
+    }
+}
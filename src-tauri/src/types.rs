@@ -2,6 +2,15 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+/// Upper limit for the configurable number of extraction threads
+pub const MAX_THREADS: u32 = 64;
+
+/// Default memory in MB for the cache of decoded PST blocks and nodes
+pub const DEFAULT_PST_CACHE_BUDGET_MB: u32 = 64;
+
+/// Upper limit for the configurable PST cache
+pub const MAX_PST_CACHE_BUDGET_MB: u32 = 4096;
+
 /// Configuration for email processing operations
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProcessingConfig {
@@ -16,6 +25,90 @@ pub struct ProcessingConfig {
     
     /// Directory where PDF files will be saved
     pub output_directory: String,
+
+    /// How emails are grouped into PDF files
+    #[serde(default)]
+    pub processing_mode: ProcessingMode,
+
+    /// Number of worker threads for email extraction (0 = one per CPU core)
+    #[serde(default)]
+    pub threads: u32,
+
+    /// Memory in MB for decoded PST blocks and nodes kept between reads
+    #[serde(default = "default_pst_cache_budget_mb")]
+    pub pst_cache_budget_mb: u32,
+
+    /// Read from an IMAP server instead of `pst_file_path`
+    #[serde(default)]
+    pub imap: Option<ImapConfig>,
+
+    /// Batch job: source files, directories or glob patterns; replaces `pst_file_path` when set
+    #[serde(default)]
+    pub sources: Vec<String>,
+
+    /// Whether the sources of a batch job are processed one after another or concurrently
+    #[serde(default)]
+    pub batch_execution: BatchExecution,
+
+    /// Whether a batch job writes one subfolder per source or a single merged PDF series
+    #[serde(default)]
+    pub batch_output: BatchOutput,
+
+    /// Within which scope duplicate emails are skipped
+    #[serde(default)]
+    pub dedup_scope: DedupScope,
+
+    /// How emails with nearly identical bodies are handled
+    #[serde(default)]
+    pub near_duplicates: NearDuplicateMode,
+
+    /// Estimated body similarity (0-1) from which two emails count as near-duplicates
+    #[serde(default = "default_near_duplicate_threshold")]
+    pub near_duplicate_threshold: f64,
+
+    /// Search PST files for deleted and orphaned messages and archive them in a separate section
+    #[serde(default)]
+    pub recover_deleted_items: bool,
+
+    /// Only archive emails that are new or changed compared to this older copy of the mailbox
+    #[serde(default)]
+    pub delta_base: Option<String>,
+
+    /// TrueType/OpenType fonts for the PDFs, tried in order before the bundled fonts
+    #[serde(default)]
+    pub font_files: Vec<String>,
+
+    /// Draw image attachments on pages after the email that carries them
+    #[serde(default)]
+    pub render_image_attachments: bool,
+
+    /// Embed the original attachments as files into the PDFs
+    #[serde(default)]
+    pub embed_attachments: bool,
+
+    /// Largest attachment in MB that is embedded
+    #[serde(default = "default_max_embedded_file_mb")]
+    pub max_embedded_file_mb: u32,
+
+    /// Largest total size in MB of the attachments embedded into one PDF
+    #[serde(default = "default_max_embedded_pdf_mb")]
+    pub max_embedded_pdf_mb: u32,
+
+    /// Archiving standard the generated PDFs conform to
+    #[serde(default)]
+    pub pdf_standard: PdfStandard,
+
+    /// List the emails of each PDF with page numbers on its first page
+    #[serde(default)]
+    pub table_of_contents: bool,
+
+    /// Running header and footer of every PDF page
+    #[serde(default)]
+    pub page_frame: PageFrame,
+
+    /// Mailbox owner written as the author of the PDFs; derived from the emails when unset
+    #[serde(default)]
+    pub mailbox_owner: Option<String>,
 }
 
 impl ProcessingConfig {
@@ -31,6 +124,27 @@ impl ProcessingConfig {
             emails_per_pdf,
             base_file_name,
             output_directory,
+            processing_mode: ProcessingMode::default(),
+            threads: 0,
+            pst_cache_budget_mb: DEFAULT_PST_CACHE_BUDGET_MB,
+            imap: None,
+            sources: Vec::new(),
+            batch_execution: BatchExecution::default(),
+            batch_output: BatchOutput::default(),
+            dedup_scope: DedupScope::default(),
+            near_duplicates: NearDuplicateMode::default(),
+            near_duplicate_threshold: DEFAULT_NEAR_DUPLICATE_THRESHOLD,
+            recover_deleted_items: false,
+            delta_base: None,
+            font_files: Vec::new(),
+            render_image_attachments: false,
+            embed_attachments: false,
+            max_embedded_file_mb: DEFAULT_MAX_EMBEDDED_FILE_MB,
+            max_embedded_pdf_mb: DEFAULT_MAX_EMBEDDED_PDF_MB,
+            pdf_standard: PdfStandard::default(),
+            table_of_contents: false,
+            page_frame: PageFrame::default(),
+            mailbox_owner: None,
         }
     }
 
@@ -38,22 +152,83 @@ impl ProcessingConfig {
     pub fn validate(&self) -> Result<(), crate::errors::ValidationError> {
         use crate::errors::ValidationError;
 
-        // Validate PST file path
-        if self.pst_file_path.is_empty() {
+        // Validate the input: an IMAP server, a list of sources or a single file/directory path
+        if let Some(imap) = &self.imap {
+            imap.validate()?;
+        } else if !self.sources.is_empty() {
+            for source in &self.sources {
+                // Glob patterns are checked when they are expanded
+                if !crate::batch_job::is_glob_pattern(source) {
+                    validate_source_path(source)?;
+                }
+            }
+        } else if self.pst_file_path.is_empty() {
             return Err(ValidationError::RequiredFieldMissing("pst_file_path".to_string()));
+        } else {
+            validate_source_path(&self.pst_file_path)?;
+        }
+
+        // Validate the mailbox copy a delta archive is compared against
+        if let Some(delta_base) = &self.delta_base {
+            validate_source_path(delta_base)?;
+        }
+
+        // Validate user-provided font files
+        for font_file in &self.font_files {
+            let path = PathBuf::from(font_file);
+            let is_font = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| ext == "ttf" || ext == "otf");
+            if !is_font || !path.is_file() {
+                return Err(ValidationError::InvalidValue {
+                    field: "font_files".to_string(),
+                    reason: format!("Keine TTF- oder OTF-Schriftdatei: {}", font_file),
+                });
+            }
+        }
+
+        // PDF/A embeds every font, and the built-in fallback fonts cannot be embedded
+        if self.pdf_standard.pdfa_part().is_some()
+            && self.font_files.is_empty()
+            && crate::pdf_fonts::FontChain::bundled().fonts().is_empty()
+        {
+            return Err(ValidationError::InvalidValue {
+                field: "font_files".to_string(),
+                reason: "PDF/A erfordert mindestens eine TTF- oder OTF-Schriftdatei".to_string(),
+            });
         }
 
-        if !self.pst_file_path.to_lowercase().ends_with(".pst") {
-            return Err(ValidationError::InvalidFileExtension {
-                expected: ".pst".to_string(),
-                actual: PathBuf::from(&self.pst_file_path)
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .unwrap_or("none")
-                    .to_string(),
+        // Validate near-duplicate similarity threshold
+        if !(self.near_duplicate_threshold > 0.0 && self.near_duplicate_threshold <= 1.0) {
+            return Err(ValidationError::InvalidValue {
+                field: "near_duplicate_threshold".to_string(),
+                reason: "Schwellenwert muss größer als 0 und höchstens 1 sein".to_string(),
             });
         }
 
+        // Validate the size limits for embedded attachments
+        if self.embed_attachments {
+            if self.max_embedded_file_mb == 0 || self.max_embedded_pdf_mb == 0 {
+                return Err(ValidationError::InvalidValue {
+                    field: "max_embedded_file_mb".to_string(),
+                    reason: "Größenlimits für eingebettete Anhänge müssen mindestens 1 MB betragen".to_string(),
+                });
+            }
+            if self.max_embedded_file_mb > self.max_embedded_pdf_mb {
+                return Err(ValidationError::InvalidValue {
+                    field: "max_embedded_file_mb".to_string(),
+                    reason: "Limit pro Anhang darf das Limit pro PDF nicht überschreiten".to_string(),
+                });
+            }
+            if !self.pdf_standard.allows_embedded_files() {
+                return Err(ValidationError::InvalidValue {
+                    field: "embed_attachments".to_string(),
+                    reason: "PDF/A-2b erlaubt keine eingebetteten Anhänge, bitte PDF/A-3b wählen".to_string(),
+                });
+            }
+        }
+
         // Validate emails per PDF count
         if self.emails_per_pdf < 1 || self.emails_per_pdf > 25 {
             return Err(ValidationError::InvalidEmailCount {
@@ -63,6 +238,22 @@ impl ProcessingConfig {
             });
         }
 
+        // Validate worker thread count (0 selects one thread per CPU core)
+        if self.threads > MAX_THREADS {
+            return Err(ValidationError::InvalidThreadCount {
+                max: MAX_THREADS,
+                actual: self.threads,
+            });
+        }
+
+        // Validate the PST cache budget
+        if self.pst_cache_budget_mb == 0 || self.pst_cache_budget_mb > MAX_PST_CACHE_BUDGET_MB {
+            return Err(ValidationError::InvalidValue {
+                field: "pst_cache_budget_mb".to_string(),
+                reason: format!("PST-Cache muss zwischen 1 und {} MB groß sein", MAX_PST_CACHE_BUDGET_MB),
+            });
+        }
+
         // Validate base filename
         if self.base_file_name.is_empty() {
             return Err(ValidationError::RequiredFieldMissing("base_file_name".to_string()));
@@ -93,6 +284,260 @@ impl ProcessingConfig {
     }
 }
 
+/// Check that a source path is a directory or has a supported mail file extension
+fn validate_source_path(path: &str) -> Result<(), crate::errors::ValidationError> {
+    let path = PathBuf::from(path);
+    if path.is_dir() {
+        return Ok(());
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_else(|| "none".to_string());
+    let supported_extensions = crate::mail_source::supported_source_extensions();
+    if !supported_extensions.contains(&extension.as_str()) {
+        return Err(crate::errors::ValidationError::InvalidFileExtension {
+            expected: supported_extensions
+                .iter()
+                .map(|ext| format!(".{}", ext))
+                .collect::<Vec<_>>()
+                .join(", "),
+            actual: extension,
+        });
+    }
+
+    Ok(())
+}
+
+/// Execution strategy for batch jobs with several sources
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum BatchExecution {
+    /// Process one source after another
+    #[default]
+    Sequential,
+    /// Process all sources concurrently
+    Parallel,
+}
+
+/// Output layout for batch jobs with several sources
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum BatchOutput {
+    /// One subfolder per source in the output directory
+    #[default]
+    PerSource,
+    /// All emails of all sources in one chronological PDF series
+    Merged,
+}
+
+/// Scope within which duplicate emails are recognized
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum DedupScope {
+    /// Archive every email, including duplicates
+    #[default]
+    Disabled,
+    /// Only copies within the same folder of the same source
+    PerFolder,
+    /// Only copies within the same source
+    PerSource,
+    /// Copies across all sources of the job
+    Global,
+}
+
+/// Handling of emails whose bodies are nearly identical
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum NearDuplicateMode {
+    /// Archive near-duplicates like any other email
+    #[default]
+    Disabled,
+    /// Archive only the longest email of each cluster
+    KeepLongest,
+    /// Archive all emails and mark near-duplicates in the PDF
+    Mark,
+}
+
+/// Archiving standard of the generated PDFs
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum PdfStandard {
+    /// Plain PDF without conformance requirements
+    #[default]
+    Plain,
+    /// PDF/A-2b (ISO 19005-2, visual appearance)
+    PdfA2b,
+    /// PDF/A-3b (ISO 19005-3), which also allows embedded files of any type
+    PdfA3b,
+}
+
+impl PdfStandard {
+    /// Part of ISO 19005 the output conforms to, if any
+    pub fn pdfa_part(self) -> Option<u8> {
+        match self {
+            PdfStandard::Plain => None,
+            PdfStandard::PdfA2b => Some(2),
+            PdfStandard::PdfA3b => Some(3),
+        }
+    }
+
+    /// Whether arbitrary attachments may be embedded as files
+    pub fn allows_embedded_files(self) -> bool {
+        self != PdfStandard::PdfA2b
+    }
+}
+
+/// Running header and footer printed on every page of the PDFs
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PageFrame {
+    /// "Seite X von Y" in the footer
+    #[serde(default)]
+    pub page_numbers: bool,
+
+    /// Sequence number of the PDF within the archive, in the header
+    #[serde(default)]
+    pub sequence_number: bool,
+
+    /// Name of the mailbox the emails come from, in the header
+    #[serde(default)]
+    pub source_name: bool,
+
+    /// Date the archive was generated, in the footer
+    #[serde(default)]
+    pub archive_date: bool,
+
+    /// Legal notice at the bottom of every page
+    #[serde(default)]
+    pub legal_notice: Option<String>,
+}
+
+/// Default similarity from which emails count as near-duplicates
+pub const DEFAULT_NEAR_DUPLICATE_THRESHOLD: f64 = 0.8;
+
+fn default_near_duplicate_threshold() -> f64 {
+    DEFAULT_NEAR_DUPLICATE_THRESHOLD
+}
+
+/// Default size limit in MB for a single embedded attachment
+pub const DEFAULT_MAX_EMBEDDED_FILE_MB: u32 = 25;
+
+/// Default size limit in MB for all attachments embedded into one PDF
+pub const DEFAULT_MAX_EMBEDDED_PDF_MB: u32 = 100;
+
+fn default_pst_cache_budget_mb() -> u32 {
+    DEFAULT_PST_CACHE_BUDGET_MB
+}
+
+fn default_max_embedded_file_mb() -> u32 {
+    DEFAULT_MAX_EMBEDDED_FILE_MB
+}
+
+fn default_max_embedded_pdf_mb() -> u32 {
+    DEFAULT_MAX_EMBEDDED_PDF_MB
+}
+
+/// Default number of messages fetched per IMAP UID FETCH command
+pub const DEFAULT_IMAP_BATCH_SIZE: u32 = 50;
+
+/// Transport security for IMAP connections
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ImapSecurity {
+    /// Implicit TLS (usually port 993)
+    #[default]
+    Tls,
+    /// Plain connection upgraded with STARTTLS (usually port 143)
+    StartTls,
+    /// Unencrypted connection, only for local test servers
+    Plain,
+}
+
+/// Connection settings for an IMAP mail source
+///
+/// The password is accepted from the frontend but never serialized back,
+/// so it only lives in memory for the duration of the session.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImapConfig {
+    /// Server host name
+    pub host: String,
+
+    /// Server port (993 for TLS, 143 for STARTTLS)
+    pub port: u16,
+
+    /// Login name
+    pub username: String,
+
+    /// Login password (never serialized)
+    #[serde(default, skip_serializing)]
+    pub password: String,
+
+    /// Transport security
+    #[serde(default)]
+    pub security: ImapSecurity,
+
+    /// Folders to archive ("/" separated); empty archives all folders
+    #[serde(default)]
+    pub folders: Vec<String>,
+
+    /// Messages per UID FETCH command
+    #[serde(default = "default_imap_batch_size")]
+    pub batch_size: u32,
+}
+
+fn default_imap_batch_size() -> u32 {
+    DEFAULT_IMAP_BATCH_SIZE
+}
+
+impl ImapConfig {
+    /// Validate the connection settings
+    pub fn validate(&self) -> Result<(), crate::errors::ValidationError> {
+        use crate::errors::ValidationError;
+
+        if self.host.trim().is_empty() {
+            return Err(ValidationError::RequiredFieldMissing("imap.host".to_string()));
+        }
+        if self.username.is_empty() {
+            return Err(ValidationError::RequiredFieldMissing("imap.username".to_string()));
+        }
+        if self.port == 0 {
+            return Err(ValidationError::InvalidValue {
+                field: "imap.port".to_string(),
+                reason: "Port darf nicht 0 sein".to_string(),
+            });
+        }
+        if self.batch_size == 0 {
+            return Err(ValidationError::InvalidValue {
+                field: "imap.batch_size".to_string(),
+                reason: "Batch-Größe muss mindestens 1 sein".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for ImapConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImapConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &"***")
+            .field("security", &self.security)
+            .field("folders", &self.folders)
+            .field("batch_size", &self.batch_size)
+            .finish()
+    }
+}
+
+/// Grouping strategy for the generated PDF files
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ProcessingMode {
+    /// Fixed-size chunks of `emails_per_pdf` emails in chronological order
+    #[default]
+    Chronological,
+
+    /// One PDF per conversation with emails in reply order
+    ByConversation,
+}
+
 /// Progress tracking for email processing operations
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ProcessingProgress {
@@ -122,6 +567,10 @@ pub struct ProcessingProgress {
     
     /// Whether processing was cancelled
     pub is_cancelled: bool,
+
+    /// Progress of the individual sources of a batch job
+    #[serde(default)]
+    pub sources: Vec<SourceProgress>,
 }
 
 impl ProcessingProgress {
@@ -137,6 +586,7 @@ impl ProcessingProgress {
             started_at: None,
             completed_at: None,
             is_cancelled: false,
+            sources: Vec::new(),
         }
     }
 
@@ -160,6 +610,37 @@ impl ProcessingProgress {
         self.status = status;
     }
 
+    /// Update the progress of one source and recompute the overall email count
+    pub fn update_source_progress(&mut self, index: usize, processed_emails: usize, status: String) {
+        if let Some(source) = self.sources.get_mut(index) {
+            source.processed_emails = processed_emails;
+            source.status = status;
+        }
+        self.processed_emails = self.sources.iter().map(|source| source.processed_emails).sum();
+    }
+
+    /// Record skipped duplicates of one source; they no longer count towards the totals
+    pub fn record_duplicates(&mut self, index: usize, duplicates: usize) {
+        if let Some(source) = self.sources.get_mut(index) {
+            source.duplicates += duplicates;
+            source.total_emails = source.total_emails.saturating_sub(duplicates);
+        }
+        self.total_emails = self.total_emails.saturating_sub(duplicates);
+    }
+
+    /// Mark one source as finished, optionally with an error
+    pub fn complete_source(&mut self, index: usize, error: Option<String>) {
+        if let Some(source) = self.sources.get_mut(index) {
+            source.is_complete = true;
+            source.status = if error.is_some() {
+                "Fehlgeschlagen".to_string()
+            } else {
+                "Abgeschlossen".to_string()
+            };
+            source.error = error;
+        }
+    }
+
     /// Mark processing as complete
     pub fn complete(&mut self) {
         self.is_complete = true;
@@ -201,6 +682,47 @@ impl ProcessingProgress {
     }
 }
 
+/// Progress of a single source within a batch job
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SourceProgress {
+    /// Display name of the source (file name or account)
+    pub source: String,
+
+    /// Number of emails in this source
+    pub total_emails: usize,
+
+    /// Number of emails of this source written to PDFs
+    pub processed_emails: usize,
+
+    /// Current status message (in German)
+    pub status: String,
+
+    /// Whether this source is finished
+    pub is_complete: bool,
+
+    /// Error message if this source failed
+    pub error: Option<String>,
+
+    /// Number of duplicate emails skipped in this source
+    #[serde(default)]
+    pub duplicates: usize,
+}
+
+impl SourceProgress {
+    /// Create progress for a source that has not started yet
+    pub fn new(source: String, total_emails: usize) -> Self {
+        Self {
+            source,
+            total_emails,
+            processed_emails: 0,
+            status: "Wartend".to_string(),
+            is_complete: false,
+            error: None,
+            duplicates: 0,
+        }
+    }
+}
+
 impl Default for ProcessingProgress {
     fn default() -> Self {
         Self::new()
@@ -304,6 +826,29 @@ pub struct Email {
     /// In-reply-to message ID
     pub in_reply_to: Option<String>,
     
+    /// Message IDs from the References header, oldest first
+    #[serde(default)]
+    pub references: Vec<String>,
+    
+    /// Hex-encoded PidTagConversationIndex
+    #[serde(default)]
+    pub conversation_index: Option<String>,
+    
+    /// Folder path within the source, using "/" as separator (None = root)
+    pub folder: Option<String>,
+    
+    /// Read/replied/flagged state from the source mailbox
+    #[serde(default)]
+    pub flags: MessageFlags,
+
+    /// Description of the email this one nearly duplicates, when near-duplicates are marked
+    #[serde(default)]
+    pub near_duplicate_of: Option<String>,
+
+    /// Deleted or orphaned item found by the recovery scan rather than in the folder hierarchy
+    #[serde(default)]
+    pub recovered: bool,
+    
     /// Email size in bytes
     pub size: usize,
 }
@@ -330,6 +875,12 @@ impl Email {
             priority: EmailPriority::Normal,
             message_id: None,
             in_reply_to: None,
+            references: Vec::new(),
+            conversation_index: None,
+            folder: None,
+            flags: MessageFlags::default(),
+            near_duplicate_of: None,
+            recovered: false,
             size: 0,
         }
     }
@@ -350,6 +901,49 @@ impl Email {
     }
 }
 
+/// Message state flags (Maildir info flags, IMAP system flags)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct MessageFlags {
+    /// Message has been read
+    pub seen: bool,
+    
+    /// Message has been answered
+    pub replied: bool,
+    
+    /// Message is flagged for follow-up
+    pub flagged: bool,
+    
+    /// Message is marked as deleted
+    pub trashed: bool,
+    
+    /// Message is an unsent draft
+    pub draft: bool,
+}
+
+/// Folder within a mail source
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MailFolder {
+    /// Full folder path using "/" as separator (empty for the root)
+    pub path: String,
+    
+    /// Display name of the folder
+    pub name: String,
+    
+    /// Number of emails directly in this folder
+    pub email_count: usize,
+}
+
+impl MailFolder {
+    /// Create a new folder entry
+    pub fn new(path: String, name: String, email_count: usize) -> Self {
+        Self {
+            path,
+            name,
+            email_count,
+        }
+    }
+}
+
 /// Email attachment information
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Attachment {
@@ -427,6 +1021,10 @@ pub struct ProcessingSession {
     
     /// Generated PDF file paths
     pub generated_files: Vec<String>,
+
+    /// Duplicate emails that were skipped, with the copy that was kept
+    #[serde(default)]
+    pub duplicates: Vec<crate::dedup::DuplicateRecord>,
     
     /// Session creation time
     pub created_at: DateTime<Utc>,
@@ -440,6 +1038,7 @@ impl ProcessingSession {
             config,
             progress: ProcessingProgress::new(),
             generated_files: Vec::new(),
+            duplicates: Vec::new(),
             created_at: Utc::now(),
         }
     }
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use crate::dedup::Deduplicator;
+use crate::errors::{MailSourceError, MailSourceResult};
+use crate::mail_source::{open_mail_source, EmailIterator, MailSource};
+use crate::types::{Email, MailFolder};
+
+/// Several mail sources presented as one mailbox
+///
+/// Folders with the same path are merged, and an email that appears in the
+/// same folder of more than one source (same Message-ID or content hash) is
+/// only returned for the first source that contains it. Copies filed in
+/// different folders are kept, so every folder shows its complete content.
+pub struct VirtualMailbox {
+    sources: Vec<Box<dyn MailSource>>,
+    index: MailboxIndex,
+}
+
+/// Result of reading all sources once
+struct MailboxIndex {
+    /// Per source, whether each item in iteration order is returned
+    keep: Vec<Vec<bool>>,
+    /// Emails per folder path after deduplication
+    folder_counts: BTreeMap<String, usize>,
+}
+
+impl VirtualMailbox {
+    /// Combine already opened sources; earlier sources win when items are duplicated
+    /// Reads every source once to find the duplicates
+    pub fn new(sources: Vec<Box<dyn MailSource>>) -> MailSourceResult<Self> {
+        if sources.is_empty() {
+            return Err(MailSourceError::InvalidFormat(
+                "Ein virtuelles Postfach benötigt mindestens eine Quelle".to_string(),
+            ));
+        }
+        let index = MailboxIndex::build(&sources)?;
+        Ok(Self { sources, index })
+    }
+
+    /// Open every path with `open_mail_source` and combine the results
+    pub fn open(paths: &[PathBuf]) -> MailSourceResult<Self> {
+        let sources = paths
+            .iter()
+            .map(|path| open_mail_source(path))
+            .collect::<MailSourceResult<Vec<_>>>()?;
+        Self::new(sources)
+    }
+
+    /// Emails of a single folder in chronological order; "" selects the root folder
+    pub fn folder_emails(&self, folder_path: &str) -> MailSourceResult<Vec<Email>> {
+        let mut emails = self.emails_chronological()?;
+        emails.retain(|email| email.folder.as_deref().unwrap_or_default() == folder_path);
+        Ok(emails)
+    }
+}
+
+impl MailboxIndex {
+    /// Mark the first copy of each email per folder; unreadable items are kept so iteration reports them
+    fn build(sources: &[Box<dyn MailSource>]) -> MailSourceResult<Self> {
+        let mut seen: HashSet<(String, String)> = HashSet::new();
+        let mut keep = Vec::with_capacity(sources.len());
+        let mut folder_counts = BTreeMap::new();
+
+        for source in sources {
+            let mut source_keep = Vec::new();
+            for email in source.emails()? {
+                let Ok(email) = email else {
+                    source_keep.push(true);
+                    continue;
+                };
+                let folder = email.folder.clone().unwrap_or_default();
+                let (identity, _) = Deduplicator::identity(&email);
+                let first_copy = seen.insert((folder.clone(), identity));
+                if first_copy {
+                    *folder_counts.entry(folder).or_insert(0) += 1;
+                }
+                source_keep.push(first_copy);
+            }
+            keep.push(source_keep);
+        }
+
+        Ok(Self { keep, folder_counts })
+    }
+}
+
+impl MailSource for VirtualMailbox {
+    fn source_name(&self) -> String {
+        self.sources
+            .iter()
+            .map(|source| source.source_name())
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    /// Number of emails after deduplication
+    fn email_count(&self) -> MailSourceResult<usize> {
+        Ok(self.index.folder_counts.values().sum())
+    }
+
+    /// Folders of all sources merged by path, with the counts after deduplication
+    fn folders(&self) -> MailSourceResult<Vec<MailFolder>> {
+        let mut merged: BTreeMap<String, MailFolder> = BTreeMap::new();
+        for source in &self.sources {
+            for folder in source.folders()? {
+                merged.entry(folder.path.clone()).or_insert(folder);
+            }
+        }
+        for folder in merged.values_mut() {
+            folder.email_count = self.index.folder_counts.get(&folder.path).copied().unwrap_or(0);
+        }
+
+        // The root belongs to no single source
+        if let Some(root) = merged.get_mut("") {
+            root.name = self.source_name();
+        }
+        Ok(merged.into_values().collect())
+    }
+
+    /// Emails of all sources in source order, skipping the copies marked by the index
+    fn emails(&self) -> MailSourceResult<EmailIterator<'_>> {
+        let emails = self
+            .sources
+            .iter()
+            .zip(&self.index.keep)
+            .flat_map(|(source, keep)| {
+                let emails: EmailIterator<'_> = match source.emails() {
+                    Ok(emails) => emails,
+                    Err(e) => Box::new(std::iter::once(Err(e))),
+                };
+                // Items beyond the index (the source changed since) are kept
+                emails
+                    .enumerate()
+                    .filter(move |(position, _)| keep.get(*position).copied().unwrap_or(true))
+                    .map(|(_, email)| email)
+            });
+        Ok(Box::new(emails))
+    }
+
+    fn finish(&self) -> MailSourceResult<()> {
+        self.sources.iter().try_for_each(|source| source.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn write_eml(dir: &std::path::Path, name: &str, subject: &str, message_id: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join(name),
+            format!(
+                "Subject: {}\nMessage-ID: <{}>\nDate: Mon, 1 Jan 2024 10:00:00 +0000\n\nText\n",
+                subject, message_id
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_merges_folders_and_skips_duplicates() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        write_eml(&old.path().join("Kunden"), "a.eml", "Angebot", "a@example.com");
+        write_eml(old.path(), "b.eml", "Rechnung", "b@example.com");
+        write_eml(&new.path().join("Kunden"), "a.eml", "Angebot", "a@example.com");
+        write_eml(&new.path().join("Kunden"), "c.eml", "Auftrag", "c@example.com");
+
+        let mailbox = VirtualMailbox::open(&[old.path().to_path_buf(), new.path().to_path_buf()]).unwrap();
+
+        let folders = mailbox.folders().unwrap();
+        let summary: Vec<(&str, usize)> = folders.iter().map(|f| (f.path.as_str(), f.email_count)).collect();
+        assert_eq!(summary, vec![("", 1), ("Kunden", 2)]);
+
+        let emails: Vec<Email> = mailbox.emails().unwrap().map(|email| email.unwrap()).collect();
+        assert_eq!(emails.len(), 3);
+        assert_eq!(mailbox.email_count().unwrap(), 3);
+
+        let kunden = mailbox.folder_emails("Kunden").unwrap();
+        let subjects: Vec<&str> = kunden.iter().map(|email| email.subject.as_str()).collect();
+        assert_eq!(subjects.len(), 2);
+        assert!(subjects.contains(&"Angebot") && subjects.contains(&"Auftrag"));
+        assert_eq!(mailbox.folder_emails("").unwrap()[0].subject, "Rechnung");
+    }
+
+    #[test]
+    fn test_copies_in_different_folders_are_kept() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        write_eml(&old.path().join("Kunden"), "a.eml", "Angebot", "a@example.com");
+        write_eml(&new.path().join("Archiv"), "a.eml", "Angebot", "a@example.com");
+
+        let mailbox = VirtualMailbox::open(&[old.path().to_path_buf(), new.path().to_path_buf()]).unwrap();
+
+        let summary: Vec<(String, usize)> = mailbox.folders().unwrap().into_iter().map(|f| (f.path, f.email_count)).collect();
+        assert_eq!(summary, vec![(String::new(), 0), ("Archiv".to_string(), 1), ("Kunden".to_string(), 1)]);
+        assert_eq!(mailbox.folder_emails("Archiv").unwrap().len(), 1);
+        assert_eq!(mailbox.folder_emails("Kunden").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_requires_a_source() {
+        assert!(matches!(VirtualMailbox::new(Vec::new()), Err(MailSourceError::InvalidFormat(_))));
+    }
+}